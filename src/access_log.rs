@@ -0,0 +1,289 @@
+/// One line per request (method, path, status, bytes, duration, model, API key id),
+/// written separately from the tracing logs
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::error;
+
+use crate::filters::bearer_token;
+use crate::proxy::ProxyState;
+
+/// Where access log lines go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogDestination {
+    Stdout,
+    File(PathBuf),
+}
+
+impl LogDestination {
+    /// `off` (None), `stdout`, or a file path
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" | "off" | "false" => None,
+            "stdout" | "-" => Some(Self::Stdout),
+            path => Some(Self::File(PathBuf::from(path))),
+        }
+    }
+
+    /// Open the destination for appending lines
+    pub fn open(&self) -> Result<Box<dyn Write + Send>, String> {
+        Ok(match self {
+            Self::Stdout => Box::new(std::io::stdout()),
+            Self::File(path) => Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?,
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AccessLogFormat {
+    /// Apache/nginx combined format, followed by the model and duration
+    #[default]
+    Combined,
+    /// One JSON object per line
+    Json,
+    /// `{field}` placeholders, e.g. `{method} {path} {status} {duration_ms}`
+    Template(String),
+}
+
+impl AccessLogFormat {
+    pub fn parse(value: &str) -> Self {
+        match value.trim() {
+            "combined" | "" => Self::Combined,
+            "json" => Self::Json,
+            template => Self::Template(template.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Combined => "combined",
+            Self::Json => "json",
+            Self::Template(template) => template,
+        }
+    }
+}
+
+/// Model a request was for, attached to the response so the access log can record it
+#[derive(Debug, Clone)]
+pub struct RequestModel(pub String);
+
+/// What is known about a request once its response body has been sent
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    pub remote: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub status: u16,
+    pub bytes: u64,
+    pub duration_ms: f64,
+    pub model: Option<String>,
+    pub key_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+}
+
+impl Entry {
+    pub fn format(&self, format: &AccessLogFormat) -> String {
+        let dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        match format {
+            AccessLogFormat::Combined => format!(
+                "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" \"{}\" {:.1}ms",
+                dash(&self.remote),
+                dash(&self.key_id),
+                Utc::now().format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.bytes,
+                dash(&self.referer),
+                dash(&self.user_agent),
+                dash(&self.model),
+                self.duration_ms
+            ),
+            AccessLogFormat::Json => json!({
+                "time": Utc::now().to_rfc3339(),
+                "remote": self.remote,
+                "method": self.method,
+                "path": self.path,
+                "status": self.status,
+                "bytes": self.bytes,
+                "duration_ms": (self.duration_ms * 10.0).round() / 10.0,
+                "model": self.model,
+                "key_id": self.key_id,
+                "user_agent": self.user_agent,
+            })
+            .to_string(),
+            AccessLogFormat::Template(template) => {
+                let fields = [
+                    ("{time}", Utc::now().to_rfc3339()),
+                    ("{remote}", dash(&self.remote)),
+                    ("{method}", self.method.clone()),
+                    ("{path}", self.path.clone()),
+                    ("{status}", self.status.to_string()),
+                    ("{bytes}", self.bytes.to_string()),
+                    ("{duration_ms}", format!("{:.1}", self.duration_ms)),
+                    ("{model}", dash(&self.model)),
+                    ("{key_id}", dash(&self.key_id)),
+                    ("{user_agent}", dash(&self.user_agent)),
+                ];
+                fields
+                    .iter()
+                    .fold(template.clone(), |line, (placeholder, value)| line.replace(placeholder, value))
+            }
+        }
+    }
+}
+
+/// A short, stable id for an API key, so keys can be told apart without logging them
+pub fn key_id(token: &str) -> String {
+    let hash = token
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("key-{:08x}", hash >> 32)
+}
+
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn open(destination: &LogDestination, format: AccessLogFormat) -> Result<Self, String> {
+        let sink = destination.open().map_err(|e| format!("Access log: {}", e))?;
+        Ok(Self { format, sink: Mutex::new(sink) })
+    }
+
+    pub fn write(&self, entry: &Entry) {
+        let line = entry.format(&self.format);
+        let mut sink = self.sink.lock().unwrap();
+        if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+            error!("❌ Failed to write access log: {}", e);
+        }
+    }
+}
+
+/// Writes the entry when the response body is finished (or dropped by a disconnecting client)
+struct PendingEntry {
+    log: Arc<AccessLog>,
+    entry: Entry,
+    started: Instant,
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        self.entry.duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        self.log.write(&self.entry);
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+}
+
+/// Middleware recording every request served by the router
+pub async fn middleware(State(state): State<ProxyState>, request: Request, next: Next) -> Response {
+    let Some(log) = state.access_log.clone() else {
+        return next.run(request).await;
+    };
+    let started = Instant::now();
+    let headers = request.headers();
+    let entry = Entry {
+        remote: header_value(headers, header::HeaderName::from_static("x-forwarded-for"))
+            .and_then(|forwarded| forwarded.split(',').next().map(|addr| addr.trim().to_string())),
+        method: request.method().to_string(),
+        path: request.uri().path_and_query().map_or_else(|| request.uri().path().to_string(), |p| p.to_string()),
+        version: format!("{:?}", request.version()),
+        key_id: bearer_token(headers).map(key_id),
+        user_agent: header_value(headers, header::USER_AGENT),
+        referer: header_value(headers, header::REFERER),
+        ..Default::default()
+    };
+
+    let response = next.run(request).await;
+    let (parts, body) = response.into_parts();
+    let mut pending = PendingEntry {
+        log,
+        entry: Entry {
+            status: parts.status.as_u16(),
+            model: parts.extensions.get::<RequestModel>().map(|model| model.0.clone()),
+            ..entry
+        },
+        started,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        // Borrow the whole guard so the closure owns it, not just the counter
+        let pending = &mut pending;
+        if let Ok(bytes) = &chunk {
+            pending.entry.bytes += bytes.len() as u64;
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            method: "POST".to_string(),
+            path: "/v1/chat/completions".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: 512,
+            duration_ms: 1234.56,
+            model: Some("llama3".to_string()),
+            key_id: Some(key_id("sk-test")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let combined = entry().format(&AccessLogFormat::Combined);
+        assert!(combined.starts_with("- - key-"));
+        assert!(combined.ends_with("\"POST /v1/chat/completions HTTP/1.1\" 200 512 \"-\" \"-\" \"llama3\" 1234.6ms"));
+
+        let json: serde_json::Value = serde_json::from_str(&entry().format(&AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["model"], "llama3");
+        assert_eq!(json["duration_ms"], 1234.6);
+
+        let template = AccessLogFormat::parse("{method} {path} {status} {model} {remote}");
+        assert_eq!(entry().format(&template), "POST /v1/chat/completions 200 llama3 -");
+    }
+
+    #[test]
+    fn test_destination_and_key_id() {
+        assert_eq!(LogDestination::parse("off"), None);
+        assert_eq!(LogDestination::parse("stdout"), Some(LogDestination::Stdout));
+        assert_eq!(
+            LogDestination::parse("/var/log/ollama-proxy/access.log"),
+            Some(LogDestination::File(PathBuf::from("/var/log/ollama-proxy/access.log")))
+        );
+        assert_eq!(key_id("sk-test"), key_id("sk-test"));
+        assert_ne!(key_id("sk-test"), key_id("sk-other"));
+        assert!(!key_id("sk-test").contains("sk-test"));
+    }
+}
@@ -0,0 +1,529 @@
+/// Admission control: per-model queue depth, wait estimates, and saturation signaling
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::latency::LatencyTracker;
+use crate::proxy::ProxyState;
+use crate::timeouts::EndpointClass;
+
+/// Header clients use to mark how urgent a request is
+pub const PRIORITY_HEADER: &str = "x-proxy-priority";
+
+/// How urgent a request is; lower priorities are shed first under overload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Batch,
+    Background,
+}
+
+impl Priority {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "interactive" => Some(Priority::Interactive),
+            "batch" => Some(Priority::Batch),
+            "background" => Some(Priority::Background),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Batch => "batch",
+            Priority::Background => "background",
+        }
+    }
+}
+
+/// What the admission layer knows about an incoming request
+#[derive(Debug, Clone)]
+pub struct RequestProfile {
+    pub model: String,
+    pub class: EndpointClass,
+    pub priority: Priority,
+    /// Estimated prompt tokens (only computed when a shed rule needs it)
+    pub estimated_tokens: Option<usize>,
+    /// Most of the model's requests the proxy sends upstream at once (its own
+    /// `max_concurrent` or the global limit), when one applies
+    pub max_concurrent: Option<usize>,
+}
+
+impl RequestProfile {
+    pub fn new(model: &str, class: EndpointClass) -> Self {
+        Self {
+            model: model.to_string(),
+            class,
+            priority: Priority::default(),
+            estimated_tokens: None,
+            max_concurrent: None,
+        }
+    }
+}
+
+/// Which requests a shed rule applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShedSelector {
+    Priority(Priority),
+    Class(EndpointClass),
+    /// Requests with more than this many estimated prompt tokens
+    LongContext(usize),
+}
+
+impl ShedSelector {
+    fn matches(&self, profile: &RequestProfile) -> bool {
+        match self {
+            ShedSelector::Priority(p) => profile.priority == *p,
+            ShedSelector::Class(c) => profile.class == *c,
+            ShedSelector::LongContext(tokens) => profile.estimated_tokens.is_some_and(|t| t > *tokens),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ShedSelector::Priority(p) => p.name().to_string(),
+            ShedSelector::Class(c) => c.name().to_string(),
+            ShedSelector::LongContext(tokens) => format!("context>{}", tokens),
+        }
+    }
+}
+
+/// Shed requests matching `selector` once this many requests are outstanding in total
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShedRule {
+    pub selector: ShedSelector,
+    pub at_depth: usize,
+}
+
+/// Operator-chosen order in which traffic is shed under overload
+#[derive(Debug, Clone, Default)]
+pub struct ShedPolicy {
+    rules: Vec<ShedRule>,
+}
+
+impl ShedPolicy {
+    /// Parse rules like `background@8,batch@12,embeddings@16,context>8000@16`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (selector, depth) = entry
+                .rsplit_once('@')
+                .ok_or_else(|| format!("Invalid shed rule '{}', expected selector@depth", entry))?;
+            let at_depth: usize = depth
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid depth '{}' in shed rule '{}'", depth.trim(), entry))?;
+            let selector = selector.trim().to_lowercase();
+            let selector = if let Some(tokens) = selector.strip_prefix("context>") {
+                ShedSelector::LongContext(
+                    tokens
+                        .parse()
+                        .map_err(|_| format!("Invalid token count in shed rule '{}'", entry))?,
+                )
+            } else if let Some(priority) = Priority::parse(&selector) {
+                ShedSelector::Priority(priority)
+            } else if let Some(class) = EndpointClass::parse(&selector) {
+                ShedSelector::Class(class)
+            } else {
+                return Err(format!(
+                    "Unknown shed selector '{}' (expected a priority, endpoint class, or context>N)",
+                    selector
+                ));
+            };
+            rules.push(ShedRule { selector, at_depth });
+        }
+        rules.sort_by_key(|r| r.at_depth);
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether any rule looks at prompt size (so callers only estimate tokens when needed)
+    pub fn needs_token_estimate(&self) -> bool {
+        self.rules.iter().any(|r| matches!(r.selector, ShedSelector::LongContext(_)))
+    }
+
+    /// The first rule that sheds `profile` at the current total depth
+    pub fn shed(&self, profile: &RequestProfile, total_depth: usize) -> Option<&ShedRule> {
+        self.rules
+            .iter()
+            .find(|r| total_depth >= r.at_depth && r.selector.matches(profile))
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        self.rules
+            .iter()
+            .map(|r| format!("{} at {} outstanding", r.selector.describe(), r.at_depth))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Limits beyond which new requests for a model are rejected with 429
+#[derive(Debug, Clone, Copy)]
+pub struct SaturationLimits {
+    /// Maximum outstanding requests per model (0 = unlimited)
+    pub max_queue_depth: usize,
+    /// Maximum estimated wait before a new request would start (None = unlimited)
+    pub max_estimated_wait: Option<Duration>,
+    /// Requests Ollama processes concurrently per model (OLLAMA_NUM_PARALLEL)
+    pub parallelism: usize,
+}
+
+impl Default for SaturationLimits {
+    fn default() -> Self {
+        Self {
+            max_queue_depth: 0,
+            max_estimated_wait: None,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Snapshot of one model's queue
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QueueStatus {
+    pub model: String,
+    pub queue_depth: usize,
+    pub estimated_wait_seconds: f64,
+}
+
+/// Why a request was not admitted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Saturated {
+    pub status: QueueStatus,
+    pub reason: String,
+    /// Shed by the overload policy rather than a per-model limit
+    pub shed: bool,
+}
+
+/// Outstanding requests for one model, and how many of them run at once
+#[derive(Debug, Clone, Copy)]
+struct ModelQueue {
+    depth: usize,
+    slots: usize,
+}
+
+/// Tracks outstanding upstream work per model
+#[derive(Debug)]
+pub struct Admission {
+    limits: SaturationLimits,
+    shed_policy: ShedPolicy,
+    latency: Arc<LatencyTracker>,
+    depth: Mutex<HashMap<String, ModelQueue>>,
+    rejected: AtomicU64,
+    shed: AtomicU64,
+}
+
+impl Admission {
+    pub fn new(limits: SaturationLimits, shed_policy: ShedPolicy, latency: Arc<LatencyTracker>) -> Self {
+        Self {
+            limits,
+            shed_policy,
+            latency,
+            depth: Mutex::new(HashMap::new()),
+            rejected: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn shed_policy(&self) -> &ShedPolicy {
+        &self.shed_policy
+    }
+
+    /// Requests for a model that run at once: Ollama's parallelism, or fewer when the
+    /// proxy's concurrency limit for the model is lower
+    fn slots(&self, max_concurrent: Option<usize>) -> usize {
+        let parallelism = self.limits.parallelism.max(1);
+        max_concurrent.filter(|&limit| limit > 0).map_or(parallelism, |limit| limit.min(parallelism))
+    }
+
+    /// Estimated wait for a request that would be queued behind `queue`
+    fn estimate_wait(&self, model: &str, queue: ModelQueue) -> Duration {
+        let typical = self.latency.typical(model).unwrap_or(Duration::ZERO);
+        let rounds = queue.depth / queue.slots.max(1);
+        typical.saturating_mul(rounds as u32)
+    }
+
+    fn status(&self, model: &str, queue: ModelQueue) -> QueueStatus {
+        QueueStatus {
+            model: model.to_string(),
+            queue_depth: queue.depth,
+            estimated_wait_seconds: self.estimate_wait(model, queue).as_secs_f64(),
+        }
+    }
+
+    /// Admit a request, or explain why it was shed or its model is saturated.
+    /// The returned guard keeps the request counted until it is dropped.
+    pub fn try_admit(self: &Arc<Self>, profile: &RequestProfile) -> Result<AdmissionGuard, Saturated> {
+        let model = profile.model.as_str();
+        let mut depth = self.depth.lock().unwrap();
+        let current = ModelQueue {
+            depth: depth.get(model).map_or(0, |queue| queue.depth),
+            slots: self.slots(profile.max_concurrent),
+        };
+
+        let total: usize = depth.values().map(|queue| queue.depth).sum();
+        if let Some(rule) = self.shed_policy.shed(profile, total) {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(Saturated {
+                status: self.status(model, current),
+                reason: format!(
+                    "Proxy overloaded ({} requests outstanding), shedding {} traffic",
+                    total,
+                    rule.selector.describe()
+                ),
+                shed: true,
+            });
+        }
+
+        let reason = if self.limits.max_queue_depth > 0 && current.depth >= self.limits.max_queue_depth {
+            Some(format!(
+                "Model '{}' has {} requests queued (limit {})",
+                model, current.depth, self.limits.max_queue_depth
+            ))
+        } else {
+            self.limits.max_estimated_wait.and_then(|max_wait| {
+                let wait = self.estimate_wait(model, current);
+                (wait > max_wait).then(|| {
+                    format!(
+                        "Model '{}' estimated wait {:.1}s exceeds limit {:.1}s",
+                        model,
+                        wait.as_secs_f64(),
+                        max_wait.as_secs_f64()
+                    )
+                })
+            })
+        };
+
+        if let Some(reason) = reason {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(Saturated { status: self.status(model, current), reason, shed: false });
+        }
+
+        depth.insert(model.to_string(), ModelQueue { depth: current.depth + 1, ..current });
+        Ok(AdmissionGuard {
+            admission: self.clone(),
+            model: model.to_string(),
+        })
+    }
+
+    /// Current queue for every model with outstanding requests
+    pub fn snapshot(&self) -> Vec<QueueStatus> {
+        let depth = self.depth.lock().unwrap();
+        let mut models: Vec<QueueStatus> = depth
+            .iter()
+            .filter(|(_, queue)| queue.depth > 0)
+            .map(|(model, &queue)| self.status(model, queue))
+            .collect();
+        models.sort_by(|a, b| a.model.cmp(&b.model));
+        models
+    }
+
+    /// Prometheus text for queue depth and rejections
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP ollama_proxy_queue_depth Outstanding requests per model");
+        let _ = writeln!(out, "# TYPE ollama_proxy_queue_depth gauge");
+        for status in self.snapshot() {
+            let _ = writeln!(out, "ollama_proxy_queue_depth{{model=\"{}\"}} {}", status.model, status.queue_depth);
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_saturation_rejections_total Requests rejected with 429 because a model was saturated");
+        let _ = writeln!(out, "# TYPE ollama_proxy_saturation_rejections_total counter");
+        let _ = writeln!(out, "ollama_proxy_saturation_rejections_total {}", self.rejected.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP ollama_proxy_load_shed_total Requests shed by the overload policy");
+        let _ = writeln!(out, "# TYPE ollama_proxy_load_shed_total counter");
+        let _ = writeln!(out, "ollama_proxy_load_shed_total {}", self.shed.load(Ordering::Relaxed));
+        out
+    }
+}
+
+/// Keeps a request counted in its model's queue until dropped
+#[derive(Debug)]
+pub struct AdmissionGuard {
+    admission: Arc<Admission>,
+    model: String,
+}
+
+impl Drop for AdmissionGuard {
+    fn drop(&mut self) {
+        let mut depth = self.admission.depth.lock().unwrap();
+        if let Some(queue) = depth.get_mut(&self.model) {
+            queue.depth = queue.depth.saturating_sub(1);
+            if queue.depth == 0 {
+                depth.remove(&self.model);
+            }
+        }
+    }
+}
+
+impl Saturated {
+    /// 429 (saturated model) or 503 (shed under overload) response with Retry-After
+    /// and a JSON body describing the saturation
+    pub fn into_response(self) -> Response<Body> {
+        let retry_after = self.status.estimated_wait_seconds.ceil().max(1.0) as u64;
+        let (status, code) = if self.shed {
+            (StatusCode::SERVICE_UNAVAILABLE, "load_shed")
+        } else {
+            (StatusCode::TOO_MANY_REQUESTS, "model_saturated")
+        };
+        let body = serde_json::json!({
+            "error": {
+                "message": self.reason,
+                "type": "server_overloaded",
+                "code": code,
+                "model": self.status.model,
+                "queue_depth": self.status.queue_depth,
+                "estimated_wait_seconds": self.status.estimated_wait_seconds,
+            }
+        });
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::RETRY_AFTER, retry_after.to_string())
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
+
+/// GET /proxy/admin/queue
+pub async fn queue_handler(State(state): State<ProxyState>) -> impl IntoResponse {
+    Json(serde_json::json!({ "models": state.admission.snapshot() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admission(limits: SaturationLimits) -> Arc<Admission> {
+        Arc::new(Admission::new(limits, ShedPolicy::default(), Arc::new(LatencyTracker::new())))
+    }
+
+    fn chat(model: &str) -> RequestProfile {
+        RequestProfile::new(model, EndpointClass::Chat)
+    }
+
+    #[test]
+    fn test_guard_tracks_depth() {
+        let admission = admission(SaturationLimits::default());
+        let a = admission.try_admit(&chat("llama3")).unwrap();
+        let b = admission.try_admit(&chat("llama3")).unwrap();
+        assert_eq!(admission.snapshot()[0].queue_depth, 2);
+        drop(a);
+        assert_eq!(admission.snapshot()[0].queue_depth, 1);
+        drop(b);
+        assert!(admission.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_queue_depth_limit() {
+        let admission = admission(SaturationLimits { max_queue_depth: 1, ..Default::default() });
+        let _held = admission.try_admit(&chat("llama3")).unwrap();
+        let err = admission.try_admit(&chat("llama3")).unwrap_err();
+        assert_eq!(err.status.queue_depth, 1);
+        assert!(admission.try_admit(&chat("other")).is_ok());
+
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[test]
+    fn test_estimated_wait_limit() {
+        let latency = Arc::new(LatencyTracker::new());
+        for _ in 0..5 {
+            latency.record("llama3", EndpointClass::Chat, Duration::from_secs(10));
+        }
+        let admission = Arc::new(Admission::new(
+            SaturationLimits { max_estimated_wait: Some(Duration::from_secs(15)), ..Default::default() },
+            ShedPolicy::default(),
+            latency,
+        ));
+
+        let _first = admission.try_admit(&chat("llama3")).unwrap();
+        let _second = admission.try_admit(&chat("llama3")).unwrap();
+        let err = admission.try_admit(&chat("llama3")).unwrap_err();
+        assert_eq!(err.status.estimated_wait_seconds, 20.0);
+        assert!(err.into_response().headers()[header::RETRY_AFTER] == "20");
+    }
+
+    #[test]
+    fn test_concurrency_limit_lengthens_the_estimate() {
+        let latency = Arc::new(LatencyTracker::new());
+        for _ in 0..5 {
+            latency.record("llama3", EndpointClass::Chat, Duration::from_secs(10));
+        }
+        let limits = SaturationLimits {
+            max_estimated_wait: Some(Duration::from_secs(15)),
+            parallelism: 4,
+            ..Default::default()
+        };
+        let admission = Arc::new(Admission::new(limits, ShedPolicy::default(), latency));
+
+        // Ollama runs four at once, but the proxy only sends this model one at a time
+        let serialized = RequestProfile { max_concurrent: Some(1), ..chat("llama3") };
+        let _first = admission.try_admit(&serialized).unwrap();
+        let _second = admission.try_admit(&serialized).unwrap();
+        let err = admission.try_admit(&serialized).unwrap_err();
+        assert_eq!(err.status.estimated_wait_seconds, 20.0);
+        assert_eq!(admission.snapshot()[0].estimated_wait_seconds, 20.0);
+
+        // Without the limit the same queue clears in one round
+        assert!(admission.try_admit(&chat("llama3")).is_ok());
+    }
+
+    #[test]
+    fn test_parse_shed_policy() {
+        let policy = ShedPolicy::parse("embeddings@16, background@8,context>8000@12").unwrap();
+        assert_eq!(policy.describe(), "background at 8 outstanding, context>8000 at 12 outstanding, embeddings at 16 outstanding");
+        assert!(policy.needs_token_estimate());
+        assert!(ShedPolicy::parse("background").is_err());
+        assert!(ShedPolicy::parse("everything@3").is_err());
+        assert!(ShedPolicy::parse("context>lots@3").is_err());
+    }
+
+    #[test]
+    fn test_low_priority_shed_first() {
+        let policy = ShedPolicy::parse("background@2,embeddings@3").unwrap();
+        let admission = Arc::new(Admission::new(SaturationLimits::default(), policy, Arc::new(LatencyTracker::new())));
+
+        let background = RequestProfile { priority: Priority::Background, ..chat("llama3") };
+        let embeddings = RequestProfile::new("nomic", EndpointClass::Embeddings);
+
+        let _a = admission.try_admit(&chat("llama3")).unwrap();
+        let _b = admission.try_admit(&background).unwrap();
+        // Two outstanding: background traffic is shed, embeddings still admitted
+        let err = admission.try_admit(&background).unwrap_err();
+        assert!(err.shed);
+        assert_eq!(err.into_response().status(), StatusCode::SERVICE_UNAVAILABLE);
+        let _c = admission.try_admit(&embeddings).unwrap();
+        // Three outstanding: embeddings shed too, interactive chat still admitted
+        assert!(admission.try_admit(&embeddings).is_err());
+        assert!(admission.try_admit(&chat("llama3")).is_ok());
+    }
+
+    #[test]
+    fn test_long_context_rule() {
+        let policy = ShedPolicy::parse("context>1000@0").unwrap();
+        let admission = Arc::new(Admission::new(SaturationLimits::default(), policy, Arc::new(LatencyTracker::new())));
+        let long = RequestProfile { estimated_tokens: Some(5000), ..chat("llama3") };
+        let short = RequestProfile { estimated_tokens: Some(50), ..chat("llama3") };
+        assert!(admission.try_admit(&long).is_err());
+        assert!(admission.try_admit(&short).is_ok());
+    }
+}
@@ -0,0 +1,245 @@
+/// Bearer-token authentication: requests must carry one of the configured API keys (or a JWT)
+use axum::{
+    extract::{Extension, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::access_log::key_id;
+use crate::errors::openai_error;
+use crate::filters::bearer_token;
+use crate::jwt::looks_like_jwt;
+use crate::keystore::require_admin;
+use crate::proxy::ProxyState;
+use crate::ratelimit::RateLimits;
+use crate::scopes::KeyScope;
+use crate::timeouts::EndpointClass;
+
+/// Routes that stay open so container and load-balancer liveness checks work without a key
+const PUBLIC_PATHS: &[&str] = &["/healthz"];
+
+/// One accepted key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub key: String,
+    /// Shown in logs instead of the key (defaults to its `key-xxxxxxxx` id)
+    pub name: String,
+    /// Overrides of the global rate limits
+    pub limits: RateLimits,
+    /// Models, endpoints and request sizes the key is limited to
+    pub scope: KeyScope,
+    /// May manage other keys through /proxy/admin/keys
+    pub admin: bool,
+}
+
+impl ApiKey {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            name: key_id(key),
+            limits: RateLimits::default(),
+            scope: KeyScope::default(),
+            admin: false,
+        }
+    }
+
+    /// `KEY [name=NAME] [admin] [rpm=N] [tpm=N] [models=A,B*] [endpoints=embeddings,chat]
+    /// [max_context=N] [max_num_predict=N]`, as written on one line of an API_KEYS_FILE
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut fields = line.split_whitespace();
+        let mut key = Self::new(fields.next().ok_or("empty API key line")?);
+        for field in fields {
+            let number = |value: &str| value.parse().map_err(|_| format!("Invalid number in '{}'", field));
+            match field.split_once('=') {
+                None if field == "admin" => key.admin = true,
+                Some(("name", name)) if !name.is_empty() => key.name = name.to_string(),
+                Some(("rpm", value)) => key.limits.requests_per_minute = Some(number(value)?),
+                Some(("tpm", value)) => key.limits.tokens_per_minute = Some(number(value)?),
+                Some(("models", models)) => key.scope.models = list(models),
+                Some(("endpoints", endpoints)) => {
+                    key.scope.endpoints = list(endpoints)
+                        .iter()
+                        .map(|name| EndpointClass::parse(name).ok_or_else(|| format!("Unknown endpoint class '{}'", name)))
+                        .collect::<Result<_, _>>()?
+                }
+                Some(("max_context", value)) => key.scope.max_context = Some(number(value)? as u32),
+                Some(("max_num_predict", value)) => key.scope.max_num_predict = Some(number(value)? as u32),
+                _ => return Err(format!("Unknown API key setting '{}'", field)),
+            }
+        }
+        Ok(key)
+    }
+}
+
+/// The key a request authenticated with, attached to the request for later handlers
+#[derive(Debug, Clone)]
+pub struct AuthenticatedKey(pub Arc<ApiKey>);
+
+/// Accepted keys; with none configured, authentication is off
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: Vec<Arc<ApiKey>>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: impl IntoIterator<Item = ApiKey>) -> Self {
+        Self { keys: keys.into_iter().map(Arc::new).collect() }
+    }
+
+    /// One key per line; blank lines and `#` comments are skipped
+    pub fn parse_file(text: &str) -> Result<Vec<ApiKey>, String> {
+        text.lines()
+            .enumerate()
+            .map(|(n, line)| (n + 1, line.split('#').next().unwrap_or("").trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(n, line)| ApiKey::parse(line).map_err(|e| format!("line {}: {}", n, e)))
+            .collect()
+    }
+
+    pub fn load_file(path: &Path) -> Result<Vec<ApiKey>, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read API_KEYS_FILE {}: {}", path.display(), e))?;
+        Self::parse_file(&text).map_err(|e| format!("Invalid API_KEYS_FILE {}: {}", path.display(), e))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ApiKey> {
+        self.keys.iter().map(Arc::as_ref)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// One line per key with its own limits or scope, for the startup summary
+    pub fn describe(&self) -> Vec<String> {
+        self.keys
+            .iter()
+            .filter(|key| key.admin || key.limits != RateLimits::default() || key.scope.is_restricted())
+            .map(|key| {
+                let mut parts = Vec::new();
+                if key.admin {
+                    parts.push("admin".to_string());
+                }
+                if key.limits != RateLimits::default() {
+                    parts.push(key.limits.describe());
+                }
+                if key.scope.is_restricted() {
+                    parts.push(key.scope.describe());
+                }
+                format!("{}: {}", key.name, parts.join(", "))
+            })
+            .collect()
+    }
+
+    /// The key matching `token`; every key is compared in full so timing doesn't reveal prefixes
+    pub fn find(&self, token: &str) -> Option<Arc<ApiKey>> {
+        let mut found = None;
+        for key in &self.keys {
+            if constant_time_eq(key.key.as_bytes(), token.as_bytes()) {
+                found = Some(key.clone());
+            }
+        }
+        found
+    }
+}
+
+fn list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Whether requests must authenticate: any key (configured or managed) or a JWT issuer is set up
+pub fn is_enabled(state: &ProxyState) -> bool {
+    state.api_keys.is_enabled() || !state.key_store.is_empty() || state.jwt.is_some()
+}
+
+/// Reject requests without a valid `Authorization: Bearer` key with an OpenAI-style 401
+pub async fn middleware(State(state): State<ProxyState>, mut request: Request, next: Next) -> Response {
+    if !is_enabled(&state) || PUBLIC_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    let (message, key) = match (bearer_token(request.headers()), &state.jwt) {
+        (None, _) => ("Missing API key: send it in an 'Authorization: Bearer <key>' header".to_string(), None),
+        (Some(token), Some(jwt)) if looks_like_jwt(token) => match jwt.verify(token).await {
+            Ok(key) => (String::new(), Some(Arc::new(key))),
+            Err(e) => (format!("Invalid token: {}", e), None),
+        },
+        (Some(token), _) => (
+            "Incorrect API key provided".to_string(),
+            state.api_keys.find(token).or_else(|| state.key_store.find(token)),
+        ),
+    };
+    match key {
+        Some(key) => {
+            request.extensions_mut().insert(AuthenticatedKey(key));
+            next.run(request).await
+        }
+        None => {
+            warn!("🔒 Rejected {} {}: {}", request.method(), request.uri().path(), message);
+            let mut response = openai_error(StatusCode::UNAUTHORIZED, &message, None, Some("invalid_api_key"));
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+/// Refuse `/proxy/admin/*` with a 403 unless the request authenticated with an admin
+/// key; runs inside [`middleware`], and lets everything through while auth is off
+pub async fn admin_middleware(State(state): State<ProxyState>, request: Request, next: Next) -> Response {
+    if is_enabled(&state) {
+        let key = request.extensions().get::<AuthenticatedKey>().cloned().map(Extension);
+        if let Some(response) = require_admin(key) {
+            warn!("🔒 Rejected {} {}: not an admin key", request.method(), request.uri().path());
+            return response;
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file_and_lookup() {
+        let keys = ApiKeys::parse_file("# team keys\nsk-alpha name=team-a rpm=60 tpm=0\n\nsk-beta admin # ci\n").unwrap();
+        assert_eq!(keys[0].name, "team-a");
+        assert!(!keys[0].admin && keys[1].admin);
+        assert_eq!(keys[0].limits, RateLimits { requests_per_minute: Some(60), tokens_per_minute: Some(0) });
+        assert_eq!(keys[1].name, key_id("sk-beta"));
+
+        let keys = ApiKeys::new(keys);
+        assert_eq!(keys.find("sk-alpha").unwrap().name, "team-a");
+        assert!(keys.find("sk-beta").is_some());
+        assert!(keys.find("sk-alph").is_none());
+        assert!(keys.find("").is_none());
+
+        let scoped = ApiKey::parse("sk-gamma models=nomic-embed-text,bge* endpoints=embeddings max_context=8192").unwrap();
+        assert_eq!(scoped.scope.models, vec!["nomic-embed-text", "bge*"]);
+        assert_eq!(scoped.scope.endpoints, vec![EndpointClass::Embeddings]);
+        assert_eq!(scoped.scope.max_context, Some(8192));
+
+        assert!(ApiKeys::parse_file("sk-alpha colour=blue").is_err());
+        assert!(ApiKeys::parse_file("sk-alpha endpoints=images").is_err());
+        assert!(ApiKeys::parse_file("sk-alpha rpm=lots").is_err());
+        assert!(!ApiKeys::default().is_enabled());
+    }
+}
@@ -0,0 +1,715 @@
+/// Pool of Ollama backends with passive and active health tracking
+use axum::http::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Consecutive failures before a backend is taken out of rotation
+const EJECT_AFTER_FAILURES: u32 = 3;
+/// How long an ejected backend stays out of rotation before it is tried again
+const EJECT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Header naming the conversation a request belongs to, for sticky routing
+pub const CONVERSATION_HEADER: &str = "x-conversation-id";
+
+/// One Ollama instance the proxy can send requests to
+#[derive(Debug)]
+pub struct Backend {
+    pub url: String,
+    /// Preference tier; lower tiers are used first, higher ones only when those are full
+    pub tier: u32,
+    /// Share of traffic within its tier under weighted placement
+    pub weight: u32,
+    /// Requests currently being served
+    in_flight: AtomicUsize,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    /// Out of rotation because health checks failed, until enough of them pass again
+    probe_down: AtomicBool,
+    /// Consecutive failed (or, while down, passed) health checks
+    probe_streak: AtomicU32,
+    last_probe_error: Mutex<Option<String>>,
+    /// Models pulled on the backend, from its `/api/tags` (None = not known yet)
+    models: Mutex<Option<HashSet<String>>>,
+}
+
+impl Backend {
+    fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            tier: 1,
+            weight: 1,
+            in_flight: AtomicUsize::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            ejected_until: Mutex::new(None),
+            probe_down: AtomicBool::new(false),
+            probe_streak: AtomicU32::new(0),
+            last_probe_error: Mutex::new(None),
+            models: Mutex::new(None),
+        }
+    }
+
+    /// Whether the backend has `model` pulled (None when its models aren't known)
+    pub fn has_model(&self, model: &str) -> Option<bool> {
+        let models = self.models.lock().unwrap();
+        models.as_ref().map(|models| models.contains(&normalize_model(model)))
+    }
+
+    /// Whether the backend is currently in rotation
+    pub fn is_healthy(&self) -> bool {
+        if self.probe_down.load(Ordering::Relaxed) {
+            return false;
+        }
+        match *self.ejected_until.lock().unwrap() {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+}
+
+/// Consecutive health check results that take a backend out of rotation and put it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeThresholds {
+    pub unhealthy: u32,
+    pub healthy: u32,
+}
+
+impl Default for ProbeThresholds {
+    fn default() -> Self {
+        Self { unhealthy: 2, healthy: 2 }
+    }
+}
+
+/// Point-in-time view of a backend, for health reports
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendState {
+    pub url: String,
+    pub healthy: bool,
+    pub tier: u32,
+    pub weight: u32,
+    pub in_flight: usize,
+    pub consecutive_failures: u32,
+    /// Seconds until an ejected backend is tried again
+    pub ejected_for_seconds: Option<u64>,
+    /// Models pulled on the backend, if its `/api/tags` has been read
+    pub models: Option<Vec<String>>,
+    /// Out of rotation because its health checks fail
+    pub failing_health_checks: bool,
+    /// Error of the most recent failed health check, cleared once one passes
+    pub last_health_check_error: Option<String>,
+}
+
+/// How a backend is chosen for a model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlacementStrategy {
+    /// Everything goes to the first healthy backend
+    #[default]
+    Failover,
+    /// Each model hashes to one backend, so its weights stay loaded on a single node
+    ConsistentHash,
+    /// Requests are spread over the backends in proportion to their weights
+    Weighted,
+}
+
+impl PlacementStrategy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "failover" => Ok(Self::Failover),
+            "consistent-hash" | "hash" => Ok(Self::ConsistentHash),
+            "weighted" => Ok(Self::Weighted),
+            other => Err(format!("Unknown placement '{}', expected failover, consistent-hash or weighted", other)),
+        }
+    }
+}
+
+/// Placement strategy plus per-model pins that override it
+#[derive(Debug, Clone, Default)]
+pub struct Placement {
+    pub strategy: PlacementStrategy,
+    /// Model -> backend URL
+    pub pins: HashMap<String, String>,
+    /// Backend URL -> tier (unlisted backends are tier 1)
+    pub tiers: HashMap<String, u32>,
+    /// Backend URL -> weight (unlisted backends weigh 1)
+    pub weights: HashMap<String, u32>,
+    /// Requests a backend serves at once before its tier counts as full (0 = never full)
+    pub max_in_flight: usize,
+    /// Keep every turn of a conversation on one backend, so Ollama's prompt cache is reused
+    pub sticky_conversations: bool,
+}
+
+impl Placement {
+    /// Parse pins like `llama3.3:70b=http://gpu-a:11434,nomic-embed-text=http://gpu-b:11434`
+    pub fn parse_pins(spec: &str) -> Result<HashMap<String, String>, String> {
+        let mut pins = HashMap::new();
+        for pin in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (model, url) = pin
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid pin '{}', expected model=backend-url", pin))?;
+            if model.trim().is_empty() || url.trim().is_empty() {
+                return Err(format!("Invalid pin '{}', expected model=backend-url", pin));
+            }
+            pins.insert(normalize_model(model.trim()), url.trim().trim_end_matches('/').to_string());
+        }
+        Ok(pins)
+    }
+
+    /// Parse per-backend numbers like `http://gpu:11434=1,http://mini:11434=2`
+    /// (tiers or weights, at least 1)
+    pub fn parse_backend_numbers(spec: &str) -> Result<HashMap<String, u32>, String> {
+        let mut numbers = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (url, number) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| format!("Invalid entry '{}', expected backend-url=number", entry))?;
+            let number: u32 = number
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("Invalid number '{}' for {}, expected a positive integer", number.trim(), url.trim()))?;
+            numbers.insert(url.trim().trim_end_matches('/').to_string(), number);
+        }
+        Ok(numbers)
+    }
+}
+
+/// `llama3` and `llama3:latest` name the same model
+pub fn normalize_model(model: &str) -> String {
+    if model.contains(':') {
+        model.to_string()
+    } else {
+        format!("{}:latest", model)
+    }
+}
+
+/// Whether `model` matches `pattern`: `*`, a `prefix*`, or a model name
+pub fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => normalize_model(pattern) == normalize_model(model),
+    }
+}
+
+/// FNV-1a, stable across builds so every replica places models the same way
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn placement_score(key: &str, url: &str) -> u64 {
+    fnv1a(key.bytes().chain([0]).chain(url.bytes()))
+}
+
+/// Identifier of the conversation a request continues: the `X-Conversation-Id` header,
+/// else a hash of the first user message, which every later turn of a chat repeats
+pub fn conversation_key(headers: &HeaderMap, body: &[u8]) -> Option<String> {
+    let header = headers.get(CONVERSATION_HEADER).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(id) = header.filter(|id| !id.is_empty()) {
+        return Some(id.to_string());
+    }
+    let body: Value = serde_json::from_slice(body).ok()?;
+    let first = body
+        .get("messages")?
+        .as_array()?
+        .iter()
+        .find(|m| m.get("role").and_then(Value::as_str) == Some("user"))?;
+    let content = first.get("content")?.to_string();
+    Some(format!("{:016x}", fnv1a(content.into_bytes())))
+}
+
+/// All configured backends; the first one is the primary
+#[derive(Debug)]
+pub struct BackendPool {
+    backends: Vec<Backend>,
+    placement: Placement,
+    /// Smooth weighted round-robin state, one entry per backend
+    rotation: Mutex<Vec<i64>>,
+}
+
+impl BackendPool {
+    /// Build a pool from the primary host plus any additional backends
+    pub fn new(primary: &str, others: &[String]) -> Self {
+        let mut backends = vec![Backend::new(primary.to_string())];
+        for url in others {
+            let backend = Backend::new(url.clone());
+            if backends.iter().all(|b| b.url != backend.url) {
+                backends.push(backend);
+            }
+        }
+        let rotation = Mutex::new(vec![0; backends.len()]);
+        Self { backends, placement: Placement::default(), rotation }
+    }
+
+    /// Choose backends per model according to `placement`
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        for backend in &mut self.backends {
+            backend.tier = placement.tiers.get(&backend.url).copied().unwrap_or(1);
+            backend.weight = placement.weights.get(&backend.url).copied().unwrap_or(1);
+        }
+        self.placement = placement;
+        self
+    }
+
+    pub fn urls(&self) -> Vec<&str> {
+        self.backends.iter().map(|b| b.url.as_str()).collect()
+    }
+
+    pub fn states(&self) -> Vec<BackendState> {
+        let now = Instant::now();
+        self.backends
+            .iter()
+            .map(|b| BackendState {
+                url: b.url.clone(),
+                healthy: b.is_healthy(),
+                tier: b.tier,
+                weight: b.weight,
+                in_flight: b.in_flight.load(Ordering::Relaxed),
+                consecutive_failures: b.consecutive_failures.load(Ordering::Relaxed),
+                ejected_for_seconds: b
+                    .ejected_until
+                    .lock()
+                    .unwrap()
+                    .filter(|until| *until > now)
+                    .map(|until| (until - now).as_secs()),
+                models: b.models.lock().unwrap().as_ref().map(|models| {
+                    let mut models: Vec<String> = models.iter().cloned().collect();
+                    models.sort();
+                    models
+                }),
+                failing_health_checks: b.probe_down.load(Ordering::Relaxed),
+                last_health_check_error: b.last_probe_error.lock().unwrap().clone(),
+            })
+            .collect()
+    }
+
+    /// First healthy backend, falling back to the configured primary
+    pub fn primary(&self) -> &Backend {
+        self.backends
+            .iter()
+            .find(|b| b.is_healthy())
+            .unwrap_or(&self.backends[0])
+    }
+
+    /// Backend for requests to `model`: its pin if that backend is healthy, then the
+    /// placement strategy among the healthy backends known to have the model pulled
+    /// (or all healthy ones when none is). Of those, the lowest tier with a backend
+    /// below `max_in_flight` is used. Consistent hashing uses rendezvous hashing,
+    /// so losing a node only moves the models that lived on it.
+    pub fn for_model(&self, model: &str) -> &Backend {
+        self.for_conversation(model, None)
+    }
+
+    /// Like [`Self::for_model`], but with sticky conversations a request continuing
+    /// `conversation` goes to the backend the conversation hashes to, among those of
+    /// the preferred tier, unless that backend is full
+    pub fn for_conversation(&self, model: &str, conversation: Option<&str>) -> &Backend {
+        let model = normalize_model(model);
+        if let Some(pinned) = self.placement.pins.get(&model) {
+            if let Some(backend) = self.backends.iter().find(|b| &b.url == pinned && b.is_healthy()) {
+                self.log_choice(&model, backend, "pinned");
+                return backend;
+            }
+        }
+        let healthy: Vec<&Backend> = self.backends.iter().filter(|b| b.is_healthy()).collect();
+        let healthy = if healthy.is_empty() { self.backends.iter().collect() } else { healthy };
+        let holding: Vec<&Backend> = healthy.iter().copied().filter(|b| b.has_model(&model) == Some(true)).collect();
+        let candidates = if holding.is_empty() { healthy } else { holding };
+        if let Some(conversation) = conversation.filter(|_| self.placement.sticky_conversations) {
+            let preferred = candidates.iter().map(|b| b.tier).min();
+            let sticky = candidates
+                .iter()
+                .copied()
+                .filter(|b| Some(b.tier) == preferred)
+                .max_by_key(|b| placement_score(conversation, &b.url));
+            if let Some(backend) = sticky.filter(|b| self.has_room(b)) {
+                self.log_choice(&model, backend, &format!("conversation {}", conversation));
+                return backend;
+            }
+        }
+        let (candidates, spilled) = self.least_loaded_tier(candidates);
+        let backend = match self.placement.strategy {
+            PlacementStrategy::Failover => candidates[0],
+            PlacementStrategy::ConsistentHash => candidates
+                .into_iter()
+                .max_by_key(|b| placement_score(&model, &b.url))
+                .unwrap_or(&self.backends[0]),
+            PlacementStrategy::Weighted => self.next_weighted(&candidates),
+        };
+        let reason = match spilled {
+            true => format!("tier {}, preferred tiers at capacity", backend.tier),
+            false => format!("tier {}", backend.tier),
+        };
+        self.log_choice(&model, backend, &reason);
+        backend
+    }
+
+    /// Backends of the lowest tier that still has room (all of the lowest tier when
+    /// every tier is full), and whether a more preferred tier was skipped
+    fn least_loaded_tier<'a>(&self, candidates: Vec<&'a Backend>) -> (Vec<&'a Backend>, bool) {
+        let has_room = |b: &&Backend| self.has_room(b);
+        let mut tiers: Vec<u32> = candidates.iter().map(|b| b.tier).collect();
+        tiers.sort_unstable();
+        tiers.dedup();
+        for (i, &tier) in tiers.iter().enumerate() {
+            let open: Vec<&Backend> = candidates.iter().copied().filter(|b| b.tier == tier).filter(has_room).collect();
+            if !open.is_empty() {
+                return (open, i > 0);
+            }
+        }
+        (candidates.into_iter().filter(|b| b.tier == tiers[0]).collect(), false)
+    }
+
+    /// Whether `backend` is below `max_in_flight`
+    fn has_room(&self, backend: &Backend) -> bool {
+        let max = self.placement.max_in_flight;
+        max == 0 || backend.in_flight.load(Ordering::Relaxed) < max
+    }
+
+    /// Whether requests are routed by conversation
+    pub fn sticky_conversations(&self) -> bool {
+        self.placement.sticky_conversations && self.backends.len() > 1
+    }
+
+    /// Smooth weighted round-robin over `candidates`
+    fn next_weighted<'a>(&self, candidates: &[&'a Backend]) -> &'a Backend {
+        let mut rotation = self.rotation.lock().unwrap();
+        let total: i64 = candidates.iter().map(|b| b.weight as i64).sum();
+        let mut best: Option<(usize, &Backend)> = None;
+        for &backend in candidates {
+            let index = self.index_of(backend);
+            rotation[index] += backend.weight as i64;
+            if best.is_none_or(|(i, _)| rotation[index] > rotation[i]) {
+                best = Some((index, backend));
+            }
+        }
+        let (index, backend) = best.expect("Placement always has a candidate backend");
+        rotation[index] -= total;
+        backend
+    }
+
+    fn index_of(&self, backend: &Backend) -> usize {
+        self.backends
+            .iter()
+            .position(|b| std::ptr::eq(b, backend))
+            .expect("Backend belongs to this pool")
+    }
+
+    fn log_choice(&self, model: &str, backend: &Backend, reason: &str) {
+        if self.backends.len() > 1 {
+            info!("🧭 {} → {} ({}, {} in flight)", model, backend.url, reason, backend.in_flight.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Count a request against the backend at `url` until the returned guard is dropped
+    pub fn track(self: &Arc<Self>, url: &str) -> Option<BackendLoad> {
+        let index = self.backends.iter().position(|b| b.url == url)?;
+        self.backends[index].in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(BackendLoad { pool: self.clone(), index })
+    }
+
+    /// Replace the known models of the backend at `url` (None forgets them)
+    pub fn set_models(&self, url: &str, models: Option<Vec<String>>) {
+        if let Some(backend) = self.backends.iter().find(|b| b.url == url) {
+            *backend.models.lock().unwrap() = models.map(|models| models.iter().map(|m| normalize_model(m)).collect());
+        }
+    }
+
+    /// Whether `model` is known not to be pulled anywhere: every backend's models
+    /// are known and none of them lists it
+    pub fn is_missing(&self, model: &str) -> bool {
+        self.backends.iter().all(|b| b.has_model(model) == Some(false))
+    }
+
+    /// A healthy backend other than `exclude`, for a hedged second attempt
+    pub fn alternate(&self, exclude: &str) -> Option<&Backend> {
+        self.backends
+            .iter()
+            .find(|b| b.url != exclude && b.is_healthy())
+    }
+
+    pub fn record_success(&self, backend: &Backend) {
+        if backend.consecutive_failures.swap(0, Ordering::Relaxed) >= EJECT_AFTER_FAILURES {
+            info!("💚 Backend {} recovered", backend.url);
+        }
+        *backend.ejected_until.lock().unwrap() = None;
+    }
+
+    pub fn record_failure(&self, backend: &Backend) {
+        let failures = backend.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= EJECT_AFTER_FAILURES && self.backends.len() > 1 {
+            if failures == EJECT_AFTER_FAILURES {
+                warn!(
+                    "💔 Backend {} failed {} times in a row, ejecting for {:?}",
+                    backend.url, failures, EJECT_COOLDOWN
+                );
+            }
+            *backend.ejected_until.lock().unwrap() = Some(Instant::now() + EJECT_COOLDOWN);
+        }
+    }
+
+    /// Record a health check of the backend at `url`: `unhealthy` failures in a row take it
+    /// out of rotation until `healthy` checks in a row pass. A lone backend is never taken out.
+    pub fn record_probe(&self, url: &str, result: Result<(), String>, thresholds: ProbeThresholds) {
+        let Some(backend) = self.backends.iter().find(|b| b.url == url) else {
+            return;
+        };
+        let down = backend.probe_down.load(Ordering::Relaxed);
+        match result {
+            Ok(()) => {
+                *backend.last_probe_error.lock().unwrap() = None;
+                if !down {
+                    backend.probe_streak.store(0, Ordering::Relaxed);
+                    return;
+                }
+                let passed = backend.probe_streak.fetch_add(1, Ordering::Relaxed) + 1;
+                if passed >= thresholds.healthy {
+                    info!("💚 Backend {} passed {} health checks, back in rotation", backend.url, passed);
+                    backend.probe_down.store(false, Ordering::Relaxed);
+                    backend.probe_streak.store(0, Ordering::Relaxed);
+                    self.record_success(backend);
+                }
+            }
+            Err(error) => {
+                *backend.last_probe_error.lock().unwrap() = Some(error.clone());
+                if down {
+                    backend.probe_streak.store(0, Ordering::Relaxed);
+                    return;
+                }
+                let failed = backend.probe_streak.fetch_add(1, Ordering::Relaxed) + 1;
+                if failed >= thresholds.unhealthy && self.backends.len() > 1 {
+                    warn!("💔 Backend {} failed {} health checks ({}), ejecting", backend.url, failed, error);
+                    backend.probe_down.store(true, Ordering::Relaxed);
+                    backend.probe_streak.store(0, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Prometheus text for whether each backend is in rotation
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP ollama_proxy_backend_healthy Whether a backend is in rotation (1) or ejected (0)");
+        let _ = writeln!(out, "# TYPE ollama_proxy_backend_healthy gauge");
+        for backend in &self.backends {
+            let _ = writeln!(
+                out,
+                "ollama_proxy_backend_healthy{{backend=\"{}\"}} {}",
+                backend.url,
+                backend.is_healthy() as u8
+            );
+        }
+        out
+    }
+}
+
+/// A request counted against a backend's load until dropped
+#[derive(Debug)]
+pub struct BackendLoad {
+    pool: Arc<BackendPool>,
+    index: usize,
+}
+
+impl Drop for BackendLoad {
+    fn drop(&mut self) {
+        self.pool.backends[self.index].in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_deduplicates_and_keeps_primary_first() {
+        let pool = BackendPool::new(
+            "http://a:11434",
+            &["http://b:11434/".to_string(), "http://a:11434".to_string()],
+        );
+        assert_eq!(pool.urls(), vec!["http://a:11434", "http://b:11434"]);
+        assert_eq!(pool.primary().url, "http://a:11434");
+        assert_eq!(pool.alternate("http://a:11434").unwrap().url, "http://b:11434");
+    }
+
+    #[test]
+    fn test_consistent_hash_placement() {
+        let others: Vec<String> = ["http://b:11434", "http://c:11434"].iter().map(|s| s.to_string()).collect();
+        let pins = Placement::parse_pins("pinned=http://c:11434/").unwrap();
+        let pool = BackendPool::new("http://a:11434", &others).with_placement(Placement {
+            strategy: PlacementStrategy::ConsistentHash,
+            pins,
+            ..Default::default()
+        });
+
+        // Same model, same node; tags are normalized
+        let home = pool.for_model("llama3").url.clone();
+        assert_eq!(pool.for_model("llama3:latest").url, home);
+        let models: Vec<String> = (0..30).map(|i| format!("model-{}", i)).collect();
+        let used: std::collections::HashSet<&str> = models.iter().map(|m| pool.for_model(m).url.as_str()).collect();
+        assert_eq!(used.len(), 3);
+        assert_eq!(pool.for_model("pinned").url, "http://c:11434");
+
+        // Losing a node only moves the models that lived there
+        let before: Vec<String> = models.iter().map(|m| pool.for_model(m).url.clone()).collect();
+        let lost = pool.backends.iter().find(|b| b.url == "http://b:11434").unwrap();
+        for _ in 0..EJECT_AFTER_FAILURES {
+            pool.record_failure(lost);
+        }
+        for (model, url) in models.iter().zip(&before) {
+            if url != "http://b:11434" {
+                assert_eq!(&pool.for_model(model).url, url);
+            }
+        }
+
+        assert!(PlacementStrategy::parse("random").is_err());
+        assert!(Placement::parse_pins("llama3").is_err());
+    }
+
+    #[test]
+    fn test_failing_backend_is_ejected_and_recovers() {
+        let pool = BackendPool::new("http://a:11434", &["http://b:11434".to_string()]);
+        let a = pool.primary();
+        for _ in 0..EJECT_AFTER_FAILURES {
+            pool.record_failure(a);
+        }
+        assert!(!pool.backends[0].is_healthy());
+        let states = pool.states();
+        assert_eq!(states[0].consecutive_failures, EJECT_AFTER_FAILURES);
+        assert!(states[0].ejected_for_seconds.is_some());
+        assert!(states[1].healthy);
+        assert_eq!(pool.primary().url, "http://b:11434");
+        assert!(pool.alternate("http://b:11434").is_none());
+
+        pool.record_success(&pool.backends[0]);
+        assert_eq!(pool.primary().url, "http://a:11434");
+    }
+
+    #[test]
+    fn test_health_checks_eject_and_restore() {
+        let pool = BackendPool::new("http://a:11434", &["http://b:11434".to_string()]);
+        let thresholds = ProbeThresholds::default();
+        let fail = || Err("connection refused".to_string());
+
+        pool.record_probe("http://a:11434", fail(), thresholds);
+        assert!(pool.backends[0].is_healthy());
+        pool.record_probe("http://a:11434", fail(), thresholds);
+        assert!(!pool.backends[0].is_healthy());
+        assert_eq!(pool.primary().url, "http://b:11434");
+        let states = pool.states();
+        assert!(states[0].failing_health_checks);
+        assert_eq!(states[0].last_health_check_error.as_deref(), Some("connection refused"));
+
+        // A flapping backend needs two passes in a row to come back
+        pool.record_probe("http://a:11434", Ok(()), thresholds);
+        pool.record_probe("http://a:11434", fail(), thresholds);
+        pool.record_probe("http://a:11434", Ok(()), thresholds);
+        assert!(!pool.backends[0].is_healthy());
+        pool.record_probe("http://a:11434", Ok(()), thresholds);
+        assert!(pool.backends[0].is_healthy());
+        assert!(pool.render_metrics().contains("ollama_proxy_backend_healthy{backend=\"http://a:11434\"} 1"));
+    }
+
+    #[test]
+    fn test_routes_to_backend_with_model() {
+        let others: Vec<String> = ["http://b:11434", "http://c:11434"].iter().map(|s| s.to_string()).collect();
+        let pool = BackendPool::new("http://a:11434", &others);
+        // Unknown inventories route as before
+        assert_eq!(pool.for_model("qwen2.5").url, "http://a:11434");
+        assert!(!pool.is_missing("qwen2.5"));
+
+        pool.set_models("http://a:11434", Some(vec!["llama3:latest".to_string()]));
+        pool.set_models("http://b:11434", Some(vec!["llama3".to_string()]));
+        pool.set_models("http://c:11434", Some(vec!["qwen2.5:latest".to_string()]));
+        assert_eq!(pool.for_model("qwen2.5").url, "http://c:11434");
+        assert_eq!(pool.for_model("llama3").url, "http://a:11434");
+        assert!(pool.is_missing("mistral"));
+        assert_eq!(pool.states()[1].models, Some(vec!["llama3:latest".to_string()]));
+
+        // The holder is down: fall back to a healthy backend
+        for _ in 0..EJECT_AFTER_FAILURES {
+            pool.record_failure(&pool.backends[2]);
+        }
+        assert_eq!(pool.for_model("qwen2.5").url, "http://a:11434");
+    }
+
+    #[test]
+    fn test_tiers_spill_under_load_and_weights_share_a_tier() {
+        let others: Vec<String> = ["http://b:11434", "http://mini:11434"].iter().map(|s| s.to_string()).collect();
+        let pool = Arc::new(BackendPool::new("http://a:11434", &others).with_placement(Placement {
+            strategy: PlacementStrategy::Weighted,
+            tiers: Placement::parse_backend_numbers("http://mini:11434/=2").unwrap(),
+            weights: Placement::parse_backend_numbers("http://a:11434=3").unwrap(),
+            max_in_flight: 1,
+            ..Default::default()
+        }));
+
+        // Tier 1 shares traffic 3:1 while it has room
+        let picks: Vec<String> = (0..8).map(|_| pool.for_model("llama3").url.clone()).collect();
+        assert_eq!(picks.iter().filter(|url| *url == "http://a:11434").count(), 6);
+        assert_eq!(picks.iter().filter(|url| *url == "http://b:11434").count(), 2);
+
+        // Both tier-1 boxes busy: spill to the Mac mini, and back once one frees up
+        let busy_a = pool.track("http://a:11434").unwrap();
+        let _busy_b = pool.track("http://b:11434").unwrap();
+        assert_eq!(pool.for_model("llama3").url, "http://mini:11434");
+        assert_eq!(pool.states()[0].in_flight, 1);
+        drop(busy_a);
+        assert_eq!(pool.for_model("llama3").url, "http://a:11434");
+
+        assert!(Placement::parse_backend_numbers("http://a:11434=0").is_err());
+        assert!(Placement::parse_backend_numbers("http://a:11434").is_err());
+    }
+
+    #[test]
+    fn test_conversations_stick_to_one_backend() {
+        let others: Vec<String> = ["http://b:11434", "http://c:11434"].iter().map(|s| s.to_string()).collect();
+        let pool = Arc::new(BackendPool::new("http://a:11434", &others).with_placement(Placement {
+            strategy: PlacementStrategy::Weighted,
+            max_in_flight: 1,
+            sticky_conversations: true,
+            ..Default::default()
+        }));
+        let turn = |history: &str| {
+            let body = format!(r#"{{"messages":[{{"role":"system","content":"Be brief"}},{{"role":"user","content":"Hi"}}{}]}}"#, history);
+            conversation_key(&HeaderMap::new(), body.as_bytes()).unwrap()
+        };
+        let first = turn("");
+        assert_eq!(turn(r#",{"role":"assistant","content":"Hello"},{"role":"user","content":"More"}"#), first);
+
+        // Every turn lands on the same backend, despite the round-robin
+        let home = pool.for_conversation("llama3", Some(&first)).url.clone();
+        for _ in 0..5 {
+            assert_eq!(pool.for_conversation("llama3", Some(&first)).url, home);
+        }
+        // A full home backend gives way to normal placement
+        let _busy = pool.track(&home).unwrap();
+        assert_ne!(pool.for_conversation("llama3", Some(&first)).url, home);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONVERSATION_HEADER, "chat-42".parse().unwrap());
+        assert_eq!(conversation_key(&headers, b"{}").as_deref(), Some("chat-42"));
+        assert_eq!(conversation_key(&HeaderMap::new(), br#"{"prompt":"hi"}"#), None);
+    }
+
+    #[test]
+    fn test_single_backend_is_never_ejected() {
+        let pool = BackendPool::new("http://a:11434", &[]);
+        for _ in 0..10 {
+            pool.record_failure(pool.primary());
+        }
+        for _ in 0..10 {
+            pool.record_probe("http://a:11434", Err("down".to_string()), ProbeThresholds::default());
+        }
+        assert!(pool.primary().is_healthy());
+        assert!(pool.alternate("http://a:11434").is_none());
+    }
+}
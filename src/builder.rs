@@ -0,0 +1,543 @@
+/// Embeddable proxy: build the axum Router without running the binary
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::access_log::{self, AccessLogFormat, LogDestination};
+use crate::auth::{self, ApiKeys};
+use crate::admission::{self, SaturationLimits, ShedPolicy};
+use crate::cache::CacheLimits;
+use crate::chunker::{Aggregation, ChunkingStrategy};
+use crate::cloud::CloudSettings;
+use crate::compression::PromptCompression;
+use crate::concurrency::ConcurrencyLimits;
+use crate::config::ProxyConfig;
+use crate::dimensions::DimensionPolicy;
+use crate::filters::OutputFilters;
+use crate::health::{self, HealthCheck};
+use crate::inventory::{self, ModelRouting};
+use crate::hedge::HedgePolicy;
+use crate::ipfilter::{self, IpFilter};
+use crate::jobs::{self, JobSettings};
+use crate::jwt::JwtSettings;
+use crate::keystore;
+use crate::metrics;
+use crate::model_metadata::{self, MetadataPrefetch};
+use crate::overrides::ModelOverrides;
+use crate::penalties::PenaltyMapping;
+use crate::preprocess::Preprocess;
+use crate::proxy::{self, ProxyState, StreamBatching};
+use crate::ratelimit::RateLimits;
+use crate::redact::BodyLogging;
+use crate::resident;
+use crate::routing::{CanaryRoutes, DefaultModels, PromptRoutes};
+use crate::schedule::{self, PrewarmSchedule};
+use crate::shadow::ShadowSettings;
+use crate::stats;
+use crate::structured::StructuredFailure;
+use crate::timeouts::{EndpointTimeouts, StreamTimeouts};
+use crate::tls::TlsSettings;
+use crate::unsupported::UnsupportedPolicy;
+use crate::upstream::{self, UpstreamOptions};
+
+/// Builds a [`ProxyConfig`], and from it the [`ProxyState`] and axum [`Router`]
+/// serving the proxy, so other services can mount the translation/modifier
+/// pipeline inside their own servers.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), String> {
+/// let proxy = ollama_proxy_rs::ProxyBuilder::new("http://127.0.0.1:11434")
+///     .max_context_override(32768)
+///     .router()?;
+/// let app: axum::Router = axum::Router::new().nest_service("/ollama", proxy);
+/// # let _ = app;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProxyBuilder {
+    config: ProxyConfig,
+}
+
+impl ProxyBuilder {
+    /// Start from the binary's defaults, proxying to `ollama_host`
+    /// (an `http(s)://` URL or `unix:///path/to/ollama.sock`).
+    /// DNS refresh stays off unless enabled with [`ProxyBuilder::dns_refresh`].
+    pub fn new(ollama_host: &str) -> Self {
+        let (ollama_host, unix_socket) = upstream::parse_ollama_host(ollama_host);
+        Self {
+            config: ProxyConfig {
+                ollama_host,
+                upstream: UpstreamOptions {
+                    unix_socket,
+                    ..Default::default()
+                },
+                dns_refresh: None,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Start from an existing config, e.g. one loaded with [`ProxyConfig::from_env`]
+    pub fn from_config(config: ProxyConfig) -> Self {
+        Self { config }
+    }
+
+    /// Address the binary listens on (ignored when mounting the router yourself)
+    pub fn listen_addr(mut self, addr: &str) -> Self {
+        self.config.listen_addr = addr.to_string();
+        self
+    }
+
+    /// Serve HTTPS with a PEM certificate and key (used by the binary's listener)
+    pub fn tls(mut self, settings: TlsSettings) -> Self {
+        self.config.tls = Some(settings);
+        self
+    }
+
+    /// Worker (0 = one per CPU core) and blocking threads of the binary's runtime
+    /// (ignored when running the router on your own runtime)
+    pub fn runtime_threads(mut self, worker_threads: usize, max_blocking_threads: usize) -> Self {
+        self.config.worker_threads = worker_threads;
+        self.config.max_blocking_threads = max_blocking_threads;
+        self
+    }
+
+    pub fn max_embedding_input_length(mut self, chars: usize) -> Self {
+        self.config.max_embedding_input_length = chars;
+        self
+    }
+
+    pub fn auto_chunking(mut self, enabled: bool) -> Self {
+        self.config.enable_auto_chunking = enabled;
+        self
+    }
+
+    /// Refuse embeddings requests that would need more chunks than this with a 413 (0 = no limit)
+    pub fn max_chunks_per_request(mut self, chunks: usize) -> Self {
+        self.config.max_chunks_per_request = chunks;
+        self
+    }
+
+    /// How long embedding inputs are split into chunks
+    pub fn chunking_strategy(mut self, strategy: ChunkingStrategy) -> Self {
+        self.config.chunking = strategy;
+        self
+    }
+
+    /// Default combination of chunk embeddings (clients can override it per request)
+    pub fn chunk_aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.config.chunk_aggregation = aggregation;
+        self
+    }
+
+    /// Return every chunk's embedding with its offsets (clients can override it per request)
+    pub fn return_chunk_embeddings(mut self, enabled: bool) -> Self {
+        self.config.return_chunk_embeddings = enabled;
+        self
+    }
+
+    /// Cache embeddings by model, input and dimensions (`max_entries` 0 disables it)
+    pub fn embedding_cache(mut self, limits: CacheLimits) -> Self {
+        self.config.embedding_cache = limits;
+        self
+    }
+
+    /// Default markup stripping for embedding inputs (clients can override it per request)
+    pub fn embedding_preprocess(mut self, mode: Preprocess) -> Self {
+        self.config.embedding_preprocess = mode;
+        self
+    }
+
+    /// Models whose embeddings may be truncated to a requested `dimensions`
+    pub fn embed_dimensions(mut self, policy: DimensionPolicy) -> Self {
+        self.config.embed_dimensions = policy;
+        self
+    }
+
+    /// Serve deprecated /api/embeddings requests through /api/embed with chunking
+    pub fn translate_legacy_embeddings(mut self, enabled: bool) -> Self {
+        self.config.translate_legacy_embeddings = enabled;
+        self
+    }
+
+    pub fn max_context_override(mut self, tokens: u32) -> Self {
+        self.config.max_context_override = tokens;
+        self
+    }
+
+    /// Refresh cached model metadata in the background once it is older than `ttl`
+    /// (None keeps it until restart)
+    pub fn metadata_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.config.metadata_ttl = ttl;
+        self
+    }
+
+    /// Fetch every installed model's metadata at startup (and every `interval`). The
+    /// prefetch task is spawned on the current Tokio runtime when building.
+    pub fn metadata_prefetch(mut self, prefetch: MetadataPrefetch) -> Self {
+        self.config.metadata_prefetch = prefetch;
+        self
+    }
+
+    /// Per-model context caps and modifier defaults, replacing the global ones for matching models
+    pub fn model_overrides(mut self, overrides: ModelOverrides) -> Self {
+        self.config.model_overrides = overrides;
+        self
+    }
+
+    /// Compress prompts that exceed the effective context instead of letting Ollama truncate them
+    pub fn prompt_compression(mut self, compression: PromptCompression) -> Self {
+        self.config.prompt_compression = compression;
+        self
+    }
+
+    /// Connection, TLS, timeout, retry and backend settings for the Ollama upstream.
+    /// The Unix socket parsed from the host is kept unless `options` sets one.
+    pub fn upstream(mut self, options: UpstreamOptions) -> Self {
+        let unix_socket = self.config.upstream.unix_socket.take();
+        self.config.upstream = options;
+        if self.config.upstream.unix_socket.is_none() {
+            self.config.upstream.unix_socket = unix_socket;
+        }
+        self
+    }
+
+    /// Per-endpoint-class request timeouts
+    pub fn timeouts(mut self, timeouts: EndpointTimeouts) -> Self {
+        self.config.upstream.timeouts = timeouts;
+        self
+    }
+
+    /// First-byte, idle and total timeouts for streamed responses
+    pub fn stream_timeouts(mut self, timeouts: StreamTimeouts) -> Self {
+        self.config.upstream.stream_timeouts = timeouts;
+        self
+    }
+
+    /// Upper bound for `X-Request-Timeout` (None ignores the header)
+    pub fn max_request_timeout(mut self, max: Option<Duration>) -> Self {
+        self.config.upstream.max_request_timeout = max;
+        self
+    }
+
+    /// Additional Ollama backends next to the primary host
+    pub fn backends(mut self, urls: &[&str]) -> Self {
+        self.config.upstream.backends = urls.iter().map(|url| url.to_string()).collect();
+        self
+    }
+
+    /// Race slow requests against a second backend
+    pub fn hedge(mut self, policy: HedgePolicy) -> Self {
+        self.config.upstream.hedge = policy;
+        self
+    }
+
+    pub fn max_buffered_response_bytes(mut self, bytes: usize) -> Self {
+        self.config.max_buffered_response_bytes = bytes;
+        self
+    }
+
+    pub fn stream_batching(mut self, batching: StreamBatching) -> Self {
+        self.config.stream_batching = batching;
+        self
+    }
+
+    /// Send per-phase latencies to clients in a Server-Timing header
+    pub fn server_timing(mut self, enabled: bool) -> Self {
+        self.config.server_timing = enabled;
+        self
+    }
+
+    /// Require one of `keys` as a bearer token on every route except /healthz
+    pub fn api_keys(mut self, keys: ApiKeys) -> Self {
+        self.config.api_keys = keys;
+        self
+    }
+
+    /// Keep keys created through /proxy/admin/keys in `path`
+    pub fn api_key_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.api_key_store = Some(path.into());
+        self
+    }
+
+    /// Accept JWTs from an OIDC issuer as well as API keys
+    pub fn jwt(mut self, settings: JwtSettings) -> Self {
+        self.config.jwt = Some(settings);
+        self
+    }
+
+    /// Only serve clients the filter allows. Serve the router with
+    /// `into_make_service_with_connect_info::<SocketAddr>()` so peer addresses are known.
+    pub fn ip_filter(mut self, filter: IpFilter) -> Self {
+        self.config.ip_filter = filter;
+        self
+    }
+
+    /// Requests and tokens per minute for each key that doesn't set its own limits
+    pub fn rate_limits(mut self, limits: RateLimits) -> Self {
+        self.config.rate_limits = limits;
+        self
+    }
+
+    /// Write one access log line per request to `destination` in `format`
+    pub fn access_log(mut self, destination: LogDestination, format: AccessLogFormat) -> Self {
+        self.config.access_log = Some(destination);
+        self.config.access_log_format = format;
+        self
+    }
+
+    /// How much of request/response bodies is logged, and which JSON fields are redacted
+    pub fn body_logging(mut self, logging: BodyLogging) -> Self {
+        self.config.body_logging = logging;
+        self
+    }
+
+    /// How OpenAI presence/frequency penalties are passed to each model
+    pub fn penalty_mapping(mut self, mapping: PenaltyMapping) -> Self {
+        self.config.penalty_mapping = mapping;
+        self
+    }
+
+    /// Role OpenAI `developer` messages are sent to Ollama as (None = unchanged)
+    pub fn developer_role(mut self, role: Option<&str>) -> Self {
+        self.config.developer_role = role.map(str::to_string);
+        self
+    }
+
+    /// Download http(s) image URLs in OpenAI vision requests; only public addresses are fetched
+    pub fn fetch_image_urls(mut self, enabled: bool) -> Self {
+        self.config.fetch_image_urls = enabled;
+        self
+    }
+
+    /// Models (or `prefix*` patterns) whose <think> spans are removed from translated responses
+    pub fn strip_think_models(mut self, models: Vec<String>) -> Self {
+        self.config.strip_think_models = models;
+        self
+    }
+
+    /// How streamed JSON-mode output that fails validation is handled
+    pub fn structured_failure(mut self, policy: StructuredFailure) -> Self {
+        self.config.structured_failure = policy;
+        self
+    }
+
+    /// How OpenAI parameters with no Ollama equivalent are handled
+    pub fn unsupported_params(mut self, policy: UnsupportedPolicy) -> Self {
+        self.config.unsupported_params = policy;
+        self
+    }
+
+    /// Cache non-streaming chat completions by request (`max_entries` 0 disables it)
+    pub fn chat_cache(mut self, limits: CacheLimits) -> Self {
+        self.config.chat_cache = limits;
+        self
+    }
+
+    pub fn prompt_routes(mut self, routes: PromptRoutes) -> Self {
+        self.config.prompt_routes = routes;
+        self
+    }
+
+    /// Split requests for an alias over model versions by percentage
+    pub fn canary_routes(mut self, routes: CanaryRoutes) -> Self {
+        self.config.canary_routes = routes;
+        self
+    }
+
+    /// Models filled in for chat/generate and embedding requests that omit one
+    pub fn default_models(mut self, models: DefaultModels) -> Self {
+        self.config.default_models = models;
+        self
+    }
+
+    pub fn saturation_limits(mut self, limits: SaturationLimits) -> Self {
+        self.config.saturation_limits = limits;
+        self
+    }
+
+    pub fn shed_policy(mut self, policy: ShedPolicy) -> Self {
+        self.config.shed_policy = policy;
+        self
+    }
+
+    /// Cap concurrent inference requests to Ollama, queueing the rest
+    pub fn concurrency_limits(mut self, limits: ConcurrencyLimits) -> Self {
+        self.config.concurrency_limits = limits;
+        self
+    }
+
+    /// Mask or replace matched text in generated output
+    pub fn output_filters(mut self, filters: OutputFilters) -> Self {
+        self.config.output_filters = filters;
+        self
+    }
+
+    /// Keep models loaded during scheduled windows. The scheduler task is
+    /// spawned on the current Tokio runtime when building.
+    pub fn prewarm_schedule(mut self, schedule: PrewarmSchedule) -> Self {
+        self.config.prewarm_schedule = schedule;
+        self
+    }
+
+    /// Journal, concurrency and retention of async embedding jobs. Jobs a restart
+    /// interrupted are resumed on the current Tokio runtime when building.
+    pub fn jobs(mut self, settings: JobSettings) -> Self {
+        self.config.jobs = settings;
+        self
+    }
+
+    /// Periodically re-resolve the upstream's DNS name (TCP upstreams only).
+    /// The refresh task is spawned on the current Tokio runtime when building.
+    pub fn dns_refresh(mut self, interval: Duration) -> Self {
+        self.config.dns_refresh = Some(interval);
+        self
+    }
+
+    /// Probe every backend in the background and take failing ones out of rotation.
+    /// The check task is spawned on the current Tokio runtime when building.
+    pub fn health_check(mut self, check: HealthCheck) -> Self {
+        self.config.health_check = check;
+        self
+    }
+
+    /// Route requests to backends that have the model pulled. The task reading the
+    /// backends' model lists is spawned on the current Tokio runtime when building.
+    pub fn model_routing(mut self, routing: ModelRouting) -> Self {
+        self.config.model_routing = routing;
+        self
+    }
+
+    /// Send OpenAI requests for models no backend has, or that fail locally, to an
+    /// OpenAI-compatible cloud upstream
+    pub fn cloud_fallback(mut self, settings: Option<CloudSettings>) -> Self {
+        self.config.cloud_fallback = settings;
+        self
+    }
+
+    /// Mirror selected chats and generations to a candidate model or backend and log
+    /// both answers for comparison
+    pub fn shadow(mut self, settings: Option<ShadowSettings>) -> Self {
+        self.config.shadow = settings;
+        self
+    }
+
+    /// The validated config
+    pub fn config(self) -> Result<ProxyConfig, String> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    /// Build the shared state without a router
+    pub fn build_state(self) -> Result<ProxyState, String> {
+        let config = self.config()?;
+        let dns_refresh = config.dns_refresh.filter(|_| config.upstream.unix_socket.is_none());
+        let ollama_host = config.ollama_host.clone();
+        let prewarm_schedule = config.prewarm_schedule.clone();
+        let health_check = config.health_check;
+        let inventory_refresh = config.model_routing.refresh;
+        let metadata_prefetch = config.metadata_prefetch;
+        // The cloud fallback needs to know which models are missing even with one backend
+        let track_inventory = !config.upstream.backends.is_empty() || config.cloud_fallback.is_some();
+        let state = ProxyState::new(config);
+
+        if let Some(interval) = dns_refresh {
+            upstream::spawn_dns_refresh(state.upstream.clone(), &ollama_host, interval);
+        }
+        // A lone backend is never ejected, so there is nothing to check
+        if let (Some(interval), true) = (health_check.interval, state.backends.urls().len() > 1) {
+            health::spawn_health_checks(state.clone(), interval, health_check.thresholds);
+        }
+        if let (Some(interval), true) = (inventory_refresh, track_inventory) {
+            inventory::spawn_inventory_refresh(state.clone(), interval);
+        }
+        if metadata_prefetch.enabled {
+            model_metadata::spawn_prefetch(state.metadata_cache.clone(), metadata_prefetch.interval);
+        }
+        if !prewarm_schedule.is_empty() {
+            schedule::spawn_prewarm(state.clone(), prewarm_schedule);
+        }
+        jobs::resume(&state);
+        Ok(state)
+    }
+
+    /// Build the router together with its state (for inspecting metrics, caches, etc.)
+    pub fn build(self) -> Result<(Router, ProxyState), String> {
+        let state = self.build_state()?;
+        Ok((router(state.clone()), state))
+    }
+
+    /// Build just the router
+    pub fn router(self) -> Result<Router, String> {
+        Ok(self.build()?.0)
+    }
+}
+
+/// Routes served by the proxy: health, metrics, admin endpoints, and the proxy itself as fallback
+pub fn router(state: ProxyState) -> Router {
+    // Admin endpoints need an admin key whenever authentication is on
+    let admin = Router::new()
+        .route("/queue", get(admission::queue_handler))
+        .route("/backends", get(health::backends_handler))
+        .route("/models", get(resident::list_handler))
+        .route("/stats", get(stats::stats_handler))
+        .route("/models/*rest", post(resident::action_handler))
+        .route("/metadata", get(model_metadata::list_handler))
+        .route("/metadata/*model", delete(model_metadata::invalidate_handler))
+        .route("/keys", get(keystore::list_handler).post(keystore::create_handler))
+        .route("/keys/:id", delete(keystore::revoke_handler))
+        .route("/keys/:id/rotate", post(keystore::rotate_handler))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth::admin_middleware));
+
+    Router::new()
+        .route("/healthz", get(health::healthz_handler))
+        .route("/healthz/details", get(health::details_handler))
+        .route("/metrics", get(metrics::metrics_handler))
+        .nest("/proxy/admin", admin)
+        .route("/proxy/jobs", post(jobs::submit_handler))
+        .route("/proxy/jobs/:id", get(jobs::status_handler))
+        .route("/proxy/jobs/:id/result", get(jobs::result_handler))
+        .fallback(proxy::proxy_handler)
+        // Inside the access log so rejected requests are still logged; addresses are checked before keys
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), ipfilter::middleware))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), access_log::middleware))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_host_is_kept_across_upstream_options() {
+        let builder = ProxyBuilder::new("unix:///run/ollama.sock").upstream(UpstreamOptions::default());
+        let config = builder.config().unwrap();
+        assert_eq!(config.ollama_host, "http://localhost");
+        assert_eq!(
+            config.upstream.unix_socket.as_deref(),
+            Some(std::path::Path::new("/run/ollama.sock"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_state_applies_settings() {
+        let (_router, state) = ProxyBuilder::new("http://127.0.0.1:11434")
+            .max_context_override(4096)
+            .auto_chunking(false)
+            .build()
+            .unwrap();
+        assert_eq!(state.ollama_host, "http://127.0.0.1:11434");
+        assert_eq!(state.max_context_override, 4096);
+        assert!(!state.enable_auto_chunking);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(ProxyBuilder::new("http://127.0.0.1:11434").max_context_override(100).config().is_err());
+    }
+}
@@ -0,0 +1,355 @@
+/// In-memory LRU caches for upstream results
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::metrics::{write_counter, write_gauge};
+
+/// Size and age limits for a cache (0 entries disables it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    /// Approximate payload bytes held (0 = no byte limit)
+    pub max_bytes: usize,
+    /// Entries older than this are treated as missing
+    pub ttl: Option<Duration>,
+}
+
+impl CacheLimits {
+    pub const DISABLED: Self = Self { max_entries: 0, max_bytes: 0, ttl: None };
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    tick: u64,
+    inserted: Instant,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Last-use tick to key, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+/// Least-recently-used cache with entry, byte and TTL limits, counting hits and misses
+pub struct LruCache<V> {
+    limits: CacheLimits,
+    inner: Mutex<Inner<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(limits: CacheLimits) -> Self {
+        Self {
+            limits,
+            inner: Mutex::new(Inner { entries: HashMap::new(), order: BTreeMap::new(), tick: 0, bytes: 0 }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limits.is_enabled()
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let expired = match inner.entries.get(key) {
+            Some(entry) => self.limits.ttl.is_some_and(|ttl| entry.inserted.elapsed() > ttl),
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        if expired {
+            inner.remove(key);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key).unwrap();
+        let old_tick = std::mem::replace(&mut entry.tick, tick);
+        let value = entry.value.clone();
+        inner.order.remove(&old_tick);
+        inner.order.insert(tick, key.to_string());
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Store `value`, `size` bytes large, evicting the least recently used entries
+    /// to stay within the limits. Values larger than the byte limit are not stored.
+    pub fn insert(&self, key: String, value: V, size: usize) {
+        if !self.is_enabled() || (self.limits.max_bytes > 0 && size > self.limits.max_bytes) {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(&key);
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.order.insert(tick, key.clone());
+        inner.entries.insert(key, Entry { value, size, tick, inserted: Instant::now() });
+        inner.bytes += size;
+        while inner.entries.len() > self.limits.max_entries
+            || (self.limits.max_bytes > 0 && inner.bytes > self.limits.max_bytes)
+        {
+            let Some((_, oldest)) = inner.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.bytes -= entry.size;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+
+    /// Fraction of lookups served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let total = hits + self.misses.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        hits as f64 / total as f64
+    }
+
+    /// Prometheus metrics for this cache, named `ollama_proxy_{name}_cache_*`
+    pub fn render_metrics(&self, name: &str, description: &str) -> String {
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            &format!("ollama_proxy_{}_cache_hits_total", name),
+            &format!("{} served from the cache", description),
+            self.hits.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            &format!("ollama_proxy_{}_cache_misses_total", name),
+            &format!("{} not found in the cache", description),
+            self.misses.load(Ordering::Relaxed),
+        );
+        write_gauge(&mut out, &format!("ollama_proxy_{}_cache_entries", name), "Entries in the cache", self.len() as f64);
+        write_gauge(&mut out, &format!("ollama_proxy_{}_cache_bytes", name), "Approximate bytes held by the cache", self.bytes() as f64);
+        write_gauge(&mut out, &format!("ollama_proxy_{}_cache_hit_ratio", name), "Fraction of lookups served from the cache", self.hit_rate());
+        out
+    }
+}
+
+impl<V> Inner<V> {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.bytes -= entry.size;
+        }
+    }
+}
+
+/// Embedding vectors keyed by model, input text and requested dimensions
+pub struct EmbeddingCache {
+    lru: LruCache<Arc<Vec<f32>>>,
+}
+
+impl EmbeddingCache {
+    pub fn new(limits: CacheLimits) -> Self {
+        Self { lru: LruCache::new(limits) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lru.is_enabled()
+    }
+
+    /// Whitespace differences don't change the key
+    fn key(model: &str, input: &str, dims: Option<usize>) -> String {
+        let mut hasher = DefaultHasher::new();
+        for word in input.split_whitespace() {
+            word.hash(&mut hasher);
+        }
+        format!("{}|{:016x}|{}", crate::backends::normalize_model(model), hasher.finish(), dims.unwrap_or(0))
+    }
+
+    pub fn get(&self, model: &str, input: &str, dims: Option<usize>) -> Option<Arc<Vec<f32>>> {
+        self.lru.get(&Self::key(model, input, dims))
+    }
+
+    pub fn insert(&self, model: &str, input: &str, dims: Option<usize>, embedding: Vec<f32>) {
+        let key = Self::key(model, input, dims);
+        let size = embedding.len() * std::mem::size_of::<f32>() + key.len();
+        self.lru.insert(key, Arc::new(embedding), size);
+    }
+
+    pub fn render_metrics(&self) -> String {
+        if !self.is_enabled() {
+            return String::new();
+        }
+        self.lru.render_metrics("embedding", "Embedding inputs")
+    }
+}
+
+/// Header carrying the chat cache result (`hit` or `miss`) on responses, and
+/// `bypass` on requests that must reach the model
+pub const CACHE_HEADER: &str = "x-proxy-cache";
+
+/// Request fields that don't change what the model generates
+const CHAT_KEY_IGNORED: &[&str] = &["stream", "stream_options", "user", "metadata", "store"];
+
+/// Whole non-streaming chat completion responses, keyed by the request
+pub struct ChatCache {
+    lru: LruCache<bytes::Bytes>,
+}
+
+impl ChatCache {
+    pub fn new(limits: CacheLimits) -> Self {
+        Self { lru: LruCache::new(limits) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lru.is_enabled()
+    }
+
+    /// Cache key for an OpenAI chat request: model, messages and sampling parameters,
+    /// scoped to the caller's API key since output filters can differ per key.
+    /// None for streaming requests, which are never cached.
+    pub fn key(model: &str, request: &Value, api_key: Option<&str>) -> Option<String> {
+        if request.get("stream").and_then(Value::as_bool) == Some(true) {
+            return None;
+        }
+        let mut request = request.as_object()?.clone();
+        for field in CHAT_KEY_IGNORED.iter().copied().chain(["model"]) {
+            request.remove(field);
+        }
+        // serde_json maps are sorted, so equal requests serialize identically
+        let canonical = format!("{}\n{}", api_key.unwrap_or_default(), Value::Object(request));
+        let mut sip = DefaultHasher::new();
+        canonical.hash(&mut sip);
+        let mut fnv: u64 = 0xcbf29ce484222325;
+        for byte in canonical.bytes() {
+            fnv ^= byte as u64;
+            fnv = fnv.wrapping_mul(0x100000001b3);
+        }
+        Some(format!("{}|{:016x}{:016x}", crate::backends::normalize_model(model), sip.finish(), fnv))
+    }
+
+    pub fn get(&self, key: &str) -> Option<bytes::Bytes> {
+        self.lru.get(key)
+    }
+
+    pub fn insert(&self, key: String, body: bytes::Bytes) {
+        let size = body.len() + key.len();
+        self.lru.insert(key, body, size);
+    }
+
+    pub fn render_metrics(&self) -> String {
+        if !self.is_enabled() {
+            return String::new();
+        }
+        self.lru.render_metrics("chat", "Chat completions")
+    }
+}
+
+/// Whether the request asks to skip the cache (`X-Proxy-Cache: bypass`)
+pub fn bypass(headers: &HeaderMap) -> bool {
+    headers
+        .get(CACHE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| matches!(v.trim().to_lowercase().as_str(), "bypass" | "no-cache" | "off"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_and_limits() {
+        let cache = LruCache::new(CacheLimits { max_entries: 2, max_bytes: 100, ttl: None });
+        cache.insert("a".to_string(), 1, 10);
+        cache.insert("b".to_string(), 2, 10);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".to_string(), 3, 10);
+        // "b" was least recently used
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+
+        cache.insert("big".to_string(), 4, 95);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.bytes(), 95);
+        cache.insert("huge".to_string(), 5, 500);
+        assert_eq!(cache.get("huge"), None);
+        assert!((cache.hit_rate() - 0.6).abs() < f64::EPSILON);
+
+        let expiring = LruCache::new(CacheLimits { max_entries: 10, max_bytes: 0, ttl: Some(Duration::ZERO) });
+        expiring.insert("a".to_string(), 1, 1);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(expiring.get("a"), None);
+        assert!(expiring.is_empty());
+    }
+
+    #[test]
+    fn test_chat_cache_keys() {
+        let request = serde_json::json!({
+            "model": "llama3",
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 0.2,
+            "user": "alice"
+        });
+        let key = ChatCache::key("llama3", &request, None).unwrap();
+
+        let mut same = request.clone();
+        same["user"] = "bob".into();
+        same["stream"] = false.into();
+        assert_eq!(ChatCache::key("llama3:latest", &same, None).unwrap(), key);
+
+        let mut warmer = request.clone();
+        warmer["temperature"] = 0.9.into();
+        assert_ne!(ChatCache::key("llama3", &warmer, None).unwrap(), key);
+        assert_ne!(ChatCache::key("mistral", &request, None).unwrap(), key);
+        assert_ne!(ChatCache::key("llama3", &request, Some("sk-other")).unwrap(), key);
+
+        let mut streaming = request.clone();
+        streaming["stream"] = true.into();
+        assert_eq!(ChatCache::key("llama3", &streaming, None), None);
+
+        let mut headers = HeaderMap::new();
+        assert!(!bypass(&headers));
+        headers.insert(CACHE_HEADER, "Bypass".parse().unwrap());
+        assert!(bypass(&headers));
+    }
+
+    #[test]
+    fn test_embedding_cache_keys() {
+        let cache = EmbeddingCache::new(CacheLimits { max_entries: 10, max_bytes: 0, ttl: None });
+        cache.insert("nomic-embed-text", "hello   world", None, vec![0.5, 0.5]);
+        assert!(cache.get("nomic-embed-text:latest", " hello world", None).is_some());
+        assert!(cache.get("nomic-embed-text", "hello world", Some(1)).is_none());
+        assert!(cache.get("mxbai-embed-large", "hello world", None).is_none());
+        assert!(EmbeddingCache::new(CacheLimits::DISABLED).get("m", "x", None).is_none());
+    }
+}
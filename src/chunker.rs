@@ -1,21 +1,284 @@
 /// Smart text chunking module for handling large embeddings inputs
+use axum::http::HeaderMap;
 use tracing::debug;
 
+/// A piece of an input, with its character offsets in that input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
 /// Chunk text into smaller pieces that don't exceed max_len
 /// 
 /// Strategy:
-/// 1. Try to split on sentence boundaries (. ! ?)
-/// 2. Fall back to word boundaries if sentences are too long
+/// 1. Try to split on sentence boundaries (. ! ? and CJK 。！？)
+/// 2. Fall back to word boundaries if sentences are too long, then clause
+///    punctuation (for text without spaces), then character boundaries
 /// 3. Add 10% overlap between chunks for context preservation
 /// 4. Ensure no chunk exceeds max_len
 pub fn chunk_text(input: &str, max_len: usize) -> Vec<String> {
+    chunk_spans(input, max_len)
+        .into_iter()
+        .map(|(start, end)| input[start..end].to_string())
+        .collect()
+}
+
+/// `chunk_text`, keeping where each chunk starts and ends in `input`
+pub fn chunk_with_offsets(input: &str, max_len: usize) -> Vec<Chunk> {
+    ChunkingStrategy::Sentence.chunk(input, max_len)
+}
+
+/// Separators tried in order by the recursive strategy
+pub const DEFAULT_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " "];
+
+/// How long inputs are split into chunks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ChunkingStrategy {
+    /// Sentence, then word boundaries near the length limit, with 10% overlap
+    #[default]
+    Sentence,
+    /// Split on the first separator that occurs, recursing into pieces that are still
+    /// too long with the next one, then merge pieces back up to the length limit
+    Recursive(Vec<String>),
+    /// Chunks never cross a heading, and fenced code blocks stay whole where they fit
+    Markdown,
+    /// Source code: split between top-level blocks (functions, classes) found by
+    /// indentation and closing braces
+    Code,
+}
+
+impl ChunkingStrategy {
+    /// `sentence`, `recursive`, `markdown` or `code`; `separators` is a `|`-separated list where `\n`
+    /// and `\t` are escapes (e.g. `\n\n|\n|. | `)
+    pub fn parse(name: &str, separators: Option<&str>) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "sentence" => Some(Self::Sentence),
+            "recursive" => Some(Self::Recursive(match separators {
+                Some(list) => parse_separators(list),
+                None => DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect(),
+            })),
+            "markdown" | "md" => Some(Self::Markdown),
+            "code" => Some(Self::Code),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sentence => "sentence",
+            Self::Recursive(_) => "recursive",
+            Self::Markdown => "markdown",
+            Self::Code => "code",
+        }
+    }
+
+    /// Split `input` into chunks of at most `max_len` bytes, with character offsets
+    pub fn chunk(&self, input: &str, max_len: usize) -> Vec<Chunk> {
+        let spans = match self {
+            Self::Sentence => chunk_spans(input, max_len),
+            Self::Recursive(separators) => recursive_spans(input, max_len, separators),
+            Self::Markdown => markdown_spans(input, max_len),
+            Self::Code => code_spans(input, max_len),
+        };
+        spans
+            .into_iter()
+            .map(|(start, end)| {
+                let start_char = input[..start].chars().count();
+                let text = input[start..end].to_string();
+                let end_char = start_char + text.chars().count();
+                Chunk { text, start: start_char, end: end_char }
+            })
+            .collect()
+    }
+}
+
+fn parse_separators(list: &str) -> Vec<String> {
+    list.split('|')
+        .map(|s| s.replace("\\n", "\n").replace("\\t", "\t"))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Byte ranges for the recursive strategy
+fn recursive_spans(input: &str, max_len: usize, separators: &[String]) -> Vec<(usize, usize)> {
+    if input.is_empty() {
+        return vec![];
+    }
+    if input.len() <= max_len {
+        return vec![(0, input.len())];
+    }
+    let mut pieces = Vec::new();
+    split_recursive(input, 0, max_len, separators, &mut pieces);
+    let chunks = merge_pieces(&pieces, max_len, (max_len as f32 * 0.1) as usize);
+    debug!("Created {} chunks from {} pieces", chunks.len(), pieces.len());
+    chunks
+}
+
+/// Break `text` (starting at byte `offset` of the input) into pieces no longer
+/// than `max_len`, each keeping the separator it ended on
+fn split_recursive(text: &str, offset: usize, max_len: usize, separators: &[String], pieces: &mut Vec<(usize, usize)>) {
+    if text.len() <= max_len {
+        pieces.push((offset, offset + text.len()));
+        return;
+    }
+    let Some(index) = separators.iter().position(|sep| text.contains(sep.as_str())) else {
+        hard_split(text, offset, max_len, pieces);
+        return;
+    };
+    let separator = &separators[index];
+    let mut start = 0;
+    for (pos, _) in text.match_indices(separator.as_str()) {
+        let end = pos + separator.len();
+        split_recursive(&text[start..end], offset + start, max_len, &separators[index + 1..], pieces);
+        start = end;
+    }
+    if start < text.len() {
+        split_recursive(&text[start..], offset + start, max_len, &separators[index + 1..], pieces);
+    }
+}
+
+/// Add a block as a piece, splitting it with `separators` if it is too long
+fn push_block(input: &str, block: (usize, usize), max_len: usize, separators: &[&str], pieces: &mut Vec<(usize, usize)>) {
+    if block.1 <= block.0 {
+        return;
+    }
+    let separators: Vec<String> = separators.iter().map(|s| s.to_string()).collect();
+    split_recursive(&input[block.0..block.1], block.0, max_len, &separators, pieces);
+}
+
+/// Byte ranges for Markdown: paragraphs, headings and fenced code blocks are
+/// pieces, and each heading starts a section that chunks don't cross
+fn markdown_spans(input: &str, max_len: usize) -> Vec<(usize, usize)> {
+    if input.len() <= max_len {
+        return recursive_spans(input, max_len, &[]);
+    }
+    let mut sections: Vec<Vec<(usize, usize)>> = vec![Vec::new()];
+    let mut paragraph: Option<usize> = None;
+    let mut fence: Option<(&str, usize)> = None;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+        let section = sections.last_mut().unwrap();
+
+        if let Some((marker, fence_start)) = fence {
+            if start > fence_start && trimmed.starts_with(marker) {
+                push_block(input, (fence_start, offset), max_len, &["\n\n", "\n"], section);
+                fence = None;
+            }
+            continue;
+        }
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        if marker.is_some() || trimmed.starts_with('#') || line.trim().is_empty() {
+            if let Some(paragraph_start) = paragraph.take() {
+                push_block(input, (paragraph_start, start), max_len, DEFAULT_SEPARATORS, section);
+            }
+        }
+        if let Some(marker) = marker {
+            fence = Some((marker, start));
+        } else if trimmed.starts_with('#') {
+            if !section.is_empty() {
+                sections.push(Vec::new());
+            }
+            sections.last_mut().unwrap().push((start, offset));
+        } else if !line.trim().is_empty() && paragraph.is_none() {
+            paragraph = Some(start);
+        }
+    }
+    let section = sections.last_mut().unwrap();
+    if let Some((_, fence_start)) = fence {
+        push_block(input, (fence_start, offset), max_len, &["\n\n", "\n"], section);
+    }
+    if let Some(paragraph_start) = paragraph {
+        push_block(input, (paragraph_start, offset), max_len, DEFAULT_SEPARATORS, section);
+    }
+
+    let overlap = (max_len as f32 * 0.1) as usize;
+    let chunks: Vec<(usize, usize)> = sections.iter().flat_map(|pieces| merge_pieces(pieces, max_len, overlap)).collect();
+    debug!("Created {} chunks from {} Markdown sections", chunks.len(), sections.len());
+    chunks
+}
+
+/// Byte ranges for source code. A block starts at an unindented line that
+/// follows a blank, indented or closing line, so a function's body, closing
+/// brace and preceding comments or decorators stay with it.
+fn code_spans(input: &str, max_len: usize) -> Vec<(usize, usize)> {
+    if input.len() <= max_len {
+        return recursive_spans(input, max_len, &[]);
+    }
+    let mut pieces = Vec::new();
+    let mut block_start = 0;
+    let mut prev_top_level = false;
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let top_level = !line.trim().is_empty() && !line.starts_with(char::is_whitespace);
+        let closing = top_level && (line.starts_with(['}', ')', ']']) || line.trim_end() == "end");
+        if top_level && !closing && !prev_top_level && start > block_start {
+            push_block(input, (block_start, start), max_len, &["\n\n", "\n"], &mut pieces);
+            block_start = start;
+        }
+        prev_top_level = top_level && !closing;
+    }
+    push_block(input, (block_start, offset), max_len, &["\n\n", "\n"], &mut pieces);
+
+    let chunks = merge_pieces(&pieces, max_len, (max_len as f32 * 0.1) as usize);
+    debug!("Created {} chunks from {} code blocks", chunks.len(), pieces.len());
+    chunks
+}
+
+/// Cut at `max_len` bytes, backing off to a character boundary
+fn hard_split(text: &str, offset: usize, max_len: usize, pieces: &mut Vec<(usize, usize)>) {
+    let mut start = 0;
+    while start < text.len() {
+        let mut end = boundary_before(text, start + max_len);
+        if end <= start {
+            // A single character (or cluster) wider than max_len
+            end = next_boundary(text, start + 1);
+        }
+        pieces.push((offset + start, offset + end));
+        start = end;
+    }
+}
+
+/// Join consecutive pieces into chunks up to `max_len`, starting each chunk with
+/// up to `overlap` bytes of trailing pieces from the previous one
+fn merge_pieces(pieces: &[(usize, usize)], max_len: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::new();
+    let mut current: std::collections::VecDeque<(usize, usize)> = std::collections::VecDeque::new();
+    let span_len = |current: &std::collections::VecDeque<(usize, usize)>| match (current.front(), current.back()) {
+        (Some(first), Some(last)) => last.1 - first.0,
+        _ => 0,
+    };
+    for &piece in pieces {
+        let len = piece.1 - piece.0;
+        if !current.is_empty() && span_len(&current) + len > max_len {
+            chunks.push((current[0].0, current[current.len() - 1].1));
+            while !current.is_empty() && (span_len(&current) > overlap || span_len(&current) + len > max_len) {
+                current.pop_front();
+            }
+        }
+        current.push_back(piece);
+    }
+    if let (Some(first), Some(last)) = (current.front(), current.back()) {
+        chunks.push((first.0, last.1));
+    }
+    chunks
+}
+
+/// Byte ranges of the chunks `chunk_text` produces
+fn chunk_spans(input: &str, max_len: usize) -> Vec<(usize, usize)> {
     // Handle empty or very short input
     if input.is_empty() {
         return vec![];
     }
     
     if input.len() <= max_len {
-        return vec![input.to_string()];
+        return vec![(0, input.len())];
     }
 
     debug!("Chunking text of length {} with max_len {}", input.len(), max_len);
@@ -31,16 +294,15 @@ pub fn chunk_text(input: &str, max_len: usize) -> Vec<String> {
         
         // If remaining text fits in one chunk, take it all
         if remaining <= max_len {
-            chunks.push(input[start..].to_string());
+            chunks.push((start, input.len()));
             break;
         }
         
         // Try to find a good breaking point
-        let end = start + max_len;
-        let chunk_end = find_break_point(&input[start..end], max_len);
+        let chunk_end = find_break_point(&input[start..], max_len);
         
         let actual_end = start + chunk_end;
-        chunks.push(input[start..actual_end].to_string());
+        chunks.push((start, actual_end));
         
         // Ensure we make progress (avoid infinite loop)
         if actual_end <= prev_end {
@@ -49,56 +311,278 @@ pub fn chunk_text(input: &str, max_len: usize) -> Vec<String> {
         prev_end = actual_end;
         
         // Move start forward, but keep overlap
-        start = actual_end.saturating_sub(overlap_size);
+        start = boundary_before(input, actual_end.saturating_sub(overlap_size)).max(start + 1);
+        start = next_boundary(input, start);
     }
     
     debug!("Created {} chunks from input", chunks.len());
     chunks
 }
 
-/// Find the best breaking point in text, preferring sentence/word boundaries
+/// Find the best breaking point in the first `max_pos` bytes of text, preferring
+/// sentence, then word, then clause boundaries. Always a character boundary.
 fn find_break_point(text: &str, max_pos: usize) -> usize {
     if text.len() <= max_pos {
         return text.len();
     }
-    
-    // Look for sentence endings (. ! ?) in the last 20% of the chunk
+    let limit = boundary_before(text, max_pos);
+    if limit == 0 {
+        // A single character (or cluster) wider than max_pos
+        return next_boundary(text, 1);
+    }
+
+    // Look for boundaries in the last 20% of the chunk, searching backwards
     let search_start = (max_pos as f32 * 0.8) as usize;
-    
-    // Search backwards from max_pos for sentence boundary
-    for i in (search_start..max_pos).rev() {
-        if let Some(ch) = text.chars().nth(i) {
-            if matches!(ch, '.' | '!' | '?') {
-                // Check if there's whitespace after (proper sentence end)
-                if i + 1 < text.len() {
-                    if let Some(next_ch) = text.chars().nth(i + 1) {
-                        if next_ch.is_whitespace() {
-                            return i + 2; // Include the punctuation and space
-                        }
+    let window: Vec<(usize, char)> = text[..limit].char_indices().filter(|(i, _)| *i >= search_start).collect();
+
+    // Sentence endings: . ! ? and CJK terminators
+    if let Some(&(i, ch)) = window.iter().rev().find(|(_, ch)| matches!(ch, '.' | '!' | '?') || is_cjk_terminator(*ch)) {
+        return i + ch.len_utf8(); // Include the punctuation
+    }
+
+    // If no sentence boundary found, look for word boundary (space)
+    if let Some(&(i, _)) = window.iter().rev().find(|(i, ch)| *i > 0 && ch.is_whitespace()) {
+        return i; // End the chunk before the space
+    }
+
+    // Languages written without spaces: break after clause punctuation
+    if let Some(&(i, ch)) = window.iter().rev().find(|(_, ch)| matches!(ch, '，' | '、' | '；' | '：' | ',' | ';')) {
+        return i + ch.len_utf8();
+    }
+
+    // If no good boundary found, split at the last character boundary
+    limit
+}
+
+/// Sentence terminators in Chinese and Japanese text, which need no space after them
+fn is_cjk_terminator(ch: char) -> bool {
+    matches!(ch, '。' | '！' | '？' | '｡' | '．')
+}
+
+/// Characters that belong to the previous character's cluster (combining marks,
+/// zero-width joiner, variation selectors), so a chunk never starts with one
+fn is_cluster_continuation(ch: char) -> bool {
+    matches!(ch, '\u{0300}'..='\u{036F}' | '\u{200D}' | '\u{FE00}'..='\u{FE0F}' | '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+/// The largest boundary at or before `pos` that splits neither a character
+/// nor a cluster (0 if there is none)
+fn boundary_before(text: &str, pos: usize) -> usize {
+    let mut pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    while pos > 0 && text[pos..].chars().next().is_some_and(is_cluster_continuation) {
+        pos -= 1;
+        while !text.is_char_boundary(pos) {
+            pos -= 1;
+        }
+    }
+    pos
+}
+
+/// The smallest such boundary at or after `pos`
+fn next_boundary(text: &str, pos: usize) -> usize {
+    let mut pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos += 1;
+    }
+    while text[pos..].chars().next().is_some_and(is_cluster_continuation) {
+        pos += text[pos..].chars().next().map_or(1, char::len_utf8);
+    }
+    pos
+}
+
+/// Header choosing the chunk aggregation for one request
+pub const AGGREGATION_HEADER: &str = "x-proxy-aggregation";
+
+/// Header asking for one embedding per chunk instead of one per input
+pub const RETURN_CHUNKS_HEADER: &str = "x-proxy-return-chunks";
+
+/// Whether the request asks for chunk embeddings (`X-Proxy-Return-Chunks: true`), or `default`
+pub fn return_chunks(headers: &HeaderMap, default: bool) -> bool {
+    match headers.get(RETURN_CHUNKS_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(value) => matches!(value.trim().to_lowercase().as_str(), "true" | "1" | "yes"),
+        None => default,
+    }
+}
+
+/// How the embeddings of one input's chunks are combined into a single vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Element-wise mean
+    #[default]
+    Mean,
+    /// Mean weighted by each chunk's length, so a short tail chunk counts less
+    WeightedMean,
+    /// Element-wise maximum
+    MaxPool,
+    /// The first chunk's embedding only
+    First,
+}
+
+impl Aggregation {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().replace('_', "-").as_str() {
+            "mean" => Some(Self::Mean),
+            "weighted-mean" | "length-weighted" | "weighted" => Some(Self::WeightedMean),
+            "max-pool" | "max" => Some(Self::MaxPool),
+            "first-chunk" | "first" => Some(Self::First),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mean => "mean",
+            Self::WeightedMean => "weighted-mean",
+            Self::MaxPool => "max-pool",
+            Self::First => "first-chunk",
+        }
+    }
+
+    /// The request's `X-Proxy-Aggregation` choice, or `default`
+    pub fn from_headers(headers: &HeaderMap, default: Self) -> Self {
+        headers
+            .get(AGGREGATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse)
+            .unwrap_or(default)
+    }
+
+    /// Combine chunk embeddings; `lengths` are the chunks' lengths in characters.
+    /// Dimensions beyond the first embedding's are ignored.
+    pub fn combine(&self, embeddings: &[Vec<f32>], lengths: &[usize]) -> Vec<f32> {
+        let Some(first) = embeddings.first() else {
+            return vec![];
+        };
+        match self {
+            Self::Mean => weighted_mean(embeddings, &vec![1.0; embeddings.len()]),
+            Self::WeightedMean => {
+                let weights: Vec<f32> = lengths.iter().map(|&len| len.max(1) as f32).collect();
+                weighted_mean(embeddings, &weights)
+            }
+            Self::MaxPool => {
+                let mut combined = first.clone();
+                for embedding in &embeddings[1..] {
+                    for (max, &val) in combined.iter_mut().zip(embedding) {
+                        *max = max.max(val);
                     }
                 }
-                return i + 1; // Include the punctuation
+                combined
             }
+            Self::First => first.clone(),
         }
     }
-    
-    // If no sentence boundary found, look for word boundary (space)
-    for i in (search_start..max_pos).rev() {
-        if let Some(ch) = text.chars().nth(i) {
-            if ch.is_whitespace() {
-                return i + 1; // Start next chunk after the space
-            }
+}
+
+fn weighted_mean(embeddings: &[Vec<f32>], weights: &[f32]) -> Vec<f32> {
+    let mut combined = vec![0.0f32; embeddings[0].len()];
+    for (embedding, &weight) in embeddings.iter().zip(weights) {
+        for (sum, &val) in combined.iter_mut().zip(embedding) {
+            *sum += val * weight;
         }
     }
-    
-    // If no good boundary found, split at max_pos
-    max_pos
+    let total: f32 = weights.iter().take(embeddings.len()).sum();
+    for val in &mut combined {
+        *val /= total;
+    }
+    combined
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_aggregation_strategies() {
+        let embeddings = [vec![1.0, 4.0], vec![3.0, 2.0]];
+        let lengths = [300, 100];
+        assert_eq!(Aggregation::Mean.combine(&embeddings, &lengths), vec![2.0, 3.0]);
+        assert_eq!(Aggregation::WeightedMean.combine(&embeddings, &lengths), vec![1.5, 3.5]);
+        assert_eq!(Aggregation::MaxPool.combine(&embeddings, &lengths), vec![3.0, 4.0]);
+        assert_eq!(Aggregation::First.combine(&embeddings, &lengths), vec![1.0, 4.0]);
+        assert!(Aggregation::Mean.combine(&[], &[]).is_empty());
+
+        assert_eq!(Aggregation::parse("max_pool"), Some(Aggregation::MaxPool));
+        let mut headers = HeaderMap::new();
+        headers.insert(AGGREGATION_HEADER, "first-chunk".parse().unwrap());
+        assert_eq!(Aggregation::from_headers(&headers, Aggregation::Mean), Aggregation::First);
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let text = "Ünïcode first sentence. Second sentence here. Third one ends it.";
+        let chunks = chunk_with_offsets(text, 30);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            let slice: String = text.chars().skip(chunk.start).take(chunk.end - chunk.start).collect();
+            assert_eq!(slice, chunk.text);
+        }
+        assert_eq!(chunks.last().unwrap().end, text.chars().count());
+    }
+
+    #[test]
+    fn test_recursive_prefers_paragraphs() {
+        let text = "First paragraph about cats.\n\nSecond paragraph, which is about dogs.\n\nThird.";
+        let strategy = ChunkingStrategy::parse("recursive", None).unwrap();
+        let chunks = strategy.chunk(text, 45);
+        assert_eq!(chunks[0].text, "First paragraph about cats.\n\n");
+        assert!(chunks[1].text.starts_with("Second paragraph"));
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= 45);
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+        }
+        assert_eq!(chunks.last().unwrap().end, text.len());
+
+        // No separators left: hard splits
+        let chunks = ChunkingStrategy::Recursive(Vec::new()).chunk(&"x".repeat(25), 10);
+        assert_eq!(chunks.iter().map(|c| c.text.len()).collect::<Vec<_>>(), vec![10, 10, 5]);
+
+        assert_eq!(parse_separators("\\n\\n|. "), vec!["\n\n".to_string(), ". ".to_string()]);
+        assert_eq!(ChunkingStrategy::parse("words", None), None);
+    }
+
+    #[test]
+    fn test_markdown_keeps_code_fences_and_sections() {
+        let text = "# Install\n\nRun the installer.\n\n```bash\ncargo build\ncargo test\n```\n\n# Usage\n\nStart the proxy and point clients at it.\n";
+        let chunks = ChunkingStrategy::Markdown.chunk(text, 80);
+        assert_eq!(chunks[0].text, "# Install\n\nRun the installer.\n\n```bash\ncargo build\ncargo test\n```\n");
+        assert!(chunks[1].text.starts_with("# Usage"));
+        assert!(chunks.iter().all(|c| c.text.len() <= 80));
+    }
+
+    #[test]
+    fn test_code_splits_between_functions() {
+        let text = "use std::io;\n\n// Adds\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\nfn sub(a: i32, b: i32) -> i32 {\n    a - b\n}\n";
+        let chunks = ChunkingStrategy::Code.chunk(text, 70);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].text.ends_with("    a + b\n}\n\n"));
+        assert!(chunks[1].text.starts_with("fn sub"));
+    }
+
+    #[test]
+    fn test_multibyte_and_cjk_boundaries() {
+        // Multi-byte characters straddling the limit must not panic
+        let text = "é".repeat(300);
+        let chunks = chunk_text(&text, 101);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= 101 && c.chars().all(|ch| ch == 'é')));
+
+        let text = "今日は良い天気です。散歩に行きましょう。公園でお弁当を食べます。".repeat(5);
+        let chunks = chunk_text(&text, 200);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.ends_with('。'), "{:?}", chunk);
+        }
+
+        // A combining accent stays with its letter
+        let text = format!("{}e\u{0301}{}", "a".repeat(9), "b".repeat(20));
+        let chunks = ChunkingStrategy::Recursive(Vec::new()).chunk(&text, 10);
+        assert_eq!(chunks[0].text, "a".repeat(9));
+        assert!(chunks[1].text.starts_with("e\u{0301}"));
+    }
+
     #[test]
     fn test_empty_string() {
         let result = chunk_text("", 100);
@@ -0,0 +1,204 @@
+/// Cloud fallback: an OpenAI-compatible upstream (OpenAI itself or any compatible
+/// service) for requests Ollama can't serve
+use axum::{
+    body::Body,
+    http::{header, Response, StatusCode},
+};
+use futures::StreamExt;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::backends::model_matches;
+use crate::errors::openai_error;
+use crate::filters::{OutputFilters, StreamScrubber};
+
+/// Header marking responses served by the cloud fallback
+pub const UPSTREAM_HEADER: &str = "x-proxy-upstream";
+
+/// OpenAI endpoints the fallback can serve as-is
+const FORWARDED_PATHS: [&str; 3] = ["/v1/chat/completions", "/v1/completions", "/v1/embeddings"];
+
+/// Where and when requests go to the cloud
+#[derive(Debug, Clone)]
+pub struct CloudSettings {
+    /// Base URL including the version, e.g. `https://api.openai.com/v1`
+    pub url: String,
+    /// Sent as `Authorization: Bearer ...` instead of the client's credentials
+    pub api_key: Option<String>,
+    /// Model patterns (`*`, `prefix*`, names) allowed to fall back; empty allows all
+    pub models: Vec<String>,
+    /// Also retry requests local inference failed (5xx, unknown model), not only missing models
+    pub on_error: bool,
+    pub timeout: Option<Duration>,
+}
+
+impl CloudSettings {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            api_key: None,
+            models: Vec::new(),
+            on_error: true,
+            timeout: Some(Duration::from_secs(300)),
+        }
+    }
+
+    /// Human-readable summary for startup logs (never includes the key)
+    pub fn describe(&self) -> String {
+        format!(
+            "{} ({}, models: {}, {})",
+            self.url,
+            if self.api_key.is_some() { "API key set" } else { "no API key" },
+            if self.models.is_empty() { "all".to_string() } else { self.models.join(", ") },
+            if self.on_error { "missing models and local failures" } else { "missing models only" }
+        )
+    }
+}
+
+/// The cloud upstream with its own HTTP client, separate from Ollama's (which may
+/// use a Unix socket, a private CA, or a client certificate)
+#[derive(Debug)]
+pub struct CloudFallback {
+    settings: CloudSettings,
+    client: reqwest::Client,
+}
+
+impl CloudFallback {
+    pub fn new(settings: CloudSettings) -> Self {
+        Self { settings, client: reqwest::Client::new() }
+    }
+
+    pub fn settings(&self) -> &CloudSettings {
+        &self.settings
+    }
+
+    /// Whether a request for `model` on `path` may go to the cloud
+    pub fn covers(&self, path: &str, model: &str) -> bool {
+        FORWARDED_PATHS.contains(&path)
+            && (self.settings.models.is_empty() || self.settings.models.iter().any(|p| model_matches(p, model)))
+    }
+
+    /// Whether a local answer with `status` should be retried in the cloud
+    pub fn should_retry(&self, status: StatusCode) -> bool {
+        self.settings.on_error && (status.is_server_error() || status == StatusCode::NOT_FOUND)
+    }
+
+    /// Send the client's original OpenAI request to the cloud and pass its answer
+    /// (streamed or not) back through the same output filters as a local one
+    pub async fn forward(&self, path: &str, body: bytes::Bytes, filters: OutputFilters) -> Response<Body> {
+        let url = format!("{}{}", self.settings.url, path.strip_prefix("/v1").unwrap_or(path));
+        info!("☁️  Forwarding to cloud fallback: {}", url);
+        let mut request = self
+            .client
+            .post(&url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(key) = &self.settings.api_key {
+            request = request.bearer_auth(key);
+        }
+        if let Some(timeout) = self.settings.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("❌ Cloud fallback failed: {}", e);
+                let message = format!("Ollama could not serve the request and the cloud fallback failed: {}", e);
+                return openai_error(StatusCode::BAD_GATEWAY, &message, None, Some("cloud_fallback_failed"));
+            }
+        };
+        info!("☁️  Cloud fallback answered {}", response.status());
+
+        // Filtering changes the length of the body
+        let filtering = !filters.is_empty() && response.status().is_success();
+        let mut builder = Response::builder()
+            .status(response.status())
+            .header(UPSTREAM_HEADER, "cloud");
+        for (key, value) in response.headers() {
+            if key != header::TRANSFER_ENCODING
+                && key != header::CONNECTION
+                && !(filtering && key == header::CONTENT_LENGTH)
+            {
+                builder = builder.header(key, value);
+            }
+        }
+        let streaming = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        let body = match (filtering, streaming) {
+            (false, _) => Body::from_stream(response.bytes_stream()),
+            (true, true) => Body::from_stream(scrub_stream(response, filters)),
+            (true, false) => match response.bytes().await {
+                Ok(bytes) => match filters.filter_body(&bytes) {
+                    Some(filtered) => {
+                        info!("🧹 Output filters changed the cloud fallback response");
+                        Body::from(filtered)
+                    }
+                    None => Body::from(bytes),
+                },
+                Err(e) => {
+                    warn!("❌ Failed to read the cloud fallback response: {}", e);
+                    return openai_error(StatusCode::BAD_GATEWAY, "Invalid cloud fallback response", None, None);
+                }
+            },
+        };
+        builder
+            .body(body)
+            .unwrap_or_else(|_| openai_error(StatusCode::BAD_GATEWAY, "Invalid cloud fallback response", None, None))
+    }
+}
+
+/// A streamed (SSE) cloud response with each complete line passed through the scrubber
+fn scrub_stream(
+    response: reqwest::Response,
+    filters: OutputFilters,
+) -> impl futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> {
+    let state = (response.bytes_stream().boxed(), StreamScrubber::new(filters), Vec::new());
+    futures::stream::unfold(Some(state), |state| async move {
+        let (mut stream, mut scrubber, mut buffer) = state?;
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                buffer.extend_from_slice(&chunk);
+                let mut ready = Vec::new();
+                while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=newline).collect();
+                    ready.extend(scrubber.scrub_line(&line));
+                }
+                Some((Ok(bytes::Bytes::from(ready)), Some((stream, scrubber, buffer))))
+            }
+            Some(Err(e)) => Some((Err(e), None)),
+            None if buffer.is_empty() => None,
+            None => Some((Ok(bytes::Bytes::from(scrubber.scrub_line(&buffer))), None)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covers_openai_paths_and_listed_models() {
+        let mut settings = CloudSettings::new("https://api.openai.com/v1/");
+        settings.models = vec!["gpt-*".to_string()];
+        let cloud = CloudFallback::new(settings);
+        assert_eq!(cloud.settings().url, "https://api.openai.com/v1");
+        assert!(cloud.covers("/v1/chat/completions", "gpt-4o-mini"));
+        assert!(!cloud.covers("/v1/chat/completions", "llama3"));
+        assert!(!cloud.covers("/api/chat", "gpt-4o-mini"));
+
+        assert!(cloud.should_retry(StatusCode::BAD_GATEWAY));
+        assert!(cloud.should_retry(StatusCode::NOT_FOUND));
+        assert!(!cloud.should_retry(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_describe_hides_key() {
+        let mut settings = CloudSettings::new("https://api.openai.com/v1");
+        settings.api_key = Some("sk-secret".to_string());
+        assert!(!settings.describe().contains("sk-secret"));
+    }
+}
@@ -0,0 +1,138 @@
+/// In-flight request coalescing ("single-flight") for identical upstream calls
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+type SharedResult<T> = Shared<BoxFuture<'static, T>>;
+
+/// Deduplicates concurrent calls that share the same key.
+///
+/// The first caller for a key starts the work on a background task; callers that
+/// arrive while it is still running wait for the same result instead of issuing
+/// their own upstream request. The key is released as soon as the work finishes,
+/// so later callers always trigger a fresh call.
+pub struct SingleFlight<T: Clone> {
+    inflight: Arc<Mutex<HashMap<String, SharedResult<T>>>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            inflight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Run `work` for `key`, or join an identical call that is already in flight.
+    /// Returns the result and whether this caller joined an existing call.
+    pub async fn run<F>(&self, key: String, work: F) -> (T, bool)
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let (shared, joined) = {
+            let mut inflight = self.inflight.lock().unwrap();
+            if let Some(existing) = inflight.get(&key) {
+                (existing.clone(), true)
+            } else {
+                // Run on a spawned task so the call completes (and the key is released)
+                // even if the caller that started it disconnects.
+                let map = self.inflight.clone();
+                let task_key = key.clone();
+                let handle = tokio::spawn(async move {
+                    let result = work.await;
+                    map.lock().unwrap().remove(&task_key);
+                    result
+                });
+                let shared = async move {
+                    match handle.await {
+                        Ok(result) => result,
+                        Err(e) => std::panic::resume_unwind(e.into_panic()),
+                    }
+                }
+                .boxed()
+                .shared();
+                inflight.insert(key, shared.clone());
+                (shared, false)
+            }
+        };
+
+        (shared.await, joined)
+    }
+
+    /// Number of distinct calls currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.inflight.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_identical_calls_share_one_execution() {
+        let flight = Arc::new(SingleFlight::<u32>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for _ in 0..5 {
+            let flight = flight.clone();
+            let calls = calls.clone();
+            handles.push(tokio::spawn(async move {
+                flight
+                    .run("same".to_string(), async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        42
+                    })
+                    .await
+            }));
+        }
+
+        let mut joined = 0;
+        for handle in handles {
+            let (value, was_joined) = handle.await.unwrap();
+            assert_eq!(value, 42);
+            if was_joined {
+                joined += 1;
+            }
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(joined, 4);
+        assert_eq!(flight.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_different_keys_run_independently() {
+        let flight = SingleFlight::<String>::new();
+
+        let (a, joined_a) = flight.run("a".to_string(), async { "first".to_string() }).await;
+        let (b, joined_b) = flight.run("b".to_string(), async { "second".to_string() }).await;
+
+        assert_eq!(a, "first");
+        assert_eq!(b, "second");
+        assert!(!joined_a);
+        assert!(!joined_b);
+    }
+
+    #[tokio::test]
+    async fn test_key_released_after_completion() {
+        let flight = SingleFlight::<u32>::new();
+
+        let (first, _) = flight.run("k".to_string(), async { 1 }).await;
+        let (second, joined) = flight.run("k".to_string(), async { 2 }).await;
+
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert!(!joined);
+    }
+}
@@ -0,0 +1,332 @@
+/// Prompt compression for prompts that exceed the effective context window
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+use crate::model_metadata::ModelMetadata;
+use crate::modifier::ParameterModifier;
+use crate::tokens::{estimate_request_tokens, estimate_tokens};
+
+/// Common words that carry little information on their own
+const STOPWORDS: &[&str] = &[
+    "a", "about", "actually", "all", "also", "an", "and", "any", "are", "as", "at", "basically", "be", "been",
+    "being", "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "here", "how",
+    "i", "if", "in", "into", "is", "it", "its", "just", "may", "might", "more", "most", "much", "of", "on", "or",
+    "other", "our", "quite", "rather", "really", "should", "so", "some", "such", "than", "that", "the", "their",
+    "them", "then", "there", "these", "they", "this", "those", "to", "very", "was", "we", "were", "what", "when",
+    "which", "while", "who", "will", "with", "would", "you", "your",
+];
+
+/// How oversized prompts are shrunk before Ollama truncates them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PromptCompression {
+    /// Leave prompts alone; Ollama drops the oldest tokens
+    #[default]
+    Off,
+    /// Drop duplicate lines and filler words, then fall back to extractive selection
+    Prune,
+    /// Keep the sentences most relevant to the question, in their original order
+    Extractive,
+}
+
+impl PromptCompression {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" | "none" => Some(Self::Off),
+            "prune" => Some(Self::Prune),
+            "extractive" => Some(Self::Extractive),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Prune => "prune",
+            Self::Extractive => "extractive",
+        }
+    }
+
+    /// Shrink `text` to about `target` tokens, favouring sentences that share terms with `query`
+    pub fn compress(&self, text: &str, target: usize, query: &str) -> String {
+        match self {
+            Self::Off => text.to_string(),
+            Self::Prune => {
+                let deduplicated = drop_duplicate_lines(text);
+                if estimate_tokens(&deduplicated) <= target {
+                    return deduplicated;
+                }
+                let pruned = drop_stopwords(&deduplicated);
+                if estimate_tokens(&pruned) <= target {
+                    return pruned;
+                }
+                select_sentences(&pruned, target, query)
+            }
+            Self::Extractive => select_sentences(text, target, query),
+        }
+    }
+}
+
+/// Compresses chat messages or a generate prompt once they no longer fit in
+/// `num_ctx` minus the room reserved for the answer
+pub struct PromptCompressionModifier(pub PromptCompression);
+
+impl ParameterModifier for PromptCompressionModifier {
+    fn modify(&self, json: &mut Value, metadata: &ModelMetadata, max_context_override: u32) -> bool {
+        if self.0 == PromptCompression::Off || metadata.model_type != "chat" {
+            return false;
+        }
+        let num_ctx = json
+            .get("options")
+            .and_then(|o| o.get("num_ctx"))
+            .and_then(Value::as_u64)
+            .map(|n| n as usize)
+            .unwrap_or(metadata.n_ctx_train.min(max_context_override) as usize);
+        let num_predict = json
+            .get("options")
+            .and_then(|o| o.get("num_predict"))
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as usize;
+        // Leave room for the answer, but never more than half the window
+        let budget = num_ctx - num_predict.min(num_ctx / 2);
+
+        let before = estimate_request_tokens(json);
+        if before <= budget {
+            return false;
+        }
+        info!(
+            "🗜️  Prompt (~{} tokens) exceeds its budget ({} of num_ctx {}), compressing ({})",
+            before, budget, num_ctx, self.0.name()
+        );
+
+        let query = question(json).unwrap_or_default();
+        let mut excess = before - budget;
+        let mut texts = compressible_texts(json);
+        texts.sort_by_key(|text| std::cmp::Reverse(text.as_str().map_or(0, str::len)));
+        for text in texts {
+            if excess == 0 {
+                break;
+            }
+            let Some(original) = text.as_str() else { continue };
+            let (body, tail) = split_question(original);
+            let tokens = estimate_tokens(body);
+            let target = tokens.saturating_sub(excess);
+            let compressed = self.0.compress(body, target, &query);
+            excess = excess.saturating_sub(tokens.saturating_sub(estimate_tokens(&compressed)));
+            *text = Value::String(format!("{}{}", compressed, tail));
+        }
+
+        let after = estimate_request_tokens(json);
+        if after > budget {
+            warn!("⚠️  Compressed prompt is still ~{} tokens over budget, Ollama will truncate it", after - budget);
+        }
+        info!("✏️  Compressed prompt: ~{} → ~{} tokens", before, after);
+        after < before
+    }
+
+    fn name(&self) -> &str {
+        "PromptCompressionModifier"
+    }
+}
+
+/// Text worth compressing: non-system chat messages, or a generate prompt
+fn compressible_texts(json: &mut Value) -> Vec<&mut Value> {
+    if json.get("messages").is_some() {
+        return json
+            .get_mut("messages")
+            .and_then(Value::as_array_mut)
+            .map(|messages| {
+                messages
+                    .iter_mut()
+                    .filter(|m| m.get("role").and_then(Value::as_str) != Some("system"))
+                    .filter_map(|m| m.get_mut("content").filter(|c| c.is_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+    json.get_mut("prompt").filter(|p| p.is_string()).into_iter().collect()
+}
+
+/// The question being asked: the end of the last user message (or the prompt)
+fn question(json: &Value) -> Option<String> {
+    let text = match json.get("messages").and_then(Value::as_array) {
+        Some(messages) => messages
+            .iter()
+            .rev()
+            .find(|m| m.get("role").and_then(Value::as_str) == Some("user"))?
+            .get("content")?
+            .as_str()?,
+        None => json.get("prompt")?.as_str()?,
+    };
+    let (body, tail) = split_question(text);
+    Some(if tail.is_empty() { body } else { tail }.to_string())
+}
+
+/// Split off a short final paragraph (usually the question) that must survive compression
+fn split_question(text: &str) -> (&str, &str) {
+    match text.trim_end().rfind("\n\n") {
+        Some(i) if text.len() - i <= text.len() / 4 => text.split_at(i),
+        _ => (text, ""),
+    }
+}
+
+fn drop_duplicate_lines(text: &str) -> String {
+    let mut seen = HashSet::new();
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| line.is_empty() || seen.insert(line.to_lowercase()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_stopword(word: &str) -> bool {
+    let bare: String = word.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+    STOPWORDS.binary_search(&bare.as_str()).is_ok()
+}
+
+/// Remove filler words, keeping any that end a sentence or clause
+fn drop_stopwords(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            line.split_whitespace()
+                .filter(|word| !is_stopword(word) || word.ends_with(|c: char| c.is_ascii_punctuation()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        let at_boundary = matches!(c, '.' | '!' | '?' | '\n')
+            && text[end..].chars().next().is_none_or(char::is_whitespace);
+        if at_boundary {
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .filter(|w| !is_stopword(w))
+}
+
+/// Keep the highest-scoring sentences that fit in `target` tokens. Terms score
+/// higher when they are rare in the text and much higher when the question uses them.
+fn select_sentences(text: &str, target: usize, query: &str) -> String {
+    let sentences = split_sentences(text);
+    let mut frequency: HashMap<String, usize> = HashMap::new();
+    for term in terms(text) {
+        *frequency.entry(term).or_default() += 1;
+    }
+    let query_terms: HashSet<String> = terms(query).collect();
+
+    let mut ranked: Vec<(usize, f64)> = sentences
+        .iter()
+        .enumerate()
+        .map(|(i, sentence)| {
+            let words: Vec<String> = terms(sentence).collect();
+            let score: f64 = words
+                .iter()
+                .map(|w| {
+                    let rarity = 1.0 / (1.0 + frequency[w] as f64).ln();
+                    if query_terms.contains(w) { rarity + 3.0 } else { rarity }
+                })
+                .sum();
+            (i, score / (words.len() as f64 + 1.0).sqrt())
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut keep = vec![false; sentences.len()];
+    let mut used = 0;
+    for (i, _) in ranked {
+        let tokens = estimate_tokens(sentences[i]) + 1;
+        if used + tokens <= target {
+            keep[i] = true;
+            used += tokens;
+        }
+    }
+    sentences
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(s, _)| *s)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chat_metadata() -> ModelMetadata {
+        ModelMetadata { n_ctx_train: 8192, model_type: "chat".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_stopwords_sorted() {
+        assert!(STOPWORDS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_prune_drops_duplicates_then_filler() {
+        let text = "The build is failing.\nThe build is failing.\nIt is really very slow.";
+        assert_eq!(PromptCompression::Prune.compress(text, 100, ""), "The build is failing.\nIt is really very slow.");
+        assert_eq!(PromptCompression::Prune.compress(text, 7, ""), "build failing.\nslow.");
+    }
+
+    #[test]
+    fn test_extractive_keeps_relevant_sentences_in_order() {
+        let text = "Paris is the capital of France. The weather was nice. Rust has no garbage collector. \
+                    The Eiffel tower is in Paris.";
+        let kept = PromptCompression::Extractive.compress(text, 20, "What is in Paris?");
+        assert_eq!(kept, "Paris is the capital of France. The Eiffel tower is in Paris.");
+    }
+
+    #[test]
+    fn test_modifier_compresses_oversized_chat() {
+        let document = (0..400)
+            .map(|i| format!("Filler sentence number {} about nothing.", i))
+            .chain(std::iter::once("The secret code is 4711.".to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut request = json!({
+            "model": "llama3",
+            "messages": [
+                {"role": "system", "content": "Answer briefly."},
+                {"role": "user", "content": format!("{}\n\nWhat is the secret code?", document)}
+            ],
+            "options": {"num_ctx": 4096, "num_predict": 512}
+        });
+        let modifier = PromptCompressionModifier(PromptCompression::Extractive);
+        assert!(modifier.modify(&mut request, &chat_metadata(), 16384));
+
+        assert!(estimate_request_tokens(&request) <= 4096 - 512);
+        let content = request["messages"][1]["content"].as_str().unwrap();
+        assert!(content.contains("The secret code is 4711."));
+        assert!(content.ends_with("\n\nWhat is the secret code?"));
+        assert_eq!(request["messages"][0]["content"], "Answer briefly.");
+
+        // Small prompts and disabled compression are left alone
+        let mut small = json!({"model": "llama3", "prompt": "hi", "options": {"num_ctx": 4096}});
+        assert!(!modifier.modify(&mut small, &chat_metadata(), 16384));
+        assert!(!PromptCompressionModifier(PromptCompression::Off).modify(&mut request, &chat_metadata(), 16384));
+    }
+}
@@ -0,0 +1,400 @@
+/// Caps on concurrent upstream inference requests (overall and per model), with a bounded queue for the excess
+use axum::{
+    body::Body,
+    http::{header, Response, StatusCode},
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::info;
+
+use crate::timing;
+
+/// How many inference requests may reach Ollama at once, and how the rest wait
+#[derive(Debug, Clone, Copy)]
+pub struct ConcurrencyLimits {
+    /// Most requests sent to Ollama at the same time (0 = unlimited)
+    pub max_concurrent: usize,
+    /// Most requests waiting for a slot; further ones are refused with 503 (0 = unbounded)
+    pub max_queued: usize,
+    /// Refuse a queued request once it has waited this long (None = wait indefinitely)
+    pub queue_timeout: Option<Duration>,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 0,
+            max_queued: 100,
+            queue_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl ConcurrencyLimits {
+    pub fn is_enabled(&self) -> bool {
+        self.max_concurrent > 0
+    }
+}
+
+/// Why a request could not get an upstream slot
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueRejection {
+    /// The queue already held `queued` requests
+    Full { queued: usize },
+    /// No slot freed up within the queue timeout
+    TimedOut { waited: Duration },
+}
+
+/// Hands out upstream slots, queueing requests while all of them are taken
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    limits: ConcurrencyLimits,
+    slots: Option<Arc<Semaphore>>,
+    /// Slots of models with their own `max_concurrent`, and that limit
+    model_slots: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+    queued: AtomicUsize,
+    /// Requests that had to wait, and their total wait
+    waited: AtomicU64,
+    wait_micros: AtomicU64,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(limits: ConcurrencyLimits) -> Self {
+        Self {
+            limits,
+            slots: limits.is_enabled().then(|| Arc::new(Semaphore::new(limits.max_concurrent))),
+            model_slots: Mutex::new(HashMap::new()),
+            queued: AtomicUsize::new(0),
+            waited: AtomicU64::new(0),
+            wait_micros: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> ConcurrencyLimits {
+        self.limits
+    }
+
+    /// Requests currently holding a slot
+    pub fn in_flight(&self) -> usize {
+        self.slots
+            .as_ref()
+            .map_or(0, |slots| self.limits.max_concurrent - slots.available_permits())
+    }
+
+    /// Requests currently waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Average time queued requests waited for their slot
+    pub fn average_wait(&self) -> Duration {
+        let waited = self.waited.load(Ordering::Relaxed);
+        if waited == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_micros(self.wait_micros.load(Ordering::Relaxed) / waited)
+    }
+
+    /// Most requests for `model` sent upstream at once: its own limit or the global
+    /// one, whichever is lower (None when neither applies)
+    pub fn slots_limit(&self, model_limit: Option<usize>) -> Option<usize> {
+        let global = Some(self.limits.max_concurrent).filter(|&limit| limit > 0);
+        match (model_limit.filter(|&limit| limit > 0), global) {
+            (Some(model), Some(global)) => Some(model.min(global)),
+            (model, global) => model.or(global),
+        }
+    }
+
+    /// Slots for `model`, created on first use with `limit` of them
+    fn slots_for(&self, model: &str, limit: usize) -> Arc<Semaphore> {
+        let mut models = self.model_slots.lock().unwrap();
+        models
+            .entry(model.to_string())
+            .or_insert_with(|| (limit, Arc::new(Semaphore::new(limit))))
+            .1
+            .clone()
+    }
+
+    /// The model's slots (when it has a limit of its own) followed by the global ones
+    fn queues_for(&self, model: &str, model_limit: Option<usize>) -> Vec<Arc<Semaphore>> {
+        model_limit
+            .filter(|&limit| limit > 0)
+            .map(|limit| self.slots_for(model, limit))
+            .into_iter()
+            .chain(self.slots.clone())
+            .collect()
+    }
+
+    /// Take whichever of `queues` have a free slot right away, stopping at the first that doesn't
+    fn take_free(queues: &[Arc<Semaphore>]) -> Vec<OwnedSemaphorePermit> {
+        let mut permits = Vec::with_capacity(queues.len());
+        for slots in queues {
+            match slots.clone().try_acquire_owned() {
+                Ok(permit) => permits.push(permit),
+                Err(_) => break,
+            }
+        }
+        permits
+    }
+
+    /// Take an upstream slot for `model` only if one is free now, without queueing
+    /// (for background work such as shadow requests). Fails as `Full` otherwise.
+    pub fn try_acquire(&self, model: &str, model_limit: Option<usize>) -> Result<Option<ConcurrencyPermit>, QueueRejection> {
+        let queues = self.queues_for(model, model_limit);
+        if queues.is_empty() {
+            return Ok(None);
+        }
+        let permits = Self::take_free(&queues);
+        if permits.len() < queues.len() {
+            return Err(QueueRejection::Full { queued: self.queued() });
+        }
+        Ok(Some(ConcurrencyPermit { _permits: permits }))
+    }
+
+    /// Wait for an upstream slot for `model`, which may be limited to `model_limit`
+    /// requests of its own (None when no limit applies). The model's slot is taken
+    /// before the global one, so requests held back by their model don't block
+    /// other models. Slots are released when the returned permit is dropped.
+    pub async fn acquire(&self, model: &str, model_limit: Option<usize>) -> Result<Option<ConcurrencyPermit>, QueueRejection> {
+        let queues = self.queues_for(model, model_limit);
+        if queues.is_empty() {
+            return Ok(None);
+        }
+        let mut permits = Self::take_free(&queues);
+        if permits.len() == queues.len() {
+            return Ok(Some(ConcurrencyPermit { _permits: permits }));
+        }
+
+        let ahead = self.queued.fetch_add(1, Ordering::Relaxed);
+        // Leaves the queue however the wait ends, including when the client hangs up
+        let _waiting = QueuedGuard(&self.queued);
+        if self.limits.max_queued > 0 && ahead >= self.limits.max_queued {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(QueueRejection::Full { queued: ahead });
+        }
+
+        info!("⏳ No free upstream slot for {}, queued behind {} request(s)", model, ahead);
+        let started = Instant::now();
+        let remaining = queues[permits.len()..].to_vec();
+        let wait = async move {
+            for slots in remaining {
+                permits.push(slots.acquire_owned().await.expect("Concurrency semaphore is never closed"));
+            }
+            permits
+        };
+        let permits = match self.limits.queue_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+                Ok(permits) => permits,
+                Err(_) => {
+                    self.timed_out.fetch_add(1, Ordering::Relaxed);
+                    return Err(QueueRejection::TimedOut { waited: started.elapsed() });
+                }
+            },
+            None => wait.await,
+        };
+
+        self.waited.fetch_add(1, Ordering::Relaxed);
+        self.wait_micros.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        timing::record("queue_wait", started);
+        Ok(Some(ConcurrencyPermit { _permits: permits }))
+    }
+
+    /// Prometheus text for slot usage, queue length and queue waits
+    pub fn render_metrics(&self) -> String {
+        let models = self.model_slots.lock().unwrap();
+        if !self.limits.is_enabled() && models.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP ollama_proxy_upstream_in_flight Inference requests holding an upstream slot");
+        let _ = writeln!(out, "# TYPE ollama_proxy_upstream_in_flight gauge");
+        let _ = writeln!(out, "ollama_proxy_upstream_in_flight {}", self.in_flight());
+        let mut models: Vec<_> = models.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+        let _ = writeln!(out, "# HELP ollama_proxy_model_in_flight Requests holding a slot of a model with its own concurrency limit");
+        let _ = writeln!(out, "# TYPE ollama_proxy_model_in_flight gauge");
+        for (model, (limit, slots)) in models {
+            let _ = writeln!(out, "ollama_proxy_model_in_flight{{model=\"{}\"}} {}", model, limit - slots.available_permits());
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_upstream_queued Inference requests waiting for an upstream slot");
+        let _ = writeln!(out, "# TYPE ollama_proxy_upstream_queued gauge");
+        let _ = writeln!(out, "ollama_proxy_upstream_queued {}", self.queued());
+        let _ = writeln!(out, "# HELP ollama_proxy_queue_wait_seconds Time requests waited for an upstream slot");
+        let _ = writeln!(out, "# TYPE ollama_proxy_queue_wait_seconds summary");
+        let _ = writeln!(
+            out,
+            "ollama_proxy_queue_wait_seconds_sum {}",
+            self.wait_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(out, "ollama_proxy_queue_wait_seconds_count {}", self.waited.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP ollama_proxy_queue_rejections_total Requests refused because the upstream queue was full or too slow");
+        let _ = writeln!(out, "# TYPE ollama_proxy_queue_rejections_total counter");
+        let _ = writeln!(out, "ollama_proxy_queue_rejections_total{{reason=\"full\"}} {}", self.rejected.load(Ordering::Relaxed));
+        let _ = writeln!(out, "ollama_proxy_queue_rejections_total{{reason=\"timeout\"}} {}", self.timed_out.load(Ordering::Relaxed));
+        out
+    }
+
+    /// 503 response with Retry-After for a request that didn't get a slot
+    pub fn rejection_response(&self, rejection: &QueueRejection) -> Response<Body> {
+        let (message, code) = match rejection {
+            QueueRejection::Full { queued } => (
+                format!("Upstream queue is full ({} requests waiting for a slot)", queued),
+                "queue_full",
+            ),
+            QueueRejection::TimedOut { waited } => (
+                format!("No upstream slot became free within {:.1}s", waited.as_secs_f64()),
+                "queue_timeout",
+            ),
+        };
+        let retry_after = self.average_wait().as_secs_f64().ceil().max(1.0) as u64;
+        let body = serde_json::json!({
+            "error": {
+                "message": message,
+                "type": "server_overloaded",
+                "code": code,
+                "queued": self.queued(),
+                "in_flight": self.in_flight(),
+                "max_concurrent": self.limits.max_concurrent,
+            }
+        });
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::RETRY_AFTER, retry_after.to_string())
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
+
+/// Upstream slots (the model's and the global one), held until dropped
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    _permits: Vec<OwnedSemaphorePermit>,
+}
+
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_concurrent: usize, max_queued: usize, queue_timeout: Option<Duration>) -> Arc<ConcurrencyLimiter> {
+        Arc::new(ConcurrencyLimiter::new(ConcurrencyLimits { max_concurrent, max_queued, queue_timeout }))
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_by_default() {
+        let limiter = ConcurrencyLimiter::new(ConcurrencyLimits::default());
+        assert!(limiter.acquire("llama3", None).await.unwrap().is_none());
+        assert!(limiter.render_metrics().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_excess_requests_wait_for_a_slot() {
+        let limiter = limiter(1, 4, None);
+        let first = limiter.acquire("llama3", None).await.unwrap();
+        assert_eq!(limiter.in_flight(), 1);
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("llama3", None).await.map(|permit| permit.is_some()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limiter.queued(), 1);
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap(), Ok(true));
+        assert_eq!(limiter.queued(), 0);
+        assert_eq!(limiter.in_flight(), 0);
+        assert!(limiter.render_metrics().contains("ollama_proxy_queue_wait_seconds_count 1"));
+    }
+
+    #[test]
+    fn test_slots_limit_is_the_lower_of_both() {
+        let limiter = limiter(4, 0, None);
+        assert_eq!(limiter.slots_limit(None), Some(4));
+        assert_eq!(limiter.slots_limit(Some(1)), Some(1));
+        assert_eq!(limiter.slots_limit(Some(8)), Some(4));
+        let unlimited = ConcurrencyLimiter::new(ConcurrencyLimits::default());
+        assert_eq!(unlimited.slots_limit(None), None);
+        assert_eq!(unlimited.slots_limit(Some(2)), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_never_queues() {
+        let limiter = limiter(1, 4, None);
+        let first = limiter.try_acquire("llama3", None).unwrap();
+        assert!(first.is_some());
+        assert_eq!(limiter.try_acquire("llama3", None).unwrap_err(), QueueRejection::Full { queued: 0 });
+        assert_eq!(limiter.queued(), 0);
+        drop(first);
+        assert!(limiter.try_acquire("llama3", None).unwrap().is_some());
+        assert!(ConcurrencyLimiter::new(ConcurrencyLimits::default()).try_acquire("llama3", None).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_is_rejected() {
+        let limiter = limiter(1, 1, None);
+        let _held = limiter.acquire("llama3", None).await.unwrap();
+        let _queued = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("llama3", None).await.is_ok() }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let rejection = limiter.acquire("llama3", None).await.unwrap_err();
+        assert_eq!(rejection, QueueRejection::Full { queued: 1 });
+        let response = limiter.rejection_response(&rejection);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(limiter.queued(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_model_limit_serializes_one_model() {
+        let limiter = limiter(0, 0, Some(Duration::from_millis(20)));
+        let big = limiter.acquire("llama3.3:70b", Some(1)).await.unwrap();
+        assert!(big.is_some());
+        // The 70B model is serialized; an embedding model with 8 slots is unaffected
+        assert!(matches!(limiter.acquire("llama3.3:70b", Some(1)).await, Err(QueueRejection::TimedOut { .. })));
+        let embeds: Vec<_> = futures::future::join_all((0..8).map(|_| limiter.acquire("nomic-embed-text", Some(8)))).await;
+        assert!(embeds.iter().all(|permit| matches!(permit, Ok(Some(_)))));
+        assert!(limiter.render_metrics().contains("ollama_proxy_model_in_flight{model=\"nomic-embed-text\"} 8"));
+
+        drop(big);
+        assert!(limiter.acquire("llama3.3:70b", Some(1)).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_model_wait_does_not_hold_a_global_slot() {
+        let limiter = limiter(2, 0, Some(Duration::from_millis(20)));
+        let _held = limiter.acquire("big", Some(1)).await.unwrap();
+        assert!(limiter.acquire("big", Some(1)).await.is_err());
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.acquire("small", None).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let limiter = limiter(1, 0, Some(Duration::from_millis(20)));
+        let _held = limiter.acquire("llama3", None).await.unwrap();
+        assert!(matches!(limiter.acquire("llama3", None).await, Err(QueueRejection::TimedOut { .. })));
+        assert_eq!(limiter.queued(), 0);
+        assert!(limiter.render_metrics().contains("ollama_proxy_queue_rejections_total{reason=\"timeout\"} 1"));
+    }
+}
@@ -0,0 +1,1227 @@
+/// Typed proxy configuration, loadable from environment variables and a config file
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::access_log::{AccessLogFormat, LogDestination};
+use crate::admission::{SaturationLimits, ShedPolicy};
+use crate::auth::{ApiKey, ApiKeys};
+use crate::backends::{BackendPool, Placement, PlacementStrategy, ProbeThresholds};
+use crate::builder::ProxyBuilder;
+use crate::cache::CacheLimits;
+use crate::chunker::{Aggregation, ChunkingStrategy};
+use crate::cloud::CloudSettings;
+use crate::compression::PromptCompression;
+use crate::concurrency::ConcurrencyLimits;
+use crate::dimensions::DimensionPolicy;
+use crate::filters::OutputFilters;
+use crate::health::HealthCheck;
+use crate::hedge::HedgePolicy;
+use crate::inventory::{MissingModel, ModelRouting};
+use crate::ipfilter::{IpFilter, IpNet};
+use crate::jobs::JobSettings;
+use crate::jwt::JwtSettings;
+use crate::keystore::KeyStore;
+use crate::latency::AdaptiveTimeouts;
+use crate::limits::LimitStore;
+use crate::model_metadata::MetadataPrefetch;
+use crate::overrides::ModelOverrides;
+use crate::penalties::PenaltyMapping;
+use crate::preprocess::Preprocess;
+use crate::proxy::StreamBatching;
+use crate::ratelimit::RateLimits;
+use crate::redact::{BodyLogging, LogBody};
+use crate::retry::{RestartRetry, SaturationRetry};
+use crate::routing::{CanaryRoutes, DefaultModels, PromptRoutes};
+use crate::schedule::PrewarmSchedule;
+use crate::shadow::ShadowSettings;
+use crate::structured::StructuredFailure;
+use crate::timeouts::{EndpointTimeouts, StreamTimeouts};
+use crate::tls::{self, TlsSettings};
+use crate::unsupported::UnsupportedPolicy;
+use crate::upstream::{self, UpstreamOptions, UpstreamProxy};
+
+/// Environment variable naming an optional TOML config file
+pub const CONFIG_FILE_ENV: &str = "PROXY_CONFIG";
+
+/// Everything needed to run the proxy. Build one with [`ProxyConfig::builder`],
+/// or load it from the environment with [`ProxyConfig::from_env`].
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Base URL of the primary Ollama server (`http://localhost` for Unix sockets)
+    pub ollama_host: String,
+    /// Address the binary listens on
+    pub listen_addr: String,
+    /// Serve HTTPS with this certificate and key instead of plain HTTP
+    pub tls: Option<TlsSettings>,
+    /// Async worker threads of the binary's runtime (0 = one per CPU core)
+    pub worker_threads: usize,
+    /// Blocking threads of the binary's runtime, used for heavy JSON work
+    pub max_blocking_threads: usize,
+    pub max_embedding_input_length: usize,
+    pub enable_auto_chunking: bool,
+    /// Most chunks one embeddings request may be split into (0 = no limit)
+    pub max_chunks_per_request: usize,
+    /// How long embedding inputs are split
+    pub chunking: ChunkingStrategy,
+    /// How a chunked input's embeddings are combined
+    pub chunk_aggregation: Aggregation,
+    /// Return one embedding per chunk (with offsets) instead of combining them
+    pub return_chunk_embeddings: bool,
+    /// Limits for the embedding cache (disabled by default)
+    pub embedding_cache: CacheLimits,
+    /// Markup stripping applied to embedding inputs before chunking
+    pub embedding_preprocess: Preprocess,
+    /// Models whose embeddings may be truncated to the requested `dimensions`
+    pub embed_dimensions: DimensionPolicy,
+    /// Serve deprecated /api/embeddings requests through the /api/embed chunking path
+    pub translate_legacy_embeddings: bool,
+    /// Hard cap for num_ctx regardless of model support
+    pub max_context_override: u32,
+    /// Age after which cached model metadata is refreshed in the background (None = never)
+    pub metadata_ttl: Option<Duration>,
+    /// Warming of the metadata cache for every installed model
+    pub metadata_prefetch: MetadataPrefetch,
+    /// Per-model replacements for the context cap and modifier defaults
+    pub model_overrides: ModelOverrides,
+    /// What to do with prompts that don't fit in the effective context
+    pub prompt_compression: PromptCompression,
+    /// Connection, TLS, timeout, retry and backend settings for Ollama
+    pub upstream: UpstreamOptions,
+    /// Largest pass-through response held in memory
+    pub max_buffered_response_bytes: usize,
+    pub stream_batching: StreamBatching,
+    /// Send a Server-Timing header with per-phase latencies on proxied responses
+    pub server_timing: bool,
+    /// Keys accepted in `Authorization: Bearer` headers (empty = no authentication)
+    pub api_keys: ApiKeys,
+    /// Per-key limits for keys that don't set their own
+    pub rate_limits: RateLimits,
+    /// JSON file holding keys managed through /proxy/admin/keys (None = kept in memory)
+    pub api_key_store: Option<PathBuf>,
+    /// Accept JWTs from this OIDC issuer as bearer tokens (None = API keys only)
+    pub jwt: Option<JwtSettings>,
+    /// Source addresses allowed to use the proxy (empty lists = everyone)
+    pub ip_filter: IpFilter,
+    /// Where access log lines are written (None = disabled)
+    pub access_log: Option<LogDestination>,
+    pub access_log_format: AccessLogFormat,
+    /// How request/response bodies appear in logs
+    pub body_logging: BodyLogging,
+    /// How OpenAI presence/frequency penalties reach each model
+    pub penalty_mapping: PenaltyMapping,
+    /// Role sent to Ollama for OpenAI `developer` messages (None = unchanged)
+    pub developer_role: Option<String>,
+    /// Download http(s) image URLs in OpenAI vision requests (public addresses only)
+    pub fetch_image_urls: bool,
+    /// Models (or `prefix*` patterns) whose <think> spans are removed from responses
+    pub strip_think_models: Vec<String>,
+    /// Handling of streamed JSON-mode output that fails validation
+    pub structured_failure: StructuredFailure,
+    /// Handling of OpenAI parameters that can't be translated for Ollama
+    pub unsupported_params: UnsupportedPolicy,
+    /// Limits for the non-streaming chat completion cache (disabled by default)
+    pub chat_cache: CacheLimits,
+    pub prompt_routes: PromptRoutes,
+    /// Percentage splits of an alias over model versions
+    pub canary_routes: CanaryRoutes,
+    /// Models filled in for requests that omit one
+    pub default_models: DefaultModels,
+    pub saturation_limits: SaturationLimits,
+    pub shed_policy: ShedPolicy,
+    /// Cap on inference requests sent to Ollama at once, and the queue for the rest
+    pub concurrency_limits: ConcurrencyLimits,
+    /// Where rate limit and quota counters live: `local` or `redis://host:port[/db]`
+    pub limit_store: String,
+    /// Windows during which models are kept loaded
+    pub prewarm_schedule: PrewarmSchedule,
+    /// Async embedding jobs: journal, concurrency and retention
+    pub jobs: JobSettings,
+    /// Masking and replacement rules for generated text
+    pub output_filters: OutputFilters,
+    /// Re-resolve the upstream's DNS name this often (None = disabled)
+    pub dns_refresh: Option<Duration>,
+    /// Background probing of the backends
+    pub health_check: HealthCheck,
+    /// Routing by which backend has a model pulled
+    pub model_routing: ModelRouting,
+    /// OpenAI-compatible upstream for models Ollama lacks or requests it fails
+    pub cloud_fallback: Option<CloudSettings>,
+    /// Mirroring of requests to a candidate model or backend for comparison
+    pub shadow: Option<ShadowSettings>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            ollama_host: "http://127.0.0.1:11434".to_string(),
+            listen_addr: "127.0.0.1:11435".to_string(),
+            tls: None,
+            worker_threads: 0,
+            max_blocking_threads: 512,
+            max_embedding_input_length: 1000,
+            enable_auto_chunking: true,
+            max_chunks_per_request: 0,
+            chunking: ChunkingStrategy::Sentence,
+            chunk_aggregation: Aggregation::Mean,
+            return_chunk_embeddings: false,
+            embedding_cache: CacheLimits { max_entries: 0, max_bytes: 256 * 1024 * 1024, ttl: None },
+            embedding_preprocess: Preprocess::Off,
+            embed_dimensions: DimensionPolicy::default(),
+            translate_legacy_embeddings: true,
+            max_context_override: 16384,
+            metadata_ttl: Some(Duration::from_secs(300)),
+            // Embedders opt in with ProxyBuilder::metadata_prefetch; the binary prefetches by default
+            metadata_prefetch: MetadataPrefetch { enabled: false, ..MetadataPrefetch::default() },
+            model_overrides: ModelOverrides::default(),
+            prompt_compression: PromptCompression::Off,
+            upstream: UpstreamOptions::default(),
+            max_buffered_response_bytes: 64 * 1024 * 1024,
+            stream_batching: StreamBatching::default(),
+            server_timing: true,
+            api_keys: ApiKeys::default(),
+            rate_limits: RateLimits::default(),
+            api_key_store: None,
+            jwt: None,
+            ip_filter: IpFilter::default(),
+            access_log: None,
+            access_log_format: AccessLogFormat::Combined,
+            body_logging: BodyLogging::default(),
+            penalty_mapping: PenaltyMapping::default(),
+            developer_role: Some("system".to_string()),
+            fetch_image_urls: false,
+            strip_think_models: Vec::new(),
+            structured_failure: StructuredFailure::Flag,
+            unsupported_params: UnsupportedPolicy::Warn,
+            chat_cache: CacheLimits {
+                max_entries: 0,
+                max_bytes: 64 * 1024 * 1024,
+                ttl: Some(Duration::from_secs(3600)),
+            },
+            prompt_routes: PromptRoutes::default(),
+            canary_routes: CanaryRoutes::default(),
+            default_models: DefaultModels::default(),
+            saturation_limits: SaturationLimits::default(),
+            shed_policy: ShedPolicy::default(),
+            concurrency_limits: ConcurrencyLimits::default(),
+            limit_store: "local".to_string(),
+            prewarm_schedule: PrewarmSchedule::default(),
+            jobs: JobSettings::default(),
+            output_filters: OutputFilters::default(),
+            dns_refresh: Some(Duration::from_secs(30)),
+            health_check: HealthCheck::default(),
+            model_routing: ModelRouting::default(),
+            cloud_fallback: None,
+            shadow: None,
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Start a config for `ollama_host` (an `http(s)://` URL or `unix:///path/to/ollama.sock`)
+    pub fn builder(ollama_host: &str) -> ProxyBuilder {
+        ProxyBuilder::new(ollama_host)
+    }
+
+    /// Load from environment variables, falling back to the TOML file named by
+    /// `PROXY_CONFIG` (keys are the variable names, in any case), then to defaults
+    pub fn from_env() -> Result<Self, String> {
+        let settings = match env::var(CONFIG_FILE_ENV).ok().filter(|p| !p.is_empty()) {
+            Some(path) => load_file(Path::new(&path))?,
+            None => Settings { file: HashMap::new(), models: ModelOverrides::default() },
+        };
+        Self::from_settings(&settings)
+    }
+
+    fn from_settings(settings: &Settings) -> Result<Self, String> {
+        let defaults = Self::default();
+
+        // unix:///path/to/ollama.sock connects over a local socket instead of TCP
+        let raw_ollama_host = settings
+            .get("OLLAMA_HOST")
+            .unwrap_or_else(|| defaults.ollama_host.clone());
+        let (ollama_host, unix_socket) = upstream::parse_ollama_host(&raw_ollama_host);
+        let proxy_host = settings.get("PROXY_HOST").unwrap_or_else(|| "127.0.0.1".to_string());
+        let proxy_port = settings.get("PROXY_PORT").unwrap_or_else(|| "11435".to_string());
+        // IPv6 hosts need brackets in a socket address
+        let listen_addr = match proxy_host.contains(':') && !proxy_host.starts_with('[') {
+            true => format!("[{}]:{}", proxy_host, proxy_port),
+            false => format!("{}:{}", proxy_host, proxy_port),
+        };
+
+        // HTTPS termination; the certificate is re-read when its files change
+        let tls_cert = settings.get("TLS_CERT").filter(|s| !s.is_empty());
+        let tls = match (tls_cert, settings.get("TLS_KEY").filter(|s| !s.is_empty())) {
+            (Some(cert), Some(key)) => Some(TlsSettings {
+                cert: PathBuf::from(cert),
+                key: PathBuf::from(key),
+                reload_interval: settings.duration_secs("TLS_RELOAD_INTERVAL_SECONDS", 60),
+            }),
+            (None, None) => None,
+            _ => return Err("TLS_CERT and TLS_KEY must be set together".to_string()),
+        };
+
+        // Per-endpoint-class overrides, e.g. "embeddings=30,chat=600,transfer=0"
+        let request_timeout_seconds = settings.parse("REQUEST_TIMEOUT_SECONDS", 120u64);
+        let timeouts = EndpointTimeouts::new(Duration::from_secs(request_timeout_seconds))
+            .with_overrides(&settings.get("ENDPOINT_TIMEOUTS").unwrap_or_default())
+            .map_err(|e| format!("Invalid ENDPOINT_TIMEOUTS: {}", e))?;
+
+        // Streams are bounded by how long they take to start and how long they go quiet
+        // rather than by the request timeout (0 = no limit)
+        let stream_timeouts = StreamTimeouts {
+            first_byte: settings.duration_secs("FIRST_BYTE_TIMEOUT_SECONDS", 300),
+            idle: settings.duration_secs("STREAM_IDLE_TIMEOUT_SECONDS", 60),
+            total: settings.duration_secs("STREAM_TIMEOUT_SECONDS", 0),
+        };
+        // Clients may pick their own timeout per request, up to this (0 = header ignored)
+        let max_request_timeout = settings.duration_secs("MAX_REQUEST_TIMEOUT_SECONDS", 3600);
+
+        // Learn per-model timeouts from observed latency (p99 * factor, clamped)
+        let adaptive = AdaptiveTimeouts::default();
+        let adaptive_timeouts = AdaptiveTimeouts {
+            enabled: settings.flag("ADAPTIVE_TIMEOUTS", false),
+            quantile: settings.parse("ADAPTIVE_TIMEOUT_PERCENTILE", adaptive.quantile * 100.0) / 100.0,
+            factor: settings.parse("ADAPTIVE_TIMEOUT_FACTOR", adaptive.factor),
+            min_samples: settings.parse("ADAPTIVE_TIMEOUT_MIN_SAMPLES", adaptive.min_samples),
+            floor: Duration::from_secs(settings.parse("ADAPTIVE_TIMEOUT_MIN_SECONDS", adaptive.floor.as_secs())),
+            ceiling: Duration::from_secs(settings.parse("ADAPTIVE_TIMEOUT_MAX_SECONDS", adaptive.ceiling.as_secs())),
+        };
+
+        // Backoff when Ollama answers 429/503 (honors Retry-After); opt-in, since the
+        // client may prefer to see the 429/503 and back off itself
+        let saturation_retry = SaturationRetry {
+            enabled: settings.flag("SATURATION_RETRY", false),
+            deadline: Duration::from_secs(settings.parse("SATURATION_RETRY_DEADLINE_SECONDS", 30)),
+            initial_backoff: Duration::from_millis(settings.parse("SATURATION_RETRY_BACKOFF_MS", 500)),
+            // Cold starts of large models routinely take 30-60s (0 = disabled)
+            loading_deadline: Duration::from_secs(settings.parse("MODEL_LOADING_RETRY_SECONDS", 120)),
+        };
+
+        // Reconnect while Ollama restarts instead of failing requests (0 = disabled)
+        let restart_retry = RestartRetry {
+            window: Duration::from_secs(settings.parse("UPSTREAM_RESTART_RETRY_SECONDS", 15)),
+            ..RestartRetry::default()
+        };
+
+        // Additional Ollama backends, and hedging onto them (0 = disabled)
+        let backends = settings.list("OLLAMA_BACKENDS");
+        // Keep each model on one backend, e.g. BACKEND_PLACEMENT=consistent-hash
+        let placement = Placement {
+            strategy: PlacementStrategy::parse(&settings.get("BACKEND_PLACEMENT").unwrap_or_default())
+                .map_err(|e| format!("Invalid BACKEND_PLACEMENT: {}", e))?,
+            pins: Placement::parse_pins(&settings.get("MODEL_PINS").unwrap_or_default())
+                .map_err(|e| format!("Invalid MODEL_PINS: {}", e))?,
+            // Prefer some boxes and spill onto others under load, e.g. BACKEND_TIERS=http://mini:11434=2
+            tiers: Placement::parse_backend_numbers(&settings.get("BACKEND_TIERS").unwrap_or_default())
+                .map_err(|e| format!("Invalid BACKEND_TIERS: {}", e))?,
+            weights: Placement::parse_backend_numbers(&settings.get("BACKEND_WEIGHTS").unwrap_or_default())
+                .map_err(|e| format!("Invalid BACKEND_WEIGHTS: {}", e))?,
+            max_in_flight: settings.parse("BACKEND_MAX_IN_FLIGHT", 0),
+            // Keep each chat on one backend so its prompt cache is reused
+            sticky_conversations: settings.flag("STICKY_CONVERSATIONS", false),
+        };
+        let hedge = HedgePolicy {
+            delay: settings.duration_millis("HEDGE_DELAY_MS", 0),
+            max_chat_body_bytes: settings.parse("HEDGE_CHAT_MAX_BYTES", HedgePolicy::default().max_chat_body_bytes),
+        };
+
+        // Forward proxy for reaching Ollama (defaults to HTTP(S)_PROXY / NO_PROXY from the environment)
+        let proxy = UpstreamProxy::parse(
+            &settings.get("UPSTREAM_PROXY").unwrap_or_default(),
+            settings.get("UPSTREAM_NO_PROXY").as_deref(),
+        )?;
+        // TLS trust for HTTPS upstreams with self-signed or private-CA certificates
+        let ca_certificates = match settings.get("UPSTREAM_CA_BUNDLE").filter(|s| !s.is_empty()) {
+            Some(path) => upstream::load_ca_bundle(Path::new(&path))?,
+            None => Vec::new(),
+        };
+        // Mutual TLS: the certificate the proxy presents to Ollama's gateway
+        let client_cert = settings.get("UPSTREAM_CLIENT_CERT").filter(|s| !s.is_empty());
+        let client_identity = match (client_cert, settings.get("UPSTREAM_CLIENT_KEY").filter(|s| !s.is_empty())) {
+            (Some(cert), Some(key)) => Some(upstream::load_client_identity(Path::new(&cert), Path::new(&key))?),
+            (None, None) => None,
+            _ => return Err("UPSTREAM_CLIENT_CERT and UPSTREAM_CLIENT_KEY must be set together".to_string()),
+        };
+
+        let upstream = UpstreamOptions {
+            timeouts,
+            stream_timeouts,
+            max_request_timeout,
+            adaptive_timeouts,
+            saturation_retry,
+            restart_retry,
+            backends,
+            placement,
+            hedge,
+            // 0 leaves connection setup bounded only by the request timeout
+            connect_timeout: settings.duration_secs("UPSTREAM_CONNECT_TIMEOUT_SECONDS", 10),
+            // 0 keeps idle connections open indefinitely
+            pool_idle_timeout: settings.duration_secs("UPSTREAM_POOL_IDLE_TIMEOUT_SECONDS", 90),
+            pool_max_idle_per_host: settings.parse("UPSTREAM_POOL_MAX_IDLE_PER_HOST", usize::MAX),
+            // 0 disables TCP keep-alive probes
+            tcp_keepalive: settings.duration_secs("UPSTREAM_TCP_KEEPALIVE_SECONDS", 60),
+            unix_socket,
+            proxy,
+            ca_certificates,
+            client_identity,
+            accept_invalid_certs: settings.flag("UPSTREAM_TLS_INSECURE", false),
+        };
+
+        // Prompt-size routing, e.g. "assistant=llama3.2:3b,>16000:qwen2.5:14b"
+        let prompt_routes = PromptRoutes::parse(&settings.get("PROMPT_ROUTES").unwrap_or_default())
+            .map_err(|e| format!("Invalid PROMPT_ROUTES: {}", e))?;
+        // Gradual rollouts, e.g. "assistant=llama3.3@10,llama3.1"
+        let canary_routes = CanaryRoutes::parse(&settings.get("CANARY_ROUTES").unwrap_or_default())
+            .map_err(|e| format!("Invalid CANARY_ROUTES: {}", e))?;
+
+        // Models for clients that don't send one
+        let default_models = DefaultModels {
+            chat: settings.get("DEFAULT_CHAT_MODEL").filter(|m| !m.is_empty()),
+            embed: settings
+                .get("DEFAULT_EMBED_MODEL")
+                .or_else(|| settings.get("DEFAULT_EMBEDDING_MODEL"))
+                .filter(|m| !m.is_empty()),
+        };
+
+        // Pre-warm windows, e.g. "llama3.3:70b@mon-fri 08:00-18:00"
+        let prewarm_schedule = PrewarmSchedule::parse(&settings.get("PREWARM_SCHEDULE").unwrap_or_default())
+            .map_err(|e| format!("Invalid PREWARM_SCHEDULE: {}", e))?;
+
+        let chunking = match settings.get("CHUNKING_STRATEGY") {
+            Some(value) => ChunkingStrategy::parse(&value, settings.get("CHUNK_SEPARATORS").as_deref())
+                .ok_or_else(|| format!("Invalid CHUNKING_STRATEGY '{}', expected sentence, recursive, markdown or code", value))?,
+            None => defaults.chunking,
+        };
+
+        let chunk_aggregation = match settings.get("CHUNK_AGGREGATION") {
+            Some(value) => Aggregation::parse(&value).ok_or_else(|| {
+                format!("Invalid CHUNK_AGGREGATION '{}', expected mean, weighted-mean, max-pool or first-chunk", value)
+            })?,
+            None => defaults.chunk_aggregation,
+        };
+
+        let embedding_preprocess = match settings.get("EMBEDDING_PREPROCESS") {
+            Some(value) => Preprocess::parse(&value)
+                .ok_or_else(|| format!("Invalid EMBEDDING_PREPROCESS '{}', expected off, html, markdown or auto", value))?,
+            None => defaults.embedding_preprocess,
+        };
+
+        let prompt_compression = match settings.get("PROMPT_COMPRESSION") {
+            Some(value) => PromptCompression::parse(&value)
+                .ok_or_else(|| format!("Invalid PROMPT_COMPRESSION '{}', expected off, prune or extractive", value))?,
+            None => defaults.prompt_compression,
+        };
+
+        // Penalty handling, e.g. "direct,llama3*=repeat,phi3=drop"
+        let penalty_mapping = PenaltyMapping::parse(&settings.get("PENALTY_MAPPING").unwrap_or_default())
+            .map_err(|e| format!("Invalid PENALTY_MAPPING: {}", e))?;
+
+        // "keep" forwards developer messages as they are
+        let developer_role = match settings.get("DEVELOPER_ROLE").map(|r| r.trim().to_lowercase()) {
+            Some(role) if role == "keep" => None,
+            Some(role) if role == "system" || role == "user" => Some(role),
+            Some(role) => return Err(format!("Invalid DEVELOPER_ROLE '{}', expected system, user or keep", role)),
+            None => defaults.developer_role,
+        };
+
+        let structured_failure = match settings.get("STRUCTURED_OUTPUT_FAILURE") {
+            Some(value) => StructuredFailure::parse(&value)
+                .ok_or_else(|| format!("Invalid STRUCTURED_OUTPUT_FAILURE '{}', expected flag or retry", value))?,
+            None => defaults.structured_failure,
+        };
+
+        // Keys from the environment and from a file (which can also name them) are combined
+        let mut api_keys: Vec<ApiKey> = settings.list("API_KEYS").iter().map(|key| ApiKey::new(key)).collect();
+        api_keys.extend(settings.list("ADMIN_API_KEYS").iter().map(|key| ApiKey { admin: true, ..ApiKey::new(key) }));
+        if let Some(path) = settings.get("API_KEYS_FILE").filter(|s| !s.is_empty()) {
+            api_keys.extend(ApiKeys::load_file(Path::new(&path))?);
+        }
+
+        // SSO: tokens from the issuer are accepted alongside API keys
+        let jwt = settings.get("JWT_ISSUER").filter(|issuer| !issuer.trim().is_empty()).map(|issuer| {
+            let defaults = JwtSettings::new(&issuer);
+            JwtSettings {
+                jwks_url: settings.get("JWT_JWKS_URL").filter(|url| !url.trim().is_empty()),
+                audience: settings.get("JWT_AUDIENCE").filter(|aud| !aud.trim().is_empty()),
+                tenant_claim: settings.get("JWT_TENANT_CLAIM").unwrap_or(defaults.tenant_claim.clone()),
+                limits_claim: settings.get("JWT_LIMITS_CLAIM").unwrap_or(defaults.limits_claim.clone()),
+                jwks_ttl: settings.duration_secs("JWT_JWKS_TTL_SECONDS", 3600).unwrap_or(defaults.jwks_ttl),
+                ..defaults
+            }
+        });
+
+        // Source-IP filtering; X-Forwarded-For is only believed from TRUSTED_PROXIES
+        let cidrs = |key: &str| {
+            IpNet::parse_list(&settings.get(key).unwrap_or_default()).map_err(|e| format!("Invalid {}: {}", key, e))
+        };
+        let ip_filter = IpFilter {
+            allow: cidrs("IP_ALLOWLIST")?,
+            deny: cidrs("IP_DENYLIST")?,
+            trusted_proxies: cidrs("TRUSTED_PROXIES")?,
+        };
+
+        let body_logging = BodyLogging {
+            policy: match settings.get("LOG_BODY") {
+                Some(value) => LogBody::parse(&value).ok_or_else(|| {
+                    format!("Invalid LOG_BODY '{}', expected none, truncated, hashed or full", value)
+                })?,
+                None => defaults.body_logging.policy,
+            },
+            max_chars: settings.parse("LOG_BODY_MAX_CHARS", defaults.body_logging.max_chars),
+            redact_fields: settings.list("LOG_REDACT_FIELDS"),
+        };
+
+        let unsupported_params = match settings.get("UNSUPPORTED_PARAMS") {
+            Some(value) => UnsupportedPolicy::parse(&value)
+                .ok_or_else(|| format!("Invalid UNSUPPORTED_PARAMS '{}', expected strip, warn or reject", value))?,
+            None => defaults.unsupported_params,
+        };
+
+        // Output scrubbing, e.g. "re:ACME-\d{4}=>[redacted];words:bluebird"
+        let output_filters = OutputFilters::parse(&settings.get("OUTPUT_FILTERS").unwrap_or_default())
+            .map_err(|e| format!("Invalid OUTPUT_FILTERS: {}", e))?;
+
+        // Saturation signaling: reject with 429 instead of queueing work that will time out
+        let saturation_limits = SaturationLimits {
+            max_queue_depth: settings.parse("MAX_QUEUE_DEPTH", 0),
+            // 0 disables the wait-based limit
+            max_estimated_wait: settings.duration_secs("MAX_ESTIMATED_WAIT_SECONDS", 0),
+            parallelism: settings.parse("MODEL_PARALLELISM", 1usize).max(1),
+        };
+
+        // Load shedding under overload, e.g. "background@8,batch@12,embeddings@16,context>8000@16"
+        let shed_policy = ShedPolicy::parse(&settings.get("LOAD_SHED_POLICY").unwrap_or_default())
+            .map_err(|e| format!("Invalid LOAD_SHED_POLICY: {}", e))?;
+
+        // Global concurrency cap; requests beyond it queue instead of piling onto Ollama
+        let concurrency_defaults = ConcurrencyLimits::default();
+        let concurrency_limits = ConcurrencyLimits {
+            max_concurrent: settings.parse("MAX_CONCURRENT_REQUESTS", concurrency_defaults.max_concurrent),
+            max_queued: settings.parse("MAX_QUEUED_REQUESTS", concurrency_defaults.max_queued),
+            // 0 waits for a slot indefinitely
+            queue_timeout: settings.duration_secs("QUEUE_TIMEOUT_SECONDS", 60),
+        };
+
+        // Streaming micro-batching (0 = forward every line immediately)
+        let stream_batching = StreamBatching {
+            flush_interval: settings.duration_millis("STREAM_FLUSH_INTERVAL_MS", 0),
+            flush_bytes: settings.parse("STREAM_FLUSH_BYTES", 0),
+        };
+
+        let config = Self {
+            ollama_host,
+            listen_addr,
+            tls,
+            worker_threads: settings.parse("WORKER_THREADS", defaults.worker_threads),
+            // 0 keeps the default rather than leaving no blocking threads
+            max_blocking_threads: match settings.parse("MAX_BLOCKING_THREADS", defaults.max_blocking_threads) {
+                0 => defaults.max_blocking_threads,
+                n => n,
+            },
+            max_embedding_input_length: settings.parse("MAX_EMBEDDING_INPUT_LENGTH", defaults.max_embedding_input_length),
+            enable_auto_chunking: settings.flag("ENABLE_AUTO_CHUNKING", defaults.enable_auto_chunking),
+            max_chunks_per_request: settings.parse("MAX_CHUNKS_PER_REQUEST", defaults.max_chunks_per_request),
+            chunking,
+            chunk_aggregation,
+            return_chunk_embeddings: settings.flag("RETURN_CHUNK_EMBEDDINGS", defaults.return_chunk_embeddings),
+            // Re-indexing runs embed the same text again and again
+            embedding_cache: CacheLimits {
+                max_entries: settings.parse("EMBED_CACHE_MAX_ENTRIES", defaults.embedding_cache.max_entries),
+                max_bytes: settings.parse("EMBED_CACHE_MAX_BYTES", defaults.embedding_cache.max_bytes),
+                ttl: settings.duration_secs("EMBED_CACHE_TTL_SECONDS", 0),
+            },
+            embedding_preprocess,
+            embed_dimensions: DimensionPolicy {
+                allow: match settings.get("EMBED_DIMENSIONS_MODELS") {
+                    Some(_) => settings.list("EMBED_DIMENSIONS_MODELS"),
+                    None => defaults.embed_dimensions.allow,
+                },
+                deny: settings.list("EMBED_DIMENSIONS_DENY"),
+            },
+            translate_legacy_embeddings: settings.flag("TRANSLATE_LEGACY_EMBEDDINGS", defaults.translate_legacy_embeddings),
+            // Context override configuration (prevents large context stalls)
+            max_context_override: settings.parse("MAX_CONTEXT_OVERRIDE", defaults.max_context_override),
+            metadata_ttl: settings.duration_secs("METADATA_TTL_SECONDS", 300),
+            metadata_prefetch: MetadataPrefetch {
+                enabled: settings.flag("METADATA_PREFETCH", true),
+                interval: settings.duration_secs("METADATA_PREFETCH_INTERVAL_SECONDS", 600),
+            },
+            model_overrides: settings.models.clone(),
+            prompt_compression,
+            upstream,
+            // Buffering configuration (caps memory used per non-streaming response)
+            max_buffered_response_bytes: settings.parse("MAX_BUFFERED_RESPONSE_BYTES", defaults.max_buffered_response_bytes),
+            stream_batching,
+            server_timing: settings.flag("SERVER_TIMING", defaults.server_timing),
+            api_keys: ApiKeys::new(api_keys),
+            jwt,
+            ip_filter,
+            api_key_store: settings.get("API_KEYS_STORE").filter(|path| !path.trim().is_empty()).map(PathBuf::from),
+            rate_limits: RateLimits {
+                requests_per_minute: settings.get("RATE_LIMIT_RPM").and_then(|s| s.trim().parse().ok()),
+                tokens_per_minute: settings.get("RATE_LIMIT_TPM").and_then(|s| s.trim().parse().ok()),
+            },
+            access_log: settings.get("ACCESS_LOG").and_then(|value| LogDestination::parse(&value)),
+            access_log_format: settings
+                .get("ACCESS_LOG_FORMAT")
+                .map_or(defaults.access_log_format, |value| AccessLogFormat::parse(&value)),
+            body_logging,
+            penalty_mapping,
+            developer_role,
+            fetch_image_urls: settings.flag("FETCH_IMAGE_URLS", defaults.fetch_image_urls),
+            // e.g. "deepseek-r1*,qwq" or "*" for every model
+            strip_think_models: settings.list("STRIP_THINK_TAGS"),
+            structured_failure,
+            unsupported_params,
+            // Eval and CI pipelines re-run identical prompts
+            chat_cache: CacheLimits {
+                max_entries: settings.parse("CHAT_CACHE_MAX_ENTRIES", defaults.chat_cache.max_entries),
+                max_bytes: settings.parse("CHAT_CACHE_MAX_BYTES", defaults.chat_cache.max_bytes),
+                ttl: settings.duration_secs("CHAT_CACHE_TTL_SECONDS", 3600),
+            },
+            prompt_routes,
+            canary_routes,
+            default_models,
+            saturation_limits,
+            shed_policy,
+            concurrency_limits,
+            // Share counters between replicas through Redis
+            limit_store: settings.get("LIMIT_STORE").unwrap_or(defaults.limit_store),
+            prewarm_schedule,
+            jobs: JobSettings {
+                journal: settings.get("JOBS_JOURNAL").filter(|path| !path.trim().is_empty()).map(PathBuf::from),
+                concurrency: settings.parse("JOBS_CONCURRENCY", defaults.jobs.concurrency),
+                ttl: settings.duration_secs("JOBS_TTL_SECONDS", 3600),
+            },
+            output_filters,
+            dns_refresh: settings.duration_secs("UPSTREAM_DNS_REFRESH_SECONDS", 30),
+            health_check: HealthCheck {
+                interval: settings.duration_secs("HEALTH_CHECK_INTERVAL_SECONDS", 10),
+                thresholds: ProbeThresholds {
+                    unhealthy: settings.parse("HEALTH_CHECK_UNHEALTHY_THRESHOLD", defaults.health_check.thresholds.unhealthy).max(1),
+                    healthy: settings.parse("HEALTH_CHECK_HEALTHY_THRESHOLD", defaults.health_check.thresholds.healthy).max(1),
+                },
+            },
+            model_routing: ModelRouting {
+                refresh: settings.duration_secs("MODEL_INVENTORY_REFRESH_SECONDS", 30),
+                missing: MissingModel::parse(&settings.get("MISSING_MODEL_POLICY").unwrap_or_default())
+                    .map_err(|e| format!("Invalid MISSING_MODEL_POLICY: {}", e))?,
+            },
+            cloud_fallback: settings.get("CLOUD_FALLBACK_URL").filter(|url| !url.trim().is_empty()).map(|url| {
+                let defaults = CloudSettings::new(&url);
+                CloudSettings {
+                    api_key: settings.get("CLOUD_FALLBACK_API_KEY").filter(|key| !key.is_empty()),
+                    models: settings.list("CLOUD_FALLBACK_MODELS"),
+                    on_error: settings.flag("CLOUD_FALLBACK_ON_ERROR", defaults.on_error),
+                    timeout: settings.duration_secs("CLOUD_FALLBACK_TIMEOUT_SECONDS", 300),
+                    ..defaults
+                }
+            }),
+            // Compare a candidate model on real traffic, e.g. SHADOW_MODEL=llama3.3
+            shadow: {
+                let model = settings.get("SHADOW_MODEL").filter(|m| !m.trim().is_empty());
+                let backend = settings.get("SHADOW_BACKEND").filter(|url| !url.trim().is_empty());
+                (model.is_some() || backend.is_some()).then(|| ShadowSettings {
+                    model,
+                    backend: backend.map(|url| url.trim().trim_end_matches('/').to_string()),
+                    models: settings.list("SHADOW_MODELS"),
+                    sample_percent: settings.parse("SHADOW_SAMPLE_PERCENT", 100),
+                    log: settings
+                        .get("SHADOW_LOG")
+                        .and_then(|value| LogDestination::parse(&value))
+                        .unwrap_or(LogDestination::Stdout),
+                })
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check ranges that would make the proxy unstable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_embedding_input_length < 100 {
+            return Err("MAX_EMBEDDING_INPUT_LENGTH must be at least 100 characters".to_string());
+        }
+        if self.max_context_override < 512 {
+            return Err("MAX_CONTEXT_OVERRIDE must be at least 512 tokens".to_string());
+        }
+        self.model_overrides.validate()?;
+        let adaptive = &self.upstream.adaptive_timeouts;
+        if !(adaptive.quantile > 0.0 && adaptive.quantile <= 1.0) {
+            return Err("ADAPTIVE_TIMEOUT_PERCENTILE must be between 0 and 100".to_string());
+        }
+        if adaptive.factor < 1.0 {
+            return Err("ADAPTIVE_TIMEOUT_FACTOR must be at least 1.0".to_string());
+        }
+        let pool = BackendPool::new(&self.ollama_host, &self.upstream.backends);
+        for (model, url) in &self.upstream.placement.pins {
+            if !pool.urls().contains(&url.as_str()) {
+                return Err(format!("MODEL_PINS pins {} to {}, which is not a configured backend", model, url));
+            }
+        }
+        let placement = &self.upstream.placement;
+        for (setting, numbers) in [("BACKEND_TIERS", &placement.tiers), ("BACKEND_WEIGHTS", &placement.weights)] {
+            if let Some(url) = numbers.keys().find(|url| !pool.urls().contains(&url.as_str())) {
+                return Err(format!("{} lists {}, which is not a configured backend", setting, url));
+            }
+        }
+        LimitStore::parse(&self.limit_store).map_err(|e| format!("Invalid LIMIT_STORE: {}", e))?;
+        KeyStore::open(self.api_key_store.clone())?;
+        if let Some(cloud) = &self.cloud_fallback {
+            if !cloud.url.starts_with("https://") && !cloud.url.starts_with("http://") {
+                return Err(format!("CLOUD_FALLBACK_URL must be an http(s) URL, got '{}'", cloud.url));
+            }
+        }
+        if let Some(shadow) = &self.shadow {
+            if !(1..=100).contains(&shadow.sample_percent) {
+                return Err(format!("SHADOW_SAMPLE_PERCENT must be between 1 and 100, got {}", shadow.sample_percent));
+            }
+            if let Some(url) = shadow.backend.as_ref().filter(|url| !url.starts_with("https://") && !url.starts_with("http://")) {
+                return Err(format!("SHADOW_BACKEND must be an http(s) URL, got '{}'", url));
+            }
+        }
+        if let Some(jwt) = &self.jwt {
+            for url in std::iter::once(&jwt.issuer).chain(&jwt.jwks_url) {
+                if !url.starts_with("https://") && !url.starts_with("http://") {
+                    return Err(format!("JWT_ISSUER and JWT_JWKS_URL must be http(s) URLs, got '{}'", url));
+                }
+            }
+        }
+        if let Some(settings) = &self.tls {
+            tls::load_certified_key(&settings.cert, &settings.key)?;
+        }
+        if self.jobs.concurrency == 0 {
+            return Err("JOBS_CONCURRENCY must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Log the effective configuration at startup
+    pub fn log_summary(&self) {
+        for line in self.summary() {
+            info!("{}", line);
+        }
+        if self.upstream.accept_invalid_certs {
+            warn!("⚠️  UPSTREAM_TLS_INSECURE is set: upstream TLS certificates are NOT verified");
+            warn!("   Only use this for trusted networks; prefer UPSTREAM_CA_BUNDLE for self-signed certs");
+        }
+    }
+
+    /// Worker threads for the binary's runtime, resolving 0 to one per CPU core
+    pub fn runtime_worker_threads(&self) -> usize {
+        match self.worker_threads {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            n => n,
+        }
+    }
+
+    /// The effective configuration, one line per setting (secrets left out)
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        macro_rules! say {
+            ($($arg:tt)*) => { lines.push(format!($($arg)*)) };
+        }
+        let upstream = &self.upstream;
+        match &self.tls {
+            Some(tls) => {
+                say!("Listening on: https://{}", self.listen_addr);
+                match tls.reload_interval {
+                    Some(interval) => {
+                        say!("  TLS certificate: {} (checked for changes every {}s)", tls.cert.display(), interval.as_secs())
+                    }
+                    None => say!("  TLS certificate: {} (not reloaded)", tls.cert.display()),
+                }
+            }
+            None => say!("Listening on: {}", self.listen_addr),
+        }
+        say!(
+            "Runtime: {} worker threads, {} max blocking threads",
+            self.runtime_worker_threads(),
+            self.max_blocking_threads
+        );
+        match &upstream.unix_socket {
+            Some(path) => say!("Proxying to: unix://{}", path.display()),
+            None => say!("Proxying to: {}", self.ollama_host),
+        }
+        match self.api_keys.len() {
+            0 if self.jwt.is_some() => say!("API key authentication: JWTs only"),
+            0 if self.api_key_store.is_some() => say!("API key authentication: managed keys only"),
+            0 => say!("API key authentication: disabled"),
+            n => {
+                say!("API key authentication: {} key(s)", n);
+                say!("  Default rate limits: {}", self.rate_limits.describe());
+                for key in self.api_keys.describe() {
+                    say!("  {}", key);
+                }
+            }
+        }
+        if let Some(path) = &self.api_key_store {
+            say!("  Managed key store: {}", path.display());
+        }
+        if let Some(jwt) = &self.jwt {
+            say!(
+                "  JWT issuer: {} (jwks: {}, audience: {})",
+                jwt.issuer,
+                jwt.jwks_url.as_deref().unwrap_or("discovered"),
+                jwt.audience.as_deref().unwrap_or("any")
+            );
+            say!("  JWT tenant claim: {}, limits claim: {}", jwt.tenant_claim, jwt.limits_claim);
+        }
+        let describe = |nets: &[IpNet]| nets.iter().map(IpNet::to_string).collect::<Vec<_>>().join(", ");
+        if !self.ip_filter.allow.is_empty() {
+            say!("IP allowlist: {}", describe(&self.ip_filter.allow));
+        }
+        if !self.ip_filter.deny.is_empty() {
+            say!("IP denylist: {}", describe(&self.ip_filter.deny));
+        }
+        if !self.ip_filter.trusted_proxies.is_empty() {
+            say!("Trusted proxies (X-Forwarded-For): {}", describe(&self.ip_filter.trusted_proxies));
+        }
+        say!("Chunking config:");
+        say!("  Max embedding input length: {}", self.max_embedding_input_length);
+        say!("  Auto chunking enabled: {}", self.enable_auto_chunking);
+        match self.max_chunks_per_request {
+            0 => say!("  Max chunks per request: unlimited"),
+            max => say!("  Max chunks per request: {}", max),
+        }
+        match &self.chunking {
+            ChunkingStrategy::Recursive(separators) => say!("  Chunking strategy: recursive {:?}", separators),
+            strategy => say!("  Chunking strategy: {}", strategy.name()),
+        }
+        say!("  Chunk aggregation: {}", self.chunk_aggregation.name());
+        say!("  Return chunk embeddings: {}", self.return_chunk_embeddings);
+        if self.embedding_cache.is_enabled() {
+            say!(
+                "  Embedding cache: {} entries, {} bytes, ttl {}",
+                self.embedding_cache.max_entries,
+                self.embedding_cache.max_bytes,
+                describe_duration(self.embedding_cache.ttl)
+            );
+        } else {
+            say!("  Embedding cache: disabled");
+        }
+        say!("  Markup stripping: {}", self.embedding_preprocess.name());
+        say!(
+            "  Dimension truncation: {} (denied: {})",
+            self.embed_dimensions.allow.join(", "),
+            if self.embed_dimensions.deny.is_empty() { "none".to_string() } else { self.embed_dimensions.deny.join(", ") }
+        );
+        say!("  Translate /api/embeddings: {}", self.translate_legacy_embeddings);
+        say!("Context config:");
+        say!("  Max context override: {} (hard cap for stability)", self.max_context_override);
+        match self.metadata_ttl {
+            Some(ttl) => say!("  Model metadata refreshed after {}s", ttl.as_secs()),
+            None => say!("  Model metadata cached until restart"),
+        }
+        match self.metadata_prefetch {
+            MetadataPrefetch { enabled: false, .. } => {}
+            MetadataPrefetch { interval: Some(interval), .. } => {
+                say!("  Metadata prefetch: at startup and every {}s", interval.as_secs())
+            }
+            MetadataPrefetch { interval: None, .. } => say!("  Metadata prefetch: at startup"),
+        }
+        for line in self.model_overrides.describe() {
+            say!("  Model {}", line);
+        }
+        say!("  Prompt compression: {}", self.prompt_compression.name());
+        say!("  Penalty mapping: {}", self.penalty_mapping.describe());
+        say!("  Developer messages sent as: {}", self.developer_role.as_deref().unwrap_or("developer"));
+        say!("  Fetch image URLs: {}", self.fetch_image_urls);
+        if !self.strip_think_models.is_empty() {
+            say!("  Strip <think> blocks for: {}", self.strip_think_models.join(", "));
+        }
+        say!("  Request timeouts: {}", upstream.timeouts.describe());
+        say!("  Stream timeouts: {}", upstream.stream_timeouts.describe());
+        match upstream.max_request_timeout {
+            Some(max) => say!("  X-Request-Timeout: honored up to {}s", max.as_secs()),
+            None => say!("  X-Request-Timeout: ignored"),
+        }
+        let adaptive = &upstream.adaptive_timeouts;
+        if adaptive.enabled {
+            say!(
+                "  Adaptive timeouts: p{} x {} after {} samples (clamped to {:?}-{:?})",
+                adaptive.quantile * 100.0,
+                adaptive.factor,
+                adaptive.min_samples,
+                adaptive.floor,
+                adaptive.ceiling
+            );
+        }
+        let retry = &upstream.saturation_retry;
+        if retry.enabled {
+            say!("  Retry on 429/503: up to {:?} (initial backoff {:?})", retry.deadline, retry.initial_backoff);
+        } else {
+            say!("  Retry on 429/503: disabled");
+        }
+        if retry.loading_deadline.is_zero() {
+            say!("  Retry while a model loads: disabled");
+        } else {
+            say!("  Retry while a model loads: up to {:?}", retry.loading_deadline);
+        }
+        if upstream.restart_retry.window.is_zero() {
+            say!("  Reconnect while Ollama restarts: disabled");
+        } else {
+            say!("  Reconnect while Ollama restarts: up to {:?}", upstream.restart_retry.window);
+        }
+        say!("  Max buffered response: {} bytes", self.max_buffered_response_bytes);
+        say!("Upstream connection config:");
+        say!("  Pool idle timeout: {} (none = never close)", describe_duration(upstream.pool_idle_timeout));
+        say!("  Max idle connections per host: {}", upstream.pool_max_idle_per_host);
+        say!("  TCP keep-alive: {}", describe_duration(upstream.tcp_keepalive));
+        say!("  Connect timeout: {}", describe_duration(upstream.connect_timeout));
+        say!("  DNS refresh: {}", describe_duration(self.dns_refresh));
+        say!("  Forward proxy: {}", upstream.proxy.describe());
+        if !upstream.ca_certificates.is_empty() {
+            say!("  Custom CA certificates: {}", upstream.ca_certificates.len());
+        }
+        if upstream.client_identity.is_some() {
+            say!("  Client certificate: presented to upstream (mutual TLS)");
+        }
+        if upstream.accept_invalid_certs {
+            say!("  TLS certificate verification: disabled");
+        }
+        if !upstream.backends.is_empty() {
+            say!("Additional backends: {}", upstream.backends.join(", "));
+            match upstream.placement.strategy {
+                PlacementStrategy::ConsistentHash => say!("  Placement: consistent hashing by model"),
+                PlacementStrategy::Weighted => say!("  Placement: weighted round-robin"),
+                PlacementStrategy::Failover => {}
+            }
+            let pool = BackendPool::new(&self.ollama_host, &upstream.backends).with_placement(upstream.placement.clone());
+            if !upstream.placement.tiers.is_empty() || !upstream.placement.weights.is_empty() {
+                for state in pool.states() {
+                    say!("  Backend {}: tier {}, weight {}", state.url, state.tier, state.weight);
+                }
+            }
+            if upstream.placement.max_in_flight > 0 {
+                say!("  Tier spills over at {} requests in flight per backend", upstream.placement.max_in_flight);
+            }
+            if upstream.placement.sticky_conversations {
+                say!("  Sticky conversations: by {} header or first user message", crate::backends::CONVERSATION_HEADER);
+            }
+            let mut pins: Vec<_> = upstream.placement.pins.iter().collect();
+            pins.sort();
+            for (model, url) in pins {
+                say!("  Pinned: {} → {}", model, url);
+            }
+            match upstream.hedge.delay {
+                Some(delay) => say!("  Hedging after {:?} (chats and generations up to {} bytes)", delay, upstream.hedge.max_chat_body_bytes),
+                None => say!("  Hedging disabled (set HEDGE_DELAY_MS to enable)"),
+            }
+            let thresholds = self.health_check.thresholds;
+            match self.health_check.interval {
+                Some(interval) => say!(
+                    "  Health checks every {:?} (eject after {} failures, restore after {} passes)",
+                    interval,
+                    thresholds.unhealthy,
+                    thresholds.healthy
+                ),
+                None => say!("  Health checks disabled"),
+            }
+            match self.model_routing.refresh {
+                Some(interval) => say!(
+                    "  Model-aware routing: model lists read every {:?}, missing models: {}",
+                    interval,
+                    self.model_routing.missing.name()
+                ),
+                None => say!("  Model-aware routing disabled"),
+            }
+        }
+        if let Some(cloud) = &self.cloud_fallback {
+            say!("Cloud fallback: {}", cloud.describe());
+        }
+        if let Some(shadow) = &self.shadow {
+            say!("Shadow traffic: {}", shadow.describe());
+        }
+        let limits = &self.saturation_limits;
+        say!("Saturation limits:");
+        say!("  Max queue depth per model: {} (0 = unlimited)", limits.max_queue_depth);
+        say!("  Max estimated wait: {}", describe_duration(limits.max_estimated_wait));
+        say!("  Model parallelism: {}", limits.parallelism);
+        if !self.shed_policy.is_empty() {
+            say!("  Load shedding: {}", self.shed_policy.describe());
+        }
+        let concurrency = &self.concurrency_limits;
+        if concurrency.is_enabled() {
+            say!(
+                "  Max concurrent requests: {} (queue up to {}, timeout {})",
+                concurrency.max_concurrent,
+                match concurrency.max_queued {
+                    0 => "unbounded".to_string(),
+                    max => max.to_string(),
+                },
+                describe_duration(concurrency.queue_timeout)
+            );
+        } else {
+            say!("  Max concurrent requests: unlimited");
+        }
+        if let Ok(store) = LimitStore::parse(&self.limit_store) {
+            say!("  Limit counters: {}", store.describe());
+        }
+        if !self.prewarm_schedule.is_empty() {
+            say!("Pre-warm windows (local time):");
+            for window in self.prewarm_schedule.describe() {
+                say!("  {}", window);
+            }
+        }
+        say!(
+            "Embedding jobs: {} at a time, kept {}, journal {}",
+            self.jobs.concurrency,
+            self.jobs.ttl.map_or("until restart".to_string(), |ttl| format!("for {:?}", ttl)),
+            self.jobs.journal.as_ref().map_or("disabled".to_string(), |path| path.display().to_string())
+        );
+        if !self.output_filters.is_empty() {
+            say!("Output filters:");
+            for rule in self.output_filters.describe() {
+                say!("  {}", rule);
+            }
+        }
+        if let Some(model) = &self.default_models.chat {
+            say!("Default chat model: {}", model);
+        }
+        if let Some(model) = &self.default_models.embed {
+            say!("Default embedding model: {}", model);
+        }
+        if !self.canary_routes.is_empty() {
+            say!("Canary routing:");
+            for route in self.canary_routes.describe() {
+                say!("  {}", route);
+            }
+        }
+        if !self.prompt_routes.is_empty() {
+            say!("Prompt-size routing:");
+            for route in self.prompt_routes.describe() {
+                say!("  {}", route);
+            }
+        }
+        say!("Streaming config:");
+        if self.stream_batching.is_enabled() {
+            say!(
+                "  Flush interval: {}, flush size: {} bytes",
+                describe_duration(self.stream_batching.interval()),
+                self.stream_batching.flush_bytes
+            );
+        } else {
+            say!("  Per-line flushing (batching disabled)");
+        }
+        say!("  Server-Timing header: {}", self.server_timing);
+        match &self.access_log {
+            Some(LogDestination::Stdout) => say!("  Access log: stdout ({})", self.access_log_format.name()),
+            Some(LogDestination::File(path)) => {
+                say!("  Access log: {} ({})", path.display(), self.access_log_format.name())
+            }
+            None => say!("  Access log: disabled"),
+        }
+        match self.body_logging.policy {
+            LogBody::Truncated => say!("  Logged bodies: truncated to {} chars", self.body_logging.max_chars),
+            policy => say!("  Logged bodies: {}", policy.name()),
+        }
+        if !self.body_logging.redact_fields.is_empty() {
+            say!("  Redacted fields: {}", self.body_logging.redact_fields.join(", "));
+        }
+        say!("  Invalid structured output: {}", self.structured_failure.name());
+        say!("  Unsupported OpenAI parameters: {}", self.unsupported_params.name());
+        if self.chat_cache.is_enabled() {
+            say!(
+                "  Chat cache: {} entries, {} bytes, ttl {}",
+                self.chat_cache.max_entries,
+                self.chat_cache.max_bytes,
+                describe_duration(self.chat_cache.ttl)
+            );
+        } else {
+            say!("  Chat cache: disabled");
+        }
+        lines
+    }
+}
+
+fn describe_duration(duration: Option<Duration>) -> String {
+    match duration {
+        Some(d) => format!("{:?}", d),
+        None => "disabled".to_string(),
+    }
+}
+
+/// Read a flat TOML file into upper-cased keys, so `max_context_override = 32768`
+/// and `MAX_CONTEXT_OVERRIDE = 32768` both set MAX_CONTEXT_OVERRIDE.
+/// Arrays become comma-separated lists; tables are left for section-specific loaders.
+fn load_file(path: &Path) -> Result<Settings, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let invalid = |e| format!("Invalid config file {}: {}", path.display(), e);
+    Ok(Settings { file: parse_file(&text).map_err(invalid)?, models: parse_model_sections(&text).map_err(invalid)? })
+}
+
+fn parse_file(text: &str) -> Result<HashMap<String, String>, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let mut values = HashMap::new();
+    for (key, value) in table {
+        let value = match value {
+            toml::Value::Table(_) => continue,
+            toml::Value::Array(items) => items
+                .iter()
+                .map(toml_scalar)
+                .collect::<Vec<_>>()
+                .join(","),
+            other => toml_scalar(&other),
+        };
+        values.insert(key.to_uppercase(), value);
+    }
+    Ok(values)
+}
+
+/// `[models."name"]` sections
+fn parse_model_sections(text: &str) -> Result<ModelOverrides, String> {
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    match table.get("models") {
+        Some(toml::Value::Table(models)) => ModelOverrides::from_toml(models),
+        Some(_) => Err("models must be a table of per-model sections".to_string()),
+        None => Ok(ModelOverrides::default()),
+    }
+}
+
+fn toml_scalar(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Setting lookup: environment variables win over the config file
+struct Settings {
+    file: HashMap<String, String>,
+    /// Per-model sections (file only)
+    models: ModelOverrides,
+}
+
+impl Settings {
+    fn get(&self, key: &str) -> Option<String> {
+        env::var(key).ok().or_else(|| self.file.get(key).cloned())
+    }
+
+    /// Parse a value, keeping `default` when it is unset or malformed
+    fn parse<T: FromStr>(&self, key: &str, default: T) -> T {
+        self.get(key).and_then(|s| s.trim().parse().ok()).unwrap_or(default)
+    }
+
+    /// Booleans accept true/false and 1/0
+    fn flag(&self, key: &str, default: bool) -> bool {
+        match self.get(key) {
+            Some(s) if default => s.to_lowercase() != "false" && s != "0",
+            Some(s) => s.to_lowercase() == "true" || s == "1",
+            None => default,
+        }
+    }
+
+    /// Seconds, where 0 means "disabled"
+    fn duration_secs(&self, key: &str, default: u64) -> Option<Duration> {
+        let seconds = self.parse(key, default);
+        (seconds > 0).then(|| Duration::from_secs(seconds))
+    }
+
+    /// Milliseconds, where 0 means "disabled"
+    fn duration_millis(&self, key: &str, default: u64) -> Option<Duration> {
+        let millis = self.parse(key, default);
+        (millis > 0).then(|| Duration::from_millis(millis))
+    }
+
+    /// Comma-separated list
+    fn list(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(pairs: &[(&str, &str)]) -> Settings {
+        Settings {
+            file: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            models: ModelOverrides::default(),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = ProxyConfig::from_settings(&settings(&[])).unwrap();
+        assert_eq!(config.max_context_override, 16384);
+        assert_eq!(config.max_embedding_input_length, 1000);
+        assert!(config.enable_auto_chunking);
+        assert_eq!(config.dns_refresh, Some(Duration::from_secs(30)));
+        assert!(!config.upstream.saturation_retry.enabled);
+        assert_eq!(config.default_models.embed, None);
+        assert!(!config.concurrency_limits.is_enabled());
+        assert_eq!(config.worker_threads, 0);
+        assert!(config.runtime_worker_threads() > 0);
+        assert_eq!(config.max_blocking_threads, 512);
+        let summary = config.summary();
+        assert_eq!(summary[0], "Listening on: 127.0.0.1:11435");
+        assert!(summary.contains(&"  Chat cache: disabled".to_string()));
+    }
+
+    #[test]
+    fn test_runtime_threads() {
+        let config = ProxyConfig::from_settings(&settings(&[("WORKER_THREADS", "3"), ("MAX_BLOCKING_THREADS", "0")])).unwrap();
+        assert_eq!(config.runtime_worker_threads(), 3);
+        assert_eq!(config.max_blocking_threads, 512);
+        assert!(config.summary().contains(&"Runtime: 3 worker threads, 512 max blocking threads".to_string()));
+    }
+
+    #[test]
+    fn test_saturation_retry_is_opt_in() {
+        let config = ProxyConfig::from_settings(&settings(&[("SATURATION_RETRY", "true")])).unwrap();
+        assert!(config.upstream.saturation_retry.enabled);
+        assert!(config.summary().iter().any(|line| line.starts_with("  Retry on 429/503: up to")));
+    }
+
+    #[test]
+    fn test_default_embedding_model_alias() {
+        let config = ProxyConfig::from_settings(&settings(&[("DEFAULT_EMBEDDING_MODEL", "nomic-embed-text")])).unwrap();
+        assert_eq!(config.default_models.embed.as_deref(), Some("nomic-embed-text"));
+    }
+
+    #[test]
+    fn test_file_settings() {
+        let text = r#"
+            max_context_override = 32768
+            ENABLE_AUTO_CHUNKING = false
+            ollama_backends = ["http://gpu-a:11434", "http://gpu-b:11434"]
+            upstream_dns_refresh_seconds = 0
+
+            [models."llama3.3"]
+            max_context = 8192
+            "#;
+        let file = parse_file(text).unwrap();
+        assert_eq!(file.get("OLLAMA_BACKENDS").unwrap(), "http://gpu-a:11434,http://gpu-b:11434");
+        assert!(!file.contains_key("MODELS"));
+
+        let models = parse_model_sections(text).unwrap();
+        let config = ProxyConfig::from_settings(&Settings { file, models }).unwrap();
+        assert_eq!(config.max_context_override, 32768);
+        assert_eq!(config.model_overrides.for_model("llama3.3").unwrap().max_context, Some(8192));
+        assert!(!config.enable_auto_chunking);
+        assert_eq!(config.upstream.backends.len(), 2);
+        assert_eq!(config.dns_refresh, None);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(ProxyConfig::from_settings(&settings(&[("MAX_CONTEXT_OVERRIDE", "100")])).is_err());
+        assert!(ProxyConfig::from_settings(&settings(&[("ENDPOINT_TIMEOUTS", "bogus=1")])).is_err());
+        assert!(ProxyConfig::from_settings(&settings(&[("LOAD_SHED_POLICY", "everything@1")])).is_err());
+        assert!(ProxyConfig::from_settings(&settings(&[("LIMIT_STORE", "memcached://cache")])).is_err());
+        assert!(ProxyConfig::from_settings(&settings(&[("MODEL_PINS", "llama3=http://elsewhere:11434")])).is_err());
+        assert!(ProxyConfig::from_settings(&settings(&[("LOG_BODY", "some")])).is_err());
+        assert!(parse_file("not toml = = =").is_err());
+    }
+}
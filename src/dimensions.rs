@@ -0,0 +1,98 @@
+/// OpenAI embeddings `dimensions`: Matryoshka-style truncation and renormalization
+use serde_json::Value;
+
+use crate::backends::model_matches;
+use crate::translator::OpenAIEmbeddingsResponse;
+
+/// Which models may be truncated. Truncation only keeps meaning for models
+/// trained with Matryoshka representation learning (e.g. nomic-embed-text v1.5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DimensionPolicy {
+    /// Models or `prefix*` patterns allowed (`*` for every model)
+    pub allow: Vec<String>,
+    /// Models or `prefix*` patterns refused, checked first
+    pub deny: Vec<String>,
+}
+
+impl Default for DimensionPolicy {
+    fn default() -> Self {
+        Self {
+            allow: vec!["*".to_string()],
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl DimensionPolicy {
+    pub fn allows(&self, model: &str) -> bool {
+        !self.deny.iter().any(|pattern| model_matches(pattern, model))
+            && self.allow.iter().any(|pattern| model_matches(pattern, model))
+    }
+}
+
+/// The `dimensions` an embeddings request asks for, if any
+pub fn requested_dimensions(body: &Value) -> Result<Option<usize>, String> {
+    match body.get("dimensions") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => match value.as_u64() {
+            Some(dims) if dims > 0 => Ok(Some(dims as usize)),
+            _ => Err(format!("Invalid dimensions {}, expected a positive integer", value)),
+        },
+    }
+}
+
+/// Keep the first `dims` values and rescale to unit length. Embeddings that
+/// are already no longer than `dims` are left alone.
+pub fn truncate(embedding: &mut Vec<f32>, dims: usize) {
+    if embedding.len() <= dims {
+        return;
+    }
+    embedding.truncate(dims);
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for val in embedding.iter_mut() {
+            *val /= norm;
+        }
+    }
+}
+
+/// Truncate every embedding in a response
+pub fn apply(response: &mut OpenAIEmbeddingsResponse, dims: usize) {
+    for item in &mut response.data {
+        truncate(&mut item.embedding, dims);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_truncate_renormalizes() {
+        let mut embedding = vec![3.0, 4.0, 12.0];
+        truncate(&mut embedding, 2);
+        assert_eq!(embedding, vec![0.6, 0.8]);
+
+        let mut short = vec![3.0, 4.0];
+        truncate(&mut short, 8);
+        assert_eq!(short, vec![3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_policy_and_request() {
+        let policy = DimensionPolicy {
+            allow: vec!["nomic-embed-text*".to_string(), "mxbai-embed-large".to_string()],
+            deny: vec!["nomic-embed-text:v1".to_string()],
+        };
+        assert!(policy.allows("nomic-embed-text:v1.5"));
+        assert!(policy.allows("mxbai-embed-large:latest"));
+        assert!(!policy.allows("nomic-embed-text:v1"));
+        assert!(!policy.allows("all-minilm"));
+        assert!(DimensionPolicy::default().allows("all-minilm"));
+
+        assert_eq!(requested_dimensions(&json!({"dimensions": 256})), Ok(Some(256)));
+        assert_eq!(requested_dimensions(&json!({"input": "x"})), Ok(None));
+        assert!(requested_dimensions(&json!({"dimensions": 0})).is_err());
+    }
+}
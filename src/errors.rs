@@ -0,0 +1,89 @@
+/// Error bodies for errors the proxy itself generates: OpenAI's format on /v1/* paths, Ollama's elsewhere
+use axum::body::Body;
+use axum::http::{header, Response, StatusCode};
+use serde_json::{json, Value};
+
+/// Whether errors on `path` should use OpenAI's `{"error": {...}}` shape
+pub fn is_openai_path(path: &str) -> bool {
+    path.starts_with("/v1/")
+}
+
+/// OpenAI's error `type` for a status
+pub fn error_type(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        429 => "rate_limit_error",
+        400..=499 => "invalid_request_error",
+        _ => "server_error",
+    }
+}
+
+/// `{"error": {"message", "type", "param", "code"}}` response
+pub fn openai_error(status: StatusCode, message: &str, param: Option<&str>, code: Option<&str>) -> Response<Body> {
+    let body = json!({
+        "error": {
+            "message": message,
+            "type": error_type(status),
+            "param": param,
+            "code": code,
+        }
+    });
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Ollama's native `{"error": "..."}` response
+pub fn ollama_error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({"error": message}).to_string()))
+        .unwrap()
+}
+
+/// The message of an OpenAI- or Ollama-format error body
+pub fn error_message(body: &Value) -> Option<&str> {
+    body.pointer("/error/message").or_else(|| body.get("error"))?.as_str()
+}
+
+/// OpenAI-format body for a bare status returned without a more specific message
+pub fn from_status(status: StatusCode) -> Response<Body> {
+    let message = match status {
+        StatusCode::BAD_REQUEST => "The proxy could not parse or translate the request",
+        StatusCode::NOT_FOUND => "Not found",
+        StatusCode::NOT_IMPLEMENTED => "This endpoint is not supported by the proxy",
+        StatusCode::BAD_GATEWAY => "The proxy could not get a valid response from Ollama",
+        StatusCode::GATEWAY_TIMEOUT => "Ollama did not respond before the request timed out",
+        _ => status.canonical_reason().unwrap_or("Proxy error"),
+    };
+    openai_error(status, message, None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_openai_error_shape() {
+        let response = openai_error(StatusCode::BAD_REQUEST, "No model specified", Some("model"), None);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(
+            body,
+            json!({"error": {"message": "No model specified", "type": "invalid_request_error", "param": "model", "code": null}})
+        );
+
+        assert_eq!(error_type(StatusCode::NOT_IMPLEMENTED), "server_error");
+        assert!(is_openai_path("/v1/chat/completions"));
+        assert!(!is_openai_path("/api/chat"));
+
+        assert_eq!(error_message(&body), Some("No model specified"));
+        assert_eq!(error_message(&json!({"error": "model not found"})), Some("model not found"));
+    }
+}
@@ -0,0 +1,440 @@
+/// Response-side output filters that mask or replace matched text in model output
+use regex_automata::meta::Regex;
+use serde_json::{json, Value};
+use tracing::warn;
+
+/// Streamed text without whitespace is held back at most this long waiting for a word to end
+const MAX_HOLDBACK_CHARS: usize = 128;
+
+/// Which requests a rule applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterScope {
+    All,
+    /// Requests for this model (`llama3` also matches `llama3:latest`)
+    Model(String),
+    /// Requests authenticated with this `Authorization: Bearer` token
+    Key(String),
+}
+
+impl FilterScope {
+    fn matches(&self, model: Option<&str>, key: Option<&str>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Model(name) => model.is_some_and(|m| m == name || m.strip_suffix(":latest") == Some(name)),
+            Self::Key(token) => key == Some(token.as_str()),
+        }
+    }
+}
+
+/// What happens to matched text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Replace every matched character with `*`
+    Mask,
+    Replace(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    pub scope: FilterScope,
+    /// Pattern as configured, for logs
+    pub pattern: String,
+    regex: Regex,
+    pub action: FilterAction,
+    /// Most words one match can span, so streams hold back enough text to catch it
+    words: usize,
+}
+
+impl FilterRule {
+    fn apply(&self, text: &str) -> Option<String> {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for m in self.regex.find_iter(text) {
+            if m.is_empty() {
+                continue;
+            }
+            out.push_str(&text[last..m.start()]);
+            match &self.action {
+                FilterAction::Mask => out.push_str(&"*".repeat(text[m.range()].chars().count())),
+                FilterAction::Replace(with) => out.push_str(with),
+            }
+            last = m.end();
+        }
+        if last == 0 {
+            return None;
+        }
+        out.push_str(&text[last..]);
+        Some(out)
+    }
+}
+
+/// Ordered output filters. Rules only touch generated text (`response`,
+/// `message.content`, OpenAI `choices`), never other response fields.
+#[derive(Debug, Clone, Default)]
+pub struct OutputFilters {
+    rules: Vec<FilterRule>,
+}
+
+impl OutputFilters {
+    /// Parse semicolon-separated rules of the form `[model:NAME@|key:TOKEN@]re:REGEX[=>REPLACEMENT]`
+    /// or `words:a,b,c[=>REPLACEMENT]`. Without a replacement, matches are masked with `*`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (scope, rest) = match rule.split_once('@').map(|(scope, rest)| (scope.split_once(':'), rest)) {
+                Some((Some(("model", name)), rest)) => (FilterScope::Model(name.trim().to_string()), rest),
+                Some((Some(("key", token)), rest)) => (FilterScope::Key(token.trim().to_string()), rest),
+                _ => (FilterScope::All, rule),
+            };
+            let (pattern, action) = match rest.rsplit_once("=>") {
+                Some((pattern, with)) => (pattern, FilterAction::Replace(with.trim().to_string())),
+                None => (rest, FilterAction::Mask),
+            };
+            let pattern = pattern.trim();
+            let (source, span) = if let Some(regex) = pattern.strip_prefix("re:") {
+                // Each literal space or \s in the pattern can separate two more words
+                (regex.to_string(), 1 + regex.matches(' ').count() + regex.matches(r"\s").count())
+            } else if let Some(words) = pattern.strip_prefix("words:") {
+                let words: Vec<&str> = words.split(',').map(str::trim).filter(|w| !w.is_empty()).collect();
+                if words.is_empty() {
+                    return Err(format!("No words in filter '{}'", rule));
+                }
+                let span = words.iter().map(|w| w.split_whitespace().count()).max().unwrap_or(1);
+                let words: Vec<String> = words.into_iter().map(escape).collect();
+                (format!(r"(?i)\b(?:{})\b", words.join("|")), span)
+            } else {
+                return Err(format!("Invalid filter '{}', expected re:REGEX or words:a,b", rule));
+            };
+            let regex = Regex::new(&source).map_err(|e| format!("Invalid regex in filter '{}': {}", rule, e))?;
+            rules.push(FilterRule { scope, pattern: pattern.to_string(), regex, action, words: span });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Most words any one match can span
+    fn span_words(&self) -> usize {
+        self.rules.iter().map(|r| r.words).max().unwrap_or(1)
+    }
+
+    /// Move `split` back to the start of any match in `text` it would cut in two
+    fn safe_split(&self, text: &str, mut split: usize) -> usize {
+        while let Some(m) = self
+            .rules
+            .iter()
+            .flat_map(|r| r.regex.find_iter(text))
+            .find(|m| m.start() < split && split < m.end())
+        {
+            split = m.start();
+        }
+        split
+    }
+
+    /// Rules that apply to a request for `model` made with bearer token `key`
+    pub fn for_request(&self, model: Option<&str>, key: Option<&str>) -> Self {
+        Self {
+            rules: self.rules.iter().filter(|r| r.scope.matches(model, key)).cloned().collect(),
+        }
+    }
+
+    /// Human-readable summary for startup logs (key tokens are not printed)
+    pub fn describe(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|r| {
+                let scope = match &r.scope {
+                    FilterScope::All => "all requests".to_string(),
+                    FilterScope::Model(name) => format!("model {}", name),
+                    FilterScope::Key(_) => "one API key".to_string(),
+                };
+                let action = match &r.action {
+                    FilterAction::Mask => "mask".to_string(),
+                    FilterAction::Replace(with) => format!("replace with '{}'", with),
+                };
+                format!("{} → {} ({})", r.pattern, action, scope)
+            })
+            .collect()
+    }
+
+    /// Apply every rule to `text` in order
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let mut current: Option<String> = None;
+        for rule in &self.rules {
+            if let Some(filtered) = rule.apply(current.as_deref().unwrap_or(text)) {
+                current = Some(filtered);
+            }
+        }
+        current
+    }
+
+    /// Filter the generated text in a complete (non-streaming) JSON response
+    pub fn filter_json(&self, json: &mut Value) -> bool {
+        let mut changed = false;
+        for field in text_fields(json) {
+            if let Some(filtered) = field.as_str().and_then(|text| self.apply(text)) {
+                *field = json!(filtered);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Filter a buffered response body: one JSON document, or NDJSON when the
+    /// client didn't ask for `stream: false`. Returns None when nothing changed.
+    pub fn filter_body(&self, body: &[u8]) -> Option<Vec<u8>> {
+        if let Ok(mut json) = serde_json::from_slice::<Value>(body) {
+            return self.filter_json(&mut json).then(|| serde_json::to_vec(&json).ok()).flatten();
+        }
+        let mut scrubber = StreamScrubber::new(self.clone());
+        let filtered: Vec<u8> = body
+            .split_inclusive(|&b| b == b'\n')
+            .flat_map(|line| scrubber.scrub_line(line))
+            .collect();
+        (filtered != body).then_some(filtered)
+    }
+}
+
+/// Filters a streamed response line by line. Text is held back until a word
+/// ends, along with as many words before it as the longest rule can span, so
+/// identifiers and phrases split across tokens are still caught.
+pub struct StreamScrubber {
+    filters: OutputFilters,
+    held: String,
+}
+
+impl StreamScrubber {
+    pub fn new(filters: OutputFilters) -> Self {
+        Self { filters, held: String::new() }
+    }
+
+    /// Filter one NDJSON (or `data: ` SSE) line, returning the line to forward
+    pub fn scrub_line(&mut self, line: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(line);
+        let body = text.trim_end_matches(['\r', '\n']);
+        let (prefix, payload) = match body.strip_prefix("data: ") {
+            Some(payload) => ("data: ", payload),
+            None => ("", body),
+        };
+        let Ok(mut json) = serde_json::from_str::<Value>(payload) else {
+            return line.to_vec();
+        };
+
+        let last = is_final(&json);
+        let mut content = std::mem::take(&mut self.held);
+        if let Some(text) = text_fields(&mut json).into_iter().next().and_then(|f| f.as_str().map(str::to_string)) {
+            content.push_str(&text);
+        }
+        if content.is_empty() {
+            return line.to_vec();
+        }
+
+        // Keep the word in progress and the words a match could still be spanning;
+        // forward the rest, without cutting through a match
+        let split = if last {
+            content.len()
+        } else {
+            match held_words_start(&content, self.filters.span_words()) {
+                Some(i) => self.filters.safe_split(&content, i),
+                None if content.chars().count() > MAX_HOLDBACK_CHARS => content.len(),
+                None => 0,
+            }
+        };
+        self.held = content.split_off(split);
+        let ready = self.filters.apply(&content).unwrap_or(content);
+
+        match content_slot(&mut json, last) {
+            Some(slot) => *slot = json!(ready),
+            None => {
+                if !ready.is_empty() {
+                    warn!("⚠️  Dropping {} bytes of filtered output with nowhere to put them", ready.len());
+                }
+                return line.to_vec();
+            }
+        }
+        format!("{}{}{}", prefix, json, &text[body.len()..]).into_bytes()
+    }
+}
+
+/// Where the last `words` words of `text` start, counting the word in progress
+/// (empty after trailing whitespace). None when `text` has no whitespace at all.
+fn held_words_start(text: &str, words: usize) -> Option<usize> {
+    let after = |i: usize| i + text[i..].chars().next().map_or(1, char::len_utf8);
+    let mut start = after(text.rfind(char::is_whitespace)?);
+    for _ in 1..words {
+        match text[..start].trim_end().rfind(char::is_whitespace) {
+            Some(i) => start = after(i),
+            None => return Some(0),
+        }
+    }
+    Some(start)
+}
+
+/// Whether this is the last line of a stream (Ollama `done`, or an OpenAI finish_reason)
+fn is_final(json: &Value) -> bool {
+    json.get("done").and_then(Value::as_bool).unwrap_or(false)
+        || json
+            .get("choices")
+            .and_then(Value::as_array)
+            .is_some_and(|choices| choices.iter().any(|c| c.get("finish_reason").is_some_and(|r| !r.is_null())))
+}
+
+/// The text field of a streamed line, created on the final line if it is missing
+fn content_slot(json: &mut Value, create: bool) -> Option<&mut Value> {
+    if json.get("response").is_some() {
+        return json.get_mut("response");
+    }
+    if json.get("message").is_some_and(Value::is_object) {
+        let message = json.get_mut("message")?;
+        if create || message.get("content").is_some() {
+            return Some(message.as_object_mut()?.entry("content").or_insert(json!("")));
+        }
+        return None;
+    }
+    let choice = json.get_mut("choices")?.get_mut(0)?;
+    if choice.get("text").is_some() {
+        return choice.get_mut("text");
+    }
+    let delta = choice.get_mut("delta")?;
+    if create || delta.get("content").is_some() {
+        return Some(delta.as_object_mut()?.entry("content").or_insert(json!("")));
+    }
+    None
+}
+
+/// Generated-text fields in an Ollama or OpenAI response
+fn text_fields(json: &mut Value) -> Vec<&mut Value> {
+    let mut fields = Vec::new();
+    let Some(object) = json.as_object_mut() else {
+        return fields;
+    };
+    for (key, value) in object.iter_mut() {
+        match (key.as_str(), value) {
+            ("response", value) => fields.push(value),
+            ("message", Value::Object(message)) => fields.extend(message.get_mut("content")),
+            ("choices", Value::Array(choices)) => {
+                for choice in choices.iter_mut().filter_map(Value::as_object_mut) {
+                    for (key, value) in choice.iter_mut() {
+                        match (key.as_str(), value) {
+                            ("text", value) => fields.push(value),
+                            ("message" | "delta", Value::Object(message)) => fields.extend(message.get_mut("content")),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// Token from an `Authorization: Bearer` header, used to scope rules to an API key
+pub fn bearer_token(headers: &axum::http::HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Escape regex metacharacters in a literal word
+fn escape(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    for c in word.chars() {
+        if r"\.+*?()|[]{}^$#&-~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_scope() {
+        let filters = OutputFilters::parse(
+            r"re:ACME-\d{4}=>[redacted]; words:bluebird,Project.X; model:llama3@words:secret=>X; key:abc@re:foo",
+        )
+        .unwrap();
+        assert_eq!(filters.rules.len(), 4);
+        assert_eq!(filters.for_request(Some("llama3:latest"), None).rules.len(), 3);
+        assert_eq!(filters.for_request(Some("qwen2.5"), Some("abc")).rules.len(), 3);
+        assert_eq!(filters.for_request(None, None).rules.len(), 2);
+
+        assert!(OutputFilters::parse("nope").is_err());
+        assert!(OutputFilters::parse("re:(unclosed").is_err());
+        assert!(OutputFilters::parse("words: , ").is_err());
+    }
+
+    #[test]
+    fn test_mask_and_replace() {
+        let filters = OutputFilters::parse(r"re:ACME-\d{4}=>[redacted]; words:bluebird,project.x").unwrap();
+        assert_eq!(
+            filters.apply("Ticket ACME-1234 for Bluebird and Project.X, not bluebirds").unwrap(),
+            "Ticket [redacted] for ******** and *********, not bluebirds"
+        );
+        assert_eq!(filters.apply("nothing here"), None);
+
+        let mut response = json!({"model": "llama3", "message": {"role": "assistant", "content": "see ACME-0001"}});
+        assert!(filters.filter_json(&mut response));
+        assert_eq!(response["message"]["content"], "see [redacted]");
+        let mut openai = json!({"choices": [{"message": {"content": "bluebird"}}, {"text": "ACME-9999"}]});
+        assert!(filters.filter_json(&mut openai));
+        assert_eq!(openai["choices"][0]["message"]["content"], "********");
+        assert_eq!(openai["choices"][1]["text"], "[redacted]");
+    }
+
+    #[test]
+    fn test_stream_catches_identifiers_split_across_lines() {
+        let filters = OutputFilters::parse(r"re:ACME-\d{4}=>[redacted]").unwrap();
+        let mut scrubber = StreamScrubber::new(filters);
+        let lines = [
+            r#"{"response":"Ticket AC","done":false}"#,
+            r#"{"response":"ME-12","done":false}"#,
+            r#"{"response":"34 is open","done":false}"#,
+            r#"{"response":"","done":true}"#,
+        ];
+        let out: Vec<Value> = lines
+            .iter()
+            .map(|l| serde_json::from_slice(&scrubber.scrub_line(format!("{}\n", l).as_bytes())).unwrap())
+            .collect();
+        let text: String = out.iter().map(|l| l["response"].as_str().unwrap()).collect();
+        assert_eq!(text, "Ticket [redacted] is open");
+        assert_eq!(out[0]["response"], "Ticket ");
+        assert_eq!(out[3]["done"], true);
+        assert_eq!(out[3]["response"], "open");
+
+        let body = b"{\"response\":\"ACME-0001\",\"done\":false}\n{\"response\":\"\",\"done\":true}\n";
+        let filtered = OutputFilters::parse(r"re:ACME-\d{4}").unwrap().filter_body(body).unwrap();
+        assert!(String::from_utf8(filtered).unwrap().contains("*********"));
+    }
+
+    #[test]
+    fn test_stream_catches_phrases_split_across_lines() {
+        let filters = OutputFilters::parse(r"words:Project Falcon=>[redacted]; re:code\s+name\s+\w+").unwrap();
+        let mut scrubber = StreamScrubber::new(filters);
+        let lines = [
+            r#"{"response":"The Project","done":false}"#,
+            r#"{"response":" Fal","done":false}"#,
+            r#"{"response":"con ships under the code","done":false}"#,
+            r#"{"response":" name Osprey","done":false}"#,
+            r#"{"response":" next week","done":false}"#,
+            r#"{"response":".","done":true}"#,
+        ];
+        let out: Vec<Value> = lines
+            .iter()
+            .map(|l| serde_json::from_slice(&scrubber.scrub_line(format!("{}\n", l).as_bytes())).unwrap())
+            .collect();
+        let text: String = out.iter().map(|l| l["response"].as_str().unwrap()).collect();
+        assert_eq!(text, "The [redacted] ships under the **************** next week.");
+        // Only the last three words (the longest span of a rule) are held back
+        assert_eq!(out[2]["response"], "The [redacted] ships ");
+    }
+}
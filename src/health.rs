@@ -0,0 +1,204 @@
+/// Health endpoints: liveness, a detailed upstream report, and active backend health checks
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use futures::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::backends::{BackendPool, BackendState, ProbeThresholds};
+use crate::proxy::ProxyState;
+
+/// How long each upstream probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Background probing of every backend, so a dead one leaves rotation before
+/// requests find out the hard way
+#[derive(Debug, Clone, Copy)]
+pub struct HealthCheck {
+    /// Time between probes (None = only passive tracking of failed requests)
+    pub interval: Option<Duration>,
+    pub thresholds: ProbeThresholds,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(10)),
+            thresholds: ProbeThresholds::default(),
+        }
+    }
+}
+
+/// GET /healthz - the proxy itself is up
+pub async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+/// Overall verdict: every backend answered, some did, or none did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Unavailable,
+}
+
+impl HealthStatus {
+    fn from_probes(reachable: usize, total: usize) -> Self {
+        match reachable {
+            0 => Self::Unavailable,
+            n if n == total => Self::Ok,
+            _ => Self::Degraded,
+        }
+    }
+}
+
+/// Result of probing one backend's `/api/version`
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendHealth {
+    #[serde(flatten)]
+    pub state: BackendState,
+    pub reachable: bool,
+    pub version: Option<String>,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataCacheHealth {
+    pub size: usize,
+    pub models: Vec<String>,
+}
+
+/// Body of `/healthz/details`
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthDetails {
+    pub status: HealthStatus,
+    /// The primary Ollama server (first backend)
+    pub upstream: BackendHealth,
+    /// Models currently loaded on the primary, from `/api/ps`
+    pub loaded_models: Vec<String>,
+    pub metadata_cache: MetadataCacheHealth,
+    pub backends: Vec<BackendHealth>,
+}
+
+/// GET /healthz/details - probe every backend and report their state.
+/// Returns 503 when no backend is reachable, so it also works as a container healthcheck.
+pub async fn details_handler(State(state): State<ProxyState>) -> impl IntoResponse {
+    let client = state.client();
+    let backends = probe_backends(&client, &state.backends).await;
+    let reachable = backends.iter().filter(|b| b.reachable).count();
+    let status = HealthStatus::from_probes(reachable, backends.len());
+
+    let loaded_models = if backends[0].reachable {
+        loaded_models(&client, &backends[0].state.url).await
+    } else {
+        Vec::new()
+    };
+    let models = state.metadata_cache.cached_models().await;
+
+    let details = HealthDetails {
+        status,
+        upstream: backends[0].clone(),
+        loaded_models,
+        metadata_cache: MetadataCacheHealth { size: models.len(), models },
+        backends,
+    };
+    let code = match status {
+        HealthStatus::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, Json(details))
+}
+
+/// GET /proxy/admin/backends - rotation and health check state of every backend, without probing
+pub async fn backends_handler(State(state): State<ProxyState>) -> impl IntoResponse {
+    Json(serde_json::json!({ "backends": state.backends.states() }))
+}
+
+/// Probe every backend's `/api/version` each `interval`, taking backends that keep
+/// failing out of rotation and restoring them once they pass again
+pub fn spawn_health_checks(state: ProxyState, interval: Duration, thresholds: ProbeThresholds) {
+    info!("🩺 Health-checking {} backends every {:?}", state.backends.urls().len(), interval);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            for health in probe_backends(&state.client(), &state.backends).await {
+                let result = match health.error {
+                    Some(error) => Err(error),
+                    None => Ok(()),
+                };
+                state.backends.record_probe(&health.state.url, result, thresholds);
+            }
+        }
+    });
+}
+
+/// Probe every backend in `pool` concurrently
+pub async fn probe_backends(client: &reqwest::Client, pool: &BackendPool) -> Vec<BackendHealth> {
+    join_all(pool.states().into_iter().map(|s| probe(client, s))).await
+}
+
+async fn probe(client: &reqwest::Client, state: BackendState) -> BackendHealth {
+    let started = Instant::now();
+    let result = async {
+        let response = client
+            .get(format!("{}/api/version", state.url))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        Ok(body.get("version").and_then(Value::as_str).map(str::to_string))
+    }
+    .await;
+
+    match result {
+        Ok(version) => BackendHealth {
+            state,
+            reachable: true,
+            version,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            error: None,
+        },
+        Err(error) => BackendHealth { state, reachable: false, version: None, latency_ms: None, error: Some(error) },
+    }
+}
+
+async fn loaded_models(client: &reqwest::Client, url: &str) -> Vec<String> {
+    let response = client.get(format!("{}/api/ps", url)).timeout(PROBE_TIMEOUT).send().await;
+    let body: Value = match response {
+        Ok(response) => response.json().await.unwrap_or(Value::Null),
+        Err(_) => return Vec::new(),
+    };
+    body.get("models")
+        .and_then(Value::as_array)
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|m| m.get("name").and_then(Value::as_str).map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_from_probes() {
+        assert_eq!(HealthStatus::from_probes(2, 2), HealthStatus::Ok);
+        assert_eq!(HealthStatus::from_probes(1, 2), HealthStatus::Degraded);
+        assert_eq!(HealthStatus::from_probes(0, 2), HealthStatus::Unavailable);
+    }
+}
@@ -0,0 +1,128 @@
+/// Hedged requests: race a second backend when the first one is slow
+use std::future::Future;
+use std::time::Duration;
+use tracing::info;
+
+/// When to hedge latency-critical requests onto a second backend
+#[derive(Debug, Clone, Copy)]
+pub struct HedgePolicy {
+    /// Start the second attempt if the first hasn't answered within this delay (None = disabled)
+    pub delay: Option<Duration>,
+    /// Only hedge chat requests whose translated body is at most this many bytes
+    pub max_chat_body_bytes: usize,
+}
+
+impl Default for HedgePolicy {
+    fn default() -> Self {
+        Self {
+            delay: None,
+            max_chat_body_bytes: 8 * 1024,
+        }
+    }
+}
+
+/// Which attempt produced the response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgeWinner {
+    Primary,
+    Hedge,
+}
+
+/// Run `primary`; if it hasn't finished after `delay`, also run `hedge` and return
+/// the first result accepted by `is_success`. The losing attempt is dropped, which
+/// cancels its in-flight upstream request. If neither result is accepted, the
+/// primary's result is returned.
+pub async fn race<T, P, H>(
+    primary: P,
+    hedge: Option<H>,
+    delay: Duration,
+    is_success: impl Fn(&T) -> bool,
+) -> (T, HedgeWinner)
+where
+    P: Future<Output = T>,
+    H: Future<Output = T>,
+{
+    tokio::pin!(primary);
+
+    let hedge = match hedge {
+        Some(hedge) => hedge,
+        None => return (primary.await, HedgeWinner::Primary),
+    };
+
+    tokio::select! {
+        result = &mut primary => return (result, HedgeWinner::Primary),
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    info!("🏁 No response after {:?}, hedging to a second backend", delay);
+    tokio::pin!(hedge);
+
+    tokio::select! {
+        result = &mut primary => {
+            if is_success(&result) {
+                return (result, HedgeWinner::Primary);
+            }
+            let hedged = hedge.await;
+            if is_success(&hedged) {
+                (hedged, HedgeWinner::Hedge)
+            } else {
+                (result, HedgeWinner::Primary)
+            }
+        }
+        hedged = &mut hedge => {
+            if is_success(&hedged) {
+                return (hedged, HedgeWinner::Hedge);
+            }
+            (primary.await, HedgeWinner::Primary)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn after(ms: u64, value: Result<&'static str, ()>) -> Result<&'static str, ()> {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        value
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_skips_hedge() {
+        let (result, winner) = race(
+            after(5, Ok("primary")),
+            Some(after(0, Ok("hedge"))),
+            Duration::from_millis(100),
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(result, Ok("primary"));
+        assert_eq!(winner, HedgeWinner::Primary);
+    }
+
+    #[tokio::test]
+    async fn test_slow_primary_loses_to_hedge() {
+        let (result, winner) = race(
+            after(500, Ok("primary")),
+            Some(after(5, Ok("hedge"))),
+            Duration::from_millis(10),
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(result, Ok("hedge"));
+        assert_eq!(winner, HedgeWinner::Hedge);
+    }
+
+    #[tokio::test]
+    async fn test_failed_hedge_waits_for_primary() {
+        let (result, winner) = race(
+            after(50, Ok("primary")),
+            Some(after(0, Err(()))),
+            Duration::from_millis(10),
+            |r| r.is_ok(),
+        )
+        .await;
+        assert_eq!(result, Ok("primary"));
+        assert_eq!(winner, HedgeWinner::Primary);
+    }
+}
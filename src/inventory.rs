@@ -0,0 +1,173 @@
+/// Model-aware routing: which backend has which model pulled, and what to do when none has it
+use axum::{body::Body, http::Response, http::StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::errors::{is_openai_path, ollama_error, openai_error};
+use crate::proxy::ProxyState;
+
+/// What happens to a request for a model no backend has pulled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingModel {
+    /// Answer 404 right away
+    #[default]
+    Reject,
+    /// Pull the model onto the backend it is placed on, then serve the request
+    Pull,
+}
+
+impl MissingModel {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "reject" => Ok(Self::Reject),
+            "pull" => Ok(Self::Pull),
+            other => Err(format!("Unknown missing model policy '{}', expected reject or pull", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::Pull => "pull",
+        }
+    }
+}
+
+/// How often backend model lists are read, and the policy for missing models
+#[derive(Debug, Clone, Copy)]
+pub struct ModelRouting {
+    /// Time between `/api/tags` polls (None = route without knowing where models are)
+    pub refresh: Option<Duration>,
+    pub missing: MissingModel,
+}
+
+impl Default for ModelRouting {
+    fn default() -> Self {
+        Self {
+            refresh: Some(Duration::from_secs(30)),
+            missing: MissingModel::default(),
+        }
+    }
+}
+
+/// Read every backend's `/api/tags` now and then every `interval`
+pub fn spawn_inventory_refresh(state: ProxyState, interval: Duration) {
+    info!("📚 Reading backend model lists every {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for url in state.backends.urls() {
+                let models = match fetch_models(&state.client(), url).await {
+                    Ok(models) => {
+                        debug!("Backend {} has {} models", url, models.len());
+                        Some(models)
+                    }
+                    Err(e) => {
+                        warn!("⚠️  Failed to read models of {}: {}", url, e);
+                        None
+                    }
+                };
+                state.backends.set_models(url, models);
+            }
+        }
+    });
+}
+
+/// Names from a backend's `/api/tags`
+pub(crate) async fn fetch_models(client: &reqwest::Client, url: &str) -> Result<Vec<String>, String> {
+    let response = client
+        .get(format!("{}/api/tags", url))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let body: Value = response.json().await.map_err(|e| e.to_string())?;
+    let models = body
+        .get("models")
+        .and_then(Value::as_array)
+        .ok_or("No models in /api/tags response")?;
+    Ok(models
+        .iter()
+        .filter_map(|m| m.get("name").or_else(|| m.get("model")).and_then(Value::as_str))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Make sure some backend has `model` before a request for it on `path` is forwarded:
+/// None to go ahead, or the error response when no backend has it and it couldn't be pulled
+pub async fn ensure_available(state: &ProxyState, path: &str, model: &str) -> Option<Response<Body>> {
+    if !state.backends.is_missing(model) {
+        return None;
+    }
+    let error = |status: StatusCode, message: &str| match is_openai_path(path) {
+        true => openai_error(status, message, Some("model"), Some("model_not_found")),
+        false => ollama_error(status, message),
+    };
+
+    match state.model_routing.missing {
+        MissingModel::Reject => {
+            warn!("📭 Model {} is not pulled on any backend, rejecting", model);
+            let message = format!(
+                "Model '{}' is not pulled on any backend ({})",
+                model,
+                state.backends.urls().join(", ")
+            );
+            Some(error(StatusCode::NOT_FOUND, &message))
+        }
+        MissingModel::Pull => {
+            let url = state.backends.for_model(model).url.clone();
+            info!("📥 Model {} is not pulled on any backend, pulling it onto {}", model, url);
+            match pull(&state.client(), &url, model).await {
+                Ok(()) => {
+                    info!("✅ Pulled {} onto {}", model, url);
+                    if let Ok(models) = fetch_models(&state.client(), &url).await {
+                        state.backends.set_models(&url, Some(models));
+                    }
+                    None
+                }
+                Err(e) => {
+                    warn!("❌ Failed to pull {} onto {}: {}", model, url, e);
+                    let message = format!("Model '{}' is not pulled on any backend, and pulling it failed: {}", model, e);
+                    Some(error(StatusCode::BAD_GATEWAY, &message))
+                }
+            }
+        }
+    }
+}
+
+/// Pull `model` onto the backend at `url`, waiting for the download to finish
+async fn pull(client: &reqwest::Client, url: &str, model: &str) -> Result<(), String> {
+    let response = client
+        .post(format!("{}/api/pull", url))
+        .json(&json!({"model": model, "stream": false}))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    if let Some(error) = body.get("error").and_then(Value::as_str) {
+        return Err(error.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_missing_model() {
+        assert_eq!(MissingModel::parse("").unwrap(), MissingModel::Reject);
+        assert_eq!(MissingModel::parse("Pull").unwrap(), MissingModel::Pull);
+        assert!(MissingModel::parse("ignore").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+/// Source-IP filtering: CIDR allow and deny lists, with X-Forwarded-For honored only from trusted proxies
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+
+use crate::errors::{is_openai_path, ollama_error, openai_error};
+use crate::proxy::ProxyState;
+
+/// An address range such as `10.0.0.0/8` or `fd00::/8`; a bare address is a single host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("Invalid IP address in '{}'", value))?;
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| format!("Invalid prefix length in '{}'", value))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                masked(u32::from(net).into(), self.prefix, 32) == masked(u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => masked(net.into(), self.prefix, 128) == masked(ip.into(), self.prefix, 128),
+            _ => false,
+        }
+    }
+
+    /// Comma-separated list
+    pub fn parse_list(value: &str) -> Result<Vec<Self>, String> {
+        value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(Self::parse).collect()
+    }
+}
+
+impl std::fmt::Display for IpNet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+fn masked(bits: u128, prefix: u8, width: u8) -> u128 {
+    match prefix {
+        0 => 0,
+        _ => bits >> (width - prefix),
+    }
+}
+
+/// Which client addresses may use the proxy; empty lists allow everyone
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpFilter {
+    /// Only these ranges may connect (empty = any)
+    pub allow: Vec<IpNet>,
+    /// These ranges are always refused, even when also allowed
+    pub deny: Vec<IpNet>,
+    /// Reverse proxies whose X-Forwarded-For header names the real client
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn is_enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Deny entries win over allow entries
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let matches = |nets: &[IpNet]| nets.iter().any(|net| net.contains(ip));
+        !matches(&self.deny) && (self.allow.is_empty() || matches(&self.allow))
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+
+    /// The address the request came from: the peer itself, or, when the peer is a trusted
+    /// proxy, the last X-Forwarded-For hop that isn't one (earlier hops can be forged)
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        let hops: Vec<Option<IpAddr>> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().parse().ok())
+            .collect();
+        let mut client = peer;
+        // An unparseable hop ends the chain: nothing before it can be attributed
+        for hop in hops.into_iter().rev().map_while(|hop| hop) {
+            client = hop;
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Refuse requests from addresses outside the allow list (or inside the deny list) with a 403
+pub async fn middleware(State(state): State<ProxyState>, request: Request, next: Next) -> Response {
+    let filter = &state.ip_filter;
+    if !filter.is_enabled() {
+        return next.run(request).await;
+    }
+    // Without the peer address (a router served without connect info) nothing can be checked
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| filter.client_ip(peer.ip(), request.headers()));
+    if client.is_some_and(|ip| filter.allows(ip)) {
+        return next.run(request).await;
+    }
+    let client = client.map_or_else(|| "unknown address".to_string(), |ip| ip.to_string());
+    warn!("🚫 Refused {} {} from {}", request.method(), request.uri().path(), client);
+    let message = format!("Requests from {} are not allowed", client);
+    match is_openai_path(request.uri().path()) {
+        true => openai_error(StatusCode::FORBIDDEN, &message, None, Some("ip_not_allowed")),
+        false => ollama_error(StatusCode::FORBIDDEN, &message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ranges() {
+        let net = IpNet::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains(ip("10.20.30.40")));
+        assert!(net.contains(ip("::ffff:10.1.1.1")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(IpNet::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+        assert!(IpNet::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(IpNet::parse("192.168.1.5").unwrap().contains(ip("192.168.1.5")));
+        assert!(!IpNet::parse("192.168.1.5").unwrap().contains(ip("192.168.1.6")));
+        assert!(IpNet::parse("10.0.0.0/33").is_err());
+        assert!(IpNet::parse_list("10.0.0.0/8, bogus").is_err());
+    }
+
+    #[test]
+    fn test_allow_deny_and_forwarded_clients() {
+        let filter = IpFilter {
+            allow: IpNet::parse_list("10.0.0.0/8,192.168.0.0/16").unwrap(),
+            deny: IpNet::parse_list("10.9.0.0/16").unwrap(),
+            trusted_proxies: IpNet::parse_list("127.0.0.1,172.16.0.0/12").unwrap(),
+        };
+        assert!(filter.allows(ip("10.1.2.3")));
+        assert!(!filter.allows(ip("10.9.2.3")));
+        assert!(!filter.allows(ip("8.8.8.8")));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "8.8.8.8, 10.1.2.3, 172.16.0.9".parse().unwrap());
+        // A spoofed first hop is skipped; the closest untrusted hop is the client
+        assert_eq!(filter.client_ip(ip("127.0.0.1"), &headers), ip("10.1.2.3"));
+        // Untrusted peers can't pick their address
+        assert_eq!(filter.client_ip(ip("10.5.5.5"), &headers), ip("10.5.5.5"));
+        assert_eq!(filter.client_ip(ip("127.0.0.1"), &HeaderMap::new()), ip("127.0.0.1"));
+        headers.insert("x-forwarded-for", "10.1.2.3, unknown".parse().unwrap());
+        assert_eq!(filter.client_ip(ip("127.0.0.1"), &headers), ip("127.0.0.1"));
+    }
+}
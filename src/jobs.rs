@@ -0,0 +1,665 @@
+/// Async embedding jobs: submit a large batch, poll its progress, fetch the result later
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tracing::{error, info, warn};
+
+use crate::auth::AuthenticatedKey;
+use crate::chunker::{self, Aggregation};
+use crate::dimensions;
+use crate::errors::openai_error;
+use crate::preprocess::Preprocess;
+use crate::proxy::{self, ProxyState};
+use crate::ratelimit;
+use crate::timeouts::EndpointClass;
+use crate::translator::InputType;
+
+/// Inputs embedded per pipeline call; progress advances in these steps
+const BATCH_INPUTS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobSettings {
+    /// Append-only file recording job state, so a restart can resume pending jobs
+    pub journal: Option<PathBuf>,
+    /// Jobs embedded at the same time
+    pub concurrency: usize,
+    /// How long finished jobs (and their results) are kept (None = until restart)
+    pub ttl: Option<Duration>,
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        Self {
+            journal: None,
+            concurrency: 1,
+            ttl: Some(Duration::from_secs(3600)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    /// Finished before a restart; the result was only held in memory
+    Expired,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Expired)
+    }
+}
+
+/// Per-request choices taken from the submitting request's headers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobOptions {
+    pub preprocess: String,
+    pub aggregation: String,
+    pub return_chunks: bool,
+}
+
+impl JobOptions {
+    fn from_headers(state: &ProxyState, headers: &HeaderMap) -> Self {
+        Self {
+            preprocess: Preprocess::from_headers(headers, state.embedding_preprocess).name().to_string(),
+            aggregation: Aggregation::from_headers(headers, state.chunk_aggregation).name().to_string(),
+            return_chunks: chunker::return_chunks(headers, state.return_chunk_embeddings),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+    /// Inputs embedded so far, out of `total`
+    pub completed: usize,
+    pub total: usize,
+    pub error: Option<String>,
+    /// Name of the API key that submitted the job (None when the proxy doesn't authenticate)
+    pub owner: Option<String>,
+    request: Value,
+    options: JobOptions,
+    result: Option<Arc<Value>>,
+}
+
+impl Job {
+    /// Whether `key` may see the job: only the key that submitted it, when one did
+    pub fn visible_to(&self, key: Option<&str>) -> bool {
+        self.owner.as_deref().is_none_or(|owner| key == Some(owner))
+    }
+
+    /// Status body returned by the jobs API
+    pub fn view(&self) -> Value {
+        let mut view = json!({
+            "id": self.id,
+            "object": "embedding.job",
+            "model": self.request.get("model"),
+            "status": self.status,
+            "created_at": self.created_at,
+            "progress": {"completed": self.completed, "total": self.total},
+        });
+        if let Some(finished_at) = self.finished_at {
+            view["finished_at"] = json!(finished_at);
+        }
+        if let Some(error) = &self.error {
+            view["error"] = json!(error);
+        }
+        view
+    }
+}
+
+/// One journal line: a job's creation (with its request) or a status change
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalRecord {
+    id: String,
+    status: JobStatus,
+    at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    options: Option<JobOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Jobs by id, with an optional on-disk journal of their state changes
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+    /// Lines for the journal writer thread, which appends and syncs them off the async runtime
+    journal: Option<mpsc::Sender<String>>,
+    permits: Arc<Semaphore>,
+    ttl: Option<Duration>,
+}
+
+impl JobStore {
+    /// Store without a journal
+    pub fn new(settings: &JobSettings) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            journal: None,
+            permits: Arc::new(Semaphore::new(settings.concurrency.max(1))),
+            ttl: settings.ttl,
+        }
+    }
+
+    /// Store backed by the configured journal. Jobs still queued or running when the
+    /// journal was last written are queued again (see [`resume`]); finished ones keep
+    /// their status, except that results weren't persisted so succeeded jobs become expired.
+    pub fn open(settings: &JobSettings) -> Result<Self, String> {
+        let mut store = Self::new(settings);
+        let Some(path) = &settings.journal else {
+            return Ok(store);
+        };
+        let jobs = match File::open(path) {
+            Ok(file) => replay(BufReader::new(file), settings.ttl),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(format!("Failed to read job journal {}: {}", path.display(), e)),
+        };
+
+        // Rewrite the journal with only the surviving jobs, then append to it
+        let compacted: String = jobs.values().flat_map(compact_records).map(|line| line + "\n").collect();
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, compacted)
+            .and_then(|_| std::fs::rename(&temp, path))
+            .map_err(|e| format!("Failed to rewrite job journal {}: {}", path.display(), e))?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open job journal {}: {}", path.display(), e))?;
+
+        store.jobs = Mutex::new(jobs);
+        store.journal = Some(spawn_writer(file).map_err(|e| format!("Failed to start job journal writer: {}", e))?);
+        Ok(store)
+    }
+
+    /// Queue a new job for `request`, an OpenAI embeddings body with `total` inputs,
+    /// on behalf of the API key named `owner`
+    pub fn submit(&self, request: Value, options: JobOptions, total: usize, owner: Option<String>) -> Job {
+        self.prune();
+        let job = Job {
+            id: format!("job-{}", uuid::Uuid::new_v4().simple()),
+            status: JobStatus::Queued,
+            created_at: unix_now(),
+            finished_at: None,
+            completed: 0,
+            total,
+            error: None,
+            owner,
+            request,
+            options,
+            result: None,
+        };
+        self.record(JournalRecord {
+            id: job.id.clone(),
+            status: JobStatus::Queued,
+            at: job.created_at,
+            request: Some(job.request.clone()),
+            options: Some(job.options.clone()),
+            owner: job.owner.clone(),
+            total: Some(total),
+            error: None,
+        });
+        self.jobs.lock().unwrap().insert(job.id.clone(), job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.prune();
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// The result of a succeeded job
+    pub fn result(&self, id: &str) -> Option<Arc<Value>> {
+        self.jobs.lock().unwrap().get(id)?.result.clone()
+    }
+
+    /// Ids of jobs waiting to run, oldest first
+    pub fn queued(&self) -> Vec<String> {
+        let jobs = self.jobs.lock().unwrap();
+        let mut queued: Vec<&Job> = jobs.values().filter(|job| job.status == JobStatus::Queued).collect();
+        queued.sort_by_key(|job| job.created_at);
+        queued.into_iter().map(|job| job.id.clone()).collect()
+    }
+
+    /// Mark a queued job as running, returning it
+    fn start(&self, id: &str) -> Option<Job> {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.get_mut(id).filter(|job| job.status == JobStatus::Queued)?;
+            job.status = JobStatus::Running;
+            job.clone()
+        };
+        self.record(status_record(&job, unix_now()));
+        Some(job)
+    }
+
+    fn set_progress(&self, id: &str, completed: usize) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.completed = completed;
+        }
+    }
+
+    fn finish(&self, id: &str, result: Result<Value, String>) {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(id) else {
+                return;
+            };
+            job.finished_at = Some(unix_now());
+            match result {
+                Ok(result) => {
+                    job.status = JobStatus::Succeeded;
+                    job.completed = job.total;
+                    job.result = Some(Arc::new(result));
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e);
+                }
+            }
+            job.clone()
+        };
+        self.record(status_record(&job, job.finished_at.unwrap_or_default()));
+    }
+
+    /// Forget finished jobs older than the TTL
+    fn prune(&self) {
+        let Some(ttl) = self.ttl else {
+            return;
+        };
+        let cutoff = unix_now().saturating_sub(ttl.as_secs());
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.finished_at.is_none_or(|finished| finished >= cutoff));
+    }
+
+    fn record(&self, record: JournalRecord) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("⚠️  Could not serialize job record: {}", e);
+                return;
+            }
+        };
+        if journal.send(line).is_err() {
+            error!("❌ Job journal writer has stopped, not recording job {}", record.id);
+        }
+    }
+}
+
+/// Append and sync journal lines on a dedicated thread, in the order they were recorded
+fn spawn_writer(mut file: File) -> std::io::Result<mpsc::Sender<String>> {
+    let (sender, lines) = mpsc::channel::<String>();
+    std::thread::Builder::new().name("job-journal".to_string()).spawn(move || {
+        for line in lines {
+            if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.sync_data()) {
+                error!("❌ Failed to write job journal: {}", e);
+            }
+        }
+    })?;
+    Ok(sender)
+}
+
+fn status_record(job: &Job, at: u64) -> JournalRecord {
+    JournalRecord {
+        id: job.id.clone(),
+        status: job.status,
+        at,
+        request: None,
+        options: None,
+        owner: None,
+        total: None,
+        error: job.error.clone(),
+    }
+}
+
+/// Rebuild jobs from journal lines, dropping finished jobs older than `ttl`
+fn replay(reader: impl BufRead, ttl: Option<Duration>) -> HashMap<String, Job> {
+    let mut jobs: HashMap<String, Job> = HashMap::new();
+    for line in reader.lines().map_while(Result::ok) {
+        let Ok(record) = serde_json::from_str::<JournalRecord>(&line) else {
+            // A torn last line from a crash mid-write
+            warn!("⚠️  Skipping unreadable job journal line");
+            continue;
+        };
+        let job = jobs.entry(record.id.clone()).or_insert_with(|| Job {
+            id: record.id.clone(),
+            status: record.status,
+            created_at: record.at,
+            finished_at: None,
+            completed: 0,
+            total: 0,
+            error: None,
+            owner: None,
+            request: Value::Null,
+            options: JobOptions {
+                preprocess: Preprocess::Off.name().to_string(),
+                aggregation: Aggregation::Mean.name().to_string(),
+                return_chunks: false,
+            },
+            result: None,
+        });
+        job.status = record.status;
+        if let Some(request) = record.request {
+            job.request = request;
+        }
+        if let Some(options) = record.options {
+            job.options = options;
+        }
+        if record.owner.is_some() {
+            job.owner = record.owner;
+        }
+        if let Some(total) = record.total {
+            job.total = total;
+        }
+        job.error = record.error;
+        job.finished_at = record.status.is_finished().then_some(record.at);
+    }
+
+    let cutoff = ttl.map(|ttl| unix_now().saturating_sub(ttl.as_secs()));
+    jobs.retain(|_, job| match (job.finished_at, cutoff) {
+        (Some(finished), Some(cutoff)) => finished >= cutoff,
+        _ => true,
+    });
+    for job in jobs.values_mut() {
+        match job.status {
+            // Interrupted: run again from the start
+            JobStatus::Queued | JobStatus::Running if !job.request.is_null() => job.status = JobStatus::Queued,
+            JobStatus::Queued | JobStatus::Running => {
+                job.status = JobStatus::Failed;
+                job.finished_at = Some(unix_now());
+                job.error = Some("The job's request was lost when the proxy restarted".to_string());
+            }
+            JobStatus::Succeeded => {
+                job.status = JobStatus::Expired;
+                job.error = Some("Results are not kept across proxy restarts; submit the job again".to_string());
+            }
+            JobStatus::Failed | JobStatus::Expired => {}
+        }
+    }
+    jobs
+}
+
+/// Journal lines recreating `job`: its request if it still has to run, else its final status
+fn compact_records(job: &Job) -> Option<String> {
+    let record = JournalRecord {
+        id: job.id.clone(),
+        status: job.status,
+        at: job.finished_at.unwrap_or(job.created_at),
+        request: (!job.status.is_finished()).then(|| job.request.clone()),
+        options: (!job.status.is_finished()).then(|| job.options.clone()),
+        owner: job.owner.clone(),
+        total: Some(job.total),
+        error: job.error.clone(),
+    };
+    serde_json::to_string(&record).ok()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Run a queued job on a background task, once a concurrency permit is free
+pub fn spawn(state: ProxyState, id: String) {
+    tokio::spawn(async move {
+        let jobs = state.jobs.clone();
+        let Ok(_permit) = jobs.permits.clone().acquire_owned().await else {
+            return;
+        };
+        let Some(job) = jobs.start(&id) else {
+            return;
+        };
+        info!("🧾 Running embedding job {} ({} inputs)", id, job.total);
+        let result = run(&state, &job).await;
+        match &result {
+            Ok(_) => info!("✅ Embedding job {} finished", id),
+            Err(e) => warn!("⚠️  Embedding job {} failed: {}", id, e),
+        }
+        jobs.finish(&id, result);
+    });
+}
+
+/// Queue the jobs a restart interrupted
+pub fn resume(state: &ProxyState) {
+    for id in state.jobs.queued() {
+        info!("🧾 Resuming embedding job {}", id);
+        spawn(state.clone(), id);
+    }
+}
+
+/// Embed a job's inputs in batches, renumbering the items as one response
+async fn run(state: &ProxyState, job: &Job) -> Result<Value, String> {
+    let inputs = match job.request.get("input").cloned().map(serde_json::from_value::<InputType>) {
+        Some(Ok(InputType::Single(input))) => vec![input],
+        Some(Ok(InputType::Multiple(inputs))) => inputs,
+        _ => return Err("input must be a string or an array of strings".to_string()),
+    };
+    let preprocess = Preprocess::parse(&job.options.preprocess).unwrap_or(Preprocess::Off);
+    let aggregation = Aggregation::parse(&job.options.aggregation).unwrap_or(Aggregation::Mean);
+
+    let mut data: Vec<Value> = Vec::new();
+    let mut prompt_tokens = 0;
+    let mut model = job.request.get("model").cloned().unwrap_or_default();
+    for (batch, batch_inputs) in inputs.chunks(BATCH_INPUTS).enumerate() {
+        let offset = batch * BATCH_INPUTS;
+        let mut request = job.request.clone();
+        request["input"] = json!(batch_inputs);
+        let response = proxy::embed_request(state, request, preprocess, aggregation, job.options.return_chunks).await?;
+
+        for mut item in response["data"].as_array().cloned().unwrap_or_default() {
+            if let Some(input_index) = item.pointer("/chunk/input_index").and_then(Value::as_u64) {
+                item["chunk"]["input_index"] = json!(offset + input_index as usize);
+            }
+            item["index"] = json!(data.len());
+            data.push(item);
+        }
+        prompt_tokens += response.pointer("/usage/prompt_tokens").and_then(Value::as_u64).unwrap_or(0);
+        model = response["model"].clone();
+        state.jobs.set_progress(&job.id, offset + batch_inputs.len());
+    }
+
+    Ok(json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        "usage": {"prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens},
+    }))
+}
+
+/// POST /proxy/jobs: queue an OpenAI embeddings request, answering 202 with the job
+pub async fn submit_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, &format!("Invalid JSON body: {}", e), None, None),
+    };
+    state.default_models.apply(EndpointClass::Embeddings, &mut request);
+    let Some(model) = request.get("model").and_then(Value::as_str).map(str::to_string) else {
+        return openai_error(StatusCode::BAD_REQUEST, "No model specified", Some("model"), None);
+    };
+    // Jobs run embeddings, so the key must be allowed those and the model
+    if let Some(Extension(AuthenticatedKey(key))) = &key {
+        if let Some(response) = key.scope.check(&key.name, "/v1/embeddings", std::slice::from_ref(&model)) {
+            warn!("🚫 Embedding job for {} is outside the scope of API key {}", model, key.name);
+            return response;
+        }
+    }
+    let total = match request.get("input").cloned().map(serde_json::from_value::<InputType>) {
+        Some(Ok(InputType::Single(_))) => 1,
+        Some(Ok(InputType::Multiple(inputs))) => inputs.len(),
+        _ => {
+            let message = "input must be a string or an array of strings";
+            return openai_error(StatusCode::BAD_REQUEST, message, Some("input"), None);
+        }
+    };
+    match dimensions::requested_dimensions(&request) {
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, &e, Some("dimensions"), None),
+        Ok(Some(_)) if !state.embed_dimensions.allows(&model) => {
+            let message = format!("The model '{}' does not support the dimensions parameter", model);
+            return openai_error(StatusCode::BAD_REQUEST, &message, Some("dimensions"), None);
+        }
+        Ok(_) => {}
+    }
+    // A job is one request against the key's budget, charged for all of its input up front
+    if let Some(Extension(AuthenticatedKey(key))) = &key {
+        if let Err(limited) = ratelimit::check(&state.limit_store, key, state.rate_limits, &body).await {
+            warn!("🚦 {}, rejecting embedding job with 429", limited.message);
+            return limited.into_response();
+        }
+    }
+
+    let options = JobOptions::from_headers(&state, &headers);
+    let owner = key.map(|Extension(AuthenticatedKey(key))| key.name.clone());
+    let job = state.jobs.submit(request, options, total, owner);
+    info!("🧾 Queued embedding job {} for {} ({} inputs)", job.id, model, total);
+    spawn(state.clone(), job.id.clone());
+    (StatusCode::ACCEPTED, Json(job.view())).into_response()
+}
+
+/// The job `id` if `key` may see it. Other keys' jobs look the same as missing ones,
+/// so ids can't be probed across keys.
+fn visible_job(state: &ProxyState, id: &str, key: Option<Extension<AuthenticatedKey>>) -> Option<Job> {
+    let key = key.map(|Extension(AuthenticatedKey(key))| key);
+    state.jobs.get(id).filter(|job| job.visible_to(key.as_ref().map(|key| key.name.as_str())))
+}
+
+/// GET /proxy/jobs/{id}: status and progress
+pub async fn status_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    match visible_job(&state, &id, key) {
+        Some(job) => Json(job.view()).into_response(),
+        None => not_found(&id),
+    }
+}
+
+/// GET /proxy/jobs/{id}/result: the embeddings response once the job succeeded
+pub async fn result_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(job) = visible_job(&state, &id, key) else {
+        return not_found(&id);
+    };
+    match state.jobs.result(&id) {
+        Some(result) => Json(result.as_ref().clone()).into_response(),
+        None => {
+            let mut message = format!("Job {} is {}", id, json!(job.status).as_str().unwrap_or_default());
+            if let Some(error) = &job.error {
+                message = format!("{}: {}", message, error);
+            }
+            openai_error(StatusCode::CONFLICT, &message, None, Some("job_not_succeeded"))
+        }
+    }
+}
+
+fn not_found(id: &str) -> Response {
+    openai_error(StatusCode::NOT_FOUND, &format!("No job {}", id), None, Some("job_not_found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal_lines(records: &[Value]) -> std::io::Cursor<String> {
+        std::io::Cursor::new(records.iter().map(|r| format!("{}\n", r)).collect())
+    }
+
+    #[test]
+    fn test_replay_resumes_and_expires() {
+        let now = unix_now();
+        let options = json!({"preprocess": "off", "aggregation": "mean", "return_chunks": false});
+        let request = json!({"model": "nomic-embed-text", "input": ["a", "b"]});
+        let journal = journal_lines(&[
+            json!({"id": "job-1", "status": "queued", "at": now, "request": request, "options": options, "total": 2, "owner": "search"}),
+            json!({"id": "job-1", "status": "running", "at": now}),
+            json!({"id": "job-2", "status": "queued", "at": now, "request": request, "options": options, "total": 2}),
+            json!({"id": "job-2", "status": "running", "at": now}),
+            json!({"id": "job-2", "status": "succeeded", "at": now}),
+            json!({"id": "job-3", "status": "failed", "at": now - 7200, "total": 1, "error": "boom"}),
+        ]);
+        let mut journal = journal.into_inner();
+        journal.push_str("{\"id\": \"job-4\", \"sta");
+
+        let jobs = replay(std::io::Cursor::new(journal), Some(Duration::from_secs(3600)));
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs["job-1"].status, JobStatus::Queued);
+        assert_eq!(jobs["job-1"].request, request);
+        assert_eq!(jobs["job-1"].total, 2);
+        assert_eq!(jobs["job-1"].owner.as_deref(), Some("search"));
+        assert_eq!(jobs["job-2"].status, JobStatus::Expired);
+        assert!(jobs["job-2"].error.is_some());
+
+        // Compacted lines replay to the same jobs
+        let compacted: Vec<Value> = jobs
+            .values()
+            .filter_map(compact_records)
+            .map(|line| serde_json::from_str(&line).unwrap())
+            .collect();
+        let again = replay(journal_lines(&compacted), None);
+        assert_eq!(again["job-1"].status, JobStatus::Queued);
+        assert_eq!(again["job-1"].owner.as_deref(), Some("search"));
+        assert_eq!(again["job-2"].status, JobStatus::Expired);
+    }
+
+    #[test]
+    fn test_store_lifecycle() {
+        let store = JobStore::new(&JobSettings::default());
+        let options = JobOptions {
+            preprocess: "off".to_string(),
+            aggregation: "mean".to_string(),
+            return_chunks: false,
+        };
+        let job = store.submit(json!({"model": "nomic-embed-text", "input": "a"}), options, 1, Some("search".to_string()));
+        assert_eq!(store.queued(), vec![job.id.clone()]);
+
+        assert_eq!(store.start(&job.id).unwrap().status, JobStatus::Running);
+        assert!(store.start(&job.id).is_none());
+        store.finish(&job.id, Ok(json!({"data": []})));
+
+        let finished = store.get(&job.id).unwrap();
+        assert_eq!(finished.status, JobStatus::Succeeded);
+        assert_eq!(finished.view()["progress"], json!({"completed": 1, "total": 1}));
+        assert_eq!(store.result(&job.id).unwrap().as_ref(), &json!({"data": []}));
+        assert!(finished.visible_to(Some("search")));
+        assert!(!finished.visible_to(Some("notebooks")));
+        assert!(!finished.visible_to(None));
+    }
+}
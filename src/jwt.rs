@@ -0,0 +1,334 @@
+/// JWT bearer tokens from an OIDC issuer, verified against its JWKS, as an alternative to
+/// static API keys; claims name the tenant and can carry its limits and scope
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::auth::ApiKey;
+use crate::keystore::KeySpec;
+
+/// Allowed difference between our clock and the issuer's for exp/nbf
+const CLOCK_LEEWAY_SECS: u64 = 60;
+/// Refetch the JWKS at most this often when a token names an unknown key
+const REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtSettings {
+    /// Required `iss`; also where the JWKS is discovered when no URL is given
+    pub issuer: String,
+    /// JWKS location (None = `jwks_uri` from the issuer's openid-configuration)
+    pub jwks_url: Option<String>,
+    /// Required `aud` (None = not checked)
+    pub audience: Option<String>,
+    /// Claim naming the tenant, which shares a rate limit budget
+    pub tenant_claim: String,
+    /// Claim holding the tenant's limits and scope, with API_KEYS_FILE setting names
+    pub limits_claim: String,
+    /// How long fetched signing keys are trusted before being refreshed
+    pub jwks_ttl: Duration,
+}
+
+impl JwtSettings {
+    pub fn new(issuer: &str) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            jwks_url: None,
+            audience: None,
+            tenant_claim: "sub".to_string(),
+            limits_claim: "ollama_proxy".to_string(),
+            jwks_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Whether a bearer token is shaped like a JWT rather than an API key
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.starts_with("eyJ") && token.matches('.').count() == 2
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// One signing key from a JWKS
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+impl Jwk {
+    /// Check `signature` over `message` with this key under `alg`
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        let field = |value: &Option<String>, name: &str| -> Result<Vec<u8>, String> {
+            let value = value.as_deref().ok_or_else(|| format!("signing key has no '{}'", name))?;
+            URL_SAFE_NO_PAD.decode(value).map_err(|_| format!("signing key has an invalid '{}'", name))
+        };
+        let verified = match (alg, self.kty.as_str()) {
+            ("RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512", "RSA") => {
+                let params: &signature::RsaParameters = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    _ => &signature::RSA_PSS_2048_8192_SHA512,
+                };
+                let key = RsaPublicKeyComponents { n: field(&self.n, "n")?, e: field(&self.e, "e")? };
+                key.verify(params, message, signature)
+            }
+            ("ES256" | "ES384", "EC") => {
+                let (params, curve): (&signature::EcdsaVerificationAlgorithm, _) = match alg {
+                    "ES256" => (&signature::ECDSA_P256_SHA256_FIXED, "P-256"),
+                    _ => (&signature::ECDSA_P384_SHA384_FIXED, "P-384"),
+                };
+                if self.crv.as_deref() != Some(curve) {
+                    return Err(format!("{} needs a {} key", alg, curve));
+                }
+                let point = [vec![0x04], field(&self.x, "x")?, field(&self.y, "y")?].concat();
+                UnparsedPublicKey::new(params, point).verify(message, signature)
+            }
+            _ => return Err(format!("unsupported algorithm {} for a {} key", alg, self.kty)),
+        };
+        verified.map_err(|_| "bad signature".to_string())
+    }
+}
+
+#[derive(Default)]
+struct Jwks {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+}
+
+/// Verifies tokens from one issuer, caching its signing keys
+pub struct JwtVerifier {
+    settings: JwtSettings,
+    client: reqwest::Client,
+    jwks: RwLock<Jwks>,
+}
+
+impl JwtVerifier {
+    pub fn new(settings: JwtSettings) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        Self { settings, client, jwks: RwLock::default() }
+    }
+
+    /// The tenant `token` was issued to, as a key carrying the limits in its claims
+    pub async fn verify(&self, token: &str) -> Result<ApiKey, String> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err("malformed token".to_string());
+        };
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| "malformed token".to_string());
+        let header: Header = serde_json::from_slice(&decode(header)?).map_err(|_| "malformed token header")?;
+        let claims: Value = serde_json::from_slice(&decode(payload)?).map_err(|_| "malformed token claims")?;
+        let signature = decode(signature)?;
+
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        let message = &token[..token.rfind('.').unwrap_or(0)];
+        jwk.verify(&header.alg, message.as_bytes(), &signature)?;
+        self.check_claims(&claims, unix_now())?;
+        self.tenant(&claims)
+    }
+
+    fn check_claims(&self, claims: &Value, now: u64) -> Result<(), String> {
+        if claims.get("iss").and_then(Value::as_str).map(|iss| iss.trim_end_matches('/')) != Some(&self.settings.issuer) {
+            return Err("token is from another issuer".to_string());
+        }
+        match claims.get("exp").and_then(Value::as_u64) {
+            Some(exp) if exp + CLOCK_LEEWAY_SECS < now => return Err("token has expired".to_string()),
+            Some(_) => {}
+            None => return Err("token has no expiry".to_string()),
+        }
+        if claims.get("nbf").and_then(Value::as_u64).is_some_and(|nbf| nbf > now + CLOCK_LEEWAY_SECS) {
+            return Err("token is not valid yet".to_string());
+        }
+        if let Some(audience) = &self.settings.audience {
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(format!("token is not for audience {}", audience));
+            }
+        }
+        Ok(())
+    }
+
+    fn tenant(&self, claims: &Value) -> Result<ApiKey, String> {
+        let tenant = match claims.get(&self.settings.tenant_claim) {
+            Some(Value::String(tenant)) => tenant.clone(),
+            Some(Value::Number(tenant)) => tenant.to_string(),
+            _ => return Err(format!("token has no '{}' claim", self.settings.tenant_claim)),
+        };
+        let spec = match claims.get(&self.settings.limits_claim) {
+            Some(limits) => serde_json::from_value::<KeySpec>(limits.clone())
+                .map_err(|e| format!("invalid '{}' claim: {}", self.settings.limits_claim, e))?,
+            None => KeySpec::default(),
+        };
+        // The tenant claim names the key, so its tokens share one rate limit budget
+        let key = spec.to_key(&tenant, "")?;
+        Ok(ApiKey { name: format!("{}:{}", self.settings.tenant_claim, tenant), ..key })
+    }
+
+    /// The JWKS key named `kid`, refetching the set when it's stale or doesn't have it
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, String> {
+        let find = |jwks: &Jwks| jwks.keys.iter().find(|key| kid.is_none() || key.kid.as_deref() == kid).cloned();
+        {
+            let jwks = self.jwks.read().await;
+            let fresh = jwks.fetched_at.is_some_and(|at| at.elapsed() < self.settings.jwks_ttl);
+            if let (true, Some(key)) = (fresh, find(&jwks)) {
+                return Ok(key);
+            }
+        }
+        let mut jwks = self.jwks.write().await;
+        let recent = jwks.fetched_at.is_some_and(|at| at.elapsed() < REFETCH_INTERVAL);
+        if !recent {
+            match self.fetch().await {
+                Ok(keys) => *jwks = Jwks { keys, fetched_at: Some(Instant::now()) },
+                // Keep verifying with the keys we have while the issuer is unreachable
+                Err(e) if !jwks.keys.is_empty() => warn!("⚠️  Could not refresh JWKS: {}", e),
+                Err(e) => return Err(format!("signing keys unavailable: {}", e)),
+            }
+        }
+        find(&jwks).ok_or_else(|| format!("unknown signing key {}", kid.unwrap_or("(none)")))
+    }
+
+    async fn fetch(&self) -> Result<Vec<Jwk>, String> {
+        let url = match &self.settings.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery = format!("{}/.well-known/openid-configuration", self.settings.issuer);
+                let config: Value = self.get_json(&discovery).await?;
+                config
+                    .get("jwks_uri")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| format!("{} has no jwks_uri", discovery))?
+                    .to_string()
+            }
+        };
+        #[derive(Deserialize)]
+        struct KeySet {
+            keys: Vec<Jwk>,
+        }
+        let keys = serde_json::from_value::<KeySet>(self.get_json(&url).await?)
+            .map_err(|e| format!("invalid JWKS from {}: {}", url, e))?
+            .keys;
+        info!("🔐 Loaded {} signing key(s) from {}", keys.len(), url);
+        Ok(keys)
+    }
+
+    async fn get_json(&self, url: &str) -> Result<Value, String> {
+        let response = self.client.get(url).send().await.map_err(|e| format!("{}: {}", url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+        response.json().await.map_err(|e| format!("{}: {}", url, e))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+
+    fn sign(key: &EcdsaKeyPair, header: Value, claims: Value) -> String {
+        let encode = |value: Value| URL_SAFE_NO_PAD.encode(value.to_string());
+        let message = format!("{}.{}", encode(header), encode(claims));
+        let signature = key.sign(&SystemRandom::new(), message.as_bytes()).unwrap();
+        format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    fn verifier_with_key(key: &EcdsaKeyPair) -> JwtVerifier {
+        let point = key.public_key().as_ref();
+        let jwk = Jwk {
+            kty: "EC".to_string(),
+            kid: Some("k1".to_string()),
+            crv: Some("P-256".to_string()),
+            n: None,
+            e: None,
+            x: Some(URL_SAFE_NO_PAD.encode(&point[1..33])),
+            y: Some(URL_SAFE_NO_PAD.encode(&point[33..])),
+        };
+        let verifier = JwtVerifier::new(JwtSettings {
+            audience: Some("ollama-proxy".to_string()),
+            tenant_claim: "org".to_string(),
+            ..JwtSettings::new("https://login.example.com/")
+        });
+        verifier.jwks.try_write().unwrap().keys.push(jwk);
+        verifier.jwks.try_write().unwrap().fetched_at = Some(Instant::now());
+        verifier
+    }
+
+    #[tokio::test]
+    async fn test_verifies_signature_and_claims() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let verifier = verifier_with_key(&key);
+        let header = json!({"alg": "ES256", "kid": "k1"});
+        let claims = json!({
+            "iss": "https://login.example.com",
+            "aud": ["ollama-proxy"],
+            "exp": unix_now() + 600,
+            "org": "research",
+            "ollama_proxy": {"rpm": 30, "models": ["llama3*"]},
+        });
+
+        let tenant = verifier.verify(&sign(&key, header.clone(), claims.clone())).await.unwrap();
+        assert_eq!(tenant.name, "org:research");
+        assert_eq!(tenant.limits.requests_per_minute, Some(30));
+        assert_eq!(tenant.scope.models, vec!["llama3*"]);
+
+        let with = |field: &str, value: Value| {
+            let mut claims = claims.clone();
+            claims[field] = value;
+            sign(&key, header.clone(), claims)
+        };
+        assert_eq!(verifier.verify(&with("exp", json!(unix_now() - 3600))).await.unwrap_err(), "token has expired");
+        assert!(verifier.verify(&with("iss", json!("https://evil.example.com"))).await.is_err());
+        assert!(verifier.verify(&with("aud", json!("someone-else"))).await.is_err());
+        assert!(verifier.verify(&with("ollama_proxy", json!({"colour": "blue"}))).await.is_err());
+
+        // Tampered claims, and algorithms the key can't have signed with
+        let token = sign(&key, header.clone(), claims.clone());
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let forged_claims = URL_SAFE_NO_PAD.encode(json!({"iss": "https://login.example.com", "exp": unix_now() + 600, "org": "admin"}).to_string());
+        let forged = format!("{}.{}.{}", token.split('.').next().unwrap(), forged_claims, signature);
+        assert_eq!(verifier.verify(&forged).await.unwrap_err(), "bad signature");
+        assert!(verifier.verify(&sign(&key, json!({"alg": "none", "kid": "k1"}), claims.clone())).await.is_err());
+        assert!(verifier.verify(&sign(&key, json!({"alg": "ES256", "kid": "k2"}), claims)).await.is_err());
+    }
+
+    #[test]
+    fn test_looks_like_jwt() {
+        assert!(looks_like_jwt("eyJhbGciOiJFUzI1NiJ9.eyJzdWIiOiJ4In0.c2ln"));
+        assert!(!looks_like_jwt("sk-1234"));
+        assert!(!looks_like_jwt("eyJhbGciOiJFUzI1NiJ9"));
+    }
+}
@@ -0,0 +1,368 @@
+/// API keys created, listed, revoked and rotated at runtime through /proxy/admin/keys,
+/// kept in a JSON file so they survive restarts
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+use crate::auth::{ApiKey, ApiKeys, AuthenticatedKey};
+use crate::errors::openai_error;
+use crate::proxy::ProxyState;
+use crate::ratelimit::RateLimits;
+use crate::scopes::KeyScope;
+use crate::timeouts::EndpointClass;
+
+/// Limits and scope of a managed key, with the same names as API_KEYS_FILE settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeySpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tpm: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_context: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_num_predict: Option<u32>,
+}
+
+impl KeySpec {
+    /// The key `secret` becomes under this spec; `id` names it when the spec doesn't
+    pub(crate) fn to_key(&self, id: &str, secret: &str) -> Result<ApiKey, String> {
+        let endpoints = self
+            .endpoints
+            .iter()
+            .map(|name| EndpointClass::parse(name).ok_or_else(|| format!("Unknown endpoint class '{}'", name)))
+            .collect::<Result<_, _>>()?;
+        Ok(ApiKey {
+            name: self.name.clone().unwrap_or_else(|| id.to_string()),
+            limits: RateLimits { requests_per_minute: self.rpm, tokens_per_minute: self.tpm },
+            scope: KeyScope {
+                models: self.models.clone(),
+                endpoints,
+                max_context: self.max_context,
+                max_num_predict: self.max_num_predict,
+            },
+            ..ApiKey::new(secret)
+        })
+    }
+}
+
+/// One managed key as written to the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    id: String,
+    key: String,
+    created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotated_at: Option<u64>,
+    #[serde(default)]
+    spec: KeySpec,
+}
+
+impl StoredKey {
+    /// Everything but the secret, which is only shown when it is issued
+    fn view(&self) -> Value {
+        json!({
+            "id": self.id,
+            "source": "api",
+            "key_prefix": format!("{}…", self.key.chars().take(7).collect::<String>()),
+            "created_at": self.created_at,
+            "rotated_at": self.rotated_at,
+            "settings": self.spec,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Keys {
+    stored: Vec<StoredKey>,
+    /// The same keys, ready for lookups
+    active: ApiKeys,
+}
+
+/// Keys managed at runtime, persisted to `path` when one is configured
+#[derive(Default)]
+pub struct KeyStore {
+    path: Option<PathBuf>,
+    keys: RwLock<Keys>,
+}
+
+impl KeyStore {
+    /// Store kept only in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store backed by `path`, which is created on the first change
+    pub fn open(path: Option<PathBuf>) -> Result<Self, String> {
+        let store = Self { path, keys: RwLock::default() };
+        let Some(path) = &store.path else {
+            return Ok(store);
+        };
+        let stored: Vec<StoredKey> = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid API key store {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read API key store {}: {}", path.display(), e)),
+        };
+        *store.keys.write().unwrap() = Keys { active: activate(&stored)?, stored };
+        Ok(store)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().unwrap().stored.is_empty()
+    }
+
+    pub fn find(&self, token: &str) -> Option<Arc<ApiKey>> {
+        self.keys.read().unwrap().active.find(token)
+    }
+
+    /// Issue a new key; the returned record holds its secret
+    fn create(&self, spec: KeySpec) -> Result<StoredKey, String> {
+        let created = StoredKey {
+            id: format!("key_{}", &uuid::Uuid::new_v4().simple().to_string()[..12]),
+            key: new_secret(),
+            created_at: unix_now(),
+            rotated_at: None,
+            spec,
+        };
+        self.update(|stored| {
+            stored.push(created.clone());
+            Ok(())
+        })?;
+        Ok(created)
+    }
+
+    /// Remove key `id`; false when there is no such key
+    fn revoke(&self, id: &str) -> Result<bool, String> {
+        self.update(|stored| {
+            let before = stored.len();
+            stored.retain(|key| key.id != id);
+            Ok(stored.len() != before)
+        })
+    }
+
+    /// Replace key `id`'s secret, keeping its settings; None when there is no such key
+    fn rotate(&self, id: &str) -> Result<Option<StoredKey>, String> {
+        self.update(|stored| {
+            Ok(stored.iter_mut().find(|key| key.id == id).map(|key| {
+                key.key = new_secret();
+                key.rotated_at = Some(unix_now());
+                key.clone()
+            }))
+        })
+    }
+
+    fn list(&self) -> Vec<Value> {
+        self.keys.read().unwrap().stored.iter().map(StoredKey::view).collect()
+    }
+
+    /// Apply `change` and persist the result; on any error nothing changes
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<StoredKey>) -> Result<T, String>) -> Result<T, String> {
+        let mut keys = self.keys.write().unwrap();
+        let mut stored = keys.stored.clone();
+        let result = change(&mut stored)?;
+        let active = activate(&stored)?;
+        if let Some(path) = &self.path {
+            let text = serde_json::to_string_pretty(&stored).map_err(|e| e.to_string())?;
+            write_private(path, &text).map_err(|e| format!("Failed to write API key store {}: {}", path.display(), e))?;
+        }
+        *keys = Keys { stored, active };
+        Ok(result)
+    }
+}
+
+fn activate(stored: &[StoredKey]) -> Result<ApiKeys, String> {
+    let keys = stored
+        .iter()
+        .map(|key| key.spec.to_key(&key.id, &key.key))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ApiKeys::new(keys))
+}
+
+/// `sk-` and 122 random bits
+fn new_secret() -> String {
+    format!("sk-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Replace `path` with `text` in one step, readable only by the proxy's user
+fn write_private(path: &std::path::Path, text: &str) -> std::io::Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, text)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&temp, path)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A 403 unless the request came with an admin key
+pub(crate) fn require_admin(key: Option<Extension<AuthenticatedKey>>) -> Option<Response> {
+    match key {
+        Some(Extension(AuthenticatedKey(key))) if key.admin => None,
+        _ => Some(openai_error(
+            StatusCode::FORBIDDEN,
+            "The admin API needs an admin key (ADMIN_API_KEYS, or `admin` in API_KEYS_FILE)",
+            None,
+            Some("admin_key_required"),
+        )),
+    }
+}
+
+fn store_error(e: String) -> Response {
+    openai_error(StatusCode::INTERNAL_SERVER_ERROR, &e, None, None)
+}
+
+fn not_found(id: &str) -> Response {
+    openai_error(StatusCode::NOT_FOUND, &format!("No API key {}", id), None, Some("key_not_found"))
+}
+
+/// GET /proxy/admin/keys: configured and managed keys, without their secrets
+pub async fn list_handler(State(state): State<ProxyState>, key: Option<Extension<AuthenticatedKey>>) -> Response {
+    if let Some(response) = require_admin(key) {
+        return response;
+    }
+    let configured = state.api_keys.iter().map(|key| {
+        json!({
+            "name": key.name,
+            "source": "config",
+            "admin": key.admin,
+        })
+    });
+    let keys: Vec<Value> = configured.chain(state.key_store.list()).collect();
+    Json(json!({ "keys": keys })).into_response()
+}
+
+/// POST /proxy/admin/keys: issue a key with the limits and scope in the body
+pub async fn create_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    body: Bytes,
+) -> Response {
+    if let Some(response) = require_admin(key) {
+        return response;
+    }
+    let spec: KeySpec = match serde_json::from_slice(if body.is_empty() { b"{}" } else { &body }) {
+        Ok(spec) => spec,
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, &format!("Invalid key settings: {}", e), None, None),
+    };
+    if let Err(e) = spec.to_key("", "") {
+        return openai_error(StatusCode::BAD_REQUEST, &e, Some("endpoints"), None);
+    }
+    match state.key_store.create(spec) {
+        Ok(created) => {
+            info!("🔑 Created API key {}", created.id);
+            let mut view = created.view();
+            view["key"] = json!(created.key);
+            (StatusCode::CREATED, Json(view)).into_response()
+        }
+        Err(e) => store_error(e),
+    }
+}
+
+/// DELETE /proxy/admin/keys/{id}: stop accepting a managed key
+pub async fn revoke_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = require_admin(key) {
+        return response;
+    }
+    match state.key_store.revoke(&id) {
+        Ok(true) => {
+            info!("🔑 Revoked API key {}", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => not_found(&id),
+        Err(e) => store_error(e),
+    }
+}
+
+/// POST /proxy/admin/keys/{id}/rotate: new secret for a managed key, which keeps its
+/// settings and rate limit budget; the old secret stops working immediately
+pub async fn rotate_handler(
+    State(state): State<ProxyState>,
+    key: Option<Extension<AuthenticatedKey>>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = require_admin(key) {
+        return response;
+    }
+    match state.key_store.rotate(&id) {
+        Ok(Some(rotated)) => {
+            info!("🔑 Rotated API key {}", id);
+            let mut view = rotated.view();
+            view["key"] = json!(rotated.key);
+            Json(view).into_response()
+        }
+        Ok(None) => not_found(&id),
+        Err(e) => store_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_persist_across_restarts() {
+        let path = std::env::temp_dir().join(format!("ollama-proxy-keys-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = KeyStore::open(Some(path.clone())).unwrap();
+        assert!(store.is_empty());
+        let spec = KeySpec { name: Some("search".to_string()), rpm: Some(60), ..Default::default() };
+        let first = store.create(spec.clone()).unwrap();
+        let second = store.create(KeySpec::default()).unwrap();
+        assert_eq!(store.find(&first.key).unwrap().name, "search");
+        assert_eq!(store.find(&first.key).unwrap().limits.requests_per_minute, Some(60));
+        assert_eq!(store.find(&second.key).unwrap().name, second.id);
+
+        let rotated = store.rotate(&first.id).unwrap().unwrap();
+        assert!(store.find(&first.key).is_none());
+        assert!(store.revoke(&second.id).unwrap());
+        assert!(!store.revoke(&second.id).unwrap());
+        assert!(store.rotate("key_missing").unwrap().is_none());
+
+        let reopened = KeyStore::open(Some(path.clone())).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reopened.find(&rotated.key).unwrap().name, "search");
+        assert!(reopened.find(&second.key).is_none());
+        assert_eq!(reopened.list().len(), 1);
+        assert!(!reopened.list()[0].to_string().contains(&rotated.key));
+    }
+
+    #[test]
+    fn test_invalid_settings_change_nothing() {
+        let store = KeyStore::new();
+        let spec = KeySpec { endpoints: vec!["images".to_string()], ..Default::default() };
+        assert!(store.create(spec).is_err());
+        assert!(store.is_empty());
+        assert!(serde_json::from_str::<KeySpec>(r#"{"colour": "blue"}"#).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+/// Per-model latency history and timeouts derived from it
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::timeouts::EndpointClass;
+
+/// Number of recent samples kept per model and endpoint class
+const WINDOW: usize = 256;
+
+/// Rolling window of completed request durations, keyed by model and endpoint class
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    samples: Mutex<HashMap<(String, EndpointClass), VecDeque<Duration>>>,
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, model: &str, class: EndpointClass, elapsed: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        let window = samples.entry((model.to_string(), class)).or_default();
+        if window.len() == WINDOW {
+            window.pop_front();
+        }
+        window.push_back(elapsed);
+    }
+
+    /// The `quantile` (0.0-1.0) of recent durations, if at least `min_samples` were recorded
+    pub fn quantile(&self, model: &str, class: EndpointClass, quantile: f64, min_samples: usize) -> Option<Duration> {
+        let samples = self.samples.lock().unwrap();
+        let window = samples.get(&(model.to_string(), class))?;
+        if window.is_empty() || window.len() < min_samples {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = window.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (quantile.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1).min(sorted.len() - 1)])
+    }
+
+    /// Copies of every window, ordered by model and class
+    pub fn windows(&self) -> Vec<((String, EndpointClass), Vec<Duration>)> {
+        let samples = self.samples.lock().unwrap();
+        let mut windows: Vec<_> = samples
+            .iter()
+            .map(|(key, window)| (key.clone(), window.iter().copied().collect()))
+            .collect();
+        windows.sort_by(|((a, x), _), ((b, y), _)| (a, x.name()).cmp(&(b, y.name())));
+        windows
+    }
+
+    /// Median duration across all endpoint classes for `model`
+    pub fn typical(&self, model: &str) -> Option<Duration> {
+        let samples = self.samples.lock().unwrap();
+        let mut all: Vec<Duration> = samples
+            .iter()
+            .filter(|((m, _), _)| m == model)
+            .flat_map(|(_, window)| window.iter().copied())
+            .collect();
+        if all.is_empty() {
+            return None;
+        }
+        all.sort_unstable();
+        Some(all[(all.len() - 1) / 2])
+    }
+}
+
+/// Timeouts computed from a model's own latency history (p99 * factor by default)
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveTimeouts {
+    pub enabled: bool,
+    /// Which latency quantile to scale (0.99 = p99)
+    pub quantile: f64,
+    /// Multiplier applied to the quantile
+    pub factor: f64,
+    /// Samples needed before the static timeout is replaced
+    pub min_samples: usize,
+    /// Adaptive timeouts never go below this
+    pub floor: Duration,
+    /// Adaptive timeouts never go above this
+    pub ceiling: Duration,
+}
+
+impl Default for AdaptiveTimeouts {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            quantile: 0.99,
+            factor: 3.0,
+            min_samples: 20,
+            floor: Duration::from_secs(10),
+            ceiling: Duration::from_secs(1800),
+        }
+    }
+}
+
+impl AdaptiveTimeouts {
+    /// Timeout for a request to `model`. Falls back to `static_timeout` until enough
+    /// history exists; unbounded classes stay unbounded.
+    pub fn resolve(
+        &self,
+        tracker: &LatencyTracker,
+        model: &str,
+        class: EndpointClass,
+        static_timeout: Option<Duration>,
+    ) -> Option<Duration> {
+        if !self.enabled || static_timeout.is_none() {
+            return static_timeout;
+        }
+        match tracker.quantile(model, class, self.quantile, self.min_samples) {
+            Some(observed) => Some(observed.mul_f64(self.factor).clamp(self.floor, self.ceiling)),
+            None => static_timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker_with(model: &str, millis: &[u64]) -> LatencyTracker {
+        let tracker = LatencyTracker::new();
+        for &ms in millis {
+            tracker.record(model, EndpointClass::Chat, Duration::from_millis(ms));
+        }
+        tracker
+    }
+
+    #[test]
+    fn test_quantile() {
+        let samples: Vec<u64> = (1..=100).collect();
+        let tracker = tracker_with("llama3", &samples);
+        assert_eq!(tracker.quantile("llama3", EndpointClass::Chat, 0.99, 1), Some(Duration::from_millis(99)));
+        assert_eq!(tracker.quantile("llama3", EndpointClass::Chat, 0.5, 1), Some(Duration::from_millis(50)));
+        assert_eq!(tracker.quantile("llama3", EndpointClass::Embeddings, 0.5, 1), None);
+        assert_eq!(tracker.quantile("llama3", EndpointClass::Chat, 0.5, 101), None);
+        assert_eq!(tracker.typical("llama3"), Some(Duration::from_millis(50)));
+        assert_eq!(tracker.typical("unknown"), None);
+    }
+
+    #[test]
+    fn test_window_is_bounded() {
+        let tracker = LatencyTracker::new();
+        for _ in 0..WINDOW {
+            tracker.record("m", EndpointClass::Chat, Duration::from_secs(100));
+        }
+        for _ in 0..WINDOW {
+            tracker.record("m", EndpointClass::Chat, Duration::from_secs(1));
+        }
+        assert_eq!(tracker.quantile("m", EndpointClass::Chat, 1.0, 1), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_resolve_scales_and_clamps() {
+        let adaptive = AdaptiveTimeouts { enabled: true, min_samples: 5, ..Default::default() };
+        let static_timeout = Some(Duration::from_secs(120));
+
+        // Not enough history yet
+        let tracker = tracker_with("big", &[60_000; 3]);
+        assert_eq!(adaptive.resolve(&tracker, "big", EndpointClass::Chat, static_timeout), static_timeout);
+
+        // Slow model gets more room than the static timeout
+        let tracker = tracker_with("big", &[60_000; 5]);
+        assert_eq!(
+            adaptive.resolve(&tracker, "big", EndpointClass::Chat, static_timeout),
+            Some(Duration::from_secs(180))
+        );
+
+        // Fast model fails fast, but never below the floor
+        let tracker = tracker_with("small", &[500; 5]);
+        assert_eq!(
+            adaptive.resolve(&tracker, "small", EndpointClass::Chat, static_timeout),
+            Some(Duration::from_secs(10))
+        );
+
+        // Unbounded classes are left alone
+        assert_eq!(adaptive.resolve(&tracker, "small", EndpointClass::Chat, None), None);
+    }
+
+    #[test]
+    fn test_timeouts_let_a_slowing_model_catch_up() {
+        let adaptive = AdaptiveTimeouts { enabled: true, min_samples: 5, ..Default::default() };
+        let static_timeout = Some(Duration::from_secs(120));
+        let tracker = tracker_with("llama3", &[500; 100]);
+        let timeout = adaptive.resolve(&tracker, "llama3", EndpointClass::Chat, static_timeout).unwrap();
+        assert_eq!(timeout, Duration::from_secs(10));
+
+        // The model now needs 20s: each request times out and is recorded at the timeout,
+        // until enough of them raise p99 past what the model needs
+        let mut timeouts = 0;
+        let mut timeout = timeout;
+        while timeout < Duration::from_secs(20) {
+            tracker.record("llama3", EndpointClass::Chat, timeout);
+            timeouts += 1;
+            timeout = adaptive.resolve(&tracker, "llama3", EndpointClass::Chat, static_timeout).unwrap();
+        }
+        assert_eq!(timeout, Duration::from_secs(30));
+        assert!(timeouts <= 2, "{} timeouts", timeouts);
+    }
+}
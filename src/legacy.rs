@@ -0,0 +1,44 @@
+/// Shim for Ollama's deprecated /api/embeddings (`prompt` in, `embedding` out)
+use serde_json::{json, Value};
+
+pub const LEGACY_EMBEDDINGS_PATH: &str = "/api/embeddings";
+
+/// The OpenAI embeddings request equivalent to a legacy `{"model", "prompt"}` body.
+/// Ollama sizes the context itself, so `options` are not carried over.
+pub fn to_openai_request(body: &Value) -> Result<Value, String> {
+    let model = body
+        .get("model")
+        .and_then(Value::as_str)
+        .ok_or("No model specified")?;
+    let prompt = match body.get("prompt") {
+        Some(Value::String(prompt)) => prompt,
+        Some(_) => return Err("prompt must be a string".to_string()),
+        None => return Err("No prompt specified".to_string()),
+    };
+    Ok(json!({"model": model, "input": prompt}))
+}
+
+/// The legacy `{"embedding": [...]}` body for an OpenAI embeddings response
+pub fn from_openai_response(response: &Value) -> Option<Value> {
+    let embedding = response.pointer("/data/0/embedding")?;
+    Some(json!({"embedding": embedding}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let request = json!({"model": "nomic-embed-text", "prompt": "hello", "options": {"num_ctx": 512}});
+        assert_eq!(
+            to_openai_request(&request),
+            Ok(json!({"model": "nomic-embed-text", "input": "hello"}))
+        );
+        assert!(to_openai_request(&json!({"model": "nomic-embed-text"})).is_err());
+        assert!(to_openai_request(&json!({"prompt": "hello"})).is_err());
+
+        let response = json!({"object": "list", "data": [{"object": "embedding", "embedding": [0.1, 0.2], "index": 0}]});
+        assert_eq!(from_openai_response(&response), Some(json!({"embedding": [0.1, 0.2]})));
+    }
+}
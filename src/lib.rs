@@ -1,7 +1,60 @@
 // Public API for testing and library usage
+pub mod access_log;
+pub mod admission;
+pub mod auth;
+pub mod backends;
+pub mod builder;
+pub mod cache;
 pub mod chunker;
+pub mod cloud;
+pub mod coalesce;
+pub mod compression;
+pub mod concurrency;
+pub mod config;
+pub mod dimensions;
+pub mod errors;
+pub mod filters;
+pub mod health;
+pub mod ipfilter;
+pub mod hedge;
+pub mod inventory;
+pub mod jobs;
+pub mod jwt;
+pub mod keystore;
+pub mod latency;
+pub mod legacy;
+pub mod limits;
+pub mod listener;
+pub mod methods;
+pub mod metrics;
 pub mod translator;
 pub mod model_metadata;
 pub mod modifier;
+pub mod overrides;
+pub mod penalties;
+pub mod preprocess;
 pub mod proxy;
+pub mod ratelimit;
+pub mod redact;
+pub mod remote_images;
+pub mod resident;
+pub mod retry;
+pub mod routing;
+pub mod schedule;
+pub mod scopes;
+pub mod shadow;
+pub mod stats;
+pub mod structured;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod timeouts;
+pub mod timing;
+pub mod tls;
+pub mod tokens;
+pub mod tools;
+pub mod unsupported;
+pub mod upstream;
 
+pub use builder::{router, ProxyBuilder};
+pub use config::ProxyConfig;
+pub use proxy::ProxyState;
@@ -0,0 +1,392 @@
+/// Counter store behind rate limits and token quotas, local or shared through Redis
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// Prefix for every key written to Redis
+const KEY_PREFIX: &str = "ollama-proxy:limit";
+/// How long to stay on local counters after Redis fails before trying it again
+const REDIS_RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Upper bound for a single Redis round trip
+const REDIS_TIMEOUT: Duration = Duration::from_millis(250);
+/// Connections kept open to Redis; more concurrent checks wait for one to come back
+const REDIS_POOL_SIZE: usize = 8;
+
+/// Token buckets keyed by name. Replicas sharing a Redis store see each other's
+/// traffic; the local store only sees this process.
+pub enum LimitStore {
+    Local(LocalCounters),
+    Redis(RedisCounters),
+}
+
+impl LimitStore {
+    /// `local` (or empty) keeps counters in memory; `redis://host:port[/db]` shares them
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local(LocalCounters::default()));
+        }
+        RedisCounters::parse(spec).map(Self::Redis)
+    }
+
+    /// Take `cost` from the token bucket `key`, which holds up to `capacity` and refills
+    /// completely over `period`. None when there was room, otherwise how long until there is.
+    /// A cost above the capacity is charged as a full bucket.
+    pub async fn take(&self, key: &str, cost: u64, capacity: u64, period: Duration) -> Option<Duration> {
+        let bucket = Bucket { capacity, period };
+        match self {
+            Self::Local(local) => local.take(key, cost.min(capacity), bucket),
+            Self::Redis(redis) => redis.take(key, cost.min(capacity), bucket).await,
+        }
+    }
+
+    /// Put `amount` back into the token bucket `key`, for a request that was charged
+    /// but then refused anyway. The bucket never holds more than its capacity.
+    pub async fn refund(&self, key: &str, amount: u64, capacity: u64, period: Duration) {
+        let bucket = Bucket { capacity, period };
+        let cost = -(amount.min(capacity) as f64);
+        match self {
+            Self::Local(local) => {
+                local.charge_at(key, cost, bucket, Instant::now());
+            }
+            Self::Redis(redis) => redis.charge(key, cost, bucket).await,
+        }
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Local(_) => "local (per replica)".to_string(),
+            Self::Redis(redis) => format!("redis {} (falls back to local)", redis.addr),
+        }
+    }
+}
+
+impl Default for LimitStore {
+    fn default() -> Self {
+        Self::Local(LocalCounters::default())
+    }
+}
+
+impl std::fmt::Debug for LimitStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe())
+    }
+}
+
+/// Token bucket size and refill time
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    capacity: u64,
+    period: Duration,
+}
+
+impl Bucket {
+    /// Wait until `missing` tokens have refilled
+    fn wait_for(&self, missing: f64) -> Duration {
+        Duration::from_secs_f64(missing * self.period.as_secs_f64() / self.capacity.max(1) as f64)
+    }
+}
+
+/// In-memory buckets for a single proxy
+#[derive(Default)]
+pub struct LocalCounters {
+    /// Key -> (tokens left, last refill)
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl LocalCounters {
+    fn take(&self, key: &str, cost: u64, bucket: Bucket) -> Option<Duration> {
+        self.charge_at(key, cost as f64, bucket, Instant::now())
+    }
+
+    /// Take `cost` from the bucket if it holds that much; a negative cost refunds
+    fn charge_at(&self, key: &str, cost: f64, bucket: Bucket, now: Instant) -> Option<Duration> {
+        let capacity = bucket.capacity as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        // Full buckets hold nothing worth keeping
+        if buckets.len() > 10_000 {
+            buckets.retain(|_, (_, at)| now.duration_since(*at) < bucket.period);
+        }
+        let (level, at) = buckets.entry(key.to_string()).or_insert((capacity, now));
+        let refilled = now.saturating_duration_since(*at).as_secs_f64() / bucket.period.as_secs_f64() * capacity;
+        *level = (*level + refilled).min(capacity);
+        *at = now;
+        if *level >= cost {
+            *level = (*level - cost).min(capacity);
+            None
+        } else {
+            Some(bucket.wait_for(cost - *level))
+        }
+    }
+}
+
+/// Token bucket as a hash of level and last refill time (ms), returning 0 when the
+/// cost was taken (or, when negative, refunded) or the milliseconds until it can be
+const TAKE_SCRIPT: &str = r#"
+local cost, capacity, period, now = tonumber(ARGV[1]), tonumber(ARGV[2]), tonumber(ARGV[3]), tonumber(ARGV[4])
+local state = redis.call('HMGET', KEYS[1], 'level', 'at')
+local level = tonumber(state[1]) or capacity
+local at = tonumber(state[2]) or now
+level = math.min(capacity, level + math.max(0, now - at) * capacity / period)
+if level < cost then
+  return math.max(1, math.ceil((cost - level) * period / capacity))
+end
+redis.call('HSET', KEYS[1], 'level', tostring(math.min(capacity, level - cost)), 'at', tostring(now))
+redis.call('PEXPIRE', KEYS[1], period)
+return 0
+"#;
+
+/// Buckets shared through Redis (one Lua script per charge), degrading to local
+/// buckets while Redis is unreachable
+pub struct RedisCounters {
+    addr: String,
+    db: Option<u32>,
+    /// One permit per pooled connection, so waiting for a free one never counts as a Redis failure
+    slots: tokio::sync::Semaphore,
+    /// Open connections not currently in use
+    idle: Mutex<Vec<BufReader<TcpStream>>>,
+    /// Set while Redis is considered down
+    failed_at: Mutex<Option<Instant>>,
+    fallback: LocalCounters,
+}
+
+impl RedisCounters {
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("Invalid limit store '{}', expected local or redis://host:port", url))?;
+        let (addr, db) = match rest.split_once('/') {
+            Some((addr, "")) => (addr, None),
+            Some((addr, db)) => (
+                addr,
+                Some(db.parse().map_err(|_| format!("Invalid Redis database '{}' in {}", db, url))?),
+            ),
+            None => (rest, None),
+        };
+        if addr.is_empty() {
+            return Err(format!("Missing Redis host in {}", url));
+        }
+        let addr = if addr.contains(':') { addr.to_string() } else { format!("{}:6379", addr) };
+        Ok(Self {
+            addr,
+            db,
+            slots: tokio::sync::Semaphore::new(REDIS_POOL_SIZE),
+            idle: Mutex::new(Vec::new()),
+            failed_at: Mutex::new(None),
+            fallback: LocalCounters::default(),
+        })
+    }
+
+    async fn take(&self, key: &str, cost: u64, bucket: Bucket) -> Option<Duration> {
+        match self.shared(key, cost as f64, bucket).await {
+            Some(wait_ms) => (wait_ms > 0).then(|| Duration::from_millis(wait_ms as u64)),
+            None => self.fallback.take(key, cost, bucket),
+        }
+    }
+
+    async fn charge(&self, key: &str, cost: f64, bucket: Bucket) {
+        if self.shared(key, cost, bucket).await.is_none() {
+            self.fallback.charge_at(key, cost, bucket, Instant::now());
+        }
+    }
+
+    /// Charge the bucket on Redis, or None while Redis is down so callers count locally
+    async fn shared(&self, key: &str, cost: f64, bucket: Bucket) -> Option<i64> {
+        let down = matches!(*self.failed_at.lock().unwrap(), Some(at) if at.elapsed() < REDIS_RETRY_AFTER);
+        if down {
+            return None;
+        }
+        // Only the round trip is timed: waiting for a pooled connection is not Redis being slow
+        let _slot = self.slots.acquire().await.ok()?;
+        let connection = self.idle.lock().unwrap().pop();
+        match tokio::time::timeout(REDIS_TIMEOUT, self.run(connection, key, cost, bucket)).await {
+            Ok(Ok((reply, connection))) => {
+                // Failed or timed-out connections are dropped instead: they may hold an unread reply
+                self.idle.lock().unwrap().push(connection);
+                if self.failed_at.lock().unwrap().take().is_some() {
+                    info!("💚 Limit store {} reachable again", self.addr);
+                }
+                Some(reply)
+            }
+            Ok(Err(e)) => {
+                self.mark_failed(&e);
+                None
+            }
+            Err(_) => {
+                self.mark_failed("timed out");
+                None
+            }
+        }
+    }
+
+    fn mark_failed(&self, error: &str) {
+        let mut failed_at = self.failed_at.lock().unwrap();
+        if failed_at.is_none() {
+            warn!("⚠️  Limit store {} unavailable ({}), using local counters", self.addr, error);
+        }
+        *failed_at = Some(Instant::now());
+    }
+
+    /// Run the bucket script on `connection` (or a new one) and hand the connection back
+    async fn run(
+        &self,
+        connection: Option<BufReader<TcpStream>>,
+        key: &str,
+        cost: f64,
+        bucket: Bucket,
+    ) -> Result<(i64, BufReader<TcpStream>), String> {
+        let mut stream = match connection {
+            Some(stream) => stream,
+            None => self.connect().await?,
+        };
+        let redis_key = format!("{}:bucket:{}", KEY_PREFIX, key);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let args = [cost.to_string(), bucket.capacity.to_string(), bucket.period.as_millis().to_string(), now.to_string()];
+        let reply =
+            command(&mut stream, &["EVAL", TAKE_SCRIPT, "1", &redis_key, &args[0], &args[1], &args[2], &args[3]]).await?;
+        Ok((reply, stream))
+    }
+
+    async fn connect(&self) -> Result<BufReader<TcpStream>, String> {
+        let stream = TcpStream::connect(&self.addr).await.map_err(|e| e.to_string())?;
+        let mut stream = BufReader::new(stream);
+        if let Some(db) = self.db {
+            command(&mut stream, &["SELECT", &db.to_string()]).await?;
+        }
+        Ok(stream)
+    }
+}
+
+/// Send one RESP command and read an integer (or OK) reply
+async fn command(stream: &mut BufReader<TcpStream>, args: &[&str]) -> Result<i64, String> {
+    stream
+        .get_mut()
+        .write_all(&encode_command(args))
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await.map_err(|e| e.to_string())?;
+    parse_reply(&line)
+}
+
+fn encode_command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len());
+    for arg in args {
+        out.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    out.into_bytes()
+}
+
+fn parse_reply(line: &str) -> Result<i64, String> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+        return Err("Connection closed".to_string());
+    }
+    let (kind, rest) = line.split_at(1);
+    match kind {
+        ":" => rest.parse().map_err(|_| format!("Bad Redis reply '{}'", line)),
+        "+" => Ok(0),
+        "-" => Err(rest.to_string()),
+        _ => Err(format!("Unexpected Redis reply '{}'", line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn test_local_buckets() {
+        let counters = LocalCounters::default();
+        let bucket = Bucket { capacity: 60, period: Duration::from_secs(60) };
+        let t0 = Instant::now();
+        assert_eq!(counters.charge_at("key", 50.0, bucket, t0), None);
+        assert_eq!(counters.charge_at("key", 10.0, bucket, t0), None);
+        // Empty: one token refills every second
+        assert_eq!(counters.charge_at("key", 3.0, bucket, t0), Some(Duration::from_secs(3)));
+        assert_eq!(counters.charge_at("key", 3.0, bucket, t0 + Duration::from_secs(3)), None);
+        // Refunds go back in, up to the capacity
+        assert_eq!(counters.charge_at("key", -2.0, bucket, t0 + Duration::from_secs(3)), None);
+        assert_eq!(counters.charge_at("key", 2.0, bucket, t0 + Duration::from_secs(3)), None);
+        assert_eq!(counters.charge_at("other", -100.0, bucket, t0), None);
+        assert_eq!(counters.charge_at("other", 61.0, bucket, t0), Some(Duration::from_secs(1)));
+        assert_eq!(counters.charge_at("other", 60.0, bucket, t0), None);
+        // Refills stop at the capacity
+        assert_eq!(counters.charge_at("key", 61.0, bucket, t0 + Duration::from_secs(600)), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_parse_store() {
+        assert!(matches!(LimitStore::parse(""), Ok(LimitStore::Local(_))));
+        assert!(matches!(LimitStore::parse("local"), Ok(LimitStore::Local(_))));
+        match LimitStore::parse("redis://cache/2").unwrap() {
+            LimitStore::Redis(redis) => {
+                assert_eq!(redis.addr, "cache:6379");
+                assert_eq!(redis.db, Some(2));
+            }
+            LimitStore::Local(_) => panic!("expected redis"),
+        }
+        assert!(LimitStore::parse("memcached://cache").is_err());
+        assert!(LimitStore::parse("redis://cache/x").is_err());
+        assert_eq!(parse_reply(":42\r\n"), Ok(42));
+        assert_eq!(parse_reply("+OK\r\n"), Ok(0));
+        assert!(parse_reply("-ERR wrong type\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redis_counts_and_falls_back() {
+        // Minimal Redis stand-in that answers every command with :7
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            while socket.read(&mut buf).await.unwrap_or(0) > 0 {
+                socket.write_all(b":7\r\n").await.unwrap();
+            }
+        });
+        let store = LimitStore::parse(&format!("redis://{}", addr)).unwrap();
+        assert_eq!(store.take("key", 1, 10, Duration::from_secs(60)).await, Some(Duration::from_millis(7)));
+
+        // Nothing listens on port 9: count locally instead of failing requests
+        let store = LimitStore::parse("redis://127.0.0.1:9").unwrap();
+        assert_eq!(store.take("key", 6, 10, Duration::from_secs(60)).await, None);
+        assert!(store.take("key", 6, 10, Duration::from_secs(60)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_redis_waits_for_a_free_connection_without_failing() {
+        // Each reply takes 100ms, so 40 checks through 8 connections queue well past the
+        // round-trip timeout; only the round trips themselves should be timed
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while socket.read(&mut buf).await.unwrap_or(0) > 0 {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        socket.write_all(b":7\r\n").await.unwrap();
+                    }
+                });
+            }
+        });
+        let store = LimitStore::parse(&format!("redis://{}", addr)).unwrap();
+        let checks = (0..40).map(|_| store.take("key", 1, 1000, Duration::from_secs(60)));
+        let waits = futures::future::join_all(checks).await;
+        // Local fallback would have had room (None); Redis answered every check
+        assert!(waits.iter().all(|wait| *wait == Some(Duration::from_millis(7))));
+        match &store {
+            LimitStore::Redis(redis) => {
+                assert!(redis.failed_at.lock().unwrap().is_none());
+                assert!(redis.idle.lock().unwrap().len() <= REDIS_POOL_SIZE);
+            }
+            LimitStore::Local(_) => panic!("expected redis"),
+        }
+    }
+}
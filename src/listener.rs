@@ -0,0 +1,72 @@
+/// Listening socket setup, including sockets inherited from systemd
+use std::net::TcpListener;
+
+/// First file descriptor passed by systemd socket activation (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Number of sockets passed to this process, following sd_listen_fds(3):
+/// LISTEN_PID must name this process and LISTEN_FDS gives the count
+#[cfg_attr(not(unix), allow(dead_code))]
+fn listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    let for_us = listen_pid.and_then(|p| p.trim().parse::<u32>().ok()) == Some(pid);
+    if !for_us {
+        return 0;
+    }
+    listen_fds.and_then(|n| n.trim().parse().ok()).unwrap_or(0)
+}
+
+/// Take the listener handed over by systemd socket activation, if any.
+/// The LISTEN_* variables are cleared so child processes don't inherit them.
+/// Call this before starting other threads.
+#[cfg(unix)]
+pub fn inherited_listener() -> Result<Option<TcpListener>, String> {
+    use std::env;
+    use std::os::unix::io::FromRawFd;
+
+    let count = listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+    match count {
+        0 => return Ok(None),
+        1 => {}
+        n => return Err(format!("Expected one socket from systemd, got {} (LISTEN_FDS)", n)),
+    }
+
+    // SAFETY: systemd passes ownership of fd 3 to this process, and nothing else uses it
+    let listener = unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener
+        .local_addr()
+        .map_err(|e| format!("Inherited fd {} is not a TCP socket: {}", LISTEN_FDS_START, e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure inherited socket: {}", e))?;
+    Ok(Some(listener))
+}
+
+/// Socket activation is only available on Unix
+#[cfg(not(unix))]
+pub fn inherited_listener() -> Result<Option<TcpListener>, String> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 2);
+        // Meant for another process (e.g. inherited through a wrapper)
+        assert_eq!(listen_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(listen_fds(None, Some("1"), 42), 0);
+        assert_eq!(listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(listen_fds(Some("42"), Some("x"), 42), 0);
+    }
+}
@@ -1,97 +1,173 @@
-mod proxy;
-mod model_metadata;
-mod modifier;
-mod translator;
-mod chunker;
-
-use axum::{Router, serve};
+use axum::serve;
+use ollama_proxy_rs::backends::BackendPool;
+use ollama_proxy_rs::metrics::Metrics;
+use ollama_proxy_rs::upstream::UpstreamClient;
+use ollama_proxy_rs::{health, listener, tls, ProxyBuilder, ProxyConfig};
 use std::env;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tracing::{info, Level};
 
-#[tokio::main]
-async fn main() {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(
-            env::var("RUST_LOG")
-                .ok()
-                .and_then(|s| s.parse::<Level>().ok())
-                .unwrap_or(Level::INFO)
-        )
-        .init();
+const USAGE: &str = "Usage: ollama-proxy [--validate-config] [--print-effective-config] [--probe-upstream]
 
-    // Configuration from environment variables
-    let ollama_host = env::var("OLLAMA_HOST")
-        .unwrap_or_else(|_| "http://127.0.0.1:11434".to_string());
-    let proxy_port = env::var("PROXY_PORT")
-        .unwrap_or_else(|_| "11435".to_string());
-    let bind_addr = format!("127.0.0.1:{}", proxy_port);
+  --validate-config         Load and validate the configuration, then exit
+  --print-effective-config  Print the configuration after env, PROXY_CONFIG and defaults are applied, then exit
+  --probe-upstream          Check that every Ollama backend answers /api/version, then exit
 
-    // Chunking configuration
-    let max_embedding_input_length = env::var("MAX_EMBEDDING_INPUT_LENGTH")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(1000);
-    
-    let enable_auto_chunking = env::var("ENABLE_AUTO_CHUNKING")
-        .ok()
-        .map(|s| s.to_lowercase() != "false" && s != "0")
-        .unwrap_or(true);
+Settings are read from environment variables and the PROXY_CONFIG file, as when serving.";
 
-    // Context override configuration (prevents large context stalls)
-    let max_context_override = env::var("MAX_CONTEXT_OVERRIDE")
-        .ok()
-        .and_then(|s| s.parse::<u32>().ok())
-        .unwrap_or(16384);
+/// One-shot checks run instead of serving (for CI and deployment pipelines)
+#[derive(Debug, Default)]
+struct Checks {
+    validate: bool,
+    print: bool,
+    probe: bool,
+}
 
-    // Timeout configuration (prevents indefinite hangs)
-    let request_timeout_seconds = env::var("REQUEST_TIMEOUT_SECONDS")
+impl Checks {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut checks = Self::default();
+        for arg in args {
+            match arg.as_str() {
+                "--validate-config" => checks.validate = true,
+                "--print-effective-config" => checks.print = true,
+                "--probe-upstream" => checks.probe = true,
+                "-h" | "--help" => return Err(String::new()),
+                other => return Err(format!("Unknown argument: {}", other)),
+            }
+        }
+        Ok((checks.validate || checks.print || checks.probe).then_some(checks))
+    }
+}
+
+fn main() -> ExitCode {
+    let checks = match Checks::parse(env::args().skip(1)) {
+        Ok(checks) => checks,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            return if e.is_empty() { ExitCode::SUCCESS } else { ExitCode::from(2) };
+        }
+    };
+
+    // Initialize logging (on stderr for checks, so stdout only carries their results)
+    let level = env::var("RUST_LOG")
         .ok()
-        .and_then(|s| s.parse::<u64>().ok())
-        .unwrap_or(120);
+        .and_then(|s| s.parse::<Level>().ok())
+        .unwrap_or(Level::INFO);
+    if let Some(checks) = checks {
+        tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).init();
+        return run_checks(checks);
+    }
+    tracing_subscriber::fmt().with_max_level(level).init();
 
-    info!("Starting Ollama Proxy");
-    info!("Listening on: {}", bind_addr);
-    info!("Proxying to: {}", ollama_host);
-    info!("Chunking config:");
-    info!("  Max embedding input length: {}", max_embedding_input_length);
-    info!("  Auto chunking enabled: {}", enable_auto_chunking);
-    info!("Context config:");
-    info!("  Max context override: {} (hard cap for stability)", max_context_override);
-    info!("  Request timeout: {} seconds", request_timeout_seconds);
+    // Configuration from environment variables, then the PROXY_CONFIG file. Loaded
+    // before the runtime is built, since it sets the runtime's thread counts.
+    let config = ProxyConfig::from_env().unwrap_or_else(|e| panic!("{}", e));
 
-    // Validate configuration
-    if max_embedding_input_length < 100 {
-        panic!("MAX_EMBEDDING_INPUT_LENGTH must be at least 100 characters");
+    // Socket handed over by systemd socket activation (taken before any threads start)
+    let inherited = listener::inherited_listener().unwrap_or_else(|e| panic!("{}", e));
+
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.runtime_worker_threads())
+        .max_blocking_threads(config.max_blocking_threads)
+        .thread_name("ollama-proxy-worker")
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime")
+        .block_on(run(config, inherited));
+    ExitCode::SUCCESS
+}
+
+fn run_checks(checks: Checks) -> ExitCode {
+    let config = match ProxyConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ Invalid configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if checks.print {
+        for line in config.summary() {
+            println!("{}", line);
+        }
+    }
+    if checks.validate {
+        println!("✅ Configuration is valid");
+    }
+    if !checks.probe {
+        return ExitCode::SUCCESS;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime");
+    let backends = runtime.block_on(async {
+        let client = UpstreamClient::new(config.upstream.clone(), Arc::new(Metrics::new())).get();
+        let pool = BackendPool::new(&config.ollama_host, &config.upstream.backends);
+        health::probe_backends(&client, &pool).await
+    });
+    let mut reachable = true;
+    for backend in backends {
+        match (backend.reachable, backend.error) {
+            (true, _) => println!(
+                "✅ {}: Ollama {} ({}ms)",
+                backend.state.url,
+                backend.version.as_deref().unwrap_or("unknown version"),
+                backend.latency_ms.unwrap_or(0)
+            ),
+            (false, error) => {
+                reachable = false;
+                println!("❌ {}: {}", backend.state.url, error.unwrap_or_default());
+            }
+        }
     }
-    if max_context_override < 512 {
-        panic!("MAX_CONTEXT_OVERRIDE must be at least 512 tokens");
+    if reachable {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
+}
 
-    // Create shared state
-    let state = proxy::ProxyState::new(
-        ollama_host,
-        max_embedding_input_length,
-        enable_auto_chunking,
-        max_context_override,
-        request_timeout_seconds,
-    );
+async fn run(config: ProxyConfig, inherited: Option<std::net::TcpListener>) {
+    info!("Starting Ollama Proxy");
+    config.log_summary();
 
-    // Build router
-    let app = Router::new()
-        .fallback(proxy::proxy_handler)
-        .with_state(state);
+    // Create shared state and router
+    let bind_addr = config.listen_addr.clone();
+    let tls_settings = config.tls.clone();
+    let app = ProxyBuilder::from_config(config)
+        .router()
+        .unwrap_or_else(|e| panic!("{}", e));
 
     // Start server
-    let listener = TcpListener::bind(&bind_addr)
-        .await
-        .expect("Failed to bind to address");
+    let listener = match inherited {
+        Some(socket) => {
+            let listener = TcpListener::from_std(socket).expect("Failed to use inherited socket");
+            info!("Using socket from systemd: {}", listener.local_addr().expect("Inherited socket has no address"));
+            listener
+        }
+        None => TcpListener::bind(&bind_addr)
+            .await
+            .expect("Failed to bind to address"),
+    };
     
-    info!("Ollama Proxy is ready");
-    
-    serve(listener, app)
-        .await
-        .expect("Server error");
+    match tls_settings {
+        Some(settings) => {
+            let acceptor = tls::acceptor(&settings).unwrap_or_else(|e| panic!("{}", e));
+            info!("Ollama Proxy is ready (HTTPS)");
+            tls::serve(listener, app, acceptor).await.expect("Server error");
+        }
+        None => {
+            info!("Ollama Proxy is ready");
+            serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("Server error");
+        }
+    }
 }
-
@@ -0,0 +1,123 @@
+/// Method handling for proxied routes: trailing slashes, OPTIONS, HEAD and 405s
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode};
+
+use crate::errors::{is_openai_path, ollama_error, openai_error};
+
+const READ: &[&str] = &["GET", "HEAD", "OPTIONS"];
+const WRITE: &[&str] = &["POST", "OPTIONS"];
+const REMOVE: &[&str] = &["DELETE", "OPTIONS"];
+const BLOB: &[&str] = &["HEAD", "POST", "OPTIONS"];
+
+/// `/api/chat/` and `/api/chat` are the same route
+pub fn normalize_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// Methods Ollama (or the translation layer) accepts on `path`; `None` for routes
+/// the proxy doesn't know, which are passed through untouched
+pub fn allowed_methods(path: &str) -> Option<&'static [&'static str]> {
+    match path {
+        "/" | "/api/tags" | "/api/ps" | "/api/version" | "/v1/models" => Some(READ),
+        "/api/chat" | "/api/generate" | "/api/embed" | "/api/embeddings" | "/api/show" | "/api/pull"
+        | "/api/push" | "/api/create" | "/api/copy" | "/v1/chat/completions" | "/v1/completions"
+        | "/v1/embeddings" => Some(WRITE),
+        "/api/delete" => Some(REMOVE),
+        p if p.starts_with("/v1/models/") => Some(READ),
+        p if p.starts_with("/api/blobs/") => Some(BLOB),
+        _ => None,
+    }
+}
+
+/// CORS preflights are left for Ollama, which applies its own origin rules
+fn is_preflight(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// Answer requests that never need to reach Ollama: plain OPTIONS on a known
+/// route (204 listing the allowed methods) and methods the route doesn't accept (405)
+pub fn check(method: &Method, path: &str, headers: &HeaderMap) -> Option<Response<Body>> {
+    let allowed = allowed_methods(path)?;
+    let allow = allowed.join(", ");
+    if *method == Method::OPTIONS && !is_preflight(headers) {
+        return Some(
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ALLOW, allow)
+                .body(Body::empty())
+                .unwrap(),
+        );
+    }
+    if allowed.contains(&method.as_str()) || *method == Method::OPTIONS {
+        return None;
+    }
+    let message = format!("method {} not allowed on {}, use {}", method, path, allow);
+    let mut response = if is_openai_path(path) {
+        openai_error(StatusCode::METHOD_NOT_ALLOWED, &message, None, Some("method_not_allowed"))
+    } else {
+        ollama_error(StatusCode::METHOD_NOT_ALLOWED, &message)
+    };
+    response.headers_mut().insert(header::ALLOW, HeaderValue::from_str(&allow).unwrap());
+    Some(response)
+}
+
+/// HEAD on a read route is sent upstream as GET (Ollama only registers HEAD on
+/// a few routes) and the body is dropped on the way back
+pub fn upstream_method(method: &Method, path: &str) -> Method {
+    if *method == Method::HEAD && allowed_methods(path) == Some(READ) {
+        Method::GET
+    } else {
+        method.clone()
+    }
+}
+
+/// Keep the status and headers of a response to a HEAD request, without the body
+pub fn strip_body(response: Response<Body>) -> Response<Body> {
+    let (parts, _) = response.into_parts();
+    Response::from_parts(parts, Body::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("/api/chat/"), "/api/chat");
+        assert_eq!(normalize_path("/v1/models"), "/v1/models");
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path("//"), "/");
+    }
+
+    #[test]
+    fn test_check_methods() {
+        let headers = HeaderMap::new();
+        assert!(check(&Method::POST, "/api/chat", &headers).is_none());
+        assert!(check(&Method::HEAD, "/api/version", &headers).is_none());
+        assert!(check(&Method::GET, "/api/unknown", &headers).is_none());
+
+        let rejected = check(&Method::GET, "/v1/chat/completions", &headers).unwrap();
+        assert_eq!(rejected.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(rejected.headers()[header::ALLOW], "POST, OPTIONS");
+
+        let options = check(&Method::OPTIONS, "/api/tags", &headers).unwrap();
+        assert_eq!(options.status(), StatusCode::NO_CONTENT);
+        assert_eq!(options.headers()[header::ALLOW], "GET, HEAD, OPTIONS");
+
+        // Preflights go to Ollama
+        let mut preflight = HeaderMap::new();
+        preflight.insert(header::ACCESS_CONTROL_REQUEST_METHOD, "POST".parse().unwrap());
+        assert!(check(&Method::OPTIONS, "/api/chat", &preflight).is_none());
+    }
+
+    #[test]
+    fn test_upstream_method() {
+        assert_eq!(upstream_method(&Method::HEAD, "/api/version"), Method::GET);
+        // Blob existence checks are real HEAD requests
+        assert_eq!(upstream_method(&Method::HEAD, "/api/blobs/sha256:abc"), Method::HEAD);
+        assert_eq!(upstream_method(&Method::POST, "/api/chat"), Method::POST);
+    }
+}
@@ -0,0 +1,197 @@
+/// Process-wide counters, exposed at /metrics in Prometheus text format
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::proxy::ProxyState;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Requests sent to Ollama by the proxy's upstream client
+    pub upstream_requests: AtomicU64,
+    /// New connections opened to Ollama (everything else reused a pooled one)
+    pub upstream_connections_opened: AtomicU64,
+    /// Retries after Ollama reported saturation (429/503)
+    pub saturation_retries: AtomicU64,
+    /// Retries while Ollama was still loading the requested model
+    pub model_loading_retries: AtomicU64,
+    /// Hedged requests where the second backend answered first
+    pub hedge_wins: AtomicU64,
+    /// Reconnect attempts while Ollama was unreachable (e.g. restarting)
+    pub restart_retries: AtomicU64,
+    /// Requests answered by joining an identical call already in flight
+    pub coalesced_requests: AtomicU64,
+    /// Requests sent to the cloud fallback instead of (or after) Ollama
+    pub cloud_fallbacks: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_upstream_request(&self) {
+        self.upstream_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_upstream_connection(&self) {
+        self.upstream_connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_saturation_retry(&self) {
+        self.saturation_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_model_loading_retry(&self) {
+        self.model_loading_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_hedge_win(&self) {
+        self.hedge_wins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_restart_retry(&self) {
+        self.restart_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced_request(&self) {
+        self.coalesced_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cloud_fallback(&self) {
+        self.cloud_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Fraction of upstream requests served over an already-open connection
+    pub fn connection_reuse_ratio(&self) -> f64 {
+        let requests = self.upstream_requests.load(Ordering::Relaxed);
+        let opened = self.upstream_connections_opened.load(Ordering::Relaxed);
+        if requests == 0 {
+            return 0.0;
+        }
+        1.0 - (opened.min(requests) as f64 / requests as f64)
+    }
+
+    /// Render all metrics in Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_requests_total",
+            "Requests sent to the Ollama upstream",
+            self.upstream_requests.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_connections_opened_total",
+            "New TCP connections opened to the Ollama upstream",
+            self.upstream_connections_opened.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_saturation_retries_total",
+            "Upstream requests retried after a 429/503 response",
+            self.saturation_retries.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_model_loading_retries_total",
+            "Upstream requests retried while Ollama was still loading the model",
+            self.model_loading_retries.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_hedge_wins_total",
+            "Hedged requests answered first by the secondary backend",
+            self.hedge_wins.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_upstream_restart_retries_total",
+            "Reconnect attempts while the Ollama upstream was unreachable",
+            self.restart_retries.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_coalesced_requests_total",
+            "Requests that joined an identical upstream call already in flight",
+            self.coalesced_requests.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "ollama_proxy_cloud_fallbacks_total",
+            "Requests sent to the cloud fallback because Ollama lacked the model or failed",
+            self.cloud_fallbacks.load(Ordering::Relaxed),
+        );
+        write_gauge(
+            &mut out,
+            "ollama_proxy_upstream_connection_reuse_ratio",
+            "Fraction of upstream requests that reused a pooled connection",
+            self.connection_reuse_ratio(),
+        );
+
+        out
+    }
+}
+
+pub(crate) fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+pub(crate) fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// GET /metrics
+pub async fn metrics_handler(State(state): State<ProxyState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render() + &state.admission.render_metrics() + &state.concurrency.render_metrics()
+            + &state.backends.render_metrics()
+            + &state.canary_routes.render_metrics()
+            + &state.embedding_cache.render_metrics()
+            + &state.chat_cache.render_metrics()
+            + &state.model_stats.render_metrics(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse_ratio() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.connection_reuse_ratio(), 0.0);
+
+        for _ in 0..4 {
+            metrics.record_upstream_request();
+        }
+        metrics.record_upstream_connection();
+
+        assert!((metrics.connection_reuse_ratio() - 0.75).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let metrics = Metrics::new();
+        metrics.record_upstream_request();
+        metrics.record_upstream_connection();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE ollama_proxy_upstream_requests_total counter"));
+        assert!(text.contains("ollama_proxy_upstream_requests_total 1"));
+        assert!(text.contains("ollama_proxy_upstream_connections_opened_total 1"));
+        assert!(text.contains("ollama_proxy_upstream_connection_reuse_ratio 0"));
+    }
+}
@@ -1,12 +1,41 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::sync::Mutex;
-use tracing::{debug, warn};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::backends::normalize_model;
+use crate::coalesce::SingleFlight;
+use crate::inventory::fetch_models;
+use crate::proxy::ProxyState;
+use crate::timeouts::apply_timeout;
+use crate::upstream::UpstreamClient;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelMetadata {
     pub n_ctx_train: u32,
     pub model_type: String,
+    /// Ollama's `capabilities` (`completion`, `tools`, `vision`, `embedding`, ...);
+    /// empty when the server is too old to report them
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Width of the model's embeddings (`<architecture>.embedding_length`)
+    #[serde(default)]
+    pub embedding_length: Option<u32>,
+    /// Quantization level, e.g. `Q4_K_M`
+    #[serde(default)]
+    pub quantization: Option<String>,
+    /// Parameter count as Ollama reports it, e.g. `8.0B`
+    #[serde(default)]
+    pub parameter_size: Option<String>,
 }
 
 impl Default for ModelMetadata {
@@ -14,59 +43,266 @@ impl Default for ModelMetadata {
         Self {
             n_ctx_train: 8192, // Reasonable default
             model_type: "unknown".to_string(),
+            capabilities: Vec::new(),
+            embedding_length: None,
+            quantization: None,
+            parameter_size: None,
         }
     }
 }
 
+impl ModelMetadata {
+    /// Whether the model accepts tool definitions (None when Ollama didn't say)
+    pub fn supports_tools(&self) -> Option<bool> {
+        self.has_capability("tools")
+    }
+
+    /// Whether the model accepts images (None when Ollama didn't say)
+    pub fn supports_vision(&self) -> Option<bool> {
+        self.has_capability("vision")
+    }
+
+    fn has_capability(&self, capability: &str) -> Option<bool> {
+        match self.capabilities.is_empty() {
+            true => None,
+            false => Some(self.capabilities.iter().any(|c| c == capability)),
+        }
+    }
+
+    /// The first of `required` the model is known to lack; models whose
+    /// capabilities are unknown are given the benefit of the doubt
+    pub fn missing_capability(&self, required: &[&'static str]) -> Option<&'static str> {
+        required.iter().copied().find(|capability| self.has_capability(capability) == Some(false))
+    }
+}
+
+/// Capabilities a chat or generate request needs from its model, in Ollama or
+/// OpenAI format: `tools` when it offers tools, `vision` when it carries images
+pub fn required_capabilities(body: &Value) -> Vec<&'static str> {
+    let non_empty = |value: &Value| value.as_array().is_some_and(|items| !items.is_empty());
+    let mut required = Vec::new();
+    if non_empty(&body["tools"]) || non_empty(&body["functions"]) {
+        required.push("tools");
+    }
+    let messages = body["messages"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let has_images = non_empty(&body["images"])
+        || messages.iter().any(|message| {
+            non_empty(&message["images"])
+                || message["content"]
+                    .as_array()
+                    .is_some_and(|parts| parts.iter().any(|part| part["type"] == "image_url"))
+        });
+    if has_images {
+        required.push("vision");
+    }
+    required
+}
+
+/// Warming of the metadata cache for every installed model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataPrefetch {
+    /// Fetch every model's metadata at startup
+    pub enabled: bool,
+    /// Time between later passes, which pick up newly pulled models (None = startup only)
+    pub interval: Option<Duration>,
+}
+
+impl Default for MetadataPrefetch {
+    fn default() -> Self {
+        Self { enabled: true, interval: Some(Duration::from_secs(600)) }
+    }
+}
+
+/// Fetch the metadata of every model in `/api/tags` now and then every `interval`
+pub fn spawn_prefetch(cache: Arc<ModelMetadataCache>, interval: Option<Duration>) {
+    tokio::spawn(async move {
+        loop {
+            match cache.prefetch().await {
+                Ok(models) => info!("📊 Prefetched metadata for {} models", models.len()),
+                Err(e) => warn!("⚠️  Failed to prefetch model metadata: {}", e),
+            }
+            match interval {
+                Some(interval) => tokio::time::sleep(interval).await,
+                None => break,
+            }
+        }
+    });
+}
+
+/// A cached entry and when it was fetched
+#[derive(Debug, Clone)]
+struct CachedMetadata {
+    metadata: ModelMetadata,
+    fetched: Instant,
+}
+
+/// Per-model metadata from `/api/show`. Hits only take a shared read lock, and a
+/// miss fetches outside any lock, joined by concurrent misses for the same model,
+/// so lookups for other models never wait on it. Entries older than the TTL are
+/// still served, while a background fetch replaces them (stale-while-revalidate).
+/// Fetches go through the proxy's pooled upstream client.
 pub struct ModelMetadataCache {
-    cache: Mutex<HashMap<String, ModelMetadata>>,
+    cache: Arc<RwLock<HashMap<String, CachedMetadata>>>,
+    fetches: Arc<SingleFlight<Result<ModelMetadata, String>>>,
+    /// Age after which an entry is refreshed (None = kept until restart)
+    ttl: Option<Duration>,
+    /// Timeout for each `/api/show` call (None = unbounded)
+    timeout: Option<Duration>,
     ollama_host: String,
-    client: reqwest::Client,
+    upstream: Arc<UpstreamClient>,
 }
 
 impl ModelMetadataCache {
-    pub fn new(ollama_host: String) -> Self {
+    pub fn new(ollama_host: String, upstream: Arc<UpstreamClient>) -> Self {
         Self {
-            cache: Mutex::new(HashMap::new()),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            fetches: Arc::new(SingleFlight::new()),
+            ttl: None,
+            timeout: None,
             ollama_host,
-            client: reqwest::Client::new(),
+            upstream,
         }
     }
 
+    /// Refresh entries once they are older than `ttl`
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Give up on an `/api/show` call after `timeout`
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Models whose metadata is cached, sorted by name
+    pub async fn cached_models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self.cache.read().await.keys().cloned().collect();
+        models.sort();
+        models
+    }
+
+    /// Cached entries with their age, sorted by model name
+    pub async fn entries(&self) -> Vec<(String, ModelMetadata, Duration)> {
+        let mut entries: Vec<_> = self
+            .cache
+            .read()
+            .await
+            .iter()
+            .map(|(model, cached)| (model.clone(), cached.metadata.clone(), cached.fetched.elapsed()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Drop a model's entry so the next lookup fetches it again; returns whether
+    /// it was cached
+    pub async fn invalidate(&self, model_name: &str) -> bool {
+        self.cache.write().await.remove(&normalize_model(model_name)).is_some()
+    }
+
     pub async fn get_model_info(&self, model_name: &str) -> Result<ModelMetadata, String> {
+        let started = std::time::Instant::now();
+        let metadata = self.lookup_model_info(model_name).await;
+        crate::timing::record("metadata_fetch", started);
+        metadata
+    }
+
+    async fn lookup_model_info(&self, model_name: &str) -> Result<ModelMetadata, String> {
+        // `llama3` and `llama3:latest` share an entry, as in /api/tags
+        let model_name = &normalize_model(model_name);
+
         // Check cache first
-        {
-            let cache = self.cache.lock().unwrap();
-            if let Some(metadata) = cache.get(model_name) {
+        let cached = self.cache.read().await.get(model_name).cloned();
+        if let Some(cached) = cached {
+            if self.ttl.is_some_and(|ttl| cached.fetched.elapsed() >= ttl) {
+                debug!("Stale cache hit for model: {}, refreshing in the background", model_name);
+                self.refresh_in_background(model_name);
+            } else {
                 debug!("Cache hit for model: {}", model_name);
-                return Ok(metadata.clone());
             }
+            return Ok(cached.metadata);
         }
 
         debug!("Cache miss for model: {}, fetching from Ollama API", model_name);
 
-        // Fetch from Ollama API
-        let metadata = self.fetch_model_info(model_name).await?;
+        // Fetch from Ollama API once for all concurrent misses
+        let (metadata, joined) = self.fetches.run(model_name.to_string(), self.fetch_and_store(model_name)).await;
+        if joined {
+            debug!("Joined in-flight metadata fetch for model: {}", model_name);
+        }
+        metadata
+    }
 
-        // Store in cache
-        {
-            let mut cache = self.cache.lock().unwrap();
-            cache.insert(model_name.to_string(), metadata.clone());
+    /// Fetch the metadata of every model installed on Ollama, logging each one's
+    /// resolved context; models that fail are logged and skipped
+    pub async fn prefetch(&self) -> Result<Vec<(String, ModelMetadata)>, String> {
+        let mut fetched = Vec::new();
+        for model in fetch_models(&self.upstream.get(), &self.ollama_host).await? {
+            match self.fetches.run(model.clone(), self.fetch_and_store(&model)).await {
+                (Ok(metadata), _) => {
+                    info!(
+                        "📊 {}: n_ctx_train {}, {}, {} ({})",
+                        model,
+                        metadata.n_ctx_train,
+                        metadata.model_type,
+                        metadata.parameter_size.as_deref().unwrap_or("size unknown"),
+                        match metadata.capabilities.is_empty() {
+                            true => "capabilities unknown".to_string(),
+                            false => metadata.capabilities.join(", "),
+                        }
+                    );
+                    fetched.push((model, metadata));
+                }
+                (Err(e), _) => warn!("⚠️  Failed to fetch metadata for {}: {}", model, e),
+            }
         }
+        Ok(fetched)
+    }
 
-        Ok(metadata)
+    /// Replace a stale entry without making the request wait; on failure the stale
+    /// entry is kept and the next lookup tries again
+    fn refresh_in_background(&self, model_name: &str) {
+        let fetches = self.fetches.clone();
+        let work = self.fetch_and_store(model_name);
+        let model = model_name.to_string();
+        tokio::spawn(async move {
+            if let (Err(e), false) = fetches.run(model.clone(), work).await {
+                warn!("⚠️  Failed to refresh metadata for {}, keeping the cached entry: {}", model, e);
+            }
+        });
     }
 
-    async fn fetch_model_info(&self, model_name: &str) -> Result<ModelMetadata, String> {
-        let url = format!("{}/api/show", self.ollama_host);
+    /// Fetch a model's metadata from Ollama and store it in the cache
+    fn fetch_and_store(&self, model_name: &str) -> impl Future<Output = Result<ModelMetadata, String>> + Send + 'static {
+        let (upstream, host, cache) = (self.upstream.clone(), self.ollama_host.clone(), self.cache.clone());
+        let timeout = self.timeout;
+        let model = model_name.to_string();
+        async move {
+            let metadata = Self::fetch_model_info(&upstream.get(), &host, &model, timeout).await?;
+            let entry = CachedMetadata { metadata: metadata.clone(), fetched: Instant::now() };
+            let previous = cache.write().await.insert(model.clone(), entry);
+            if let Some(previous) = previous.filter(|p| p.metadata.n_ctx_train != metadata.n_ctx_train) {
+                info!("🔄 Metadata for {} changed: n_ctx_train {} → {}", model, previous.metadata.n_ctx_train, metadata.n_ctx_train);
+            }
+            Ok(metadata)
+        }
+    }
+
+    async fn fetch_model_info(
+        client: &reqwest::Client,
+        ollama_host: &str,
+        model_name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<ModelMetadata, String> {
+        let url = format!("{}/api/show", ollama_host);
         
         let request_body = serde_json::json!({
             "name": model_name
         });
 
-        let response = self.client
-            .post(&url)
-            .json(&request_body)
+        let response = apply_timeout(client.post(&url).json(&request_body), timeout)
             .send()
             .await
             .map_err(|e| format!("Failed to fetch model info: {}", e))?;
@@ -80,32 +316,55 @@ impl ModelMetadataCache {
             .await
             .map_err(|e| format!("Failed to parse response: {}", e))?;
 
-        // Extract n_ctx_train from model details
-        let n_ctx_train = self.extract_n_ctx_train(&response_json);
-        let model_type = self.extract_model_type(&response_json);
+        Ok(Self::parse_show(&response_json))
+    }
 
-        Ok(ModelMetadata {
-            n_ctx_train,
-            model_type,
-        })
+    /// Metadata from an `/api/show` response
+    fn parse_show(response: &Value) -> ModelMetadata {
+        let details = &response["details"];
+        let text = |value: &Value| value.as_str().filter(|s| !s.is_empty()).map(str::to_string);
+        ModelMetadata {
+            n_ctx_train: Self::extract_n_ctx_train(response),
+            model_type: Self::extract_model_type(response),
+            capabilities: response["capabilities"]
+                .as_array()
+                .map(|items| items.iter().filter_map(|c| c.as_str().map(str::to_string)).collect())
+                .unwrap_or_default(),
+            embedding_length: Self::architecture_value(response, "embedding_length")
+                .and_then(Value::as_u64)
+                .map(|value| value as u32),
+            quantization: text(&details["quantization_level"]),
+            parameter_size: text(&details["parameter_size"]),
+        }
     }
 
-    fn extract_n_ctx_train(&self, response: &serde_json::Value) -> u32 {
-        // Try to extract from model_info -> llama.context_length or similar fields
-        // The response structure may vary, so we'll try multiple paths
-        
-        // Check modelinfo first
-        if let Some(model_info) = response.get("model_info") {
-            if let Some(params) = model_info.as_object() {
-                // Try various possible field names
-                for key in params.keys() {
-                    if key.contains("context") || key.contains("ctx") {
-                        if let Some(value) = params.get(key).and_then(|v| v.as_u64()) {
-                            debug!("Found n_ctx_train in model_info.{}: {}", key, value);
-                            return value as u32;
-                        }
-                    }
-                }
+    /// `model_info["<architecture>.<key>"]`, for the model's `general.architecture`
+    fn architecture_value<'a>(response: &'a Value, key: &str) -> Option<&'a Value> {
+        let model_info = response.get("model_info")?;
+        let architecture = model_info.get("general.architecture")?.as_str()?;
+        model_info.get(format!("{}.{}", architecture, key))
+    }
+
+    fn extract_n_ctx_train(response: &serde_json::Value) -> u32 {
+        // The GGUF key for the training context is `<architecture>.context_length`
+        // (`llama.context_length`, `qwen2.context_length`, `bert.context_length`, ...),
+        // next to look-alikes such as `phi3.rope.scaling.original_context_length` or a
+        // vision tower's `clip.context_length`
+        if let Some(value) = Self::architecture_value(response, "context_length").and_then(|v| v.as_u64()) {
+            let architecture = response["model_info"]["general.architecture"].as_str().unwrap_or_default();
+            debug!("Found n_ctx_train in model_info.{}.context_length: {}", architecture, value);
+            return value as u32;
+        }
+
+        // Without `general.architecture`, take the only top-level `<name>.context_length`
+        if let Some(model_info) = response.get("model_info").and_then(|v| v.as_object()) {
+            let mut candidates = model_info
+                .iter()
+                .filter(|(key, _)| key.strip_suffix(".context_length").is_some_and(|name| !name.contains('.')))
+                .filter_map(|(key, value)| Some((key, value.as_u64()?)));
+            if let (Some((key, value)), None) = (candidates.next(), candidates.next()) {
+                debug!("Found n_ctx_train in model_info.{}: {}", key, value);
+                return value as u32;
             }
         }
 
@@ -121,7 +380,7 @@ impl ModelMetadataCache {
 
         // Try parsing the modelfile for context information
         if let Some(modelfile) = response.get("modelfile").and_then(|v| v.as_str()) {
-            if let Some(ctx) = self.extract_ctx_from_modelfile(modelfile) {
+            if let Some(ctx) = Self::extract_ctx_from_modelfile(modelfile) {
                 debug!("Found context in modelfile: {}", ctx);
                 return ctx;
             }
@@ -130,7 +389,7 @@ impl ModelMetadataCache {
         // Try template or parameters
         if let Some(parameters) = response.get("parameters") {
             if let Some(params_str) = parameters.as_str() {
-                if let Some(ctx) = self.extract_ctx_from_params(params_str) {
+                if let Some(ctx) = Self::extract_ctx_from_params(params_str) {
                     debug!("Found context in parameters: {}", ctx);
                     return ctx;
                 }
@@ -143,7 +402,17 @@ impl ModelMetadataCache {
         8192 // Default fallback
     }
 
-    fn extract_model_type(&self, response: &serde_json::Value) -> String {
+    fn extract_model_type(response: &serde_json::Value) -> String {
+        // Newer Ollama versions say so directly
+        if let Some(capabilities) = response.get("capabilities").and_then(|v| v.as_array()) {
+            if capabilities.iter().any(|c| c == "embedding") {
+                return "embedding".to_string();
+            }
+            if capabilities.iter().any(|c| c == "completion") {
+                return "chat".to_string();
+            }
+        }
+
         // Check if this is an embedding model
         if let Some(modelfile) = response.get("modelfile").and_then(|v| v.as_str()) {
             if modelfile.to_lowercase().contains("embed") {
@@ -160,7 +429,7 @@ impl ModelMetadataCache {
         "chat".to_string()
     }
 
-    fn extract_ctx_from_modelfile(&self, modelfile: &str) -> Option<u32> {
+    fn extract_ctx_from_modelfile(modelfile: &str) -> Option<u32> {
         // Look for PARAMETER num_ctx in the modelfile
         for line in modelfile.lines() {
             if line.to_lowercase().contains("parameter") && line.contains("num_ctx") {
@@ -176,7 +445,7 @@ impl ModelMetadataCache {
         None
     }
 
-    fn extract_ctx_from_params(&self, params: &str) -> Option<u32> {
+    fn extract_ctx_from_params(params: &str) -> Option<u32> {
         // Look for num_ctx in a parameter string
         for line in params.lines() {
             if line.contains("num_ctx") {
@@ -192,3 +461,145 @@ impl ModelMetadataCache {
     }
 }
 
+/// GET /proxy/admin/metadata - cached model metadata and how old each entry is
+pub async fn list_handler(State(state): State<ProxyState>) -> Json<Value> {
+    let cache = &state.metadata_cache;
+    let models: Vec<Value> = cache
+        .entries()
+        .await
+        .into_iter()
+        .map(|(model, metadata, age)| {
+            json!({
+                "model": model,
+                "n_ctx_train": metadata.n_ctx_train,
+                "model_type": metadata.model_type,
+                "capabilities": metadata.capabilities,
+                "embedding_length": metadata.embedding_length,
+                "quantization": metadata.quantization,
+                "parameter_size": metadata.parameter_size,
+                "age_seconds": age.as_secs(),
+                "stale": cache.ttl.is_some_and(|ttl| age >= ttl),
+            })
+        })
+        .collect();
+    Json(json!({
+        "ttl_seconds": cache.ttl.map(|ttl| ttl.as_secs()),
+        "models": models,
+    }))
+}
+
+/// DELETE /proxy/admin/metadata/{name} - forget a model's metadata, e.g. after its
+/// Modelfile was edited, so the next request fetches it again
+pub async fn invalidate_handler(State(state): State<ProxyState>, Path(model): Path<String>) -> Response {
+    let model = model.trim_matches('/');
+    if model.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Missing model name" }))).into_response();
+    }
+    match state.metadata_cache.invalidate(model).await {
+        true => {
+            info!("🗑️  Invalidated cached metadata for {}", normalize_model(model));
+            StatusCode::NO_CONTENT.into_response()
+        }
+        false => {
+            let message = format!("No cached metadata for '{}'", model);
+            (StatusCode::NOT_FOUND, Json(json!({ "error": message }))).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_show_records_capabilities() {
+        let show = json!({
+            "details": {"family": "llama", "parameter_size": "8.0B", "quantization_level": "Q4_K_M"},
+            "model_info": {
+                "general.architecture": "llama",
+                "llama.context_length": 131072,
+                "llama.embedding_length": 4096
+            },
+            "capabilities": ["completion", "tools"]
+        });
+        let metadata = ModelMetadataCache::parse_show(&show);
+        assert_eq!(metadata.n_ctx_train, 131072);
+        assert_eq!(metadata.model_type, "chat");
+        assert_eq!(metadata.embedding_length, Some(4096));
+        assert_eq!(metadata.quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(metadata.parameter_size.as_deref(), Some("8.0B"));
+        assert_eq!(metadata.supports_tools(), Some(true));
+        assert_eq!(metadata.supports_vision(), Some(false));
+        assert_eq!(metadata.missing_capability(&["tools", "vision"]), Some("vision"));
+
+        // Older servers don't report capabilities, so nothing is ruled out
+        let metadata = ModelMetadataCache::parse_show(&json!({"model_info": {}}));
+        assert_eq!(metadata.supports_tools(), None);
+        assert_eq!(metadata.missing_capability(&["tools", "vision"]), None);
+    }
+
+    fn fixture(name: &str) -> Value {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/show").join(name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_context_length_of_captured_models() {
+        let cases = [
+            ("llama3.1.json", 131072, "chat", Some(4096)),
+            ("qwen2.5.json", 32768, "chat", Some(3584)),
+            ("phi3.json", 131072, "chat", Some(3072)),
+            ("gemma3.json", 131072, "chat", Some(2560)),
+            ("nomic-embed-text.json", 2048, "embedding", Some(768)),
+            ("mxbai-embed-large.json", 512, "embedding", Some(1024)),
+        ];
+        for (name, n_ctx_train, model_type, embedding_length) in cases {
+            let metadata = ModelMetadataCache::parse_show(&fixture(name));
+            assert_eq!(metadata.n_ctx_train, n_ctx_train, "{}", name);
+            assert_eq!(metadata.model_type, model_type, "{}", name);
+            assert_eq!(metadata.embedding_length, embedding_length, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_context_length_ignores_look_alike_keys() {
+        // A vision tower's text context sorts before the language model's
+        let llava = json!({"model_info": {
+            "clip.context_length": 77,
+            "general.architecture": "llama",
+            "llama.context_length": 4096
+        }});
+        assert_eq!(ModelMetadataCache::extract_n_ctx_train(&llava), 4096);
+
+        // Without general.architecture, only a top-level `<name>.context_length` counts
+        let untagged = json!({"model_info": {
+            "phi3.context_length": 131072,
+            "phi3.rope.scaling.original_context_length": 4096
+        }});
+        assert_eq!(ModelMetadataCache::extract_n_ctx_train(&untagged), 131072);
+
+        // Otherwise the Modelfile's num_ctx is used
+        let modelfile = json!({"model_info": {}, "modelfile": "FROM x\nPARAMETER num_ctx 16384\n"});
+        assert_eq!(ModelMetadataCache::extract_n_ctx_train(&modelfile), 16384);
+    }
+
+    #[test]
+    fn test_required_capabilities() {
+        let tools = json!({"messages": [], "tools": [{"type": "function"}]});
+        assert_eq!(required_capabilities(&tools), vec!["tools"]);
+
+        let native_images = json!({"messages": [{"role": "user", "content": "hi", "images": ["aGk="]}]});
+        assert_eq!(required_capabilities(&native_images), vec!["vision"]);
+
+        let openai_images = json!({"messages": [{"role": "user", "content": [
+            {"type": "text", "text": "hi"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,aGk="}}
+        ]}]});
+        assert_eq!(required_capabilities(&openai_images), vec!["vision"]);
+
+        let generate = json!({"prompt": "hi", "images": ["aGk="], "tools": []});
+        assert_eq!(required_capabilities(&generate), vec!["vision"]);
+        assert!(required_capabilities(&json!({"messages": [{"role": "user", "content": "hi"}]})).is_empty());
+    }
+}
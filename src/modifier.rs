@@ -1,6 +1,9 @@
 use serde_json::Value;
 use tracing::{info, warn};
+use crate::backends::model_matches;
+use crate::compression::{PromptCompression, PromptCompressionModifier};
 use crate::model_metadata::ModelMetadata;
+use crate::overrides::{ModelSettings, DEFAULT_NUM_PREDICT};
 
 /// Trait for parameter modifiers
 /// Each modifier can inspect and modify request parameters
@@ -9,8 +12,24 @@ pub trait ParameterModifier {
     fn name(&self) -> &str;
 }
 
+/// Trait for response modifiers
+/// Each modifier can inspect and modify an Ollama response before it is translated
+pub trait ResponseModifier {
+    fn modify_response(&self, json: &mut Value, model: &str) -> bool;
+    fn name(&self) -> &str;
+}
+
 /// Num predict modifier - adds num_predict to prevent infinite generation
-pub struct NumPredictModifier;
+pub struct NumPredictModifier {
+    /// Limit used when the request has no max_tokens either
+    pub default: u32,
+}
+
+impl Default for NumPredictModifier {
+    fn default() -> Self {
+        Self { default: DEFAULT_NUM_PREDICT }
+    }
+}
 
 impl ParameterModifier for NumPredictModifier {
     fn modify(&self, json: &mut Value, _metadata: &ModelMetadata, _max_context_override: u32) -> bool {
@@ -32,10 +51,11 @@ impl ParameterModifier for NumPredictModifier {
             return false;
         }
 
-        // Get max_tokens from request (OpenAI format) or use default
-        let max_tokens = json.get("max_tokens")
+        // Get max_completion_tokens (newer OpenAI clients) or max_tokens, or use default
+        let max_tokens = json.get("max_completion_tokens")
             .and_then(|v| v.as_u64())
-            .unwrap_or(4096) as u32;
+            .or_else(|| json.get("max_tokens").and_then(|v| v.as_u64()))
+            .map_or(self.default, |tokens| tokens as u32);
 
         info!("ℹ️  No num_predict specified, adding to prevent infinite generation");
         
@@ -54,7 +74,7 @@ impl ParameterModifier for NumPredictModifier {
                     Value::Number(max_tokens.into())
                 );
                 info!(
-                    "✏️  Added options.num_predict: {} (from max_tokens or default)",
+                    "✏️  Added options.num_predict: {} (from max_completion_tokens, max_tokens or default)",
                     max_tokens
                 );
                 modified = true;
@@ -246,11 +266,122 @@ impl ContextLimitModifier {
     }
 }
 
-/// Apply all modifiers to the request
-pub fn apply_modifiers(json: &mut Value, metadata: &ModelMetadata, max_context_override: u32) -> bool {
+/// Think tag modifier - removes <think>...</think> reasoning spans from the
+/// assistant content of matching models, for UIs that can't render them
+pub struct ThinkTagModifier<'a> {
+    /// Model names or `prefix*` patterns (`*` for every model)
+    pub models: &'a [String],
+}
+
+impl ResponseModifier for ThinkTagModifier<'_> {
+    fn modify_response(&self, json: &mut Value, model: &str) -> bool {
+        if !self.models.iter().any(|pattern| model_matches(pattern, model)) {
+            return false;
+        }
+        let Some(content) = json.pointer_mut("/message/content") else {
+            return false;
+        };
+        let Some(text) = content.as_str() else {
+            return false;
+        };
+        let stripped = strip_think_tags(text);
+        if stripped == text {
+            return false;
+        }
+        info!("✂️  Removed {} characters of <think> reasoning", text.len() - stripped.len());
+        *content = Value::String(stripped);
+        true
+    }
+
+    fn name(&self) -> &str {
+        "ThinkTagModifier"
+    }
+}
+
+/// Remove `<think>...</think>` spans. An unclosed span runs to the end, and a
+/// closing tag without an opening one (opened by the chat template) ends a span
+/// that started at the beginning.
+pub fn strip_think_tags(text: &str) -> String {
+    const OPEN: &str = "<think>";
+    const CLOSE: &str = "</think>";
+
+    let mut rest = text;
+    if let Some(end) = rest.find(CLOSE) {
+        if !rest[..end].contains(OPEN) {
+            rest = &rest[end + CLOSE.len()..];
+        }
+    }
+    let mut out = String::with_capacity(rest.len());
+    while let Some(start) = rest.find(OPEN) {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find(CLOSE) {
+            Some(end) => &rest[start + end + CLOSE.len()..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    if out.len() == text.len() {
+        return out;
+    }
+    out.trim_start().to_string()
+}
+
+/// Apply all response modifiers to an Ollama response for `model`
+pub fn apply_response_modifiers(json: &mut Value, model: &str, strip_think_models: &[String]) -> bool {
+    let modifiers: Vec<Box<dyn ResponseModifier + '_>> = vec![
+        Box::new(ThinkTagModifier { models: strip_think_models }),
+    ];
+
+    let mut any_modified = false;
+
+    for modifier in modifiers {
+        if modifier.modify_response(json, model) {
+            info!("🔧 {} applied modifications", modifier.name());
+            any_modified = true;
+        }
+    }
+
+    any_modified
+}
+
+/// Keep alive modifier - sends the keep_alive configured for the model
+pub struct KeepAliveModifier(pub Option<String>);
+
+impl ParameterModifier for KeepAliveModifier {
+    fn modify(&self, json: &mut Value, _metadata: &ModelMetadata, _max_context_override: u32) -> bool {
+        let (Some(keep_alive), Some(obj)) = (&self.0, json.as_object_mut()) else {
+            return false;
+        };
+        if obj.get("keep_alive").and_then(Value::as_str) == Some(keep_alive) {
+            return false;
+        }
+        info!("✏️  Set keep_alive: {}", keep_alive);
+        obj.insert("keep_alive".to_string(), Value::String(keep_alive.clone()));
+        true
+    }
+
+    fn name(&self) -> &str {
+        "KeepAliveModifier"
+    }
+}
+
+/// Apply all modifiers to the request. `settings` (from a `[models."name"]` section)
+/// replace the global context cap and defaults for this model.
+pub fn apply_modifiers(
+    json: &mut Value,
+    metadata: &ModelMetadata,
+    max_context_override: u32,
+    compression: PromptCompression,
+    settings: Option<&ModelSettings>,
+) -> bool {
+    let settings = settings.cloned().unwrap_or_default();
+    let max_context_override = settings.max_context.unwrap_or(max_context_override);
+    let num_predict = NumPredictModifier { default: settings.num_predict_default.unwrap_or(DEFAULT_NUM_PREDICT) };
     let modifiers: Vec<Box<dyn ParameterModifier>> = vec![
-        Box::new(NumPredictModifier),  // Must run first to prevent infinite generation
+        Box::new(num_predict),  // Must run first to prevent infinite generation
         Box::new(ContextLimitModifier),
+        Box::new(KeepAliveModifier(settings.keep_alive)),
+        Box::new(PromptCompressionModifier(compression)),  // Needs the final num_ctx and num_predict
         // Future modifiers can be added here
     ];
 
@@ -283,6 +414,7 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 8192,
             model_type: "embedding".to_string(),
+            ..Default::default()
         };
 
         let modifier = ContextLimitModifier;
@@ -307,6 +439,7 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 8192,
             model_type: "embedding".to_string(),
+            ..Default::default()
         };
 
         let modifier = ContextLimitModifier;
@@ -329,6 +462,7 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 8192,
             model_type: "embedding".to_string(),
+            ..Default::default()
         };
 
         let modifier = ContextLimitModifier;
@@ -351,6 +485,7 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 131072,
             model_type: "chat".to_string(),
+            ..Default::default()
         };
 
         let modifier = ContextLimitModifier;
@@ -377,6 +512,7 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 131072,
             model_type: "chat".to_string(),
+            ..Default::default()
         };
 
         let modifier = ContextLimitModifier;
@@ -402,9 +538,10 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 131072,
             model_type: "chat".to_string(),
+            ..Default::default()
         };
 
-        let modifier = NumPredictModifier;
+        let modifier = NumPredictModifier::default();
         let modified = modifier.modify(&mut request, &metadata, 16384);
 
         assert!(modified);
@@ -427,9 +564,10 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 131072,
             model_type: "chat".to_string(),
+            ..Default::default()
         };
 
-        let modifier = NumPredictModifier;
+        let modifier = NumPredictModifier::default();
         let modified = modifier.modify(&mut request, &metadata, 16384);
 
         assert!(modified);
@@ -439,6 +577,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_num_predict_prefers_max_completion_tokens() {
+        let mut request = json!({
+            "model": "gpt-oss:20b",
+            "messages": [
+                {"role": "user", "content": "Hello"}
+            ],
+            "max_tokens": 2048,
+            "max_completion_tokens": 512
+        });
+
+        let metadata = ModelMetadata {
+            n_ctx_train: 131072,
+            model_type: "chat".to_string(),
+            ..Default::default()
+        };
+
+        let modifier = NumPredictModifier::default();
+        let modified = modifier.modify(&mut request, &metadata, 16384);
+
+        assert!(modified);
+        assert_eq!(
+            request["options"]["num_predict"].as_u64().unwrap(),
+            512 // max_completion_tokens wins over max_tokens
+        );
+    }
+
+    #[test]
+    fn test_strip_think_tags() {
+        assert_eq!(strip_think_tags("<think>hmm, 2 + 2</think>\n\nIt is 4."), "It is 4.");
+        assert_eq!(strip_think_tags("reasoning only closed</think>Answer"), "Answer");
+        assert_eq!(strip_think_tags("A <think>aside</think>B <think>cut off"), "A B ");
+        assert_eq!(strip_think_tags("  no tags here"), "  no tags here");
+    }
+
+    #[test]
+    fn test_think_tag_modifier_matches_models() {
+        let models = vec!["deepseek-r1*".to_string()];
+        let mut response = json!({"message": {"role": "assistant", "content": "<think>x</think>Hi"}});
+
+        assert!(!apply_response_modifiers(&mut response, "llama3", &models));
+        assert_eq!(response["message"]["content"], "<think>x</think>Hi");
+        assert!(apply_response_modifiers(&mut response, "deepseek-r1:14b", &models));
+        assert_eq!(response["message"]["content"], "Hi");
+    }
+
     #[test]
     fn test_num_predict_preserved_when_exists() {
         let mut request = json!({
@@ -454,9 +638,10 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 131072,
             model_type: "chat".to_string(),
+            ..Default::default()
         };
 
-        let modifier = NumPredictModifier;
+        let modifier = NumPredictModifier::default();
         let modified = modifier.modify(&mut request, &metadata, 16384);
 
         assert!(!modified); // Should not modify
@@ -476,9 +661,10 @@ mod tests {
         let metadata = ModelMetadata {
             n_ctx_train: 8192,
             model_type: "embedding".to_string(),
+            ..Default::default()
         };
 
-        let modifier = NumPredictModifier;
+        let modifier = NumPredictModifier::default();
         let modified = modifier.modify(&mut request, &metadata, 16384);
 
         assert!(!modified); // Should not modify embeddings
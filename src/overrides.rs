@@ -0,0 +1,190 @@
+/// Per-model settings from `[models."name"]` sections of the config file
+use std::collections::BTreeMap;
+
+use crate::backends::model_matches;
+
+/// Generation limit added to chat requests that don't set one
+pub const DEFAULT_NUM_PREDICT: u32 = 4096;
+
+/// Settings that replace the global defaults for one model
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModelSettings {
+    /// Replaces MAX_CONTEXT_OVERRIDE
+    pub max_context: Option<u32>,
+    /// num_predict added when the request sets no limit
+    pub num_predict_default: Option<u32>,
+    /// keep_alive sent with every request (e.g. `10m`, `-1`)
+    pub keep_alive: Option<String>,
+    /// Requests to the model sent to Ollama at once (1 serializes them)
+    pub max_concurrent: Option<u32>,
+}
+
+/// Model names (or `prefix*` patterns) and their settings
+#[derive(Debug, Clone, Default)]
+pub struct ModelOverrides {
+    models: BTreeMap<String, ModelSettings>,
+}
+
+impl ModelOverrides {
+    /// Read the `models` table of a config file:
+    ///
+    /// ```toml
+    /// [models."llama3.3"]
+    /// max_context = 32768
+    /// num_predict_default = 2048
+    /// keep_alive = "10m"
+    /// max_concurrent = 1
+    /// ```
+    pub fn from_toml(models: &toml::Table) -> Result<Self, String> {
+        let mut overrides = BTreeMap::new();
+        for (model, section) in models {
+            let section = section
+                .as_table()
+                .ok_or_else(|| format!("[models.\"{}\"] must be a table", model))?;
+            let mut settings = ModelSettings::default();
+            for (key, value) in section {
+                let number = || {
+                    value
+                        .as_integer()
+                        .and_then(|n| u32::try_from(n).ok())
+                        .ok_or_else(|| format!("models.\"{}\".{} must be a positive integer", model, key))
+                };
+                match key.as_str() {
+                    "max_context" => settings.max_context = Some(number()?),
+                    "num_predict_default" => settings.num_predict_default = Some(number()?),
+                    "max_concurrent" => settings.max_concurrent = Some(number()?),
+                    "keep_alive" => {
+                        settings.keep_alive = Some(match value {
+                            toml::Value::String(duration) => duration.clone(),
+                            toml::Value::Integer(seconds) => seconds.to_string(),
+                            _ => return Err(format!("models.\"{}\".keep_alive must be a duration or seconds", model)),
+                        })
+                    }
+                    other => return Err(format!("Unknown setting models.\"{}\".{}", model, other)),
+                }
+            }
+            overrides.insert(model.clone(), settings);
+        }
+        Ok(Self { models: overrides })
+    }
+
+    /// Same bounds as the global settings they replace
+    pub fn validate(&self) -> Result<(), String> {
+        for (model, settings) in &self.models {
+            if settings.max_context.is_some_and(|max_context| max_context < 512) {
+                return Err(format!("models.\"{}\".max_context must be at least 512 tokens", model));
+            }
+            if settings.max_concurrent == Some(0) {
+                return Err(format!("models.\"{}\".max_concurrent must be at least 1", model));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    pub fn insert(&mut self, model: &str, settings: ModelSettings) {
+        self.models.insert(model.to_string(), settings);
+    }
+
+    /// The same sections with every max_context lowered to at most `cap`
+    pub fn cap_context(&self, cap: u32) -> Self {
+        let mut capped = self.clone();
+        for settings in capped.models.values_mut() {
+            settings.max_context = settings.max_context.map(|max_context| max_context.min(cap));
+        }
+        capped
+    }
+
+    /// Settings for `model`: an exact name wins over the longest matching `prefix*` pattern
+    pub fn for_model(&self, model: &str) -> Option<&ModelSettings> {
+        self.models
+            .iter()
+            .filter(|(pattern, _)| !pattern.ends_with('*') && model_matches(pattern, model))
+            .chain(
+                self.models
+                    .iter()
+                    .filter(|(pattern, _)| pattern.ends_with('*') && model_matches(pattern, model))
+                    .max_by_key(|(pattern, _)| pattern.len()),
+            )
+            .map(|(_, settings)| settings)
+            .next()
+    }
+
+    /// One line per model, for the startup summary
+    pub fn describe(&self) -> Vec<String> {
+        self.models
+            .iter()
+            .map(|(model, settings)| {
+                let mut parts = Vec::new();
+                if let Some(max_context) = settings.max_context {
+                    parts.push(format!("max_context={}", max_context));
+                }
+                if let Some(num_predict) = settings.num_predict_default {
+                    parts.push(format!("num_predict_default={}", num_predict));
+                }
+                if let Some(keep_alive) = &settings.keep_alive {
+                    parts.push(format!("keep_alive={}", keep_alive));
+                }
+                if let Some(max_concurrent) = settings.max_concurrent {
+                    parts.push(format!("max_concurrent={}", max_concurrent));
+                }
+                format!("{}: {}", model, parts.join(" "))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(text: &str) -> Result<ModelOverrides, String> {
+        let table: toml::Table = text.parse().unwrap();
+        ModelOverrides::from_toml(table["models"].as_table().unwrap())
+    }
+
+    #[test]
+    fn test_sections_and_lookup() {
+        let overrides = overrides(
+            r#"
+            [models."llama3.3"]
+            max_context = 32768
+            num_predict_default = 2048
+            keep_alive = "10m"
+
+            [models."qwen*"]
+            keep_alive = -1
+            max_concurrent = 1
+
+            [models."qwen2.5-coder*"]
+            max_context = 65536
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            overrides.for_model("llama3.3:latest"),
+            Some(&ModelSettings {
+                max_context: Some(32768),
+                num_predict_default: Some(2048),
+                keep_alive: Some("10m".to_string()),
+                max_concurrent: None,
+            })
+        );
+        assert_eq!(overrides.for_model("qwen2.5:7b").unwrap().keep_alive.as_deref(), Some("-1"));
+        assert_eq!(overrides.for_model("qwen2.5:7b").unwrap().max_concurrent, Some(1));
+        assert_eq!(overrides.for_model("qwen2.5-coder:32b").unwrap().max_context, Some(65536));
+        assert_eq!(overrides.for_model("llama3"), None);
+    }
+
+    #[test]
+    fn test_invalid_sections_are_rejected() {
+        assert!(overrides("[models.\"llama3\"]\nmax_context = 100").unwrap().validate().is_err());
+        assert!(overrides("[models.\"llama3\"]\nmax_concurrent = 0").unwrap().validate().is_err());
+        assert!(overrides("[models.\"llama3\"]\nnum_ctx = 8192").is_err());
+        assert!(overrides("[models.\"llama3\"]\nnum_predict_default = \"lots\"").is_err());
+    }
+}
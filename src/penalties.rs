@@ -0,0 +1,151 @@
+/// Mapping of OpenAI presence/frequency penalties onto Ollama sampling options
+use crate::backends::{model_matches, normalize_model};
+use crate::translator::OllamaChatOptions;
+
+/// How a model receives OpenAI's `presence_penalty` and `frequency_penalty`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PenaltyMode {
+    /// Ollama's own `presence_penalty` / `frequency_penalty` options
+    #[default]
+    Direct,
+    /// Folded into `repeat_penalty`, for runners that ignore the OpenAI-style options
+    Repeat,
+    /// Discarded
+    Drop,
+}
+
+impl PenaltyMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "direct" => Some(Self::Direct),
+            "repeat" | "repeat_penalty" => Some(Self::Repeat),
+            "drop" | "ignore" => Some(Self::Drop),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Direct => "direct",
+            Self::Repeat => "repeat",
+            Self::Drop => "drop",
+        }
+    }
+}
+
+/// Default mode plus per-model overrides
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PenaltyMapping {
+    default: PenaltyMode,
+    /// (model or `prefix*` pattern, mode), first match wins
+    models: Vec<(String, PenaltyMode)>,
+}
+
+impl PenaltyMapping {
+    /// Parse `repeat` or `direct,llama3*=repeat,phi3:mini=drop`: a bare mode sets
+    /// the default, `model=mode` overrides it (a trailing `*` matches a prefix)
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut mapping = Self::default();
+        for rule in spec.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let invalid = || format!("Invalid penalty mapping '{}', expected direct, repeat or drop", rule);
+            match rule.split_once('=') {
+                Some((model, mode)) => {
+                    let model = model.trim();
+                    if model.is_empty() {
+                        return Err(invalid());
+                    }
+                    let pattern = match model.strip_suffix('*') {
+                        Some(prefix) => format!("{}*", prefix),
+                        None => normalize_model(model),
+                    };
+                    mapping.models.push((pattern, PenaltyMode::parse(mode).ok_or_else(invalid)?));
+                }
+                None => mapping.default = PenaltyMode::parse(rule).ok_or_else(invalid)?,
+            }
+        }
+        Ok(mapping)
+    }
+
+    pub fn mode_for(&self, model: &str) -> PenaltyMode {
+        self.models
+            .iter()
+            .find(|(pattern, _)| model_matches(pattern, model))
+            .map_or(self.default, |(_, mode)| *mode)
+    }
+
+    /// Rewrite the penalties the translator copied into `options` for `model`
+    pub fn apply(&self, model: &str, options: &mut OllamaChatOptions) {
+        match self.mode_for(model) {
+            PenaltyMode::Direct => {}
+            PenaltyMode::Drop => {
+                options.presence_penalty = None;
+                options.frequency_penalty = None;
+            }
+            PenaltyMode::Repeat => {
+                let presence = options.presence_penalty.take().unwrap_or(0.0);
+                let frequency = options.frequency_penalty.take().unwrap_or(0.0);
+                let strongest = presence.max(frequency);
+                // OpenAI's -2..2 range onto repeat_penalty's multiplier around 1.0
+                if strongest != 0.0 && options.repeat_penalty.is_none() {
+                    options.repeat_penalty = Some((1.0 + strongest / 2.0).clamp(0.5, 2.0));
+                }
+            }
+        }
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.default.name().to_string()];
+        parts.extend(self.models.iter().map(|(model, mode)| format!("{}={}", model, mode.name())));
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(presence: Option<f32>, frequency: Option<f32>) -> OllamaChatOptions {
+        OllamaChatOptions {
+            num_ctx: None,
+            num_predict: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            presence_penalty: presence,
+            frequency_penalty: frequency,
+            repeat_penalty: None,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let mapping = PenaltyMapping::parse("drop, llama3*=repeat, phi3=direct").unwrap();
+        assert_eq!(mapping.mode_for("llama3.2:3b"), PenaltyMode::Repeat);
+        assert_eq!(mapping.mode_for("phi3:latest"), PenaltyMode::Direct);
+        assert_eq!(mapping.mode_for("mistral"), PenaltyMode::Drop);
+        assert_eq!(mapping.describe(), "drop, llama3*=repeat, phi3:latest=direct");
+        assert_eq!(PenaltyMapping::parse("").unwrap().mode_for("mistral"), PenaltyMode::Direct);
+        assert!(PenaltyMapping::parse("llama3=halve").is_err());
+    }
+
+    #[test]
+    fn test_apply_modes() {
+        let mapping = PenaltyMapping::parse("repeat,qwen*=drop,phi3=direct").unwrap();
+
+        let mut repeat = options(Some(0.4), Some(1.0));
+        mapping.apply("llama3", &mut repeat);
+        assert_eq!((repeat.presence_penalty, repeat.frequency_penalty), (None, None));
+        assert_eq!(repeat.repeat_penalty, Some(1.5));
+
+        let mut dropped = options(Some(0.4), Some(1.0));
+        mapping.apply("qwen2.5:7b", &mut dropped);
+        assert_eq!((dropped.presence_penalty, dropped.frequency_penalty, dropped.repeat_penalty), (None, None, None));
+
+        let mut direct = options(Some(0.4), None);
+        mapping.apply("phi3", &mut direct);
+        assert_eq!(direct.presence_penalty, Some(0.4));
+        assert_eq!(direct.repeat_penalty, None);
+    }
+}
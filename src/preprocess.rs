@@ -0,0 +1,324 @@
+/// Embedding input preprocessing: strip HTML tags or markdown syntax before chunking
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+/// Header clients use to pick the preprocessing for one request
+pub const PREPROCESS_HEADER: &str = "x-proxy-preprocess";
+
+/// Tags that break text into separate lines when removed
+const BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption", "footer", "form",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "table",
+    "td", "th", "tr", "ul",
+];
+
+/// How embedding inputs are cleaned up before they are chunked and embedded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preprocess {
+    /// Embed inputs as sent
+    #[default]
+    Off,
+    Html,
+    Markdown,
+    /// HTML when the input contains tags, markdown otherwise
+    Auto,
+}
+
+impl Preprocess {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" | "none" => Some(Self::Off),
+            "html" => Some(Self::Html),
+            "markdown" | "md" => Some(Self::Markdown),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Html => "html",
+            Self::Markdown => "markdown",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// The mode requested by the `X-Proxy-Preprocess` header, else `default`
+    pub fn from_headers(headers: &HeaderMap, default: Self) -> Self {
+        headers
+            .get(PREPROCESS_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse)
+            .unwrap_or(default)
+    }
+
+    /// Plain text for `text`
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Self::Off => text.to_string(),
+            Self::Html => strip_html(text),
+            Self::Markdown => strip_markdown(text),
+            Self::Auto if looks_like_html(text) => strip_html(text),
+            Self::Auto => strip_markdown(text),
+        }
+    }
+
+    /// Rewrite the `input` (OpenAI, /api/embed) or `prompt` (/api/embeddings)
+    /// of an embeddings request. Returns whether anything changed.
+    pub fn apply_to_request(&self, json: &mut Value) -> bool {
+        if *self == Self::Off {
+            return false;
+        }
+        let mut changed = false;
+        let mut clean = |value: &mut Value| {
+            if let Some(text) = value.as_str() {
+                let plain = self.apply(text);
+                if plain != text {
+                    *value = Value::String(plain);
+                    changed = true;
+                }
+            }
+        };
+        for field in ["input", "prompt"] {
+            match json.get_mut(field) {
+                Some(Value::Array(items)) => items.iter_mut().for_each(&mut clean),
+                Some(value) => clean(value),
+                None => {}
+            }
+        }
+        changed
+    }
+}
+
+fn looks_like_html(text: &str) -> bool {
+    text.match_indices('<').any(|(i, _)| {
+        let rest = &text[i + 1..];
+        rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') && rest.contains('>')
+    })
+}
+
+/// Drop tags, comments, scripts and styles; decode common entities
+fn strip_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let tag_start = &rest[start..];
+        if let Some(comment) = tag_start.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let inner = &tag_start[1..];
+        let is_tag = inner.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let Some(end) = inner.find('>').filter(|_| is_tag) else {
+            // A bare '<', e.g. "a < b"
+            out.push('<');
+            rest = inner;
+            continue;
+        };
+        let tag = &inner[..end];
+        rest = &inner[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if !closing && (name == "script" || name == "style") {
+            // Skip everything up to the matching closing tag
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(i) => rest[i..].find('>').map_or("", |e| &rest[i + e + 1..]),
+                None => "",
+            };
+            continue;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+    collapse_whitespace(&decode_entities(&out))
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let entity = &rest[start + 1..];
+        let decoded = entity.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &entity[..end];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => match name.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &entity[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = entity;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Turn markdown into plain text, keeping code block contents
+fn strip_markdown(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            lines.push(line.to_string());
+            continue;
+        }
+        let mut line = trimmed;
+        while let Some(quoted) = line.strip_prefix('>') {
+            line = quoted.trim_start();
+        }
+        // Horizontal rules and table separators carry no text
+        if line.len() >= 3 && line.chars().all(|c| matches!(c, '-' | '*' | '_' | ' ' | '|' | ':')) {
+            lines.push(String::new());
+            continue;
+        }
+        line = line.trim_start_matches('#').trim_start();
+        line = strip_list_marker(line);
+        let line = strip_inline_markdown(line).replace(" | ", "  ");
+        lines.push(line.trim_matches('|').trim().to_string());
+    }
+    collapse_whitespace(&strip_html(&lines.join("\n")))
+}
+
+fn strip_list_marker(line: &str) -> &str {
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']).filter(|r| r.starts_with(' ')) {
+        return rest.trim_start();
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    match line[digits..].strip_prefix(['.', ')']) {
+        Some(rest) if digits > 0 && rest.starts_with(' ') => rest.trim_start(),
+        _ => line,
+    }
+}
+
+/// Links and images keep their text; emphasis and code markers are dropped
+fn strip_inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // [text](url) and ![alt](url)
+        if c == '[' || (c == '!' && chars.get(i + 1) == Some(&'[')) {
+            let open = if c == '!' { i + 1 } else { i };
+            if let Some(close) = chars[open..].iter().position(|&c| c == ']').map(|p| open + p) {
+                if chars.get(close + 1) == Some(&'(') {
+                    if let Some(end) = chars[close..].iter().position(|&c| c == ')').map(|p| close + p) {
+                        out.extend(&chars[open + 1..close]);
+                        i = end + 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match c {
+            '`' => {}
+            '*' | '_' | '~' => {
+                // Keep markers inside words, e.g. snake_case
+                let prev = i.checked_sub(1).and_then(|p| chars.get(p)).is_some_and(|c| c.is_alphanumeric());
+                let next = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+                if prev && next {
+                    out.push(c);
+                }
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Collapse runs of spaces, trim lines, and keep at most one blank line in a row
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = Vec::new();
+    let mut blank = false;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            if !blank && !out.is_empty() {
+                out.push(line);
+            }
+            blank = true;
+        } else {
+            out.push(line);
+            blank = false;
+        }
+    }
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_strip_html() {
+        let html = r#"<html><head><style>p { color: red }</style><script>alert("x")</script></head>
+            <body><h1>Title</h1><p>Fish &amp; chips &lt;3 <b>today</b>&#33;</p><!-- note --><p>a < b</p></body></html>"#;
+        assert_eq!(Preprocess::Html.apply(html), "Title\n\nFish & chips <3 today!\n\na < b");
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let markdown = "# Setup\n\n> **Note:** see [the docs](https://example.com) and ![diagram](d.png)\n\n\
+                        - install `ollama`\n1. run snake_case_tool\n\n---\n\n```bash\nollama serve *\n```";
+        assert_eq!(
+            Preprocess::Markdown.apply(markdown),
+            "Setup\n\nNote: see the docs and diagram\n\ninstall ollama\nrun snake_case_tool\n\nollama serve *"
+        );
+    }
+
+    #[test]
+    fn test_apply_to_request() {
+        let mut body = json!({"model": "nomic-embed-text", "input": ["<p>one</p>", "two"]});
+        assert!(Preprocess::Auto.apply_to_request(&mut body));
+        assert_eq!(body["input"], json!(["one", "two"]));
+
+        let mut prompt = json!({"model": "nomic-embed-text", "prompt": "**bold**"});
+        assert!(Preprocess::Auto.apply_to_request(&mut prompt));
+        assert_eq!(prompt["prompt"], "bold");
+        assert!(!Preprocess::Off.apply_to_request(&mut prompt));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(PREPROCESS_HEADER, "html".parse().unwrap());
+        assert_eq!(Preprocess::from_headers(&headers, Preprocess::Off), Preprocess::Html);
+        assert_eq!(Preprocess::from_headers(&HeaderMap::new(), Preprocess::Auto), Preprocess::Auto);
+    }
+}
@@ -1,6 +1,6 @@
 use axum::{
     extract::State,
-    http::{Request, Response, StatusCode},
+    http::{header, Request, Response, StatusCode},
     body::Body,
 };
 use http_body_util::BodyExt;
@@ -8,56 +8,357 @@ use std::sync::Arc;
 use tracing::{info, warn, error, debug};
 use serde_json::Value;
 
-use crate::model_metadata::ModelMetadataCache;
-use crate::modifier::apply_modifiers;
+use crate::admission::{Admission, AdmissionGuard, Priority, RequestProfile, PRIORITY_HEADER};
+use crate::backends::{conversation_key, fnv1a, BackendPool};
+use crate::cache::{self, ChatCache, EmbeddingCache, CACHE_HEADER};
+use crate::chunker::{self, Aggregation, Chunk, ChunkingStrategy};
+use crate::cloud::CloudFallback;
+use crate::coalesce::SingleFlight;
+use crate::compression::PromptCompression;
+use crate::concurrency::{ConcurrencyLimiter, ConcurrencyPermit, QueueRejection};
+use crate::config::ProxyConfig;
+use crate::dimensions::{self, DimensionPolicy};
+use crate::errors::{self, ollama_error, openai_error};
+use crate::filters::{bearer_token, OutputFilters, StreamScrubber};
+use crate::hedge::{race, HedgePolicy, HedgeWinner};
+use crate::inventory::{self, ModelRouting};
+use crate::ipfilter::IpFilter;
+use crate::jobs::JobStore;
+use crate::jwt::JwtVerifier;
+use crate::keystore::KeyStore;
+use crate::latency::{AdaptiveTimeouts, LatencyTracker};
+use crate::legacy::{self, LEGACY_EMBEDDINGS_PATH};
+use crate::limits::LimitStore;
+use crate::methods;
+use crate::metrics::Metrics;
+use crate::model_metadata::{self, ModelMetadataCache};
+use crate::modifier::{apply_modifiers, apply_response_modifiers};
+use crate::penalties::PenaltyMapping;
+use crate::preprocess::Preprocess;
 use crate::translator::{
     needs_translation, get_ollama_endpoint,
     translate_openai_embeddings_to_ollama, translate_ollama_embed_to_openai,
     translate_openai_chat_to_ollama, translate_ollama_chat_to_openai,
-    OllamaEmbedRequest, OllamaOptions, prepare_embeddings_input, InputType,
+    translate_ollama_tags_to_openai, find_model, normalize_roles, keep_first_tool_call,
+    OllamaChatRequest, OllamaEmbedRequest, OllamaOptions, prepare_embeddings_chunks, InputType,
 };
+use crate::resident::ResidentModels;
+use crate::scopes;
+use crate::routing::{CanaryRoutes, DefaultModels, PromptRoutes, DEFAULT_MODEL_HEADER};
+use crate::shadow::{self, Shadow};
+use crate::structured::{
+    mismatch_response, requested_format, validate, Checked, RetryRequest, StructuredCheck, StructuredFailure,
+};
+use crate::retry::{is_connection_lost, is_idempotent, send_resilient, RestartRetry, SaturationRetry};
+use crate::timeouts::{apply_timeout, requested_timeout, EndpointClass, EndpointTimeouts, StreamTimeouts};
+use crate::access_log::{AccessLog, RequestModel};
+use crate::auth::{self, ApiKeys, AuthenticatedKey};
+use crate::overrides::{ModelOverrides, DEFAULT_NUM_PREDICT};
+use crate::ratelimit::{self, RateLimits};
+use crate::redact::{self, BodyLogging};
+use crate::remote_images;
+use crate::stats::{self, Eval, ModelStats, StreamRecorder};
+use crate::timing::{self, Timings, SERVER_TIMING_HEADER};
+use crate::unsupported::{self, UnsupportedPolicy, WARNINGS_HEADER};
+use crate::upstream::UpstreamClient;
+
+/// Status and raw body of a completed upstream call
+pub type UpstreamReply = Result<(StatusCode, bytes::Bytes), String>;
 
 #[derive(Clone)]
 pub struct ProxyState {
     pub ollama_host: String,
-    pub client: reqwest::Client,
+    pub upstream: Arc<UpstreamClient>,
+    pub backends: Arc<BackendPool>,
+    pub hedge: HedgePolicy,
+    /// Which backend has which model, and what to do when none has it
+    pub model_routing: ModelRouting,
+    /// OpenAI-compatible upstream for models Ollama lacks or requests it fails
+    pub cloud_fallback: Option<Arc<CloudFallback>>,
+    pub shadow: Option<Arc<Shadow>>,
     pub metadata_cache: Arc<ModelMetadataCache>,
     pub max_embedding_input_length: usize,
     pub enable_auto_chunking: bool,
+    pub max_chunks_per_request: usize,
+    pub chunk_aggregation: Aggregation,
+    pub return_chunk_embeddings: bool,
+    pub chunking: Arc<ChunkingStrategy>,
+    pub embedding_cache: Arc<EmbeddingCache>,
+    pub embedding_preprocess: Preprocess,
+    pub embed_dimensions: Arc<DimensionPolicy>,
+    pub translate_legacy_embeddings: bool,
     pub max_context_override: u32,
-    pub request_timeout_seconds: u64,
+    pub model_overrides: Arc<ModelOverrides>,
+    pub prompt_compression: PromptCompression,
+    pub timeouts: EndpointTimeouts,
+    pub stream_timeouts: StreamTimeouts,
+    pub max_request_timeout: Option<std::time::Duration>,
+    /// Timeout the client picked for the current request (X-Request-Timeout)
+    pub requested_timeout: Option<std::time::Duration>,
+    /// Conversation the current request continues, when routing is sticky
+    pub conversation: Option<String>,
+    pub adaptive_timeouts: AdaptiveTimeouts,
+    pub latency: Arc<LatencyTracker>,
+    pub saturation_retry: SaturationRetry,
+    pub restart_retry: RestartRetry,
+    pub max_buffered_response_bytes: usize,
+    pub stream_batching: StreamBatching,
+    pub embed_flight: Arc<SingleFlight<UpstreamReply>>,
+    pub chat_flight: Arc<SingleFlight<UpstreamReply>>,
+    pub metrics: Arc<Metrics>,
+    pub model_stats: Arc<ModelStats>,
+    pub prompt_routes: Arc<PromptRoutes>,
+    pub canary_routes: Arc<CanaryRoutes>,
+    pub default_models: Arc<DefaultModels>,
+    pub admission: Arc<Admission>,
+    /// Slots for concurrent upstream inference requests
+    pub concurrency: Arc<ConcurrencyLimiter>,
+    pub limit_store: Arc<LimitStore>,
+    pub resident_models: Arc<ResidentModels>,
+    pub output_filters: Arc<OutputFilters>,
+    pub structured_failure: StructuredFailure,
+    pub unsupported_params: UnsupportedPolicy,
+    pub chat_cache: Arc<ChatCache>,
+    pub server_timing: bool,
+    pub api_keys: Arc<ApiKeys>,
+    pub rate_limits: RateLimits,
+    pub key_store: Arc<KeyStore>,
+    pub jwt: Option<Arc<JwtVerifier>>,
+    /// Client addresses allowed to use the proxy
+    pub ip_filter: Arc<IpFilter>,
+    pub access_log: Option<Arc<AccessLog>>,
+    pub body_logging: Arc<BodyLogging>,
+    pub jobs: Arc<JobStore>,
+    pub penalty_mapping: Arc<PenaltyMapping>,
+    pub developer_role: Option<String>,
+    /// Download http(s) image URLs in OpenAI vision requests
+    pub fetch_image_urls: bool,
+    pub strip_think_models: Arc<Vec<String>>,
 }
 
 impl ProxyState {
-    pub fn new(
-        ollama_host: String,
-        max_embedding_input_length: usize,
-        enable_auto_chunking: bool,
-        max_context_override: u32,
-        request_timeout_seconds: u64,
-    ) -> Self {
+    /// Shared state for a proxy configured by `config`
+    pub fn new(config: ProxyConfig) -> Self {
+        let ProxyConfig { ollama_host, upstream, .. } = config;
+        let metrics = Arc::new(Metrics::new());
+        let latency = Arc::new(LatencyTracker::new());
+        // One pooled client for proxied requests and metadata lookups alike
+        let upstream_client = Arc::new(UpstreamClient::new(upstream.clone(), metrics.clone()));
         Self {
             ollama_host: ollama_host.clone(),
-            client: reqwest::Client::builder()
-                .timeout(std::time::Duration::from_secs(request_timeout_seconds))
-                .build()
-                .expect("Failed to build HTTP client"),
-            metadata_cache: Arc::new(ModelMetadataCache::new(ollama_host)),
-            max_embedding_input_length,
-            enable_auto_chunking,
-            max_context_override,
-            request_timeout_seconds,
+            upstream: upstream_client.clone(),
+            backends: Arc::new(
+                BackendPool::new(&ollama_host, &upstream.backends).with_placement(upstream.placement.clone()),
+            ),
+            hedge: upstream.hedge,
+            model_routing: config.model_routing,
+            cloud_fallback: config.cloud_fallback.map(|settings| Arc::new(CloudFallback::new(settings))),
+            shadow: config.shadow.and_then(|settings| {
+                Shadow::open(settings, config.body_logging.clone())
+                    .map(Arc::new)
+                    .map_err(|e| error!("❌ {}, shadow traffic disabled", e))
+                    .ok()
+            }),
+            metadata_cache: Arc::new(
+                ModelMetadataCache::new(ollama_host, upstream_client)
+                    .with_ttl(config.metadata_ttl)
+                    .with_timeout(upstream.timeouts.for_class(EndpointClass::Other)),
+            ),
+            max_embedding_input_length: config.max_embedding_input_length,
+            enable_auto_chunking: config.enable_auto_chunking,
+            max_chunks_per_request: config.max_chunks_per_request,
+            chunk_aggregation: config.chunk_aggregation,
+            return_chunk_embeddings: config.return_chunk_embeddings,
+            chunking: Arc::new(config.chunking),
+            embedding_cache: Arc::new(EmbeddingCache::new(config.embedding_cache)),
+            embedding_preprocess: config.embedding_preprocess,
+            embed_dimensions: Arc::new(config.embed_dimensions),
+            translate_legacy_embeddings: config.translate_legacy_embeddings,
+            max_context_override: config.max_context_override,
+            model_overrides: Arc::new(config.model_overrides),
+            prompt_compression: config.prompt_compression,
+            timeouts: upstream.timeouts.clone(),
+            stream_timeouts: upstream.stream_timeouts,
+            max_request_timeout: upstream.max_request_timeout,
+            requested_timeout: None,
+            conversation: None,
+            adaptive_timeouts: upstream.adaptive_timeouts,
+            latency: latency.clone(),
+            saturation_retry: upstream.saturation_retry,
+            restart_retry: upstream.restart_retry,
+            max_buffered_response_bytes: config.max_buffered_response_bytes,
+            stream_batching: config.stream_batching,
+            embed_flight: Arc::new(SingleFlight::new()),
+            chat_flight: Arc::new(SingleFlight::new()),
+            metrics,
+            model_stats: Arc::new(ModelStats::new(latency.clone())),
+            prompt_routes: Arc::new(config.prompt_routes),
+            canary_routes: Arc::new(config.canary_routes),
+            default_models: Arc::new(config.default_models),
+            admission: Arc::new(Admission::new(config.saturation_limits, config.shed_policy, latency)),
+            concurrency: Arc::new(ConcurrencyLimiter::new(config.concurrency_limits)),
+            // Validated with the config
+            limit_store: Arc::new(LimitStore::parse(&config.limit_store).unwrap_or_default()),
+            resident_models: Arc::new(ResidentModels::new()),
+            output_filters: Arc::new(config.output_filters),
+            structured_failure: config.structured_failure,
+            unsupported_params: config.unsupported_params,
+            chat_cache: Arc::new(ChatCache::new(config.chat_cache)),
+            server_timing: config.server_timing,
+            api_keys: Arc::new(config.api_keys),
+            rate_limits: config.rate_limits,
+            jwt: config.jwt.clone().map(|settings| Arc::new(JwtVerifier::new(settings))),
+            ip_filter: Arc::new(config.ip_filter.clone()),
+            key_store: Arc::new(KeyStore::open(config.api_key_store.clone()).unwrap_or_else(|e| {
+                error!("❌ {}, keys created through the admin API won't be kept", e);
+                KeyStore::new()
+            })),
+            access_log: config.access_log.as_ref().and_then(|destination| {
+                AccessLog::open(destination, config.access_log_format.clone())
+                    .map(Arc::new)
+                    .map_err(|e| error!("❌ {}, access logging disabled", e))
+                    .ok()
+            }),
+            body_logging: Arc::new(config.body_logging),
+            jobs: Arc::new(JobStore::open(&config.jobs).unwrap_or_else(|e| {
+                error!("❌ {}, embedding jobs won't survive a restart", e);
+                JobStore::new(&config.jobs)
+            })),
+            penalty_mapping: Arc::new(config.penalty_mapping),
+            developer_role: config.developer_role,
+            fetch_image_urls: config.fetch_image_urls,
+            strip_think_models: Arc::new(config.strip_think_models),
         }
     }
+
+    /// HTTP client for the Ollama upstream
+    pub fn client(&self) -> reqwest::Client {
+        self.upstream.get()
+    }
+
+    /// Backend for the current request to `model`, following its conversation
+    pub fn backend_for(&self, model: &str) -> &crate::backends::Backend {
+        self.backends.for_conversation(model, self.conversation.as_deref())
+    }
+
+    /// Context cap for `model`: its `[models]` section's max_context, else MAX_CONTEXT_OVERRIDE
+    pub fn max_context_for(&self, model: &str) -> u32 {
+        self.model_overrides
+            .for_model(model)
+            .and_then(|settings| settings.max_context)
+            .unwrap_or(self.max_context_override)
+    }
+
+    /// Lower every context cap to at most `cap` for one request
+    pub fn cap_context(&mut self, cap: u32) {
+        self.max_context_override = self.max_context_override.min(cap);
+        self.model_overrides = Arc::new(self.model_overrides.cap_context(cap));
+    }
+
+    /// Generation limit added to chat requests for `model` that don't set one
+    pub fn num_predict_default_for(&self, model: &str) -> u32 {
+        self.model_overrides
+            .for_model(model)
+            .and_then(|settings| settings.num_predict_default)
+            .unwrap_or(DEFAULT_NUM_PREDICT)
+    }
+
+    /// Wait for a global upstream slot, and a slot of the request's model when its
+    /// `[models]` section sets max_concurrent
+    pub async fn acquire_upstream_slot(
+        &self,
+        body_bytes: &[u8],
+    ) -> Result<Option<ConcurrencyPermit>, QueueRejection> {
+        let model = body_model(body_bytes).unwrap_or_default();
+        self.concurrency.acquire(&model, self.model_concurrency_limit(&model)).await
+    }
+
+    /// An upstream slot for background work on `model`, only if one is free right now
+    pub fn try_upstream_slot(&self, model: &str) -> Result<Option<ConcurrencyPermit>, QueueRejection> {
+        self.concurrency.try_acquire(model, self.model_concurrency_limit(model))
+    }
+
+    fn model_concurrency_limit(&self, model: &str) -> Option<usize> {
+        self.model_overrides
+            .for_model(model)
+            .and_then(|settings| settings.max_concurrent)
+            .map(|limit| limit as usize)
+    }
+
+    /// Timeout for a request to `model`: the client's own pick if it sent one, else
+    /// adapted to the model's latency history when enabled
+    pub fn timeout_for(&self, class: EndpointClass, model: &str) -> Option<std::time::Duration> {
+        if self.requested_timeout.is_some() {
+            return self.requested_timeout;
+        }
+        self.adaptive_timeouts
+            .resolve(&self.latency, model, class, self.timeouts.for_class(class))
+    }
+
+    /// Count a failed request to `model` that ran out of `timeout` as taking that long.
+    /// Only successes are recorded otherwise, so a model that slows down would keep
+    /// timing out against the history from when it was fast.
+    fn record_timeout(&self, model: &str, class: EndpointClass, timeout: Option<std::time::Duration>, started: std::time::Instant) {
+        if self.requested_timeout.is_some() {
+            return;
+        }
+        if let Some(timeout) = timeout.filter(|&timeout| started.elapsed() >= timeout) {
+            self.latency.record(model, class, timeout);
+        }
+    }
+}
+
+/// Flush interval for batches with only a size trigger, so a slow stream that never
+/// fills one still reaches the client
+pub const DEFAULT_STREAM_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Micro-batching settings for the NDJSON streaming bridge.
+/// The default forwards every line as soon as it arrives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamBatching {
+    /// Flush pending lines at most this long after the first one was queued
+    pub flush_interval: Option<std::time::Duration>,
+    /// Flush as soon as this many bytes are pending (0 = no size trigger)
+    pub flush_bytes: usize,
+}
+
+impl StreamBatching {
+    pub fn is_enabled(&self) -> bool {
+        self.flush_interval.is_some() || self.flush_bytes > 0
+    }
+
+    /// How long batched lines may wait: the configured interval, or
+    /// [`DEFAULT_STREAM_FLUSH_INTERVAL`] when batching only by size
+    pub fn interval(&self) -> Option<std::time::Duration> {
+        self.flush_interval
+            .or((self.flush_bytes > 0).then_some(DEFAULT_STREAM_FLUSH_INTERVAL))
+    }
 }
 
 pub async fn proxy_handler(
     State(state): State<ProxyState>,
     req: Request<Body>,
 ) -> Result<Response<Body>, StatusCode> {
+    // Boxed: the handler's future is large, and debug builds copy it onto the stack
+    let handle = Box::pin(handle_request(state.clone(), req));
+    if !state.server_timing {
+        return handle.await;
+    }
+    let started = std::time::Instant::now();
+    let timings = Timings::new();
+    let response = timings.clone().scope(handle).await;
+    response.map(|mut response| {
+        if let Some(value) = timings.header_value(started.elapsed()) {
+            response.headers_mut().insert(SERVER_TIMING_HEADER, value);
+        }
+        response
+    })
+}
+
+async fn handle_request(mut state: ProxyState, req: Request<Body>) -> Result<Response<Body>, StatusCode> {
     let method = req.method().clone();
     let uri = req.uri().clone();
-    let path = uri.path().to_string();
+    let path = methods::normalize_path(uri.path()).to_string();
     let query = uri.query().unwrap_or("");
     
     info!("📨 Incoming request: {} {}{}", 
@@ -68,7 +369,21 @@ pub async fn proxy_handler(
 
     // Collect headers
     let headers = req.headers().clone();
-    debug!("Headers: {:?}", headers);
+    debug!("Headers: {:?}", redact::redact_headers(&headers));
+
+    // OPTIONS and wrong methods on known routes are answered here
+    if let Some(response) = methods::check(&method, &path, &headers) {
+        info!("↩️  Answered {} {} without forwarding ({})", method, path, response.status());
+        return Ok(response);
+    }
+
+    let api_key = req.extensions().get::<AuthenticatedKey>().map(|key| key.0.clone());
+
+    // Batch clients may allow themselves more time (and interactive ones less), up to the cap
+    if let Some(timeout) = state.max_request_timeout.and_then(|max| requested_timeout(&headers, max)) {
+        info!("⏱️  Client requested a {:.1}s timeout", timeout.as_secs_f64());
+        state.requested_timeout = Some(timeout);
+    }
 
     // Read the body
     let body_bytes = match req.into_body().collect().await {
@@ -79,13 +394,342 @@ pub async fn proxy_handler(
         }
     };
 
+    // Follow-up turns of a chat go where its earlier turns left Ollama's prompt cache
+    if state.backends.sticky_conversations() {
+        state.conversation = conversation_key(&headers, &body_bytes);
+    }
+
+    // Fill in a default model for clients that don't send one (it may be a routing alias)
+    let (body_bytes, default_model) = fill_default_model(&state.default_models, &path, body_bytes);
+
+    // Split canary aliases over model versions, then swap aliased models by prompt size,
+    // before any translation happens
+    let body_bytes = route_canary(&state.canary_routes, &headers, body_bytes);
+    let body_bytes = route_by_prompt_size(&state.prompt_routes, body_bytes);
+    let model = state.access_log.is_some().then(|| body_model(&body_bytes)).flatten();
+
+    // Hold the key to its models, endpoints and request sizes before anything is translated
+    let body_bytes = match &api_key {
+        Some(key) if key.scope.is_restricted() => {
+            let model = body_model(&body_bytes);
+            if let Some(response) = key.scope.check(&key.name, &path, &scopes::requested_models(&path, &body_bytes)) {
+                warn!("🚫 {} {} is outside the scope of API key {}", method, path, key.name);
+                return Ok(response);
+            }
+            if let Some(cap) = key.scope.max_context {
+                state.cap_context(cap);
+            }
+            let default = model.as_deref().map_or(DEFAULT_NUM_PREDICT, |model| state.num_predict_default_for(model));
+            key.scope.cap_num_predict(&path, body_bytes, default)
+        }
+        _ => body_bytes,
+    };
+
+    // Charge the key's request and token budgets before the request takes a queue slot
+    if let Some(key) = &api_key {
+        if let Err(limited) = ratelimit::check(&state.limit_store, key, state.rate_limits, &body_bytes).await {
+            warn!("🚦 {}, rejecting with 429", limited.message);
+            return Ok(limited.into_response());
+        }
+    }
+
+    // OpenAI requests the cloud fallback may serve, with the body to send it and the
+    // output filters its answer goes through
+    let cloud = state.cloud_fallback.clone().and_then(|cloud| {
+        let model = body_model(&body_bytes).filter(|model| cloud.covers(&path, model))?;
+        let filters = match EndpointClass::from_path(&path) {
+            EndpointClass::Chat | EndpointClass::Generate => {
+                state.output_filters.for_request(Some(&model), bearer_token(&headers))
+            }
+            _ => OutputFilters::default(),
+        };
+        Some((cloud, model, body_bytes.clone(), filters))
+    });
+
+    // Requests for a model no backend has go to the cloud, or are refused (or the model pulled) up front
+    if let Some((cloud, model, body, filters)) = &cloud {
+        if state.backends.is_missing(model) {
+            info!("☁️  {} is not pulled on any backend, using the cloud fallback", model);
+            state.metrics.record_cloud_fallback();
+            return Ok(cloud.forward(&path, body.clone(), filters.clone()).await);
+        }
+    }
+    if EndpointClass::from_path(&path).is_inference() {
+        if let Some(model) = body_model(&body_bytes) {
+            if let Some(response) = inventory::ensure_available(&state, &path, &model).await {
+                return Ok(response);
+            }
+            if let Some(response) = check_capabilities(&state, &path, &model, &body_bytes).await {
+                return Ok(response);
+            }
+        }
+    }
+
+    let started = std::time::Instant::now();
+
+    // Count the request against its model's queue, refusing it if the model is saturated
+    let guard = match admit(&state, &path, &headers, &body_bytes) {
+        Ok(guard) => guard,
+        Err(saturated) => {
+            warn!("🚦 {}, rejecting with 429", saturated.reason);
+            return Ok(saturated.into_response());
+        }
+    };
+
+    // Wait for an upstream slot so Ollama isn't handed more work than it can run at once
+    // (or than a model configured with its own max_concurrent)
+    let permit = match EndpointClass::from_path(&path).is_inference() {
+        true => match state.acquire_upstream_slot(&body_bytes).await {
+            Ok(permit) => permit,
+            Err(rejection) => {
+                warn!("🚦 No upstream slot for {} {} ({:?}), rejecting with 503", method, path, rejection);
+                return Ok(state.concurrency.rejection_response(&rejection));
+            }
+        },
+        false => None,
+    };
+
+    // Mirror the request to the shadow model in the background, for comparison with the real
+    // answer. It runs once the real request has its slot, and only in a slot that is free.
+    let shadow = state.shadow.as_ref().and_then(|shadow| {
+        let primary = body_model(&body_bytes).map_or(state.ollama_host.as_str(), |model| state.backend_for(&model).url.as_str());
+        shadow.mirror(&state, primary, &path, &body_bytes)
+    });
+
     // Check if this is an OpenAI endpoint that needs translation
-    if needs_translation(&path) {
-        return handle_translated_request(state, &path, body_bytes, headers).await;
+    let metrics = state.metrics.clone();
+    let response = if needs_translation(&path) {
+        handle_translated_request(state, &path, body_bytes, headers).await
+    } else if path == LEGACY_EMBEDDINGS_PATH && state.translate_legacy_embeddings {
+        handle_legacy_embeddings(state, body_bytes, headers).await
+    } else {
+        // For non-translated requests, use the original logic
+        let upstream_method = methods::upstream_method(&method, &path);
+        handle_standard_request(state, &path, query, upstream_method, body_bytes, headers).await
+    };
+    // Local inference failed: free the local slots and retry the original request in the cloud
+    let status = response.as_ref().map_or_else(|status| *status, |response| response.status());
+    let (response, guard, permit) = match cloud {
+        Some((cloud, model, body, filters)) if cloud.should_retry(status) => {
+            warn!("☁️  Ollama answered {} for {}, retrying on the cloud fallback", status, model);
+            drop((response, guard, permit));
+            metrics.record_cloud_fallback();
+            (Ok(cloud.forward(&path, body, filters).await), None, None)
+        }
+        _ => (response, guard, permit),
+    };
+    // OpenAI SDKs expect a JSON error body, not a bare status
+    let response = match response {
+        Err(status) if errors::is_openai_path(&path) => Ok(errors::from_status(status)),
+        other => other,
+    };
+    let response = if method == axum::http::Method::HEAD {
+        response.map(methods::strip_body)
+    } else {
+        response
+    };
+    let response = response.map(|mut response| {
+        if let Some(value) = default_model.and_then(|m| axum::http::HeaderValue::from_str(&m).ok()) {
+            response.headers_mut().insert(DEFAULT_MODEL_HEADER, value);
+        }
+        if let Some(model) = model {
+            response.extensions_mut().insert(RequestModel(model));
+        }
+        match shadow {
+            Some(pending) => shadow::capture(response, pending, started),
+            None => response,
+        }
+    });
+
+    response.map(|response| match (guard, permit) {
+        (None, None) => response,
+        held => hold_until_body_done(response, held),
+    })
+}
+
+/// The `model` field of a JSON request body
+pub(crate) fn body_model(body_bytes: &[u8]) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct ModelOnly {
+        model: Option<String>,
+    }
+    serde_json::from_slice::<ModelOnly>(body_bytes).ok()?.model
+}
+
+/// Admit inference requests (embeddings, chat, generate) through the admission layer
+fn admit(
+    state: &ProxyState,
+    path: &str,
+    headers: &axum::http::HeaderMap,
+    body_bytes: &bytes::Bytes,
+) -> Result<Option<AdmissionGuard>, crate::admission::Saturated> {
+    let class = EndpointClass::from_path(path);
+    if !class.is_inference() {
+        return Ok(None);
+    }
+
+    let Some(model) = body_model(body_bytes) else {
+        return Ok(None);
+    };
+
+    let mut profile = RequestProfile::new(&model, class);
+    profile.max_concurrent = state.concurrency.slots_limit(state.model_concurrency_limit(&model));
+    if let Some(priority) = headers
+        .get(PRIORITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Priority::parse)
+    {
+        profile.priority = priority;
+    }
+    if state.admission.shed_policy().needs_token_estimate() {
+        profile.estimated_tokens = serde_json::from_slice::<Value>(body_bytes)
+            .ok()
+            .map(|json| crate::tokens::estimate_request_tokens(&json));
+    }
+
+    state.admission.try_admit(&profile).map(Some)
+}
+
+/// Delay before a job batch turned away by admission or the upstream queue asks again
+const JOB_BATCH_RETRY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Admit an embedding job batch as background work and wait for its upstream slot.
+/// A job has no client to hand a 429 or 503 to, so it waits out saturation instead.
+async fn admit_job_batch(
+    state: &ProxyState,
+    body_bytes: &bytes::Bytes,
+) -> (Option<AdmissionGuard>, Option<ConcurrencyPermit>) {
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert(PRIORITY_HEADER, axum::http::HeaderValue::from_static(Priority::Background.name()));
+    loop {
+        let rejection = match admit(state, "/v1/embeddings", &headers, body_bytes) {
+            Ok(guard) => match state.acquire_upstream_slot(body_bytes).await {
+                Ok(permit) => return (guard, permit),
+                Err(rejection) => format!("{:?}", rejection),
+            },
+            Err(saturated) => saturated.reason,
+        };
+        debug!("🧾 Job batch not admitted yet ({}), retrying", rejection);
+        tokio::time::sleep(JOB_BATCH_RETRY).await;
+    }
+}
+
+/// Keep `guard` alive until the response body has been fully sent (or dropped),
+/// so streaming responses stay counted (and keep their slot) for their whole duration
+fn hold_until_body_done<G: Send + 'static>(response: Response<Body>, guard: G) -> Response<Body> {
+    use futures::StreamExt;
+
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// Set the configured default model on requests that omit one
+fn fill_default_model(
+    defaults: &DefaultModels,
+    path: &str,
+    body_bytes: bytes::Bytes,
+) -> (bytes::Bytes, Option<String>) {
+    let class = EndpointClass::from_path(path);
+    if defaults.for_class(class).is_none() {
+        return (body_bytes, None);
+    }
+    let mut json: Value = match serde_json::from_slice(&body_bytes) {
+        Ok(json) => json,
+        Err(_) => return (body_bytes, None),
+    };
+    let Some(model) = defaults.apply(class, &mut json) else {
+        return (body_bytes, None);
+    };
+
+    info!("🎯 No model in {} request, using default '{}'", class.name(), model);
+    match serde_json::to_vec(&json) {
+        Ok(bytes) => (bytes.into(), Some(model)),
+        Err(e) => {
+            warn!("⚠️  Could not re-serialize request with default model: {}", e);
+            (body_bytes, None)
+        }
+    }
+}
+
+/// Refuse a request that needs tools or vision from a model Ollama says lacks them,
+/// instead of letting it fail (or silently ignore the tools and images) upstream.
+/// The metadata is only looked up for requests that need either.
+async fn check_capabilities(state: &ProxyState, path: &str, model: &str, body_bytes: &[u8]) -> Option<Response<Body>> {
+    let body: Value = serde_json::from_slice(body_bytes).ok()?;
+    let required = model_metadata::required_capabilities(&body);
+    if required.is_empty() {
+        return None;
+    }
+    let metadata = state.metadata_cache.get_model_info(model).await.ok()?;
+    let missing = metadata.missing_capability(&required)?;
+    warn!("🚫 Model {} does not support {}, rejecting", model, missing);
+    let message = format!("Model '{}' does not support {}", model, missing);
+    Some(match errors::is_openai_path(path) {
+        true => {
+            let param = if missing == "tools" { "tools" } else { "messages" };
+            openai_error(StatusCode::BAD_REQUEST, &message, Some(param), Some("unsupported_capability"))
+        }
+        false => ollama_error(StatusCode::BAD_REQUEST, &message),
+    })
+}
+
+/// Pick a model version for a canary alias: by conversation, so every turn of a chat
+/// sees the same model, else at random
+fn route_canary(routes: &CanaryRoutes, headers: &axum::http::HeaderMap, body_bytes: bytes::Bytes) -> bytes::Bytes {
+    if routes.is_empty() {
+        return body_bytes;
     }
+    let mut json: Value = match serde_json::from_slice(&body_bytes) {
+        Ok(json) => json,
+        Err(_) => return body_bytes,
+    };
+    let roll = match conversation_key(headers, &body_bytes) {
+        Some(conversation) => fnv1a(conversation.into_bytes()),
+        None => uuid::Uuid::new_v4().as_u64_pair().0,
+    };
+    let Some(decision) = routes.apply(&mut json, (roll % 100) as u32) else {
+        return body_bytes;
+    };
 
-    // For non-translated requests, use the original logic
-    handle_standard_request(state, &path, query, method, body_bytes, headers).await
+    info!("🧭 Routed '{}' to '{}' ({}% canary split)", decision.alias, decision.model, decision.percent);
+    match serde_json::to_vec(&json) {
+        Ok(bytes) => bytes.into(),
+        Err(e) => {
+            warn!("⚠️  Could not re-serialize routed request: {}", e);
+            body_bytes
+        }
+    }
+}
+
+/// Apply prompt-size routing rules to a JSON request body, returning it unchanged
+/// when no rule matches
+fn route_by_prompt_size(routes: &PromptRoutes, body_bytes: bytes::Bytes) -> bytes::Bytes {
+    if routes.is_empty() {
+        return body_bytes;
+    }
+    let mut json: Value = match serde_json::from_slice(&body_bytes) {
+        Ok(json) => json,
+        Err(_) => return body_bytes,
+    };
+    let Some(decision) = routes.apply(&mut json) else {
+        return body_bytes;
+    };
+
+    info!(
+        "🧭 Routed '{}' to '{}' (~{} prompt tokens)",
+        decision.alias, decision.model, decision.estimated_tokens
+    );
+    match serde_json::to_vec(&json) {
+        Ok(bytes) => bytes.into(),
+        Err(e) => {
+            warn!("⚠️  Could not re-serialize routed request: {}", e);
+            body_bytes
+        }
+    }
 }
 
 /// Handle requests that need OpenAI to Ollama translation
@@ -93,17 +737,24 @@ async fn handle_translated_request(
     state: ProxyState,
     path: &str,
     body_bytes: bytes::Bytes,
-    _headers: axum::http::HeaderMap,
+    headers: axum::http::HeaderMap,
 ) -> Result<Response<Body>, StatusCode> {
+    // Model listing has no request body
+    if let Some(rest) = path.strip_prefix("/v1/models") {
+        return handle_models(state, rest.strip_prefix('/')).await;
+    }
+
     // Parse the incoming OpenAI request
-    let body_json: Value = match serde_json::from_slice(&body_bytes) {
+    let mut body_json: Value = match serde_json::from_slice(&body_bytes) {
         Ok(json) => {
-            info!("📋 OpenAI Request body: {}", serde_json::to_string_pretty(&json).unwrap_or_default());
+            if let Some(body) = state.body_logging.render(&json) {
+                info!("📋 OpenAI Request body: {}", body);
+            }
             json
         }
         Err(e) => {
             error!("Failed to parse OpenAI request body: {}", e);
-            return Err(StatusCode::BAD_REQUEST);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &format!("Invalid JSON body: {}", e), None, None));
         }
     };
 
@@ -112,7 +763,7 @@ async fn handle_translated_request(
         Some(name) => name,
         None => {
             error!("No model specified in request");
-            return Err(StatusCode::BAD_REQUEST);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, "No model specified", Some("model"), None));
         }
     };
 
@@ -132,21 +783,220 @@ async fn handle_translated_request(
 
     // Handle embeddings specially with chunking support
     if path == "/v1/embeddings" {
-        return handle_embeddings_with_chunking(state, body_json, metadata.n_ctx_train, model_name).await;
+        let dims = match dimensions::requested_dimensions(&body_json) {
+            Ok(dims) => dims,
+            Err(e) => return Ok(openai_error(StatusCode::BAD_REQUEST, &e, Some("dimensions"), None)),
+        };
+        if dims.is_some() && !state.embed_dimensions.allows(&model_name) {
+            warn!("⚠️  {} is not configured for dimension truncation", model_name);
+            let message = format!("The model '{}' does not support the dimensions parameter", model_name);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &message, Some("dimensions"), None));
+        }
+        preprocess_embeddings(&state, &headers, &mut body_json);
+        let aggregation = Aggregation::from_headers(&headers, state.chunk_aggregation);
+        let return_chunks = chunker::return_chunks(&headers, state.return_chunk_embeddings);
+        return handle_embeddings_with_chunking(
+            state,
+            body_json,
+            metadata.n_ctx_train,
+            model_name,
+            dims,
+            aggregation,
+            return_chunks,
+        )
+        .await;
     }
 
     // Handle chat completions
     if path == "/v1/chat/completions" {
-        // Calculate effective context: respect user's MAX_CONTEXT_OVERRIDE
-        let effective_ctx = metadata.n_ctx_train.min(state.max_context_override);
+        // Calculate effective context: respect user's MAX_CONTEXT_OVERRIDE (or the model's own cap)
+        let max_context = state.max_context_for(&model_name);
+        let effective_ctx = metadata.n_ctx_train.min(max_context);
         info!("🎯 Context calculation: model={}, override={}, effective={}", 
-            metadata.n_ctx_train, state.max_context_override, effective_ctx);
+            metadata.n_ctx_train, max_context, effective_ctx);
         
-        return handle_chat_completions(state, body_json, Some(effective_ctx), model_name, metadata).await;
+        let unsupported = unsupported::find_chat_params(&body_json);
+        if !unsupported.is_empty() {
+            match state.unsupported_params {
+                UnsupportedPolicy::Reject => {
+                    warn!("🚫 Rejecting unsupported parameters: {}", unsupported.join(", "));
+                    let message = format!("Unsupported parameter: '{}' cannot be translated for Ollama", unsupported[0]);
+                    return Ok(openai_error(StatusCode::BAD_REQUEST, &message, Some(unsupported[0]), Some("unsupported_parameter")));
+                }
+                UnsupportedPolicy::Warn => warn!("⚠️  Ignoring unsupported parameters: {}", unsupported.join(", ")),
+                UnsupportedPolicy::Strip => debug!("Ignoring unsupported parameters: {}", unsupported.join(", ")),
+            }
+        }
+        let warn_unsupported = state.unsupported_params == UnsupportedPolicy::Warn && !unsupported.is_empty();
+
+        let cache_key = if state.chat_cache.is_enabled() && !cache::bypass(&headers) {
+            ChatCache::key(&model_name, &body_json, bearer_token(&headers))
+        } else {
+            None
+        };
+        let response = match cache_key.as_ref().and_then(|key| state.chat_cache.get(key)) {
+            Some(body) => {
+                info!("♻️  Serving cached chat completion for {}", model_name);
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(axum::http::header::CONTENT_TYPE, "application/json")
+                    .header(CACHE_HEADER, "hit")
+                    .body(Body::from(body))
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            None => {
+                let cache = state.chat_cache.clone();
+                let filters = state.output_filters.for_request(Some(&model_name), bearer_token(&headers));
+                let response = handle_chat_completions(state, body_json, Some(effective_ctx), model_name, metadata, filters).await;
+                match (cache_key, response) {
+                    (Some(key), Ok(response)) if response.status() == StatusCode::OK => {
+                        store_chat_completion(&cache, key, response).await
+                    }
+                    (_, response) => response,
+                }
+            }
+        };
+        return response.map(|mut response| {
+            if warn_unsupported {
+                if let Ok(value) = axum::http::HeaderValue::from_str(&unsupported::warning(&unsupported)) {
+                    response.headers_mut().insert(WARNINGS_HEADER, value);
+                }
+            }
+            response
+        });
     }
 
     error!("Translation not implemented for path: {}", path);
-    Err(StatusCode::NOT_IMPLEMENTED)
+    Ok(openai_error(StatusCode::NOT_IMPLEMENTED, &format!("{} is not supported by the proxy", path), None, None))
+}
+
+/// Buffer a successful chat completion into the cache and hand it on
+async fn store_chat_completion(cache: &ChatCache, key: String, response: Response<Body>) -> Result<Response<Body>, StatusCode> {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            error!("❌ Failed to read chat completion for caching: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+    cache.insert(key, bytes.clone());
+    parts.headers.insert(CACHE_HEADER, axum::http::HeaderValue::from_static("miss"));
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
+
+/// Serve a deprecated /api/embeddings request (`prompt` in, `embedding` out)
+/// through the same chunking and num_ctx handling as /v1/embeddings
+async fn handle_legacy_embeddings(
+    state: ProxyState,
+    body_bytes: bytes::Bytes,
+    headers: axum::http::HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let request = serde_json::from_slice::<Value>(&body_bytes)
+        .map_err(|e| format!("Invalid JSON body: {}", e))
+        .and_then(|json| legacy::to_openai_request(&json));
+    let mut body_json = match request {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to translate /api/embeddings request: {}", e);
+            return Ok(ollama_error(StatusCode::BAD_REQUEST, &e));
+        }
+    };
+    let model_name = extract_model_name(&body_json).unwrap_or_default();
+    info!("🔁 Translating legacy /api/embeddings request for {} to /api/embed", model_name);
+
+    let metadata = match state.metadata_cache.get_model_info(&model_name).await {
+        Ok(meta) => meta,
+        Err(e) => {
+            warn!("⚠️  Could not fetch model metadata: {}, using default", e);
+            crate::model_metadata::ModelMetadata::default()
+        }
+    };
+    preprocess_embeddings(&state, &headers, &mut body_json);
+    let aggregation = Aggregation::from_headers(&headers, state.chunk_aggregation);
+    let response =
+        handle_embeddings_with_chunking(state, body_json, metadata.n_ctx_train, model_name, None, aggregation, false).await?;
+
+    // Back to the legacy shape, errors included
+    let (parts, body) = response.into_parts();
+    let bytes = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            error!("❌ Failed to read embeddings response: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+    let json: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if !parts.status.is_success() {
+        let message = errors::error_message(&json).unwrap_or("Embedding request failed");
+        return Ok(ollama_error(parts.status, message));
+    }
+    match legacy::from_openai_response(&json) {
+        Some(legacy_json) => Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(legacy_json.to_string()))
+            .unwrap()),
+        None => {
+            error!("Embeddings response had no embedding to return");
+            Err(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+/// Run an OpenAI embeddings request through the chunking pipeline outside of an
+/// HTTP request (async jobs), returning the OpenAI response body or the error message
+pub(crate) async fn embed_request(
+    state: &ProxyState,
+    mut body_json: Value,
+    preprocess: Preprocess,
+    aggregation: Aggregation,
+    return_chunks: bool,
+) -> Result<Value, String> {
+    let model_name = extract_model_name(&body_json).ok_or("No model specified")?;
+    let dims = dimensions::requested_dimensions(&body_json)?;
+    let metadata = state.metadata_cache.get_model_info(&model_name).await.unwrap_or_else(|e| {
+        warn!("⚠️  Could not fetch model metadata: {}, using default", e);
+        crate::model_metadata::ModelMetadata::default()
+    });
+    preprocess.apply_to_request(&mut body_json);
+    // Batches queue for the model and an upstream slot like any embeddings request
+    let body_bytes = serde_json::to_vec(&body_json).map_err(|e| e.to_string())?.into();
+    let _admitted = admit_job_batch(state, &body_bytes).await;
+    let response = handle_embeddings_with_chunking(
+        state.clone(),
+        body_json,
+        metadata.n_ctx_train,
+        model_name,
+        dims,
+        aggregation,
+        return_chunks,
+    )
+    .await
+    .map_err(|status| format!("Embedding failed with status {}", status))?;
+
+    let status = response.status();
+    let bytes = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read embeddings response: {}", e))?
+        .to_bytes();
+    let json: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if !status.is_success() {
+        let message = errors::error_message(&json).unwrap_or("Embedding request failed");
+        return Err(format!("{} ({})", message, status));
+    }
+    Ok(json)
+}
+
+/// Strip markup from embedding inputs as configured, or as the request's
+/// `X-Proxy-Preprocess` header asks
+fn preprocess_embeddings(state: &ProxyState, headers: &axum::http::HeaderMap, json: &mut Value) {
+    let mode = Preprocess::from_headers(headers, state.embedding_preprocess);
+    if mode.apply_to_request(json) {
+        info!("🧽 Stripped {} markup from embedding input", mode.name());
+    }
 }
 
 /// Handle embeddings requests with automatic chunking for large inputs
@@ -155,6 +1005,9 @@ async fn handle_embeddings_with_chunking(
     body_json: Value,
     num_ctx: u32,
     model_name: String,
+    dims: Option<usize>,
+    aggregation: Aggregation,
+    return_chunks: bool,
 ) -> Result<Response<Body>, StatusCode> {
     // Parse input
     #[derive(serde::Deserialize)]
@@ -166,7 +1019,7 @@ async fn handle_embeddings_with_chunking(
         Ok(r) => r,
         Err(e) => {
             error!("Failed to parse embeddings request: {}", e);
-            return Err(StatusCode::BAD_REQUEST);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &format!("Invalid embeddings request: {}", e), Some("input"), None));
         }
     };
 
@@ -182,41 +1035,68 @@ async fn handle_embeddings_with_chunking(
 
     if !needs_chunking {
         // No chunking needed, process normally
-        return handle_single_embeddings_request(state, body_json, num_ctx, model_name).await;
+        return handle_single_embeddings_request(state, body_json, num_ctx, model_name, dims).await;
     }
 
     // Chunking needed - process each chunk separately
     info!("🔀 Processing large input with sequential chunking");
     
-    // Prepare chunked inputs
-    let chunked_inputs = match prepare_embeddings_input(
+    // Prepare chunked inputs, grouped by the input they came from
+    let chunked_inputs = match prepare_embeddings_chunks(
         inputs,
         max_len,
         state.enable_auto_chunking,
+        &state.chunking,
     ) {
         Ok(chunks) => chunks,
         Err(e) => {
             error!("Chunking failed: {}", e);
-            return Err(StatusCode::BAD_REQUEST);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &e, Some("input"), None));
         }
     };
 
-    info!("📦 Processing {} chunks sequentially", chunked_inputs.len());
+    let total_chunks: usize = chunked_inputs.iter().map(Vec::len).sum();
+    if state.max_chunks_per_request > 0 && total_chunks > state.max_chunks_per_request {
+        warn!("🚫 Request needs {} chunks, more than the limit of {}", total_chunks, state.max_chunks_per_request);
+        let message = format!(
+            "Input would be split into {} chunks, more than the proxy's limit of {}; send smaller inputs",
+            total_chunks, state.max_chunks_per_request
+        );
+        return Ok(openai_error(StatusCode::PAYLOAD_TOO_LARGE, &message, Some("input"), Some("too_many_chunks")));
+    }
+    info!("📦 Processing {} chunks sequentially", total_chunks);
 
-    // Process each chunk as a separate request
-    let mut all_embeddings = Vec::new();
+    // Process each chunk as a separate request, keeping embeddings per input
+    let mut input_embeddings: Vec<Vec<Vec<f32>>> = vec![Vec::new(); chunked_inputs.len()];
+    let mut chunk_spans: Vec<Vec<(usize, usize)>> = vec![Vec::new(); chunked_inputs.len()];
     let target_path = get_ollama_endpoint("/v1/embeddings");
-    let target_url = format!("{}{}", state.ollama_host, target_path);
+    // Ollama's own count for embedded chunks, the local tokenizer's for cached ones
+    let mut prompt_tokens: u32 = 0;
+
+    let chunks: Vec<(usize, Chunk)> = chunked_inputs
+        .into_iter()
+        .enumerate()
+        .flat_map(|(input_idx, chunks)| chunks.into_iter().map(move |chunk| (input_idx, chunk)))
+        .collect();
+    for (idx, (input_idx, chunk)) in chunks.into_iter().enumerate() {
+        info!("   Processing chunk {}/{} (input {})", idx + 1, total_chunks, input_idx);
+        let span = (chunk.start, chunk.end);
+        if let Some(hit) = state.embedding_cache.get(&model_name, &chunk.text, None) {
+            debug!("💾 Chunk {} served from the embedding cache", idx + 1);
+            prompt_tokens += crate::tokens::count_tokens(&chunk.text) as u32;
+            input_embeddings[input_idx].push(hit.as_ref().clone());
+            chunk_spans[input_idx].push(span);
+            continue;
+        }
+        let cache_text = state.embedding_cache.is_enabled().then(|| chunk.text.clone());
+        let chunk_text = chunk.text.clone();
 
-    for (idx, chunk) in chunked_inputs.iter().enumerate() {
-        info!("   Processing chunk {}/{}", idx + 1, chunked_inputs.len());
-        
         let ollama_req = OllamaEmbedRequest {
             model: model_name.clone(),
-            input: vec![chunk.clone()],
+            input: vec![chunk.text],
             truncate: Some(true),
             options: Some(OllamaOptions { num_ctx }),
-            keep_alive: None,
+            keep_alive: state.resident_models.keep_alive_for(&model_name),
         };
 
         let req_body = match serde_json::to_vec(&ollama_req) {
@@ -227,16 +1107,15 @@ async fn handle_embeddings_with_chunking(
             }
         };
 
-        // Send request with retry
-        let response = match send_with_retry(&state.client, &target_url, req_body, 2).await {
-            Ok(resp) => resp,
+        // Send request with retry, sharing the call with identical in-flight chunks
+        let (status, response_bytes) = match post_embed_coalesced(&state, target_path, &model_name, req_body, 2).await {
+            Ok(reply) => reply,
             Err(e) => {
                 error!("Failed to process chunk {}: {}", idx + 1, e);
                 return Err(StatusCode::BAD_GATEWAY);
             }
         };
 
-        let status = response.status();
         if !status.is_success() {
             if status == StatusCode::INTERNAL_SERVER_ERROR {
                 error!("❌ Ollama server error (500) for chunk {}: This may indicate memory allocation failure", idx + 1);
@@ -244,28 +1123,18 @@ async fn handle_embeddings_with_chunking(
             } else {
                 error!("Ollama returned error for chunk {}: {}", idx + 1, status);
             }
-            let error_body = response.bytes().await.unwrap_or_default();
-            let error_text = String::from_utf8_lossy(&error_body);
+            let error_text = String::from_utf8_lossy(&response_bytes);
             if !error_text.is_empty() {
                 error!("   Error details: {}", error_text);
             }
             return Ok(Response::builder()
                 .status(status)
                 .header("Content-Type", "application/json")
-                .body(Body::from(error_body))
+                .body(Body::from(response_bytes))
                 .unwrap());
         }
 
-        // Parse response
-        let response_bytes = match response.bytes().await {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                error!("Failed to read chunk {} response: {}", idx + 1, e);
-                return Err(StatusCode::BAD_GATEWAY);
-            }
-        };
-
-        let ollama_resp: Value = match serde_json::from_slice(&response_bytes) {
+        let ollama_resp: Value = match parse_json_offloaded(response_bytes).await {
             Ok(json) => json,
             Err(e) => {
                 error!("Failed to parse chunk {} response: {}", idx + 1, e);
@@ -273,6 +1142,11 @@ async fn handle_embeddings_with_chunking(
             }
         };
 
+        prompt_tokens += ollama_resp
+            .get("prompt_eval_count")
+            .and_then(Value::as_u64)
+            .map_or_else(|| crate::tokens::count_tokens(&chunk_text) as u32, |count| count as u32);
+
         // Extract embeddings
         if let Some(embeddings) = ollama_resp.get("embeddings").and_then(|e| e.as_array()) {
             for embedding in embeddings {
@@ -280,53 +1154,72 @@ async fn handle_embeddings_with_chunking(
                     let float_vec: Vec<f32> = vec.iter()
                         .filter_map(|v| v.as_f64().map(|f| f as f32))
                         .collect();
-                    all_embeddings.push(float_vec);
+                    if let Some(text) = &cache_text {
+                        state.embedding_cache.insert(&model_name, text, None, float_vec.clone());
+                    }
+                    input_embeddings[input_idx].push(float_vec);
+                    chunk_spans[input_idx].push(span);
                 }
             }
         }
     }
 
-    info!("✅ Collected {} embeddings from chunks", all_embeddings.len());
-
-    // Combine embeddings by averaging
-    let combined_embedding = if all_embeddings.is_empty() {
-        vec![]
+    let embedding_count: usize = input_embeddings.iter().map(Vec::len).sum();
+    info!("✅ Collected {} embeddings from chunks", embedding_count);
+
+    let data = if return_chunks {
+        // One embedding per chunk, tagged with its input and offsets
+        input_embeddings
+            .into_iter()
+            .zip(chunk_spans)
+            .enumerate()
+            .flat_map(|(input_index, (embeddings, spans))| {
+                embeddings.into_iter().zip(spans).map(move |(embedding, (start, end))| (input_index, embedding, start, end))
+            })
+            .enumerate()
+            .map(|(index, (input_index, embedding, start, end))| crate::translator::OpenAIEmbedding {
+                object: "embedding".to_string(),
+                embedding,
+                index,
+                chunk: Some(crate::translator::OpenAIEmbeddingChunk { input_index, start, end }),
+            })
+            .collect()
     } else {
-        let dim = all_embeddings[0].len();
-        let mut combined = vec![0.0f32; dim];
-        
-        for embedding in &all_embeddings {
-            for (i, &val) in embedding.iter().enumerate() {
-                if i < dim {
-                    combined[i] += val;
+        // Combine embeddings only within each original input
+        info!("🧮 Combining chunk embeddings with {}", aggregation.name());
+        input_embeddings
+            .iter()
+            .zip(&chunk_spans)
+            .enumerate()
+            .map(|(index, (embeddings, spans))| {
+                let lengths: Vec<usize> = spans.iter().map(|(start, end)| end - start).collect();
+                crate::translator::OpenAIEmbedding {
+                    object: "embedding".to_string(),
+                    embedding: aggregation.combine(embeddings, &lengths),
+                    index,
+                    chunk: None,
                 }
-            }
-        }
-        
-        // Average
-        for val in &mut combined {
-            *val /= all_embeddings.len() as f32;
-        }
-        
-        combined
+            })
+            .collect()
     };
 
     // Build OpenAI response
-    let openai_resp = crate::translator::OpenAIEmbeddingsResponse {
+    let mut openai_resp = crate::translator::OpenAIEmbeddingsResponse {
         object: "list".to_string(),
-        data: vec![crate::translator::OpenAIEmbedding {
-            object: "embedding".to_string(),
-            embedding: combined_embedding,
-            index: 0,
-        }],
+        data,
         model: model_name,
         usage: crate::translator::OpenAIUsage {
-            prompt_tokens: all_embeddings.len() as u32 * 10, // Approximate
-            total_tokens: all_embeddings.len() as u32 * 10,
+            prompt_tokens,
+            total_tokens: prompt_tokens,
         },
     };
 
-    let response_body = match serde_json::to_vec(&openai_resp) {
+    if let Some(dims) = dims {
+        dimensions::apply(&mut openai_resp, dims);
+        info!("📐 Truncated embeddings to {} dimensions", dims);
+    }
+
+    let response_body = match serialize_embeddings_offloaded(openai_resp).await {
         Ok(b) => b,
         Err(e) => {
             error!("Failed to serialize response: {}", e);
@@ -347,8 +1240,35 @@ async fn handle_single_embeddings_request(
     body_json: Value,
     num_ctx: u32,
     model_name: String,
+    dims: Option<usize>,
 ) -> Result<Response<Body>, StatusCode> {
-    let ollama_req = match translate_openai_embeddings_to_ollama(
+    // Serve inputs seen before from the embedding cache and only embed the rest
+    let mut body_json = body_json;
+    let cache = state.embedding_cache.clone();
+    let inputs = match body_json.get("input").cloned().map(serde_json::from_value::<InputType>) {
+        Some(Ok(InputType::Single(input))) if cache.is_enabled() => vec![input],
+        Some(Ok(InputType::Multiple(inputs))) if cache.is_enabled() => inputs,
+        _ => Vec::new(),
+    };
+    let cached: Vec<Option<Arc<Vec<f32>>>> = inputs.iter().map(|input| cache.get(&model_name, input, dims)).collect();
+    let missing: Vec<usize> = (0..inputs.len()).filter(|&i| cached[i].is_none()).collect();
+    if !inputs.is_empty() && missing.is_empty() {
+        info!("💾 All {} embedding inputs served from the cache", inputs.len());
+        let prompt_tokens = count_input_tokens(&inputs);
+        let openai_resp = crate::translator::OpenAIEmbeddingsResponse {
+            object: "list".to_string(),
+            data: merge_cached_embeddings(cached, Vec::new()),
+            model: model_name,
+            usage: crate::translator::OpenAIUsage { prompt_tokens, total_tokens: prompt_tokens },
+        };
+        return embeddings_response(openai_resp).await;
+    }
+    if missing.len() < inputs.len() {
+        info!("💾 {} of {} embedding inputs served from the cache", inputs.len() - missing.len(), inputs.len());
+        body_json["input"] = serde_json::json!(missing.iter().map(|&i| &inputs[i]).collect::<Vec<_>>());
+    }
+
+    let mut ollama_req = match translate_openai_embeddings_to_ollama(
         body_json,
         num_ctx,
         state.max_embedding_input_length,
@@ -360,6 +1280,10 @@ async fn handle_single_embeddings_request(
             return Err(StatusCode::BAD_REQUEST);
         }
     };
+    if let Some(keep_alive) = state.resident_models.keep_alive_for(&model_name) {
+        ollama_req.keep_alive = Some(keep_alive);
+    }
+    let sent_tokens = count_input_tokens(&ollama_req.input);
 
     let body = match serde_json::to_vec(&ollama_req) {
         Ok(b) => b,
@@ -369,26 +1293,21 @@ async fn handle_single_embeddings_request(
         }
     };
 
-    info!("📤 Translated request: {}", serde_json::to_string_pretty(&ollama_req).unwrap_or_default());
+    if let Some(body) = state.body_logging.render(&ollama_req) {
+        info!("📤 Translated request: {}", body);
+    }
 
     let target_path = get_ollama_endpoint("/v1/embeddings");
-    let target_url = format!("{}{}", state.ollama_host, target_path);
-    info!("🔄 Forwarding to Ollama native API: {}", target_url);
+    info!("🔄 Forwarding to Ollama native API: {}{}", state.backend_for(&model_name).url, target_path);
 
-    let response = match state.client.post(&target_url)
-        .body(body)
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-    {
-        Ok(resp) => resp,
+    let (status, response_bytes) = match post_embed_coalesced(&state, target_path, &model_name, body, 1).await {
+        Ok(reply) => reply,
         Err(e) => {
             error!("❌ Failed to proxy request: {}", e);
             return Err(StatusCode::BAD_GATEWAY);
         }
     };
 
-    let status = response.status();
     info!("📬 Ollama response status: {}", status);
 
     if !status.is_success() {
@@ -400,27 +1319,18 @@ async fn handle_single_embeddings_request(
         } else {
             error!("Ollama returned error status: {}", status);
         }
-        let error_body = response.bytes().await.unwrap_or_default();
-        let error_text = String::from_utf8_lossy(&error_body);
+        let error_text = String::from_utf8_lossy(&response_bytes);
         if !error_text.is_empty() {
             debug!("   Error details: {}", error_text);
         }
         return Ok(Response::builder()
             .status(status)
             .header("Content-Type", "application/json")
-            .body(Body::from(error_body))
+            .body(Body::from(response_bytes))
             .unwrap());
     }
 
-    let response_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            error!("Failed to read response body: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
-        }
-    };
-
-    let ollama_resp: Value = match serde_json::from_slice(&response_bytes) {
+    let ollama_resp: Value = match parse_json_offloaded(response_bytes).await {
         Ok(json) => json,
         Err(e) => {
             error!("Failed to parse Ollama response: {}", e);
@@ -428,19 +1338,73 @@ async fn handle_single_embeddings_request(
         }
     };
 
-    debug!("📥 Ollama response: {}", serde_json::to_string_pretty(&ollama_resp).unwrap_or_default());
+    if let Some(body) = state.body_logging.render(&ollama_resp) {
+        debug!("📥 Ollama response: {}", body);
+    }
 
-    let openai_resp = match translate_ollama_embed_to_openai(ollama_resp, model_name) {
+    let mut openai_resp = match translate_ollama_embed_to_openai(ollama_resp, model_name.clone()) {
         Ok(resp) => resp,
         Err(e) => {
             error!("Failed to translate response: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
+    if openai_resp.usage.prompt_tokens == 0 {
+        openai_resp.usage.prompt_tokens = sent_tokens;
+        openai_resp.usage.total_tokens = sent_tokens;
+    }
+    if let Some(dims) = dims {
+        dimensions::apply(&mut openai_resp, dims);
+        info!("📐 Truncated embeddings to {} dimensions", dims);
+    }
+    if !inputs.is_empty() && openai_resp.data.len() == missing.len() {
+        for (item, &i) in openai_resp.data.iter().zip(&missing) {
+            cache.insert(&model_name, &inputs[i], dims, item.embedding.clone());
+        }
+        let cached_tokens: usize =
+            inputs.iter().zip(&cached).filter(|(_, hit)| hit.is_some()).map(|(input, _)| crate::tokens::count_tokens(input)).sum();
+        openai_resp.usage.prompt_tokens += cached_tokens as u32;
+        openai_resp.usage.total_tokens = openai_resp.usage.prompt_tokens;
+        let fresh = std::mem::take(&mut openai_resp.data);
+        openai_resp.data = merge_cached_embeddings(cached, fresh);
+    }
 
     info!("✅ Translated response back to OpenAI format");
 
-    let response_body = match serde_json::to_vec(&openai_resp) {
+    embeddings_response(openai_resp).await
+}
+
+/// Local token count for embedding inputs Ollama didn't report on
+fn count_input_tokens(inputs: &[String]) -> u32 {
+    inputs.iter().map(|input| crate::tokens::count_tokens(input)).sum::<usize>() as u32
+}
+
+/// Cached embeddings in input order, with the gaps filled from `fresh` in order
+fn merge_cached_embeddings(
+    cached: Vec<Option<Arc<Vec<f32>>>>,
+    fresh: Vec<crate::translator::OpenAIEmbedding>,
+) -> Vec<crate::translator::OpenAIEmbedding> {
+    let mut fresh = fresh.into_iter();
+    cached
+        .into_iter()
+        .enumerate()
+        .map(|(index, hit)| crate::translator::OpenAIEmbedding {
+            object: "embedding".to_string(),
+            embedding: match hit {
+                Some(embedding) => embedding.as_ref().clone(),
+                None => fresh.next().map(|item| item.embedding).unwrap_or_default(),
+            },
+            index,
+            chunk: None,
+        })
+        .collect()
+}
+
+/// 200 response with a serialized OpenAI embeddings body
+async fn embeddings_response(
+    openai_resp: crate::translator::OpenAIEmbeddingsResponse,
+) -> Result<Response<Body>, StatusCode> {
+    let response_body = match serialize_embeddings_offloaded(openai_resp).await {
         Ok(b) => b,
         Err(e) => {
             error!("Failed to serialize OpenAI response: {}", e);
@@ -455,6 +1419,89 @@ async fn handle_single_embeddings_request(
         .unwrap())
 }
 
+/// Serve /v1/models (or /v1/models/{id} when `id` is given) from Ollama's /api/tags
+async fn handle_models(state: ProxyState, id: Option<&str>) -> Result<Response<Body>, StatusCode> {
+    let target_path = get_ollama_endpoint("/v1/models");
+    let url = format!("{}{}", state.ollama_host, target_path);
+    info!("🔄 Listing models from Ollama native API: {}", url);
+
+    let request = apply_timeout(state.client().get(&url), state.timeouts.for_path(target_path));
+    let response = match send_resilient(request, &state.metrics, state.saturation_retry, state.restart_retry, true).await {
+        Ok(resp) if resp.status().is_success() => resp,
+        Ok(resp) => {
+            error!("Ollama returned {} for {}", resp.status(), target_path);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+        Err(e) => {
+            error!("Failed to list models: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+    let tags: Value = match response.json().await {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to parse Ollama tags response: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+    let list = match translate_ollama_tags_to_openai(&tags) {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Failed to translate model list: {}", e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+
+    let body = match id {
+        Some(id) => match find_model(list, id) {
+            Some(model) => serde_json::to_vec(&model),
+            None => {
+                warn!("⚠️  Model '{}' not found", id);
+                let message = format!("The model '{}' does not exist", id);
+                return Ok(openai_error(StatusCode::NOT_FOUND, &message, Some("model"), Some("model_not_found")));
+            }
+        },
+        None => serde_json::to_vec(&list),
+    };
+    let body = body.map_err(|e| {
+        error!("Failed to serialize model list: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("✅ Translated model list to OpenAI format");
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap())
+}
+
+/// Pass images given by http(s) URL to Ollama inline, as it only accepts base64
+/// image data. Downloading is off unless enabled, and what went wrong is only
+/// logged, so clients can't use the proxy to probe hosts it can reach.
+async fn inline_remote_images(request: &mut OllamaChatRequest, enabled: bool) -> Result<(), &'static str> {
+    use base64::Engine;
+
+    if request.messages.iter().all(|m| m.image_urls.is_empty()) {
+        return Ok(());
+    }
+    if !enabled {
+        warn!("🖼️  Refusing image URL: FETCH_IMAGE_URLS is off");
+        return Err("Image URLs are not fetched by this proxy; send images as base64 data: URLs");
+    }
+    for message in &mut request.messages {
+        for url in std::mem::take(&mut message.image_urls) {
+            info!("🖼️  Fetching image {}", url);
+            let bytes = remote_images::fetch(&url).await.map_err(|e| {
+                warn!("❌ Failed to fetch image {}: {}", url, e);
+                "Failed to fetch image URL"
+            })?;
+            message.images.push(base64::engine::general_purpose::STANDARD.encode(&bytes));
+        }
+    }
+    Ok(())
+}
+
 /// Handle chat completions request
 async fn handle_chat_completions(
     state: ProxyState,
@@ -462,6 +1509,7 @@ async fn handle_chat_completions(
     num_ctx: Option<u32>,
     model_name: String,
     metadata: crate::model_metadata::ModelMetadata,
+    filters: OutputFilters,
 ) -> Result<Response<Body>, StatusCode> {
     // Check if streaming is requested
     if let Some(stream) = body_json.get("stream").and_then(|s| s.as_bool()) {
@@ -472,13 +1520,20 @@ async fn handle_chat_completions(
         }
     }
     
-    let ollama_req = match translate_openai_chat_to_ollama(body_json, num_ctx) {
+    let mut ollama_req = match translate_openai_chat_to_ollama(body_json, num_ctx) {
         Ok(req) => req,
         Err(e) => {
             error!("Failed to translate chat request: {}", e);
-            return Err(StatusCode::BAD_REQUEST);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &e, None, None));
         }
     };
+    normalize_roles(&mut ollama_req, state.developer_role.as_deref());
+    if let Some(options) = ollama_req.options.as_mut() {
+        state.penalty_mapping.apply(&model_name, options);
+    }
+    if let Err(message) = inline_remote_images(&mut ollama_req, state.fetch_image_urls).await {
+        return Ok(openai_error(StatusCode::BAD_REQUEST, message, Some("messages"), Some("invalid_image_url")));
+    }
 
     // Convert to Value for modifier application
     let mut ollama_req_json = match serde_json::to_value(&ollama_req) {
@@ -491,10 +1546,22 @@ async fn handle_chat_completions(
 
     // Apply modifiers (context limits, num_predict, etc.)
     info!("🔧 Applying modifiers to translated chat request");
-    let modified = apply_modifiers(&mut ollama_req_json, &metadata, state.max_context_override);
+    let modifiers_started = std::time::Instant::now();
+    let modified = apply_modifiers(
+        &mut ollama_req_json,
+        &metadata,
+        state.max_context_override,
+        state.prompt_compression,
+        state.model_overrides.for_model(&model_name),
+    );
+    timing::record("modifiers", modifiers_started);
     if modified {
         info!("✏️  Request modified by modifiers");
     }
+    state.resident_models.apply(&mut ollama_req_json);
+    let prompt_tokens = crate::tokens::count_request_tokens(&ollama_req_json) as u32;
+    let format = requested_format(&ollama_req_json);
+    let parallel_tool_calls = ollama_req.parallel_tool_calls;
 
     let body = match serde_json::to_vec(&ollama_req_json) {
         Ok(b) => b,
@@ -504,67 +1571,71 @@ async fn handle_chat_completions(
         }
     };
 
-    info!("📤 Final chat request: {}", serde_json::to_string_pretty(&ollama_req_json).unwrap_or_default());
+    if let Some(body) = state.body_logging.render(&ollama_req_json) {
+        info!("📤 Final chat request: {}", body);
+    }
 
     let target_path = get_ollama_endpoint("/v1/chat/completions");
-    let target_url = format!("{}{}", state.ollama_host, target_path);
-    info!("🔄 Forwarding to Ollama native API: {}", target_url);
+    info!("🔄 Forwarding to Ollama native API: {}{}", state.backend_for(&model_name).url, target_path);
 
-    let response = match state.client.post(&target_url)
-        .body(body)
-        .header("Content-Type", "application/json")
-        .send()
-        .await
-    {
-        Ok(resp) => resp,
+    let (status, response_bytes) = match post_chat_coalesced(&state, target_path, &model_name, body).await {
+        Ok(reply) => reply,
         Err(e) => {
             error!("❌ Failed to proxy chat request: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
+            return Ok(openai_error(StatusCode::BAD_GATEWAY, &format!("Failed to reach Ollama: {}", e), None, None));
         }
     };
-
-    let status = response.status();
     info!("📬 Ollama chat response status: {}", status);
 
     if !status.is_success() {
         error!("Ollama returned error status: {}", status);
-        let error_body = response.bytes().await.unwrap_or_default();
-        let error_text = String::from_utf8_lossy(&error_body);
+        let error_text = String::from_utf8_lossy(&response_bytes);
         if !error_text.is_empty() {
             debug!("   Error details: {}", error_text);
         }
         return Ok(Response::builder()
             .status(status)
             .header("Content-Type", "application/json")
-            .body(Body::from(error_body))
+            .body(Body::from(response_bytes))
             .unwrap());
     }
 
-    let response_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            error!("Failed to read chat response body: {}", e);
-            return Err(StatusCode::BAD_GATEWAY);
-        }
-    };
-
-    let ollama_resp: Value = match serde_json::from_slice(&response_bytes) {
+    let mut ollama_resp: Value = match serde_json::from_slice(&response_bytes) {
         Ok(json) => json,
         Err(e) => {
             error!("Failed to parse Ollama chat response: {}", e);
             return Err(StatusCode::BAD_GATEWAY);
         }
     };
+    if filters.filter_json(&mut ollama_resp) {
+        info!("🧹 Output filters changed the chat response");
+    }
+    apply_response_modifiers(&mut ollama_resp, &model_name, &state.strip_think_models);
+
+    // response_format asked for JSON: check the content before handing it back
+    let calls_tools = ollama_resp.pointer("/message/tool_calls").and_then(Value::as_array).is_some_and(|c| !c.is_empty());
+    if let Some(schema) = format.as_ref().filter(|_| !calls_tools) {
+        let content = ollama_resp.pointer("/message/content").and_then(Value::as_str).unwrap_or_default();
+        if let Err(e) = validate(content, schema.as_ref()) {
+            warn!("⚠️  Chat response does not match the requested format: {}", e);
+            return Ok(mismatch_response(&e));
+        }
+    }
 
-    debug!("📥 Ollama chat response: {}", serde_json::to_string_pretty(&ollama_resp).unwrap_or_default());
+    if let Some(body) = state.body_logging.render(&ollama_resp) {
+        debug!("📥 Ollama chat response: {}", body);
+    }
 
-    let openai_resp = match translate_ollama_chat_to_openai(ollama_resp, model_name) {
+    let mut openai_resp = match translate_ollama_chat_to_openai(ollama_resp, model_name, prompt_tokens) {
         Ok(resp) => resp,
         Err(e) => {
             error!("Failed to translate chat response: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
+    if !parallel_tool_calls {
+        keep_first_tool_call(&mut openai_resp);
+    }
 
     info!("✅ Translated chat response back to OpenAI format");
 
@@ -583,11 +1654,231 @@ async fn handle_chat_completions(
         .unwrap())
 }
 
+/// JSON payloads larger than this are (de)serialized on the blocking pool so
+/// big embedding arrays don't stall the async workers
+const OFFLOAD_JSON_BYTES: usize = 256 * 1024;
+
+/// Parse a JSON body, moving large payloads off the async workers
+async fn parse_json_offloaded(bytes: bytes::Bytes) -> Result<Value, String> {
+    if bytes.len() < OFFLOAD_JSON_BYTES {
+        return serde_json::from_slice(&bytes).map_err(|e| e.to_string());
+    }
+
+    debug!("Parsing {} byte JSON body on the blocking pool", bytes.len());
+    tokio::task::spawn_blocking(move || serde_json::from_slice(&bytes))
+        .await
+        .map_err(|e| format!("JSON parsing task failed: {}", e))?
+        .map_err(|e| e.to_string())
+}
+
+/// Serialize an embeddings response, moving large payloads off the async workers
+async fn serialize_embeddings_offloaded(
+    resp: crate::translator::OpenAIEmbeddingsResponse,
+) -> Result<Vec<u8>, String> {
+    // Roughly 10 bytes per serialized float
+    let approx_bytes: usize = resp.data.iter().map(|d| d.embedding.len() * 10).sum();
+    if approx_bytes < OFFLOAD_JSON_BYTES {
+        return serde_json::to_vec(&resp).map_err(|e| e.to_string());
+    }
+
+    debug!("Serializing ~{} byte embeddings response on the blocking pool", approx_bytes);
+    tokio::task::spawn_blocking(move || serde_json::to_vec(&resp))
+        .await
+        .map_err(|e| format!("JSON serialization task failed: {}", e))?
+        .map_err(|e| e.to_string())
+}
+
+/// Post an embed request to Ollama, joining an identical request that is already in flight
+async fn post_embed_coalesced(
+    state: &ProxyState,
+    target_path: &'static str,
+    model: &str,
+    body: Vec<u8>,
+    max_retries: usize,
+) -> UpstreamReply {
+    let key = format!("{}\n{}", target_path, String::from_utf8_lossy(&body));
+    let timeout = state.timeout_for(EndpointClass::Embeddings, model);
+    let model = model.to_string();
+    let flight_state = state.clone();
+    let timings = timing::current();
+    let started = std::time::Instant::now();
+
+    let (reply, joined) = state.embed_flight.run(key, async move {
+        let state = &flight_state;
+        let send_embed = |url: String| {
+            let body = body.clone();
+            async move { send_with_retry(state, &url, body, timeout, max_retries).await }
+        };
+        let response =
+            post_to_backends(&state.backends, &state.metrics, state.hedge.delay, &model, state.conversation.as_deref(), target_path, send_embed)
+                .await
+                .inspect_err(|_| state.record_timeout(&model, EndpointClass::Embeddings, timeout, started))?;
+        if let Some(timings) = &timings {
+            timings.record("upstream_ttfb", started.elapsed());
+        }
+        let status = response.status();
+        if status.is_success() {
+            state.latency.record(&model, EndpointClass::Embeddings, started.elapsed());
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read response body: {}", e))?;
+        Ok((status, bytes))
+    }).await;
+
+    timing::record("upstream_total", started);
+    if joined {
+        info!("🔗 Joined identical in-flight embeddings request");
+        state.metrics.record_coalesced_request();
+    }
+    debug!("   In-flight embeddings calls: {}", state.embed_flight.in_flight());
+
+    reply
+}
+
+/// Post a translated chat request to Ollama, joining an identical request that is
+/// already in flight (client retries, duplicate eval jobs)
+async fn post_chat_coalesced(state: &ProxyState, target_path: &'static str, model: &str, body: Vec<u8>) -> UpstreamReply {
+    let key = format!("{}\n{}", target_path, String::from_utf8_lossy(&body));
+    // Only short chats are worth duplicating onto a second backend
+    let hedge_delay = state.hedge.delay.filter(|_| body.len() <= state.hedge.max_chat_body_bytes);
+    let timeout = state.timeout_for(EndpointClass::Chat, model);
+    debug!("   Chat timeout: {:?}", timeout);
+    let model = model.to_string();
+    let flight_state = state.clone();
+    let timings = timing::current();
+    let started = std::time::Instant::now();
+
+    let (reply, joined) = state.chat_flight.run(key, async move {
+        let state = &flight_state;
+        let send_chat = |url: String| {
+            let request = state.client().post(url)
+                .body(body.clone())
+                .header("Content-Type", "application/json");
+            async move {
+                send_resilient(apply_timeout(request, timeout), &state.metrics, state.saturation_retry, state.restart_retry, true)
+                    .await
+                    .map_err(|e| {
+                        if e.is_connect() {
+                            state.upstream.report_connect_error();
+                        }
+                        e.to_string()
+                    })
+            }
+        };
+        let response = post_to_backends(&state.backends, &state.metrics, hedge_delay, &model, state.conversation.as_deref(), target_path, send_chat)
+            .await
+            .inspect_err(|_| state.record_timeout(&model, EndpointClass::Chat, timeout, started))?;
+        if let Some(timings) = &timings {
+            timings.record("upstream_ttfb", started.elapsed());
+        }
+        let status = response.status();
+        if status.is_success() {
+            state.latency.record(&model, EndpointClass::Chat, started.elapsed());
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read chat response body: {}", e))?;
+        if let Some(eval) = Eval::scan(&bytes).filter(|_| status.is_success()) {
+            state.model_stats.record_eval(&model, eval);
+        }
+        Ok((status, bytes))
+    }).await;
+
+    timing::record("upstream_total", started);
+    if joined {
+        info!("🔗 Joined identical in-flight chat request");
+        state.metrics.record_coalesced_request();
+    }
+    debug!("   In-flight chat calls: {}", state.chat_flight.in_flight());
+
+    reply
+}
+
+/// Delay, target and a copy of `request` for a second backend when a standard request
+/// should be hedged: inference requests only, and chats or generations only while short
+fn hedge_request(
+    state: &ProxyState,
+    class: EndpointClass,
+    primary: &str,
+    request: &reqwest::RequestBuilder,
+    path_and_query: &str,
+    body_len: usize,
+) -> Option<(std::time::Duration, String, reqwest::RequestBuilder)> {
+    let delay = state.hedge.delay.filter(|_| class.is_inference())?;
+    if matches!(class, EndpointClass::Chat | EndpointClass::Generate) && body_len > state.hedge.max_chat_body_bytes {
+        return None;
+    }
+    let alternate = state.backends.alternate(primary)?;
+    let mut hedged = request.try_clone()?.build().ok()?;
+    *hedged.url_mut() = format!("{}{}", alternate.url, path_and_query).parse().ok()?;
+    Some((delay, alternate.url.clone(), reqwest::RequestBuilder::from_parts(state.client(), hedged)))
+}
+
+/// Send a request to `model`'s backend (following `conversation`), hedging onto a second
+/// one if it hasn't answered within `hedge_delay`. `send` issues the request to a full URL.
+async fn post_to_backends<F, Fut>(
+    backends: &Arc<BackendPool>,
+    metrics: &Metrics,
+    hedge_delay: Option<std::time::Duration>,
+    model: &str,
+    conversation: Option<&str>,
+    path: &str,
+    send: F,
+) -> Result<reqwest::Response, String>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<reqwest::Response, String>>,
+{
+    let primary = backends.for_conversation(model, conversation);
+    let url_for = |target: &crate::backends::Backend| format!("{}{}", target.url, path);
+
+    let (delay, alternate) = match hedge_delay.and_then(|d| backends.alternate(&primary.url).map(|b| (d, b))) {
+        Some((delay, alternate)) => (delay, alternate),
+        None => return track_backend_health(backends, primary, send(url_for(primary))).await,
+    };
+
+    let (result, winner) = race(
+        track_backend_health(backends, primary, send(url_for(primary))),
+        Some(track_backend_health(backends, alternate, send(url_for(alternate)))),
+        delay,
+        |r| matches!(r, Ok(resp) if resp.status().is_success()),
+    )
+    .await;
+    if winner == HedgeWinner::Hedge {
+        info!("🏁 Hedged request to {} answered first", alternate.url);
+        metrics.record_hedge_win();
+    }
+    result
+}
+
+/// Await a backend response, counting connection-level failures against the
+/// backend's health (HTTP error statuses don't count)
+async fn track_backend_health<Fut>(
+    backends: &Arc<BackendPool>,
+    target: &crate::backends::Backend,
+    response: Fut,
+) -> Result<reqwest::Response, String>
+where
+    Fut: std::future::Future<Output = Result<reqwest::Response, String>>,
+{
+    let _load = backends.track(&target.url);
+    let result = response.await;
+    match &result {
+        Ok(_) => backends.record_success(target),
+        Err(_) => backends.record_failure(target),
+    }
+    result
+}
+
 /// Send request with retry logic
 async fn send_with_retry(
-    client: &reqwest::Client,
+    state: &ProxyState,
     url: &str,
     body: Vec<u8>,
+    timeout: Option<std::time::Duration>,
     max_retries: usize,
 ) -> Result<reqwest::Response, String> {
     let mut attempts = 0;
@@ -595,17 +1886,27 @@ async fn send_with_retry(
     loop {
         attempts += 1;
         
-        match client.post(url)
+        let request = state.client().post(url)
             .body(body.clone())
-            .header("Content-Type", "application/json")
-            .send()
-            .await
-        {
+            .header("Content-Type", "application/json");
+
+        let sent = send_resilient(
+            apply_timeout(request, timeout),
+            &state.metrics,
+            state.saturation_retry,
+            state.restart_retry,
+            true,
+        )
+        .await;
+        match sent {
             Ok(resp) => return Ok(resp),
             Err(e) => {
                 if e.is_timeout() {
                     return Err(format!("Request timed out: {}", e));
                 }
+                if e.is_connect() {
+                    state.upstream.report_connect_error();
+                }
                 if attempts >= max_retries {
                     return Err(format!("Failed after {} attempts: {}", attempts, e));
                 }
@@ -629,7 +1930,9 @@ async fn handle_standard_request(
     let mut body_json: Option<Value> = if !body_bytes.is_empty() {
         match serde_json::from_slice(&body_bytes) {
             Ok(json) => {
-                info!("📋 Request body: {}", serde_json::to_string_pretty(&json).unwrap_or_default());
+                if let Some(body) = state.body_logging.render(&json) {
+                    info!("📋 Request body: {}", body);
+                }
                 Some(json)
             }
             Err(_) => {
@@ -641,18 +1944,41 @@ async fn handle_standard_request(
         None
     };
 
+    let model_name = body_json.as_ref().and_then(extract_model_name);
+    let class = EndpointClass::from_path(path);
+
+    if let (Some(json), EndpointClass::Embeddings) = (body_json.as_mut(), class) {
+        preprocess_embeddings(&state, &headers, json);
+    }
+
+    // Output filters only apply to generated text
+    let filters = match class {
+        EndpointClass::Chat | EndpointClass::Generate => {
+            state.output_filters.for_request(model_name.as_deref(), bearer_token(&headers))
+        }
+        _ => OutputFilters::default(),
+    };
+
     // Apply modifications if this is a request with a body that needs parameter adjustment
     let modified_body_bytes = if let Some(ref mut json) = body_json {
-        if let Some(model_name) = extract_model_name(json) {
+        if let Some(model_name) = &model_name {
             info!("🔍 Detected model: {}", model_name);
             
             // Fetch model metadata
-            match state.metadata_cache.get_model_info(&model_name).await {
+            match state.metadata_cache.get_model_info(model_name).await {
                 Ok(metadata) => {
                     info!("📊 Model metadata - n_ctx_train: {}", metadata.n_ctx_train);
                     
                     // Apply modifiers
-                    let modified = apply_modifiers(json, &metadata, state.max_context_override);
+                    let modifiers_started = std::time::Instant::now();
+                    let modified = apply_modifiers(
+                        json,
+                        &metadata,
+                        state.max_context_override,
+                        state.prompt_compression,
+                        state.model_overrides.for_model(model_name),
+                    );
+                    timing::record("modifiers", modifiers_started);
                     if modified {
                         info!("✏️  Request modified - see changes above");
                     }
@@ -661,6 +1987,7 @@ async fn handle_standard_request(
                     warn!("⚠️  Could not fetch model metadata: {}", e);
                 }
             }
+            state.resident_models.apply(json);
         }
         
         // Serialize the potentially modified JSON back to bytes
@@ -670,34 +1997,50 @@ async fn handle_standard_request(
     };
 
     // Build the proxied request
-    let target_url = format!("{}{}", state.ollama_host, path);
-    let full_url = if query.is_empty() {
-        target_url
+    // Requests naming a model go to the backend that model is placed on
+    let base_url = match &model_name {
+        Some(model) => state.backend_for(model).url.as_str(),
+        None => state.ollama_host.as_str(),
+    };
+    let path_and_query = if query.is_empty() {
+        path.to_string()
     } else {
-        format!("{}?{}", target_url, query)
+        format!("{}?{}", path, query)
     };
+    let full_url = format!("{}{}", base_url, path_and_query);
+    let body_len = modified_body_bytes.len();
+    // Counts toward the backend's load (for tier spill-over) until the body is done
+    let load = state.backends.track(base_url);
 
     debug!("🔄 Forwarding to: {}", full_url);
-    debug!("📦 Request body size: {} bytes", modified_body_bytes.len());
+    debug!("📦 Request body size: {} bytes", body_len);
     
     // Log the actual body being sent for debugging
-    if let Ok(body_str) = String::from_utf8(modified_body_bytes.clone()) {
-        debug!("📤 Request body being sent to Ollama: {}", body_str);
+    if let Some(body) = serde_json::from_slice::<Value>(&modified_body_bytes)
+        .ok()
+        .and_then(|json| state.body_logging.render(&json))
+    {
+        debug!("📤 Request body being sent to Ollama: {}", body);
     }
 
     // Create the proxied request
-    let mut proxy_req = state.client
+    let mut proxy_req = state.client()
         .request(method.clone(), &full_url)
         .body(modified_body_bytes);
 
     // Copy headers, but skip host and content-length
-    // (content-length will be set automatically by reqwest based on body)
+    // (content-length will be set automatically by reqwest based on body),
+    // and the client's proxy credentials, which are no business of Ollama's
     let mut has_content_type = false;
+    let strip_authorization = auth::is_enabled(&state);
     for (key, value) in headers.iter() {
         let key_lower = key.as_str().to_lowercase();
         if key_lower == "content-type" {
             has_content_type = true;
         }
+        if strip_authorization && key == header::AUTHORIZATION {
+            continue;
+        }
         if key_lower != "host" && key_lower != "content-length" {
             proxy_req = proxy_req.header(key, value);
         }
@@ -711,27 +2054,96 @@ async fn handle_standard_request(
 
     // Check if this is a streaming request (do this BEFORE sending)
     let is_streaming = is_streaming_request(&body_json);
+    // Structured outputs (format: "json" or a schema) are validated as they stream
+    let structured = body_json
+        .as_ref()
+        .filter(|_| is_streaming && matches!(class, EndpointClass::Chat | EndpointClass::Generate))
+        .and_then(|json| {
+            let schema = requested_format(json)?;
+            let mut retry_body = json.clone();
+            retry_body["stream"] = Value::Bool(false);
+            let timeout = match &model_name {
+                Some(model) => state.timeout_for(class, model),
+                None => state.timeouts.for_path(path),
+            };
+            let retry = RetryRequest { client: state.client(), url: full_url.clone(), body: retry_body, timeout };
+            Some(StructuredCheck::new(schema, state.structured_failure, retry))
+        });
     if is_streaming {
         info!("🌊 Streaming request detected - will forward chunks in real-time");
     } else {
-        info!("📦 Non-streaming request - will buffer full response");
+        info!("📦 Non-streaming request - will pass the response body through");
     }
 
-    // Send the request
-    info!("🚀 Sending request to Ollama (timeout: {}s)", state.request_timeout_seconds);
+    // Send the request with the timeout for this endpoint class, adapted to the
+    // model's history for non-streaming calls (headers arrive once generation is done)
+    let latency_model = model_name.as_deref().filter(|_| !is_streaming);
+    // Generation throughput and time to first token
+    let stats_model = model_name
+        .as_deref()
+        .filter(|_| matches!(class, EndpointClass::Chat | EndpointClass::Generate));
+    // Streams are bounded by their own deadlines: first byte here, idle gaps while forwarding
+    let timeout = match latency_model {
+        _ if state.requested_timeout.is_some() => state.requested_timeout,
+        _ if is_streaming => state.stream_timeouts.total,
+        Some(model) => state.timeout_for(class, model),
+        None => state.timeouts.for_path(path),
+    };
+    proxy_req = apply_timeout(proxy_req, timeout);
+    match timeout {
+        Some(t) => info!("🚀 Sending request to Ollama (timeout: {}s)", t.as_secs()),
+        None => info!("🚀 Sending request to Ollama (no timeout)"),
+    }
     debug!("📤 Awaiting response from Ollama...");
-    let response = match proxy_req.send().await {
+    let started = std::time::Instant::now();
+    let idempotent = is_idempotent(&method, path);
+    let first_byte = state.stream_timeouts.first_byte.filter(|_| is_streaming);
+    // A slow primary can be raced against a second backend; for streams the race ends
+    // with the first response, and the losing request is cancelled
+    let hedge = hedge_request(&state, class, base_url, &proxy_req, &path_and_query, body_len);
+    let send = |request| send_resilient(request, &state.metrics, state.saturation_retry, state.restart_retry, idempotent);
+    let sent = async {
+        let Some((delay, alternate, hedged)) = hedge else {
+            return send(proxy_req).await;
+        };
+        let is_success = |r: &Result<reqwest::Response, reqwest::Error>| matches!(r, Ok(resp) if resp.status().is_success());
+        let (result, winner) = race(send(proxy_req), Some(send(hedged)), delay, is_success).await;
+        if winner == HedgeWinner::Hedge {
+            info!("🏁 Hedged request to {} answered first", alternate);
+            state.metrics.record_hedge_win();
+        }
+        result
+    };
+    let sent = match first_byte {
+        Some(limit) => match tokio::time::timeout(limit, sent).await {
+            Ok(sent) => sent,
+            Err(_) => {
+                error!("⏱️  Ollama didn't start streaming within {} seconds", limit.as_secs());
+                error!("   The model may still be loading; raise FIRST_BYTE_TIMEOUT_SECONDS if this is expected");
+                return Err(StatusCode::GATEWAY_TIMEOUT);
+            }
+        },
+        None => sent.await,
+    };
+    let response = match sent {
         Ok(resp) => {
             debug!("✓ Received response headers from Ollama");
+            timing::record("upstream_ttfb", started);
             resp
         }
         Err(e) => {
             if e.is_timeout() {
-                error!("⏱️  Request timed out after {} seconds", state.request_timeout_seconds);
+                if let Some(model) = latency_model {
+                    state.record_timeout(model, class, timeout, started);
+                }
+                error!("⏱️  Request timed out after {} seconds", timeout.map(|t| t.as_secs()).unwrap_or(0));
                 error!("   This usually indicates Ollama is stalled or processing very large context");
                 error!("   Try: Reduce MAX_CONTEXT_OVERRIDE, restart Ollama, or check Ollama logs");
                 return Err(StatusCode::GATEWAY_TIMEOUT);
             }
+            if e.is_connect() {
+                state.upstream.report_connect_error();
+            }
             error!("❌ Failed to proxy request: {}", e);
             return Err(StatusCode::BAD_GATEWAY);
         }
@@ -739,36 +2151,90 @@ async fn handle_standard_request(
 
     let status = response.status();
     info!("📬 Response status: {}", status);
+    if let (Some(model), true) = (latency_model, status.is_success()) {
+        state.latency.record(model, class, started.elapsed());
+    }
 
     // Only use streaming for successful responses (2xx)
     // Error responses (4xx, 5xx) are single JSON objects, not NDJSON streams
     if is_streaming && status.is_success() {
         info!("🌊 Forwarding response chunks in real-time");
-        return stream_standard_response(response, status).await;
+        let recorder = stats_model.map(|model| StreamRecorder::new(state.model_stats.clone(), model, started));
+        let idle = state.stream_timeouts.idle;
+        return stream_standard_response(response, status, state.stream_batching, idle, filters, structured, recorder)
+            .await
+            .map(|response| hold_until_body_done(response, load));
     } else if is_streaming && !status.is_success() {
         warn!("⚠️  Streaming requested but got error status {}, falling back to buffered response", status);
     }
     
-    if !status.is_success() {
-        debug!("📥 Reading error response body...");
-    } else {
-        debug!("📥 Reading response body...");
-    }
-
     // Build response
     let mut builder = Response::builder().status(status);
     
-    // Copy response headers
+    // Copy response headers (filtered bodies change length)
+    let filtering = !filters.is_empty() && status.is_success();
     for (key, value) in response.headers().iter() {
+        if filtering && key == axum::http::header::CONTENT_LENGTH {
+            continue;
+        }
         builder = builder.header(key, value);
     }
 
-    // Get response body
-    let response_bytes = match response.bytes().await {
-        Ok(bytes) => {
+    // Nothing needs to inspect the body, so stream it straight through
+    if !filtering && !response_needs_buffering(status) {
+        debug!("📥 Streaming response body through without buffering");
+        let body = match stats_model.filter(|_| status.is_success()) {
+            Some(model) => Body::from_stream(stats::record_passthrough(
+                state.model_stats.clone(),
+                model,
+                response.bytes_stream(),
+            )),
+            None => Body::from_stream(response.bytes_stream()),
+        };
+        let result = builder.body(body).map_err(|e| {
+            error!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        });
+        if result.is_ok() {
+            info!("✅ Successfully completed request - response streaming to client");
+        }
+        return result.map(|response| hold_until_body_done(response, load));
+    }
+
+    if !status.is_success() {
+        debug!("📥 Reading error response body...");
+    } else {
+        debug!("📥 Reading response body...");
+    }
+
+    // Get response body, switching to streaming passthrough if it exceeds the buffer cap
+    let response_bytes = match read_body_capped(response, state.max_buffered_response_bytes).await {
+        Ok(CappedBody::Complete(bytes)) => {
             debug!("✓ Read {} bytes from response body", bytes.len());
+            timing::record("upstream_total", started);
+            if let Some(model) = stats_model.filter(|_| status.is_success()) {
+                if let Some(eval) = Eval::scan(&bytes) {
+                    state.model_stats.record_eval(model, eval);
+                }
+            }
             bytes
         }
+        Ok(CappedBody::Overflow(body)) => {
+            warn!(
+                "⚠️  Response exceeds buffer limit ({} bytes), streaming it through instead",
+                state.max_buffered_response_bytes
+            );
+            if filtering {
+                warn!("   Output filters were NOT applied to this response");
+            }
+            return builder
+                .body(body)
+                .map(|response| hold_until_body_done(response, load))
+                .map_err(|e| {
+                    error!("Failed to build streaming response: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                });
+        }
         Err(e) => {
             error!("❌ Failed to read response body: {}", e);
             return Err(StatusCode::BAD_GATEWAY);
@@ -780,12 +2246,20 @@ async fn handle_standard_request(
         if let Ok(json) = serde_json::from_slice::<Value>(&response_bytes) {
             if !status.is_success() {
                 error!("❌ Ollama error response: {}", serde_json::to_string_pretty(&json).unwrap_or_default());
-            } else {
-                debug!("📄 Response body: {}", serde_json::to_string_pretty(&json).unwrap_or_default());
+            } else if let Some(body) = state.body_logging.render(&json) {
+                debug!("📄 Response body: {}", body);
             }
         }
     }
 
+    let response_bytes = match filtering.then(|| filters.filter_body(&response_bytes)).flatten() {
+        Some(filtered) => {
+            info!("🧹 Output filters changed the response");
+            bytes::Bytes::from(filtered)
+        }
+        None => response_bytes,
+    };
+
     let body = Body::from(response_bytes);
     
     debug!("✓ Building response to send back to client");
@@ -800,6 +2274,55 @@ async fn handle_standard_request(
     result
 }
 
+/// Whether a pass-through response body must be buffered before returning it.
+/// Bodies are only held in memory when they will be logged: error responses
+/// always are, successful ones only at debug level.
+fn response_needs_buffering(status: StatusCode) -> bool {
+    !status.is_success() || tracing::enabled!(tracing::Level::DEBUG)
+}
+
+/// Result of reading an upstream body under the buffering cap
+enum CappedBody {
+    /// The whole body fit within the cap
+    Complete(bytes::Bytes),
+    /// The cap was exceeded; the body replays what was read and streams the rest
+    Overflow(Body),
+}
+
+/// Buffer a response body up to `max_bytes`, falling back to a pass-through stream
+/// once the limit is exceeded so huge responses never sit in memory all at once
+async fn read_body_capped(
+    response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<CappedBody, reqwest::Error> {
+    use futures::StreamExt;
+
+    // Known-large bodies go straight to streaming
+    if let Some(len) = response.content_length() {
+        if len > max_bytes as u64 {
+            debug!("Content-Length {} exceeds buffer limit {}", len, max_bytes);
+            return Ok(CappedBody::Overflow(Body::from_stream(response.bytes_stream())));
+        }
+    }
+
+    let mut stream = response.bytes_stream();
+    let mut buffer = bytes::BytesMut::new();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        buffer.extend_from_slice(&chunk);
+
+        if buffer.len() > max_bytes {
+            debug!("Buffered {} bytes, exceeding limit {}", buffer.len(), max_bytes);
+            let prefix = buffer.freeze();
+            let replay = futures::stream::once(async move { Ok::<_, reqwest::Error>(prefix) });
+            return Ok(CappedBody::Overflow(Body::from_stream(replay.chain(stream))));
+        }
+    }
+
+    Ok(CappedBody::Complete(buffer.freeze()))
+}
+
 /// Check if a request has streaming enabled
 fn is_streaming_request(json: &Option<Value>) -> bool {
     let stream_value = json.as_ref().and_then(|j| j.get("stream"));
@@ -812,6 +2335,11 @@ fn is_streaming_request(json: &Option<Value>) -> bool {
 async fn stream_standard_response(
     response: reqwest::Response,
     status: StatusCode,
+    batching: StreamBatching,
+    idle_timeout: Option<std::time::Duration>,
+    filters: OutputFilters,
+    structured: Option<StructuredCheck>,
+    recorder: Option<StreamRecorder>,
 ) -> Result<Response<Body>, StatusCode> {
     use tokio_stream::wrappers::ReceiverStream;
     
@@ -820,8 +2348,12 @@ async fn stream_standard_response(
     
     let mut builder = Response::builder().status(status);
     
-    // Copy response headers (especially Content-Type)
+    // Copy response headers (especially Content-Type); filtered or validated streams change length
+    let rewrites = !filters.is_empty() || structured.is_some();
     for (key, value) in response.headers().iter() {
+        if rewrites && key == axum::http::header::CONTENT_LENGTH {
+            continue;
+        }
         builder = builder.header(key, value);
         debug!("   Header: {}: {:?}", key, value);
     }
@@ -831,7 +2363,7 @@ async fn stream_standard_response(
     
     // Spawn background task to process Ollama's stream
     tokio::spawn(async move {
-        if let Err(e) = process_streaming_chunks(response, tx, start_time).await {
+        if let Err(e) = process_streaming_chunks(response, tx, start_time, batching, idle_timeout, filters, structured, recorder).await {
             error!("❌ Streaming task failed: {}", e);
         }
     });
@@ -847,22 +2379,68 @@ async fn stream_standard_response(
 }
 
 /// Process streaming chunks from Ollama, forwarding complete NDJSON lines immediately
+/// (or in small batches when `batching` is enabled), after any output filters and
+/// structured-output validation. A stream quiet for longer than `idle_timeout` is ended
+/// with an error line
+#[allow(clippy::too_many_arguments)]
 async fn process_streaming_chunks(
     response: reqwest::Response,
     tx: tokio::sync::mpsc::Sender<Result<bytes::Bytes, std::io::Error>>,
     start_time: std::time::Instant,
+    batching: StreamBatching,
+    idle_timeout: Option<std::time::Duration>,
+    filters: OutputFilters,
+    mut structured: Option<StructuredCheck>,
+    mut recorder: Option<StreamRecorder>,
 ) -> Result<(), String> {
-    use futures::StreamExt;
-    
     let mut stream = response.bytes_stream();
     let mut buffer = Vec::new();
     let mut chunk_count = 0;
     let mut total_bytes = 0;
     let mut lines_forwarded = 0;
+
+    // Lines waiting to be flushed together when batching is enabled
+    let mut pending: Vec<u8> = Vec::new();
+    let mut flush_deadline: Option<tokio::time::Instant> = None;
+    let mut frames_sent = 0;
+
+    let mut scrubber = (!filters.is_empty()).then(|| StreamScrubber::new(filters));
     
     info!("📡 Stream processor started, waiting for chunks from Ollama...");
+    if batching.is_enabled() {
+        info!("   Batching lines (interval: {:?}, size: {} bytes)", batching.interval(), batching.flush_bytes);
+    }
     
-    while let Some(result) = stream.next().await {
+    // Restarted by every chunk, not by batch flushes
+    let mut idle_deadline = idle_timeout.map(|d| tokio::time::Instant::now() + d);
+
+    'stream: loop {
+        let chunk = next_chunk(&mut stream, idle_deadline);
+        let next = match flush_deadline {
+            Some(deadline) => tokio::select! {
+                item = chunk => item,
+                _ = tokio::time::sleep_until(deadline) => {
+                    flush_deadline = None;
+                    flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+                    continue;
+                }
+            },
+            None => chunk.await,
+        };
+        let Some(next) = next else {
+            let idle = idle_timeout.unwrap_or_default().as_secs();
+            error!("⏱️  No data from Ollama for {} seconds, ending the stream", idle);
+            if let Some(check) = structured.as_mut() {
+                pending.extend(check.take_held());
+            }
+            flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+            let message = format!("Ollama sent no data for {} seconds; the response is incomplete", idle);
+            let _ = tx.send(Ok(stream_error_line(&message, "upstream_timeout", lines_forwarded))).await;
+            return Err("Stream idle timeout".to_string());
+        };
+        idle_deadline = idle_timeout.map(|d| tokio::time::Instant::now() + d);
+        let Some(result) = next else { break };
+
         match result {
             Ok(chunk) => {
                 chunk_count += 1;
@@ -876,33 +2454,51 @@ async fn process_streaming_chunks(
                 buffer.extend_from_slice(&chunk);
                 
                 // Process complete lines from buffer
-                loop {
-                    if let Some(newline_pos) = buffer.iter().position(|&b| b == b'\n') {
-                        // Extract complete line (including newline)
-                        let line_bytes = buffer.drain(..=newline_pos).collect::<Vec<u8>>();
-                        let line_len = line_bytes.len();
-                        
-                        lines_forwarded += 1;
-                        debug!("✉️  Forwarding line #{}: {} bytes", lines_forwarded, line_len);
-                        
-                        // Forward line to client immediately
-                        let send_result = tx.send(Ok(bytes::Bytes::from(line_bytes))).await;
-                        
-                        match send_result {
-                            Ok(_) => {
-                                debug!("✓ Line #{} forwarded successfully", lines_forwarded);
-                            }
-                            Err(_) => {
-                                // Channel closed, client disconnected
-                                warn!("⚠️  Client disconnected (channel closed) after {} lines", lines_forwarded);
-                                return Err("Client disconnected".to_string());
+                while let Some(newline_pos) = buffer.iter().position(|&b| b == b'\n') {
+                    // Extract complete line (including newline)
+                    let mut line_bytes = buffer.drain(..=newline_pos).collect::<Vec<u8>>();
+                    lines_forwarded += 1;
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.line(&line_bytes);
+                    }
+                    if let Some(scrubber) = scrubber.as_mut() {
+                        line_bytes = scrubber.scrub_line(&line_bytes);
+                    }
+                    if let Some(check) = structured.as_mut() {
+                        match check.check_line(line_bytes) {
+                            Checked::Forward(bytes) => line_bytes = bytes,
+                            Checked::Hold => continue,
+                            Checked::Retry => {
+                                // Replace the held output and stop reading the invalid stream
+                                pending.extend(check.retry().await);
+                                buffer.clear();
+                                break 'stream;
                             }
                         }
-                    } else {
-                        // No complete line yet, wait for more data
-                        debug!("⏳ Partial line in buffer ({} bytes), waiting for more data", buffer.len());
-                        break;
                     }
+
+                    if batching.is_enabled() {
+                        pending.extend_from_slice(&line_bytes);
+                        if flush_deadline.is_none() {
+                            flush_deadline = batching.interval().map(|d| tokio::time::Instant::now() + d);
+                        }
+                        if batching.flush_bytes > 0 && pending.len() >= batching.flush_bytes {
+                            flush_deadline = None;
+                            flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+                        }
+                        continue;
+                    }
+
+                    debug!("✉️  Forwarding line #{}: {} bytes", lines_forwarded, line_bytes.len());
+                    
+                    // Forward line to client immediately
+                    pending = line_bytes;
+                    flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+                }
+
+                if !buffer.is_empty() {
+                    // No complete line yet, wait for more data
+                    debug!("⏳ Partial line in buffer ({} bytes), waiting for more data", buffer.len());
                 }
             }
             Err(e) => {
@@ -910,9 +2506,25 @@ async fn process_streaming_chunks(
                 
                 // Don't break on transient errors, log and continue
                 if e.is_timeout() {
-                    error!("   Timeout error - this may indicate Ollama is stalled");
-                } else if e.is_connect() {
-                    error!("   Connection error - Ollama may have disconnected");
+                    // The total deadline (STREAM_TIMEOUT_SECONDS) ran out
+                    error!("   Timeout error - the stream ran past its deadline");
+                    if let Some(check) = structured.as_mut() {
+                        pending.extend(check.take_held());
+                    }
+                    flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+                    let message = "The stream ran past its deadline; the response is incomplete";
+                    let _ = tx.send(Ok(stream_error_line(message, "upstream_timeout", lines_forwarded))).await;
+                    return Err(format!("Timeout: {}", e));
+                } else if e.is_connect() || is_connection_lost(&e) {
+                    error!("   Connection error - Ollama may have disconnected or restarted");
+                    // Deliver what arrived, then tell the client the stream is incomplete
+                    // (a partial line in the buffer is dropped, it isn't valid JSON)
+                    if let Some(check) = structured.as_mut() {
+                        pending.extend(check.take_held());
+                    }
+                    flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
+                    let message = "Lost connection to Ollama mid-stream (it may be restarting); the response is incomplete";
+                    let _ = tx.send(Ok(stream_error_line(message, "upstream_disconnected", lines_forwarded))).await;
                     return Err(format!("Connection error: {}", e));
                 } else {
                     warn!("   Transient error, continuing stream: {}", e);
@@ -920,6 +2532,12 @@ async fn process_streaming_chunks(
             }
         }
     }
+
+    // Flush any batched (or still held back) lines before finishing
+    if let Some(check) = structured.as_mut() {
+        pending.extend(check.take_held());
+    }
+    flush_pending(&tx, &mut pending, &mut frames_sent, lines_forwarded).await?;
     
     // Stream ended, check for remaining data in buffer
     if !buffer.is_empty() {
@@ -936,12 +2554,57 @@ async fn process_streaming_chunks(
     info!("   Total chunks: {}", chunk_count);
     info!("   Total bytes: {}", total_bytes);
     info!("   Lines forwarded: {}", lines_forwarded);
+    info!("   Frames sent: {}", frames_sent);
     info!("   Duration: {:?}", elapsed);
     info!("   Throughput: {:.2} KB/s", (total_bytes as f64 / 1024.0) / elapsed.as_secs_f64());
     
     Ok(())
 }
 
+/// NDJSON error line (Ollama's streaming error shape) for a stream cut off by the upstream
+fn stream_error_line(message: &str, error_type: &str, lines_forwarded: usize) -> bytes::Bytes {
+    let line = serde_json::json!({
+        "error": message,
+        "error_type": error_type,
+        "lines_received": lines_forwarded,
+    });
+    bytes::Bytes::from(format!("{}\n", line))
+}
+
+/// Next item from the upstream stream, or None when `deadline` passes first
+async fn next_chunk<S: futures::Stream + Unpin>(stream: &mut S, deadline: Option<tokio::time::Instant>) -> Option<Option<S::Item>> {
+    use futures::StreamExt;
+
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, stream.next()).await.ok(),
+        None => Some(stream.next().await),
+    }
+}
+
+/// Send all pending bytes to the client as a single frame
+async fn flush_pending(
+    tx: &tokio::sync::mpsc::Sender<Result<bytes::Bytes, std::io::Error>>,
+    pending: &mut Vec<u8>,
+    frames_sent: &mut usize,
+    lines_forwarded: usize,
+) -> Result<(), String> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let frame = bytes::Bytes::from(std::mem::take(pending));
+    debug!("✉️  Sending frame #{}: {} bytes", *frames_sent + 1, frame.len());
+
+    if tx.send(Ok(frame)).await.is_err() {
+        // Channel closed, client disconnected
+        warn!("⚠️  Client disconnected (channel closed) after {} lines", lines_forwarded);
+        return Err("Client disconnected".to_string());
+    }
+
+    *frames_sent += 1;
+    Ok(())
+}
+
 fn extract_model_name(json: &Value) -> Option<String> {
     // Try OpenAI API format first
     if let Some(model) = json.get("model").and_then(|v| v.as_str()) {
@@ -0,0 +1,142 @@
+/// Per-API-key request and token rate limits, as token buckets in the shared limit store
+use axum::body::Body;
+use axum::http::{header, Response, StatusCode};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::auth::ApiKey;
+use crate::errors::openai_error;
+use crate::limits::LimitStore;
+use crate::tokens::estimate_request_tokens;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Requests and tokens per minute; None defers to the global limit, 0 means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u64>,
+    pub tokens_per_minute: Option<u64>,
+}
+
+impl RateLimits {
+    /// These limits, with unset ones taken from `defaults`
+    pub fn or(self, defaults: RateLimits) -> RateLimits {
+        RateLimits {
+            requests_per_minute: self.requests_per_minute.or(defaults.requests_per_minute),
+            tokens_per_minute: self.tokens_per_minute.or(defaults.tokens_per_minute),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requests_per_minute.is_some_and(|n| n > 0) || self.tokens_per_minute.is_some_and(|n| n > 0)
+    }
+
+    pub fn describe(&self) -> String {
+        let limit = |value: Option<u64>| match value {
+            Some(n) if n > 0 => n.to_string(),
+            _ => "unlimited".to_string(),
+        };
+        format!("{} requests/min, {} tokens/min", limit(self.requests_per_minute), limit(self.tokens_per_minute))
+    }
+}
+
+/// A request refused because its key is over a limit
+#[derive(Debug)]
+pub struct RateLimited {
+    pub message: String,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// OpenAI-style 429 with Retry-After in whole seconds
+    pub fn into_response(self) -> Response<Body> {
+        let mut response =
+            openai_error(StatusCode::TOO_MANY_REQUESTS, &self.message, None, Some("rate_limit_exceeded"));
+        let seconds = self.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        response.headers_mut().insert(header::RETRY_AFTER, seconds.into());
+        response
+    }
+}
+
+/// Tokens a request is charged: its estimated prompt plus the output limit it asks for
+pub fn request_cost(body: &[u8]) -> u64 {
+    let Ok(json) = serde_json::from_slice::<Value>(body) else {
+        return 0;
+    };
+    let output = ["/max_completion_tokens", "/max_tokens", "/options/num_predict"]
+        .iter()
+        .find_map(|pointer| json.pointer(pointer).and_then(Value::as_u64))
+        .unwrap_or(0);
+    estimate_request_tokens(&json) as u64 + output
+}
+
+/// Charge one request and its tokens to `key`'s buckets. A request refused for its
+/// tokens gets its request back, so it doesn't count against the requests limit too.
+pub async fn check(store: &LimitStore, key: &ApiKey, defaults: RateLimits, body: &[u8]) -> Result<(), RateLimited> {
+    let limits = key.limits.or(defaults);
+    let rpm = limits.requests_per_minute.filter(|&n| n > 0);
+    let rpm_key = format!("rpm:{}", key.name);
+    if let Some(rpm) = rpm {
+        if let Some(retry_after) = store.take(&rpm_key, 1, rpm, MINUTE).await {
+            return Err(RateLimited {
+                message: format!("Rate limit reached for {}: {} requests per minute", key.name, rpm),
+                retry_after,
+            });
+        }
+    }
+    if let Some(tpm) = limits.tokens_per_minute.filter(|&n| n > 0) {
+        let cost = request_cost(body);
+        if let Some(retry_after) = store.take(&format!("tpm:{}", key.name), cost, tpm, MINUTE).await {
+            if let Some(rpm) = rpm {
+                store.refund(&rpm_key, 1, rpm, MINUTE).await;
+            }
+            return Err(RateLimited {
+                message: format!(
+                    "Rate limit reached for {}: {} tokens per minute (this request needs about {})",
+                    key.name, tpm, cost
+                ),
+                retry_after,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_key_limits_fall_back_to_global() {
+        let global = RateLimits { requests_per_minute: Some(60), tokens_per_minute: Some(10_000) };
+        let key = RateLimits { requests_per_minute: Some(0), tokens_per_minute: None };
+        assert_eq!(key.or(global), RateLimits { requests_per_minute: Some(0), tokens_per_minute: Some(10_000) });
+        assert!(key.or(global).is_enabled());
+        assert!(!key.is_enabled());
+        assert_eq!(key.or(global).describe(), "unlimited requests/min, 10000 tokens/min");
+    }
+
+    #[test]
+    fn test_request_cost_includes_output_limit() {
+        let body = br#"{"model": "llama3", "messages": [{"role": "user", "content": "12345678"}], "max_tokens": 100}"#;
+        assert_eq!(request_cost(body), estimate_request_tokens(&serde_json::from_slice(body).unwrap()) as u64 + 100);
+        assert_eq!(request_cost(b"not json"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_token_rejections_give_the_request_back() {
+        let store = LimitStore::default();
+        let key = ApiKey::parse("sk-search name=search rpm=2 tpm=50").unwrap();
+        let small = br#"{"model": "llama3", "prompt": "hi"}"#;
+        let large = br#"{"model": "llama3", "prompt": "hi", "options": {"num_predict": 30}}"#;
+        assert!(check(&store, &key, RateLimits::default(), large).await.is_ok());
+        // Out of tokens: without the refund the second of these would hit the request limit
+        for _ in 0..2 {
+            let limited = check(&store, &key, RateLimits::default(), large).await.unwrap_err();
+            assert!(limited.message.contains("50 tokens per minute"));
+        }
+        assert!(check(&store, &key, RateLimits::default(), small).await.is_ok());
+        let limited = check(&store, &key, RateLimits::default(), small).await.unwrap_err();
+        assert!(limited.message.contains("2 requests per minute"));
+    }
+}
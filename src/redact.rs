@@ -0,0 +1,224 @@
+/// How request and response bodies appear in logs: redacted fields and a size/visibility policy
+use axum::http::{header, HeaderMap, HeaderValue};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// How much of a body is logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogBody {
+    /// Bodies are never logged
+    None,
+    /// Pretty-printed, cut off after `max_chars`
+    Truncated,
+    /// Only a hash and the size, enough to tell identical requests apart
+    Hashed,
+    /// Pretty-printed in full
+    #[default]
+    Full,
+}
+
+impl LogBody {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "off" => Some(Self::None),
+            "truncated" => Some(Self::Truncated),
+            "hashed" => Some(Self::Hashed),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Truncated => "truncated",
+            Self::Hashed => "hashed",
+            Self::Full => "full",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BodyLogging {
+    pub policy: LogBody,
+    /// Longest body logged under the `truncated` policy, in characters
+    pub max_chars: usize,
+    /// JSON keys whose values are replaced before logging, at any depth (e.g. `content`, `input`)
+    pub redact_fields: Vec<String>,
+}
+
+impl Default for BodyLogging {
+    fn default() -> Self {
+        Self { policy: LogBody::Full, max_chars: 2000, redact_fields: Vec::new() }
+    }
+}
+
+impl BodyLogging {
+    /// The text to log for `body`, or None when bodies aren't logged
+    pub fn render<T: Serialize>(&self, body: &T) -> Option<String> {
+        if self.policy == LogBody::None {
+            return None;
+        }
+        let body = serde_json::to_value(body).ok()?;
+        match self.policy {
+            LogBody::None => None,
+            LogBody::Hashed => Some(hashed(&body.to_string())),
+            LogBody::Full => Some(self.pretty(body)),
+            LogBody::Truncated => Some(self.truncate(self.pretty(body))),
+        }
+    }
+
+    /// Generated text taken from one of the JSON keys `fields`, as it may appear in a
+    /// structured log (e.g. the shadow comparisons), or None when bodies aren't logged.
+    /// Redacted like those keys would be in a body.
+    pub fn render_text(&self, fields: &[&str], text: &str) -> Option<Value> {
+        match self.policy {
+            LogBody::None => None,
+            _ if self.redact_fields.iter().any(|field| fields.contains(&field.as_str())) => {
+                Some(placeholder(&Value::String(text.to_string())))
+            }
+            LogBody::Hashed => Some(Value::String(hashed(text))),
+            LogBody::Full => Some(Value::String(text.to_string())),
+            LogBody::Truncated => Some(Value::String(self.truncate(text.to_string()))),
+        }
+    }
+
+    fn pretty(&self, mut body: Value) -> String {
+        redact(&mut body, &self.redact_fields);
+        serde_json::to_string_pretty(&body).unwrap_or_default()
+    }
+
+    fn truncate(&self, text: String) -> String {
+        match text.char_indices().nth(self.max_chars) {
+            Some((end, _)) => format!("{}… ({} bytes total)", &text[..end], text.len()),
+            None => text,
+        }
+    }
+}
+
+/// A hash and the size of `text`, enough to tell identical bodies apart
+fn hashed(text: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    format!("<{:016x}, {} bytes>", hasher.finish(), text.len())
+}
+
+/// Replace the values of `fields` (at any depth) with a placeholder giving their size
+pub fn redact(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if fields.iter().any(|field| field == key) {
+                    *value = placeholder(value);
+                } else {
+                    redact(value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, fields)),
+        _ => {}
+    }
+}
+
+/// Headers carrying credentials, never logged
+const SECRET_HEADERS: [header::HeaderName; 3] = [header::AUTHORIZATION, header::PROXY_AUTHORIZATION, header::COOKIE];
+
+/// A copy of `headers` safe to log, with credentials replaced by a placeholder
+pub fn redact_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut redacted = headers.clone();
+    for name in SECRET_HEADERS {
+        if let header::Entry::Occupied(mut entry) = redacted.entry(name) {
+            entry.insert(HeaderValue::from_static("[redacted]"));
+        }
+    }
+    redacted
+}
+
+fn placeholder(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::String(text) => Value::String(format!("[redacted {} chars]", text.chars().count())),
+        // e.g. `input: ["a", "b"]` or multimodal content parts
+        Value::Array(items) => Value::String(format!("[redacted {} items]", items.len())),
+        _ => Value::String("[redacted]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redacts_configured_fields_at_any_depth() {
+        let mut body = json!({
+            "model": "llama3",
+            "messages": [{"role": "user", "content": "my password is hunter2"}],
+            "input": ["a", "b"],
+        });
+        redact(&mut body, &["content".to_string(), "input".to_string()]);
+        assert_eq!(
+            body,
+            json!({
+                "model": "llama3",
+                "messages": [{"role": "user", "content": "[redacted 22 chars]"}],
+                "input": "[redacted 2 items]",
+            })
+        );
+    }
+
+    #[test]
+    fn test_redacts_credentials_in_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer sk-secret"));
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted[header::AUTHORIZATION], "[redacted]");
+        assert_eq!(redacted[header::CONTENT_TYPE], "application/json");
+        assert!(!format!("{:?}", redacted).contains("sk-secret"));
+    }
+
+    #[test]
+    fn test_generated_text_follows_the_body_policy() {
+        let logging = |policy, redact_fields: &[&str]| BodyLogging {
+            policy,
+            max_chars: 5,
+            redact_fields: redact_fields.iter().map(|f| f.to_string()).collect(),
+        };
+        let fields = ["response", "content"];
+        assert_eq!(logging(LogBody::Full, &[]).render_text(&fields, "Hello there"), Some(json!("Hello there")));
+        assert_eq!(logging(LogBody::None, &[]).render_text(&fields, "Hello there"), None);
+        assert_eq!(
+            logging(LogBody::Truncated, &[]).render_text(&fields, "Hello there"),
+            Some(json!("Hello… (11 bytes total)"))
+        );
+        let hashed = logging(LogBody::Hashed, &[]).render_text(&fields, "Hello there").unwrap();
+        assert!(hashed.as_str().unwrap().ends_with(", 11 bytes>"));
+        assert_eq!(
+            logging(LogBody::Full, &["content"]).render_text(&fields, "Hello there"),
+            Some(json!("[redacted 11 chars]"))
+        );
+        assert_eq!(logging(LogBody::Full, &["input"]).render_text(&fields, "Hi"), Some(json!("Hi")));
+    }
+
+    #[test]
+    fn test_policies() {
+        let body = json!({"model": "llama3", "prompt": "x".repeat(100)});
+        let logging = |policy| BodyLogging { policy, max_chars: 30, redact_fields: Vec::new() };
+
+        assert_eq!(logging(LogBody::None).render(&body), None);
+        assert!(logging(LogBody::Full).render(&body).unwrap().contains(&"x".repeat(100)));
+
+        let truncated = logging(LogBody::Truncated).render(&body).unwrap();
+        assert!(truncated.starts_with("{\n  \"model\": \"llama3\""));
+        assert!(truncated.ends_with(" bytes total)"));
+        assert!(!truncated.contains(&"x".repeat(100)));
+
+        let hashed = logging(LogBody::Hashed).render(&body).unwrap();
+        assert_eq!(hashed, logging(LogBody::Hashed).render(&body).unwrap());
+        assert!(!hashed.contains("llama3"));
+        assert_ne!(hashed, logging(LogBody::Hashed).render(&json!({"model": "llama3"})).unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+/// Downloads of http(s) image URLs in OpenAI vision requests, which Ollama only accepts
+/// inline. Clients choose the URL, so only public addresses are fetched: anything on
+/// the proxy's own network (loopback, private ranges, cloud metadata services) is refused.
+use futures::StreamExt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+/// Largest image downloaded for a vision request
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+/// How long one image download may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether `ip` is a globally routable address, i.e. not loopback, private, link-local,
+/// shared (CGNAT), multicast, documentation or otherwise reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // shared address space (CGNAT)
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+        || (a == 198 && (b == 18 || b == 19)) // benchmarking
+        || a >= 240) // reserved
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00 // unique local
+        || (segments[0] & 0xffc0) == 0xfe80 // link-local
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8) // documentation
+        || (segments[0] == 0x0064 && segments[1] == 0xff9b)) // NAT64, maps onto IPv4
+}
+
+/// Download an image, refusing URLs that resolve to non-public addresses. The
+/// connection is pinned to the checked address, and redirects are not followed, so
+/// neither DNS rebinding nor a redirect can point the request elsewhere. Errors are
+/// detailed for the log and must not be returned to the client.
+pub async fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme {}", parsed.scheme()));
+    }
+    let host = parsed.host_str().ok_or("URL has no host")?.trim_matches(|c| c == '[' || c == ']').to_string();
+    let port = parsed.port_or_known_default().ok_or("URL has no port")?;
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| format!("failed to resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} did not resolve", host));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} resolves to non-public address {}", host, addr.ip()));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+        .resolve_to_addrs(&host, &addrs)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = client.get(parsed).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    if response.content_length().is_some_and(|len| len as usize > MAX_IMAGE_BYTES) {
+        return Err(format!("larger than {} bytes", MAX_IMAGE_BYTES));
+    }
+    let mut bytes = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        bytes.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(format!("larger than {} bytes", MAX_IMAGE_BYTES));
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_public_addresses_are_fetched() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.10",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refuses_local_urls_before_connecting() {
+        let error = fetch("http://127.0.0.1:9/cat.png").await.unwrap_err();
+        assert!(error.contains("non-public"), "{}", error);
+        let error = fetch("http://localhost:9/cat.png").await.unwrap_err();
+        assert!(error.contains("non-public"), "{}", error);
+        assert!(fetch("file:///etc/passwd").await.is_err());
+    }
+}
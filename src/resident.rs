@@ -0,0 +1,170 @@
+/// Models pinned in memory by operators (keep_alive=-1), via admin endpoints
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::backends::normalize_model;
+use crate::proxy::ProxyState;
+
+/// keep_alive that keeps a model loaded until it is explicitly unloaded
+pub const PINNED_KEEP_ALIVE: &str = "-1m";
+
+/// Models that must stay resident. Requests for them always carry
+/// `keep_alive: -1`, so a client's own keep_alive can't unload them.
+#[derive(Debug, Default)]
+pub struct ResidentModels {
+    pinned: Mutex<BTreeSet<String>>,
+}
+
+impl ResidentModels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pin(&self, model: &str) {
+        self.pinned.lock().unwrap().insert(normalize_model(model));
+    }
+
+    /// Forget a pin; returns whether the model was pinned
+    pub fn unpin(&self, model: &str) -> bool {
+        self.pinned.lock().unwrap().remove(&normalize_model(model))
+    }
+
+    pub fn is_pinned(&self, model: &str) -> bool {
+        self.pinned.lock().unwrap().contains(&normalize_model(model))
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.pinned.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// keep_alive to send for `model`, if it is pinned
+    pub fn keep_alive_for(&self, model: &str) -> Option<String> {
+        self.is_pinned(model).then(|| PINNED_KEEP_ALIVE.to_string())
+    }
+
+    /// Force keep_alive on a native request body for a pinned model
+    pub fn apply(&self, json: &mut Value) -> bool {
+        let Some(model) = json.get("model").and_then(Value::as_str) else {
+            return false;
+        };
+        if !self.is_pinned(model) || json.get("keep_alive") == Some(&json!(PINNED_KEEP_ALIVE)) {
+            return false;
+        }
+        info!("📌 {} is pinned, keeping it loaded (keep_alive={})", model, PINNED_KEEP_ALIVE);
+        json["keep_alive"] = json!(PINNED_KEEP_ALIVE);
+        true
+    }
+}
+
+/// GET /proxy/admin/models - pinned models
+pub async fn list_handler(State(state): State<ProxyState>) -> Json<Value> {
+    Json(json!({ "pinned": state.resident_models.list() }))
+}
+
+/// POST /proxy/admin/models/{name}/pin or /unpin
+pub async fn action_handler(State(state): State<ProxyState>, Path(rest): Path<String>) -> Response {
+    let Some((model, action)) = rest.rsplit_once('/') else {
+        return error_response(StatusCode::NOT_FOUND, "Expected /proxy/admin/models/{name}/pin or /unpin");
+    };
+    let model = model.trim_matches('/');
+    let pin = match action {
+        "pin" => true,
+        "unpin" => false,
+        _ => return error_response(StatusCode::NOT_FOUND, &format!("Unknown model action '{}'", action)),
+    };
+    if model.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Missing model name");
+    }
+
+    if !pin {
+        state.resident_models.unpin(model);
+    }
+    let keep_alive = if pin { json!(PINNED_KEEP_ALIVE) } else { json!(0) };
+    match set_keep_alive(&state, model, keep_alive).await {
+        Ok(()) => {
+            if pin {
+                state.resident_models.pin(model);
+                info!("📌 Pinned {} in memory", model);
+            } else {
+                info!("📌 Unpinned and unloaded {}", model);
+            }
+            Json(json!({
+                "model": model,
+                "pinned": pin,
+                "status": if pin { "loaded" } else { "unloaded" },
+            }))
+            .into_response()
+        }
+        Err(e) => {
+            warn!("⚠️  Failed to {} {}: {}", action, model, e);
+            error_response(StatusCode::BAD_GATEWAY, &e)
+        }
+    }
+}
+
+/// Load or unload `model` with an empty request. Embedding-only models reject
+/// /api/generate, so /api/embed is tried next.
+pub(crate) async fn set_keep_alive(state: &ProxyState, model: &str, keep_alive: Value) -> Result<(), String> {
+    let base = &state.backends.for_model(model).url;
+    let attempts = [
+        ("/api/generate", json!({"model": model, "keep_alive": keep_alive})),
+        ("/api/embed", json!({"model": model, "input": [], "keep_alive": keep_alive})),
+    ];
+    let mut last_error = String::new();
+    for (path, body) in attempts {
+        let response = state
+            .client()
+            .post(format!("{}{}", base, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Ollama unreachable: {}", e))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let text = response.text().await.unwrap_or_default();
+        last_error = format!("Ollama returned {} for {}: {}", status, path, text.trim());
+        if status == reqwest::StatusCode::NOT_FOUND {
+            // Unknown model: trying another endpoint won't help
+            break;
+        }
+    }
+    Err(last_error)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_models_force_keep_alive() {
+        let resident = ResidentModels::new();
+        resident.pin("llama3");
+        assert!(resident.is_pinned("llama3:latest"));
+        assert_eq!(resident.list(), vec!["llama3:latest"]);
+
+        let mut body = json!({"model": "llama3", "prompt": "hi", "keep_alive": "5m"});
+        assert!(resident.apply(&mut body));
+        assert_eq!(body["keep_alive"], PINNED_KEEP_ALIVE);
+
+        let mut other = json!({"model": "qwen2.5:7b", "keep_alive": "5m"});
+        assert!(!resident.apply(&mut other));
+        assert_eq!(other["keep_alive"], "5m");
+
+        assert!(resident.unpin("llama3:latest"));
+        assert!(!resident.unpin("llama3"));
+        assert_eq!(resident.keep_alive_for("llama3"), None);
+    }
+}
@@ -0,0 +1,398 @@
+/// Backoff policy for upstream saturation responses (429 / 503) and restarts
+use axum::http::{HeaderMap, Method, StatusCode};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::metrics::Metrics;
+use crate::timeouts::EndpointClass;
+
+/// Longest single wait between attempts when Ollama gives no Retry-After
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Phrases in Ollama's 500/503 error bodies while a model is still being loaded
+const MODEL_LOADING_MARKERS: [&str; 4] = [
+    "model is loading",
+    "loading model",
+    "still loading",
+    "waiting for llama runner to start",
+];
+
+/// How to react when Ollama (or a gateway in front of it) reports it is saturated
+#[derive(Debug, Clone, Copy)]
+pub struct SaturationRetry {
+    /// Retry 429/503 responses instead of passing them straight to the client
+    pub enabled: bool,
+    /// Stop retrying once waiting again would exceed this much total time
+    pub deadline: Duration,
+    /// First wait when the response has no Retry-After (doubles on each attempt)
+    pub initial_backoff: Duration,
+    /// Keep retrying 500/503 responses saying the model is still loading for this
+    /// long, since cold starts of large models take far longer than saturation (zero = don't)
+    pub loading_deadline: Duration,
+}
+
+impl Default for SaturationRetry {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            deadline: Duration::from_secs(30),
+            initial_backoff: Duration::from_millis(500),
+            loading_deadline: Duration::from_secs(120),
+        }
+    }
+}
+
+impl SaturationRetry {
+    /// How long to wait before attempt number `attempt + 1`
+    pub fn next_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after.unwrap_or_else(|| {
+            self.initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(MAX_BACKOFF)
+        })
+    }
+}
+
+/// How long to keep reconnecting while Ollama restarts (connection refused or reset)
+#[derive(Debug, Clone, Copy)]
+pub struct RestartRetry {
+    /// Give up once reconnecting would take longer than this (zero = no retries)
+    pub window: Duration,
+    /// Wait between reconnect attempts
+    pub interval: Duration,
+}
+
+impl Default for RestartRetry {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(15),
+            interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Whether a request can be sent again after the connection dropped mid-request.
+/// Model management (pull, push, create, copy, delete) has side effects and is never replayed.
+pub fn is_idempotent(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => true,
+        Method::POST => EndpointClass::from_path(path) != EndpointClass::Transfer,
+        _ => false,
+    }
+}
+
+/// Whether the connection to Ollama was lost after the request went out
+/// (reset, or closed before a response)
+pub fn is_connection_lost(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::UnexpectedEof
+            ) {
+                return true;
+            }
+        }
+        if let Some(hyper) = err.downcast_ref::<hyper::Error>() {
+            if hyper.is_incomplete_message() || hyper.is_closed() {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Send a request, waiting out saturation responses (see [`send_honoring_saturation`])
+/// and upstream restarts. Refused connections are always retried within
+/// `restart.window` since nothing reached Ollama; dropped connections only
+/// when the request is `idempotent`.
+pub async fn send_resilient(
+    request: reqwest::RequestBuilder,
+    metrics: &Metrics,
+    saturation: SaturationRetry,
+    restart: RestartRetry,
+    idempotent: bool,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let started = Instant::now();
+    let mut request = request;
+    let mut retried = false;
+
+    loop {
+        // Bodies that can't be cloned (streams) can only be sent once
+        let retry_request = if restart.window.is_zero() { None } else { request.try_clone() };
+
+        let error = match send_honoring_saturation(request, metrics, saturation).await {
+            Ok(response) => {
+                if retried {
+                    info!("🔌 Ollama reachable again after {:?}", started.elapsed());
+                }
+                return Ok(response);
+            }
+            Err(e) => e,
+        };
+        let retryable = error.is_connect() || (idempotent && is_connection_lost(&error));
+        let Some(next_request) = retry_request.filter(|_| retryable) else {
+            return Err(error);
+        };
+        if started.elapsed() + restart.interval > restart.window {
+            warn!("🔌 Ollama still unreachable after {:?}, giving up: {}", started.elapsed(), error);
+            return Err(error);
+        }
+
+        warn!("🔌 Lost connection to Ollama (restarting?), retrying in {:?}: {}", restart.interval, error);
+        metrics.record_restart_retry();
+        tokio::time::sleep(restart.interval).await;
+        request = next_request;
+        retried = true;
+    }
+}
+
+/// Whether a status means "busy, try again later"
+pub fn is_saturation_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// Parse a Retry-After header given either as delay-seconds or as an HTTP date
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Whether an error body says the requested model is still loading
+pub fn is_model_loading(body: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(body).to_lowercase();
+    MODEL_LOADING_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+/// Read a (small) error response to check whether the model is still loading,
+/// handing back an equivalent response for the caller
+async fn check_model_loading(response: reqwest::Response) -> Result<(reqwest::Response, bool), reqwest::Error> {
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+    let loading = is_model_loading(&body);
+
+    let mut rebuilt = axum::http::Response::new(reqwest::Body::from(body));
+    *rebuilt.status_mut() = status;
+    *rebuilt.version_mut() = version;
+    *rebuilt.headers_mut() = headers;
+    Ok((reqwest::Response::from(rebuilt), loading))
+}
+
+/// Send a request, waiting out 429/503 responses, and 500/503 responses for a model
+/// that is still loading, according to `policy`. Once the deadline would be exceeded
+/// the last response is returned as-is.
+pub async fn send_honoring_saturation(
+    request: reqwest::RequestBuilder,
+    metrics: &Metrics,
+    policy: SaturationRetry,
+) -> Result<reqwest::Response, reqwest::Error> {
+    let started = Instant::now();
+    let mut request = request;
+    let mut attempt = 0;
+    let retry_loading = !policy.loading_deadline.is_zero();
+
+    loop {
+        // Bodies that can't be cloned (streams) can only be sent once
+        let retry_request = if policy.enabled || retry_loading { request.try_clone() } else { None };
+
+        metrics.record_upstream_request();
+        let response = request.send().await?;
+
+        let status = response.status();
+        let may_be_loading = retry_loading
+            && (status == StatusCode::INTERNAL_SERVER_ERROR || status == StatusCode::SERVICE_UNAVAILABLE);
+        let saturated = policy.enabled && is_saturation_status(status);
+        let Some(next_request) = retry_request.filter(|_| may_be_loading || saturated) else {
+            return Ok(response);
+        };
+        let (response, loading) = match may_be_loading {
+            true => check_model_loading(response).await?,
+            false => (response, false),
+        };
+        if !loading && !saturated {
+            return Ok(response);
+        }
+
+        let delay = policy.next_delay(attempt, parse_retry_after(response.headers()));
+        let deadline = if loading { policy.loading_deadline } else { policy.deadline };
+        if started.elapsed() + delay > deadline {
+            warn!(
+                "⏳ Ollama still {} ({}) after {:?}, giving up on retries",
+                if loading { "loading the model" } else { "saturated" },
+                status,
+                started.elapsed()
+            );
+            return Ok(response);
+        }
+
+        if loading {
+            info!("⏳ Model still loading (attempt {}), retrying in {:?}", attempt + 1, delay);
+            metrics.record_model_loading_retry();
+        } else {
+            warn!(
+                "⏳ Ollama returned {} (attempt {}), retrying in {:?}",
+                status,
+                attempt + 1,
+                delay
+            );
+            metrics.record_saturation_retry();
+        }
+        tokio::time::sleep(delay).await;
+
+        request = next_request;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_saturation_statuses() {
+        assert!(is_saturation_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_saturation_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_saturation_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_saturation_status(StatusCode::OK));
+    }
+
+    #[test]
+    fn test_model_loading_bodies() {
+        assert!(is_model_loading(br#"{"error":"timed out waiting for llama runner to start: context canceled"}"#));
+        assert!(is_model_loading(br#"{"error":"Model is loading, please retry"}"#));
+        assert!(!is_model_loading(br#"{"error":"model 'llama9' not found"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_model_loading_is_retried_until_loaded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // The first two attempts find the model still loading
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/api/chat",
+            axum::routing::post({
+                let attempts = attempts.clone();
+                move || async move {
+                    match attempts.fetch_add(1, Ordering::SeqCst) {
+                        0 | 1 => (StatusCode::INTERNAL_SERVER_ERROR, r#"{"error":"model is loading"}"#),
+                        _ => (StatusCode::OK, r#"{"done":true}"#),
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let metrics = Metrics::new();
+        let policy = SaturationRetry { initial_backoff: Duration::from_millis(10), ..SaturationRetry::default() };
+        let request = reqwest::Client::new().post(format!("http://{}/api/chat", addr)).body("{}");
+        let response = send_honoring_saturation(request, &metrics, policy).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(metrics.model_loading_retries.load(Ordering::Relaxed), 2);
+
+        // Other 500s are returned with their body intact
+        attempts.store(0, Ordering::SeqCst);
+        let request = reqwest::Client::new().post(format!("http://{}/api/chat", addr)).body("{}");
+        let disabled = SaturationRetry { loading_deadline: Duration::ZERO, ..policy };
+        let response = send_honoring_saturation(request, &metrics, disabled).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.text().await.unwrap(), r#"{"error":"model is loading"}"#);
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn test_parse_retry_after_http_date() {
+        let mut headers = HeaderMap::new();
+        let future = chrono::Utc::now() + chrono::Duration::seconds(30);
+        headers.insert(
+            "retry-after",
+            HeaderValue::from_str(&future.to_rfc2822()).unwrap(),
+        );
+        let wait = parse_retry_after(&headers).unwrap();
+        assert!(wait <= Duration::from_secs(30));
+        assert!(wait >= Duration::from_secs(28));
+    }
+
+    #[test]
+    fn test_parse_retry_after_missing_or_invalid() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_idempotent_requests() {
+        assert!(is_idempotent(&Method::GET, "/api/tags"));
+        assert!(is_idempotent(&Method::POST, "/api/embed"));
+        assert!(is_idempotent(&Method::POST, "/api/chat"));
+        assert!(!is_idempotent(&Method::POST, "/api/pull"));
+        assert!(!is_idempotent(&Method::DELETE, "/api/delete"));
+    }
+
+    #[tokio::test]
+    async fn test_restart_retry_waits_for_upstream() {
+        // Reserve a port, then start listening on it only after the first attempt fails
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            let app = axum::Router::new().route("/api/version", axum::routing::get(|| async { "{}" }));
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let metrics = Metrics::new();
+        let restart = RestartRetry { window: Duration::from_secs(5), interval: Duration::from_millis(100) };
+        let request = reqwest::Client::new().get(format!("http://{}/api/version", addr));
+        let response = send_resilient(request, &metrics, SaturationRetry::default(), restart, true)
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert!(metrics.restart_retries.load(std::sync::atomic::Ordering::Relaxed) >= 1);
+
+        // Disabled: fail immediately
+        let request = reqwest::Client::new().get("http://127.0.0.1:9/api/version");
+        let disabled = RestartRetry { window: Duration::ZERO, ..restart };
+        assert!(send_resilient(request, &metrics, SaturationRetry::default(), disabled, true).await.is_err());
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let policy = SaturationRetry::default();
+        assert_eq!(policy.next_delay(0, None), Duration::from_millis(500));
+        assert_eq!(policy.next_delay(1, None), Duration::from_millis(1000));
+        assert_eq!(policy.next_delay(2, None), Duration::from_millis(2000));
+        assert_eq!(policy.next_delay(10, None), MAX_BACKOFF);
+        assert_eq!(
+            policy.next_delay(3, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+    }
+}
@@ -0,0 +1,369 @@
+/// Prompt-size-based and percentage-based (canary) model routing
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::timeouts::EndpointClass;
+use crate::tokens::estimate_request_tokens;
+
+/// Response header naming the model substituted for a request that omitted one
+pub const DEFAULT_MODEL_HEADER: &str = "x-proxy-default-model";
+
+/// Models used for requests that don't name one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefaultModels {
+    /// Chat and generate requests
+    pub chat: Option<String>,
+    pub embed: Option<String>,
+}
+
+impl DefaultModels {
+    pub fn for_class(&self, class: EndpointClass) -> Option<&str> {
+        match class {
+            EndpointClass::Chat | EndpointClass::Generate => self.chat.as_deref(),
+            EndpointClass::Embeddings => self.embed.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Set `model` on a request body that has neither `model` nor `name`,
+    /// returning the model filled in
+    pub fn apply(&self, class: EndpointClass, json: &mut Value) -> Option<String> {
+        let default = self.for_class(class)?;
+        let object = json.as_object_mut()?;
+        let named = ["model", "name"]
+            .iter()
+            .any(|key| object.get(*key).and_then(Value::as_str).is_some_and(|m| !m.is_empty()));
+        if named {
+            return None;
+        }
+        object.insert("model".to_string(), Value::String(default.to_string()));
+        Some(default.to_string())
+    }
+}
+
+/// Routing rules keyed by the model name clients request (an alias or a real model)
+#[derive(Debug, Clone, Default)]
+pub struct PromptRoutes {
+    /// Alias -> (minimum prompt tokens, target model), sorted by threshold
+    routes: HashMap<String, Vec<(usize, String)>>,
+}
+
+/// A model substitution made by a routing rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteDecision {
+    pub alias: String,
+    pub model: String,
+    pub estimated_tokens: usize,
+}
+
+impl PromptRoutes {
+    /// Parse rules like `assistant=llama3.2:3b,>4000:llama3.1:8b,>16000:qwen2.5:14b`.
+    /// Multiple aliases are separated by `;`. The first target (no threshold) is used
+    /// for short prompts; each `>N:model` applies once the prompt exceeds N tokens.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut routes = HashMap::new();
+
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (alias, targets) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid route '{}', expected alias=model,>tokens:model", rule))?;
+            let alias = alias.trim();
+            if alias.is_empty() {
+                return Err(format!("Missing alias in route '{}'", rule));
+            }
+
+            let mut thresholds = Vec::new();
+            for target in targets.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                match target.strip_prefix('>') {
+                    Some(rest) => {
+                        let (tokens, model) = rest
+                            .split_once(':')
+                            .ok_or_else(|| format!("Invalid threshold '{}' for {}, expected >tokens:model", target, alias))?;
+                        let tokens: usize = tokens
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("Invalid token count '{}' for {}", tokens.trim(), alias))?;
+                        thresholds.push((tokens + 1, model.trim().to_string()));
+                    }
+                    None => thresholds.push((0, target.to_string())),
+                }
+            }
+            if thresholds.is_empty() {
+                return Err(format!("Route for '{}' has no target models", alias));
+            }
+            thresholds.sort_by_key(|(tokens, _)| *tokens);
+            routes.insert(alias.to_string(), thresholds);
+        }
+
+        Ok(Self { routes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Model to use for `alias` with a prompt of `tokens`, if a rule applies
+    pub fn route(&self, alias: &str, tokens: usize) -> Option<&str> {
+        self.routes
+            .get(alias)?
+            .iter()
+            .rev()
+            .find(|(min_tokens, _)| tokens >= *min_tokens)
+            .map(|(_, model)| model.as_str())
+    }
+
+    /// Rewrite the `model` field of a request body according to the rules
+    pub fn apply(&self, json: &mut Value) -> Option<RouteDecision> {
+        let alias = json.get("model")?.as_str()?.to_string();
+        if !self.routes.contains_key(&alias) {
+            return None;
+        }
+        let estimated_tokens = estimate_request_tokens(json);
+        let model = self.route(&alias, estimated_tokens)?.to_string();
+        json["model"] = Value::String(model.clone());
+        Some(RouteDecision { alias, model, estimated_tokens })
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .routes
+            .iter()
+            .map(|(alias, thresholds)| {
+                let targets = thresholds
+                    .iter()
+                    .map(|(tokens, model)| match tokens {
+                        0 => model.clone(),
+                        n => format!(">{}:{}", n - 1, model),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{} → {}", alias, targets)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+/// Percentage splits of an alias's traffic over model versions, for gradual rollouts
+#[derive(Debug, Clone, Default)]
+pub struct CanaryRoutes {
+    /// Alias -> (model, percent), in the order given
+    splits: HashMap<String, Vec<(String, u32)>>,
+    /// (alias, model) -> requests routed there
+    counts: Arc<Mutex<BTreeMap<(String, String), u64>>>,
+}
+
+/// A model picked for an alias by a canary split
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanaryDecision {
+    pub alias: String,
+    pub model: String,
+    pub percent: u32,
+}
+
+impl CanaryRoutes {
+    /// Parse rules like `assistant=llama3.3@10,llama3.1`. Multiple aliases are separated
+    /// by `;`. Each `model@N` gets N percent of the alias's requests; one model may omit
+    /// the percentage and takes the rest, otherwise the percentages must add up to 100.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut splits = HashMap::new();
+
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let (alias, targets) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid canary route '{}', expected alias=model@percent,model", rule))?;
+            let alias = alias.trim();
+            if alias.is_empty() {
+                return Err(format!("Missing alias in canary route '{}'", rule));
+            }
+
+            let mut models = Vec::new();
+            let mut rest = None;
+            for target in targets.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                match target.rsplit_once('@') {
+                    Some((model, percent)) => {
+                        let percent: u32 = percent
+                            .trim()
+                            .trim_end_matches('%')
+                            .parse()
+                            .ok()
+                            .filter(|p| (1..=100).contains(p))
+                            .ok_or_else(|| format!("Invalid percentage '{}' for {}, expected 1-100", percent.trim(), alias))?;
+                        models.push((model.trim().to_string(), percent));
+                    }
+                    None if rest.is_none() => {
+                        rest = Some(models.len());
+                        models.push((target.to_string(), 0));
+                    }
+                    None => return Err(format!("Canary route for '{}' has more than one model without a percentage", alias)),
+                }
+            }
+            if models.len() < 2 {
+                return Err(format!("Canary route for '{}' needs at least two models", alias));
+            }
+            let assigned: u32 = models.iter().map(|(_, percent)| percent).sum();
+            match rest {
+                Some(index) if assigned < 100 => models[index].1 = 100 - assigned,
+                Some(_) => return Err(format!("Canary route for '{}' leaves no traffic for its default model", alias)),
+                None if assigned != 100 => {
+                    return Err(format!("Canary percentages for '{}' add up to {}, expected 100", alias, assigned))
+                }
+                None => {}
+            }
+            splits.insert(alias.to_string(), models);
+        }
+
+        Ok(Self { splits, counts: Arc::default() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.splits.is_empty()
+    }
+
+    /// Model for `alias` given a `roll` in 0..100, if a rule applies
+    pub fn pick(&self, alias: &str, roll: u32) -> Option<(&str, u32)> {
+        let mut upper = 0;
+        for (model, percent) in self.splits.get(alias)? {
+            upper += percent;
+            if roll < upper {
+                return Some((model.as_str(), *percent));
+            }
+        }
+        None
+    }
+
+    /// Rewrite the `model` field of a request body according to the rules, counting
+    /// the pick for /metrics
+    pub fn apply(&self, json: &mut Value, roll: u32) -> Option<CanaryDecision> {
+        let alias = json.get("model")?.as_str()?.to_string();
+        let (model, percent) = self.pick(&alias, roll % 100)?;
+        let model = model.to_string();
+        json["model"] = Value::String(model.clone());
+        *self.counts.lock().unwrap().entry((alias.clone(), model.clone())).or_default() += 1;
+        Some(CanaryDecision { alias, model, percent })
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .splits
+            .iter()
+            .map(|(alias, models)| {
+                let targets = models
+                    .iter()
+                    .map(|(model, percent)| format!("{}% {}", percent, model))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{} → {}", alias, targets)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    /// Prometheus text for /metrics
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        if self.is_empty() {
+            return out;
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_canary_requests_total Requests for a canary alias, by the model chosen");
+        let _ = writeln!(out, "# TYPE ollama_proxy_canary_requests_total counter");
+        for ((alias, model), count) in self.counts.lock().unwrap().iter() {
+            let _ = writeln!(out, "ollama_proxy_canary_requests_total{{alias=\"{}\",model=\"{}\"}} {}", alias, model, count);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_default_models() {
+        let defaults = DefaultModels { chat: Some("llama3".to_string()), embed: None };
+
+        let mut chat = json!({"messages": [{"role": "user", "content": "hi"}]});
+        assert_eq!(defaults.apply(EndpointClass::Chat, &mut chat).as_deref(), Some("llama3"));
+        assert_eq!(chat["model"], "llama3");
+
+        let mut named = json!({"model": "qwen2.5", "prompt": "hi"});
+        assert_eq!(defaults.apply(EndpointClass::Generate, &mut named), None);
+        assert_eq!(named["model"], "qwen2.5");
+
+        // No embedding default configured
+        let mut embed = json!({"input": "hi"});
+        assert_eq!(defaults.apply(EndpointClass::Embeddings, &mut embed), None);
+        assert!(embed.get("model").is_none());
+    }
+
+    #[test]
+    fn test_route_by_prompt_size() {
+        let routes = PromptRoutes::parse("assistant=llama3.2:3b,>4000:llama3.1:8b,>16000:qwen2.5:14b").unwrap();
+        assert_eq!(routes.route("assistant", 100), Some("llama3.2:3b"));
+        assert_eq!(routes.route("assistant", 4000), Some("llama3.2:3b"));
+        assert_eq!(routes.route("assistant", 4001), Some("llama3.1:8b"));
+        assert_eq!(routes.route("assistant", 20000), Some("qwen2.5:14b"));
+        assert_eq!(routes.route("other", 100), None);
+    }
+
+    #[test]
+    fn test_threshold_only_routes_keep_short_prompts() {
+        let routes = PromptRoutes::parse("llama3.1:8b=>16000:llama3.1:8b-128k; embed=nomic-embed-text").unwrap();
+        assert_eq!(routes.route("llama3.1:8b", 100), None);
+        assert_eq!(routes.route("llama3.1:8b", 16001), Some("llama3.1:8b-128k"));
+        assert_eq!(routes.route("embed", 1), Some("nomic-embed-text"));
+    }
+
+    #[test]
+    fn test_apply_rewrites_model() {
+        let routes = PromptRoutes::parse("assistant=small,>10:large").unwrap();
+        let mut body = json!({"model": "assistant", "messages": [{"role": "user", "content": "x".repeat(100)}]});
+        let decision = routes.apply(&mut body).unwrap();
+        assert_eq!(decision.model, "large");
+        assert_eq!(decision.estimated_tokens, 25);
+        assert_eq!(body["model"], "large");
+
+        let mut untouched = json!({"model": "llama3", "prompt": "hi"});
+        assert!(routes.apply(&mut untouched).is_none());
+        assert_eq!(untouched["model"], "llama3");
+    }
+
+    #[test]
+    fn test_canary_split() {
+        let routes = CanaryRoutes::parse("assistant=llama3.3@10,llama3.1; embed=a@50%,b@50%").unwrap();
+        assert_eq!(routes.pick("assistant", 0), Some(("llama3.3", 10)));
+        assert_eq!(routes.pick("assistant", 9), Some(("llama3.3", 10)));
+        assert_eq!(routes.pick("assistant", 10), Some(("llama3.1", 90)));
+        assert_eq!(routes.pick("embed", 99), Some(("b", 50)));
+        assert_eq!(routes.pick("llama3", 0), None);
+
+        let mut body = json!({"model": "assistant", "prompt": "hi"});
+        let decision = routes.apply(&mut body, 42).unwrap();
+        assert_eq!(decision.model, "llama3.1");
+        assert_eq!(body["model"], "llama3.1");
+        assert!(routes
+            .render_metrics()
+            .contains("ollama_proxy_canary_requests_total{alias=\"assistant\",model=\"llama3.1\"} 1\n"));
+
+        assert!(CanaryRoutes::parse("assistant=llama3.3@10,llama3.2@20").is_err());
+        assert!(CanaryRoutes::parse("assistant=llama3.3,llama3.1").is_err());
+        assert!(CanaryRoutes::parse("assistant=llama3.3@100,llama3.1").is_err());
+        assert!(CanaryRoutes::parse("assistant=llama3.3@0,llama3.1").is_err());
+        assert!(CanaryRoutes::parse("assistant=llama3.3").is_err());
+    }
+
+    #[test]
+    fn test_invalid_routes() {
+        assert!(PromptRoutes::parse("assistant").is_err());
+        assert!(PromptRoutes::parse("=small").is_err());
+        assert!(PromptRoutes::parse("assistant=>big:large").is_err());
+        assert!(PromptRoutes::parse("assistant=").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+/// Scheduled pre-warm windows: keep models loaded during set hours, unload them after
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime};
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::proxy::ProxyState;
+use crate::resident::{set_keep_alive, PINNED_KEEP_ALIVE};
+
+/// How often the schedule is evaluated
+const TICK: Duration = Duration::from_secs(60);
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// One model kept warm between `start` and `end` (local time) on `days`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrewarmWindow {
+    pub model: String,
+    /// Indexed Monday = 0
+    pub days: [bool; 7],
+    pub start: NaiveTime,
+    /// Before `start` for windows that run past midnight
+    pub end: NaiveTime,
+}
+
+impl PrewarmWindow {
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        let today = now.weekday().num_days_from_monday() as usize;
+        let time = now.time();
+        if self.start <= self.end {
+            self.days[today] && time >= self.start && time < self.end
+        } else {
+            // Overnight: the tail end belongs to the day the window started
+            let yesterday = (today + 6) % 7;
+            (self.days[today] && time >= self.start) || (self.days[yesterday] && time < self.end)
+        }
+    }
+}
+
+/// All pre-warm windows
+#[derive(Debug, Clone, Default)]
+pub struct PrewarmSchedule {
+    windows: Vec<PrewarmWindow>,
+}
+
+impl PrewarmSchedule {
+    /// Parse windows like `llama3.3:70b@mon-fri 08:00-18:00; qwen2.5:14b@daily 09:00-17:00`.
+    /// Days are `daily`, a day (`sat`), a range (`mon-fri`) or a list (`sat,sun`).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut windows = Vec::new();
+        for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            let invalid = || format!("Invalid pre-warm window '{}', expected model@days HH:MM-HH:MM", rule);
+            let (model, when) = rule.rsplit_once('@').ok_or_else(invalid)?;
+            let (days, hours) = when.trim().split_once(' ').ok_or_else(invalid)?;
+            let (start, end) = hours.trim().split_once('-').ok_or_else(invalid)?;
+            let model = model.trim();
+            if model.is_empty() {
+                return Err(invalid());
+            }
+            windows.push(PrewarmWindow {
+                model: model.to_string(),
+                days: parse_days(days)?,
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            });
+        }
+        Ok(Self { windows })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Models that should be warm at `now`
+    pub fn active_models(&self, now: NaiveDateTime) -> HashSet<String> {
+        self.windows
+            .iter()
+            .filter(|w| w.is_active(now))
+            .map(|w| w.model.clone())
+            .collect()
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> Vec<String> {
+        self.windows
+            .iter()
+            .map(|w| {
+                let days: Vec<&str> = DAYS.iter().zip(w.days).filter(|(_, on)| *on).map(|(d, _)| *d).collect();
+                format!("{} → {} {}-{}", w.model, days.join(","), w.start.format("%H:%M"), w.end.format("%H:%M"))
+            })
+            .collect()
+    }
+}
+
+fn parse_day(day: &str) -> Result<usize, String> {
+    let day = day.trim().to_lowercase();
+    DAYS.iter()
+        .position(|d| day.starts_with(d))
+        .ok_or_else(|| format!("Unknown day '{}'", day))
+}
+
+fn parse_days(spec: &str) -> Result<[bool; 7], String> {
+    let mut days = [false; 7];
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if part == "daily" || part == "*" {
+            return Ok([true; 7]);
+        }
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (parse_day(from)?, parse_day(to)?);
+                let mut day = from;
+                loop {
+                    days[day] = true;
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days[parse_day(part)?] = true,
+        }
+    }
+    if !days.contains(&true) {
+        return Err(format!("No days in '{}'", spec));
+    }
+    Ok(days)
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| format!("Invalid time '{}', expected HH:MM", value.trim()))
+}
+
+/// Evaluate the schedule every minute: pin models whose window opened, unpin
+/// and unload those whose window closed. Models an operator pinned by hand are left alone.
+pub fn spawn_prewarm(state: ProxyState, schedule: PrewarmSchedule) {
+    tokio::spawn(async move {
+        // Models this task pinned (and is therefore allowed to unload)
+        let mut warmed: HashSet<String> = HashSet::new();
+        let mut ticker = tokio::time::interval(TICK);
+        loop {
+            ticker.tick().await;
+            let active = schedule.active_models(Local::now().naive_local());
+
+            for model in &active {
+                if warmed.contains(model) || state.resident_models.is_pinned(model) {
+                    continue;
+                }
+                match set_keep_alive(&state, model, json!(PINNED_KEEP_ALIVE)).await {
+                    Ok(()) => {
+                        info!("🌅 Pre-warm window open, loaded {}", model);
+                        state.resident_models.pin(model);
+                        warmed.insert(model.clone());
+                    }
+                    // Retried on the next tick
+                    Err(e) => warn!("⚠️  Failed to pre-warm {}: {}", model, e),
+                }
+            }
+
+            let closed: Vec<String> = warmed.iter().filter(|m| !active.contains(*m)).cloned().collect();
+            for model in closed {
+                state.resident_models.unpin(&model);
+                match set_keep_alive(&state, &model, json!(0)).await {
+                    Ok(()) => info!("🌙 Pre-warm window closed, unloaded {}", model),
+                    Err(e) => warn!("⚠️  Failed to unload {}: {}", model, e),
+                }
+                warmed.remove(&model);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(date: (i32, u32, u32), time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(date.0, date.1, date.2)
+            .unwrap()
+            .and_time(parse_time(time).unwrap())
+    }
+
+    #[test]
+    fn test_office_hours_window() {
+        let schedule = PrewarmSchedule::parse("llama3.3:70b@mon-fri 08:00-18:00").unwrap();
+        // 2025-01-06 is a Monday
+        assert!(schedule.active_models(at((2025, 1, 6), "08:00")).contains("llama3.3:70b"));
+        assert!(schedule.active_models(at((2025, 1, 10), "17:59")).contains("llama3.3:70b"));
+        assert!(schedule.active_models(at((2025, 1, 6), "18:00")).is_empty());
+        assert!(schedule.active_models(at((2025, 1, 6), "07:59")).is_empty());
+        assert!(schedule.active_models(at((2025, 1, 11), "12:00")).is_empty());
+    }
+
+    #[test]
+    fn test_overnight_window() {
+        let schedule = PrewarmSchedule::parse("batch@fri 22:00-06:00").unwrap();
+        // Friday night into Saturday morning, but not Saturday night
+        assert!(!schedule.active_models(at((2025, 1, 10), "21:00")).contains("batch"));
+        assert!(schedule.active_models(at((2025, 1, 10), "23:00")).contains("batch"));
+        assert!(schedule.active_models(at((2025, 1, 11), "05:59")).contains("batch"));
+        assert!(!schedule.active_models(at((2025, 1, 11), "23:00")).contains("batch"));
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule = PrewarmSchedule::parse("a@daily 09:00-17:00; b@sat,sun 10:00-12:00; c@fri-mon 00:00-23:59").unwrap();
+        assert_eq!(
+            schedule.describe(),
+            vec![
+                "a → mon,tue,wed,thu,fri,sat,sun 09:00-17:00",
+                "b → sat,sun 10:00-12:00",
+                "c → mon,fri,sat,sun 00:00-23:59",
+            ]
+        );
+        assert!(PrewarmSchedule::parse("a@someday 09:00-17:00").is_err());
+        assert!(PrewarmSchedule::parse("a@daily 9am-5pm").is_err());
+        assert!(PrewarmSchedule::parse("a daily 09:00-17:00").is_err());
+        assert!(PrewarmSchedule::parse("").unwrap().is_empty());
+    }
+}
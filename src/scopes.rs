@@ -0,0 +1,187 @@
+/// What an API key may do: which models and endpoints it can use, and how large its requests can be
+use axum::body::Body;
+use axum::http::{Response, StatusCode};
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use crate::backends::model_matches;
+use crate::errors::{is_openai_path, ollama_error, openai_error};
+use crate::timeouts::EndpointClass;
+
+/// Paths of Ollama's model management API, whose bodies can name models outside `model`
+const MANAGEMENT_PATHS: [&str; 6] = ["/api/pull", "/api/push", "/api/create", "/api/copy", "/api/delete", "/api/show"];
+/// Fields naming a model in management requests: `name` (older clients), `source` and
+/// `destination` of `/api/copy`, and the base model `from` of `/api/create`
+const MANAGEMENT_MODEL_FIELDS: [&str; 4] = ["name", "source", "destination", "from"];
+
+/// Every model a request names: its `model` field, plus for model management paths
+/// any other field that names one
+pub fn requested_models(path: &str, body: &[u8]) -> Vec<String> {
+    let Ok(json) = serde_json::from_slice::<Value>(body) else {
+        return Vec::new();
+    };
+    let management = MANAGEMENT_PATHS.contains(&path.trim_end_matches('/'));
+    std::iter::once("model")
+        .chain(MANAGEMENT_MODEL_FIELDS.into_iter().filter(|_| management))
+        .filter_map(|field| json.get(field)?.as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Restrictions on one key; the default allows everything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScope {
+    /// Model names or `prefix*` patterns the key may use (empty = any)
+    pub models: Vec<String>,
+    /// Endpoint classes the key may call (empty = all)
+    pub endpoints: Vec<EndpointClass>,
+    /// Largest num_ctx the key's requests get
+    pub max_context: Option<u32>,
+    /// Largest num_predict (max_tokens) the key's requests get
+    pub max_num_predict: Option<u32>,
+}
+
+impl KeyScope {
+    pub fn is_restricted(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// A 403 response if `path` or any of `models` (see [`requested_models`]) is outside the scope
+    pub fn check(&self, key_name: &str, path: &str, models: &[String]) -> Option<Response<Body>> {
+        let class = EndpointClass::from_path(path);
+        if !self.endpoints.is_empty() && !self.endpoints.contains(&class) {
+            let message = format!("API key {} may not use {} endpoints", key_name, class.name());
+            return Some(forbidden(path, &message, None, "endpoint_not_allowed"));
+        }
+        if self.models.is_empty() {
+            return None;
+        }
+        let model = models.iter().find(|model| !self.models.iter().any(|pattern| model_matches(pattern, model)))?;
+        let message = format!("API key {} may not use model '{}'", key_name, model);
+        Some(forbidden(path, &message, Some("model"), "model_not_allowed"))
+    }
+
+    /// Clamp the generation limit a chat or generate request asks for, adding `default`
+    /// (capped) when it asks for none
+    pub fn cap_num_predict(&self, path: &str, body: Bytes, default: u32) -> Bytes {
+        let Some(cap) = self.max_num_predict else {
+            return body;
+        };
+        if !matches!(EndpointClass::from_path(path), EndpointClass::Chat | EndpointClass::Generate) {
+            return body;
+        }
+        let Ok(mut json) = serde_json::from_slice::<Value>(&body) else {
+            return body;
+        };
+        let Some(object) = json.as_object_mut() else {
+            return body;
+        };
+        // Negative num_predict (-1 infinite, -2 fill context) is over any cap
+        let clamp = |value: &mut Value| {
+            if value.as_i64().is_some_and(|n| n < 0 || n > cap as i64) {
+                *value = json!(cap);
+            }
+        };
+        if is_openai_path(path) {
+            let fields = ["max_completion_tokens", "max_tokens"];
+            if fields.iter().all(|field| object.get(*field).is_none_or(Value::is_null)) {
+                object.insert("max_tokens".to_string(), json!(default.min(cap)));
+            }
+            for field in fields {
+                if let Some(value) = object.get_mut(field) {
+                    clamp(value);
+                }
+            }
+        } else {
+            let options = object.entry("options").or_insert_with(|| json!({}));
+            match options.get_mut("num_predict") {
+                Some(num_predict) => clamp(num_predict),
+                None => {
+                    if let Some(options) = options.as_object_mut() {
+                        options.insert("num_predict".to_string(), json!(default.min(cap)));
+                    }
+                }
+            }
+        }
+        serde_json::to_vec(&json).map(Bytes::from).unwrap_or(body)
+    }
+
+    /// Space-separated settings, in key-file form, for the startup summary
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.models.is_empty() {
+            parts.push(format!("models={}", self.models.join(",")));
+        }
+        if !self.endpoints.is_empty() {
+            let endpoints: Vec<&str> = self.endpoints.iter().map(EndpointClass::name).collect();
+            parts.push(format!("endpoints={}", endpoints.join(",")));
+        }
+        if let Some(max_context) = self.max_context {
+            parts.push(format!("max_context={}", max_context));
+        }
+        if let Some(max_num_predict) = self.max_num_predict {
+            parts.push(format!("max_num_predict={}", max_num_predict));
+        }
+        parts.join(" ")
+    }
+}
+
+fn forbidden(path: &str, message: &str, param: Option<&str>, code: &str) -> Response<Body> {
+    if is_openai_path(path) {
+        openai_error(StatusCode::FORBIDDEN, message, param, Some(code))
+    } else {
+        ollama_error(StatusCode::FORBIDDEN, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_and_endpoints() {
+        let scope = KeyScope {
+            models: vec!["nomic-embed-text".to_string(), "bge*".to_string()],
+            endpoints: vec![EndpointClass::Embeddings, EndpointClass::Other],
+            ..Default::default()
+        };
+        let models = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert!(scope.check("team", "/v1/embeddings", &models(&["nomic-embed-text:latest"])).is_none());
+        assert!(scope.check("team", "/api/embed", &models(&["bge-m3"])).is_none());
+        assert!(scope.check("team", "/api/tags", &[]).is_none());
+        assert_eq!(scope.check("team", "/v1/embeddings", &models(&["llama3"])).unwrap().status(), 403);
+        assert_eq!(scope.check("team", "/v1/chat/completions", &models(&["bge-m3"])).unwrap().status(), 403);
+        assert_eq!(scope.check("team", "/api/delete", &models(&["bge-m3", "llama3"])).unwrap().status(), 403);
+        assert!(KeyScope::default().check("team", "/api/pull", &models(&["llama3"])).is_none());
+    }
+
+    #[test]
+    fn test_management_requests_name_models_in_other_fields() {
+        let models = |path: &str, body: Value| requested_models(path, body.to_string().as_bytes());
+        assert_eq!(models("/api/delete", json!({"name": "llama3"})), ["llama3"]);
+        assert_eq!(models("/api/copy", json!({"source": "llama3", "destination": "bge-copy"})), ["llama3", "bge-copy"]);
+        assert_eq!(models("/api/create", json!({"model": "bge-custom", "from": "llama3"})), ["bge-custom", "llama3"]);
+        // Outside model management, only `model` counts
+        assert_eq!(models("/v1/embeddings", json!({"model": "bge-m3", "name": "x"})), ["bge-m3"]);
+        assert!(models("/api/tags", json!(null)).is_empty());
+    }
+
+    #[test]
+    fn test_num_predict_is_capped() {
+        let scope = KeyScope { max_num_predict: Some(256), ..Default::default() };
+        let capped = |path: &str, body: Value| -> Value {
+            serde_json::from_slice(&scope.cap_num_predict(path, Bytes::from(body.to_string()), 4096)).unwrap()
+        };
+
+        let body = capped("/v1/chat/completions", json!({"model": "llama3", "max_tokens": 1000}));
+        assert_eq!(body["max_tokens"], 256);
+        let body = capped("/v1/chat/completions", json!({"model": "llama3", "max_completion_tokens": 100}));
+        assert_eq!(body["max_completion_tokens"], 100);
+        assert!(body.get("max_tokens").is_none());
+        assert_eq!(capped("/v1/completions", json!({"model": "llama3"}))["max_tokens"], 256);
+
+        assert_eq!(capped("/api/chat", json!({"options": {"num_predict": -1}}))["options"]["num_predict"], 256);
+        assert_eq!(capped("/api/generate", json!({"prompt": "hi"}))["options"]["num_predict"], 256);
+        assert!(capped("/api/embed", json!({"input": "hi"})).get("options").is_none());
+    }
+}
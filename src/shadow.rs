@@ -0,0 +1,285 @@
+/// Shadow traffic: mirror selected requests to a candidate model or backend in the
+/// background and log both answers side by side, without touching the client's response
+use axum::{body::Body, http::Response};
+use chrono::Utc;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{error, info, warn};
+
+use crate::access_log::LogDestination;
+use crate::backends::model_matches;
+use crate::proxy::{body_model, ProxyState};
+use crate::redact::BodyLogging;
+use crate::timeouts::EndpointClass;
+
+/// Most of the client's response kept for the comparison
+const MAX_CAPTURED_BYTES: usize = 1024 * 1024;
+/// How long a mirrored request may take
+const SHADOW_TIMEOUT: Duration = Duration::from_secs(600);
+/// Keys the logged answer text is read from, for `LOG_REDACT_FIELDS`
+const ANSWER_FIELDS: [&str; 3] = ["response", "content", "text"];
+
+/// Which requests are mirrored, where to, and where comparisons are written
+#[derive(Debug, Clone)]
+pub struct ShadowSettings {
+    /// Model the mirrored request asks for (None = the client's model)
+    pub model: Option<String>,
+    /// Ollama server the mirrored request goes to (None = the primary)
+    pub backend: Option<String>,
+    /// Model patterns (`*`, `prefix*`, names) whose requests are mirrored; empty mirrors all
+    pub models: Vec<String>,
+    /// Share of matching chat and generate requests mirrored
+    pub sample_percent: u32,
+    pub log: LogDestination,
+}
+
+impl ShadowSettings {
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        format!(
+            "{}% of {} → {} on {}, compared in {}",
+            self.sample_percent,
+            if self.models.is_empty() { "chats and generations".to_string() } else { self.models.join(", ") },
+            self.model.as_deref().unwrap_or("the same model"),
+            self.backend.as_deref().unwrap_or("the primary backend"),
+            match &self.log {
+                LogDestination::Stdout => "stdout".to_string(),
+                LogDestination::File(path) => path.display().to_string(),
+            }
+        )
+    }
+}
+
+/// A finished response, as seen by the comparison
+#[derive(Debug, Clone)]
+struct Observed {
+    status: u16,
+    latency: Duration,
+    body: Vec<u8>,
+}
+
+impl Observed {
+    /// The answer is logged under the same policy as bodies
+    fn summary(&self, logging: &BodyLogging) -> Value {
+        let (text, tokens) = answer_text(&self.body);
+        json!({
+            "status": self.status,
+            "latency_ms": round_ms(self.latency),
+            "tokens": tokens,
+            "response": logging.render_text(&ANSWER_FIELDS, &text),
+        })
+    }
+}
+
+fn round_ms(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+/// Text and generated token count of a response: a JSON body, NDJSON lines or SSE
+/// events, in Ollama or OpenAI format (the raw body when none parses)
+pub fn answer_text(body: &[u8]) -> (String, Option<u64>) {
+    let mut text = String::new();
+    let mut tokens = None;
+    let mut parsed = false;
+    for line in String::from_utf8_lossy(body).lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("data:").map_or(line, str::trim);
+        let Ok(chunk) = serde_json::from_str::<Value>(line) else {
+            continue;
+        };
+        parsed = true;
+        let choice = &chunk["choices"][0];
+        let piece = [
+            &chunk["message"]["content"],
+            &chunk["response"],
+            &choice["message"]["content"],
+            &choice["delta"]["content"],
+            &choice["text"],
+        ]
+        .into_iter()
+        .find_map(Value::as_str);
+        text.push_str(piece.unwrap_or_default());
+        if let Some(count) = chunk["eval_count"].as_u64().or_else(|| chunk["usage"]["completion_tokens"].as_u64()) {
+            tokens = Some(count);
+        }
+    }
+    if !parsed {
+        text = String::from_utf8_lossy(body).into_owned();
+    }
+    (text, tokens)
+}
+
+/// The shadow mode with its comparison log
+pub struct Shadow {
+    settings: ShadowSettings,
+    logging: BodyLogging,
+    sink: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+/// A mirrored request waiting for the client's response to compare against
+pub struct PendingComparison(oneshot::Sender<Observed>);
+
+impl std::fmt::Debug for Shadow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shadow").field("settings", &self.settings).finish()
+    }
+}
+
+impl Shadow {
+    /// Open the comparison log; answers are written to it following `logging`
+    pub fn open(settings: ShadowSettings, logging: BodyLogging) -> Result<Self, String> {
+        let sink = settings.log.open().map_err(|e| format!("Shadow log: {}", e))?;
+        Ok(Self { settings, logging, sink: Arc::new(Mutex::new(sink)) })
+    }
+
+    /// Mirror the request if it is selected: the copy is sent to `primary` (or the
+    /// configured backend) right away, and the client's response is compared against
+    /// it once [`capture`] has seen all of it. The copy holds an upstream slot like any
+    /// request, and is skipped when none is free rather than queueing for one.
+    pub fn mirror(&self, state: &ProxyState, primary: &str, path: &str, body: &[u8]) -> Option<PendingComparison> {
+        if !matches!(EndpointClass::from_path(path), EndpointClass::Chat | EndpointClass::Generate) {
+            return None;
+        }
+        let model = body_model(body)?;
+        if !self.settings.models.is_empty() && !self.settings.models.iter().any(|p| model_matches(p, &model)) {
+            return None;
+        }
+        if uuid::Uuid::new_v4().as_u64_pair().0 % 100 >= self.settings.sample_percent as u64 {
+            return None;
+        }
+        let mut json: Value = serde_json::from_slice(body).ok()?;
+        let shadow_model = self.settings.model.clone().unwrap_or_else(|| model.clone());
+        json["model"] = json!(shadow_model);
+        json["stream"] = json!(false);
+
+        let permit = match state.try_upstream_slot(&shadow_model) {
+            Ok(permit) => permit,
+            Err(_) => {
+                info!("👥 No free upstream slot, not mirroring {} request for {}", path, model);
+                return None;
+            }
+        };
+        let client = state.client();
+
+        let backend = self.settings.backend.as_deref().unwrap_or(primary).to_string();
+        let url = format!("{}{}", backend, path);
+        let (sender, receiver) = oneshot::channel::<Observed>();
+        let record = json!({
+            "path": path,
+            "model": model,
+            "shadow_model": shadow_model,
+            "shadow_backend": backend,
+        });
+        info!("👥 Mirroring {} request for {} to {} on {}", path, model, shadow_model, backend);
+
+        let sink = self.sink.clone();
+        let logging = self.logging.clone();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let mirrored = async {
+                // The slot is freed as soon as the shadow backend has answered
+                let _slot = permit;
+                let response = client.post(&url).json(&json).timeout(SHADOW_TIMEOUT).send().await?;
+                let status = response.status().as_u16();
+                let body = response.bytes().await?;
+                Ok::<_, reqwest::Error>(Observed { status, latency: started.elapsed(), body: body.to_vec() })
+            };
+            let (mirrored, primary) = tokio::join!(mirrored, receiver);
+            let Ok(primary) = primary else {
+                return;
+            };
+            let mirrored = mirrored.unwrap_or_else(|e| {
+                warn!("⚠️  Shadow request to {} failed: {}", url, e);
+                Observed { status: 0, latency: started.elapsed(), body: e.to_string().into_bytes() }
+            });
+            let line = compare(record, &primary, &mirrored, &logging);
+            let mut sink = sink.lock().unwrap();
+            if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+                error!("❌ Failed to write shadow log: {}", e);
+            }
+        });
+        Some(PendingComparison(sender))
+    }
+}
+
+/// One comparison line: both answers plus their latency and token deltas
+fn compare(mut record: Value, primary: &Observed, shadow: &Observed, logging: &BodyLogging) -> Value {
+    let (primary_summary, shadow_summary) = (primary.summary(logging), shadow.summary(logging));
+    let latency_delta = (shadow.latency.as_secs_f64() - primary.latency.as_secs_f64()) * 1000.0;
+    let token_delta = match (primary_summary["tokens"].as_i64(), shadow_summary["tokens"].as_i64()) {
+        (Some(primary), Some(shadow)) => Some(shadow - primary),
+        _ => None,
+    };
+    info!(
+        "👥 Shadow {} vs {}: {:+.0}ms, {} tokens",
+        record["model"].as_str().unwrap_or_default(),
+        record["shadow_model"].as_str().unwrap_or_default(),
+        latency_delta,
+        token_delta.map_or("?".to_string(), |delta| format!("{:+}", delta))
+    );
+    record["time"] = json!(Utc::now().to_rfc3339());
+    record["primary"] = primary_summary;
+    record["shadow"] = shadow_summary;
+    record["latency_delta_ms"] = json!((latency_delta * 10.0).round() / 10.0);
+    record["token_delta"] = json!(token_delta);
+    record
+}
+
+/// Hands the client's response to the comparison once its body is finished
+struct Capture {
+    sender: Option<oneshot::Sender<Observed>>,
+    observed: Observed,
+    started: Instant,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.observed.latency = self.started.elapsed();
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(std::mem::replace(&mut self.observed, Observed { status: 0, latency: Duration::ZERO, body: Vec::new() }));
+        }
+    }
+}
+
+/// Copy the response body, as it is streamed to the client, for the comparison
+pub fn capture(response: Response<Body>, pending: PendingComparison, started: Instant) -> Response<Body> {
+    let (parts, body) = response.into_parts();
+    let mut capture = Capture {
+        sender: Some(pending.0),
+        observed: Observed { status: parts.status.as_u16(), latency: Duration::ZERO, body: Vec::new() },
+        started,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        // Borrow the whole guard so the closure owns it, not just the buffer
+        let capture = &mut capture;
+        if let Ok(bytes) = &chunk {
+            let room = MAX_CAPTURED_BYTES.saturating_sub(capture.observed.body.len());
+            capture.observed.body.extend_from_slice(&bytes[..bytes.len().min(room)]);
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_text_across_formats() {
+        let ndjson = b"{\"response\":\"Hel\",\"done\":false}\n{\"response\":\"lo\",\"done\":true,\"eval_count\":2}\n";
+        assert_eq!(answer_text(ndjson), ("Hello".to_string(), Some(2)));
+
+        let sse = b"data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: [DONE]\n\n";
+        assert_eq!(answer_text(sse), ("Hi".to_string(), None));
+
+        let openai = br#"{"choices":[{"message":{"content":"Sure"}}],"usage":{"completion_tokens":7}}"#;
+        assert_eq!(answer_text(openai), ("Sure".to_string(), Some(7)));
+
+        assert_eq!(answer_text(b"upstream error"), ("upstream error".to_string(), None));
+    }
+}
@@ -0,0 +1,336 @@
+/// Per-model generation throughput, time to first token and latency percentiles,
+/// exposed at /metrics and /proxy/admin/stats
+use axum::{extract::State, response::IntoResponse, Json};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::latency::LatencyTracker;
+use crate::proxy::ProxyState;
+
+/// Number of recent samples kept per model
+const WINDOW: usize = 256;
+
+/// Percentiles reported for every distribution
+const QUANTILES: [(f64, &str); 3] = [(0.5, "p50"), (0.9, "p90"), (0.99, "p99")];
+
+/// Generated tokens and generation time reported by Ollama (`eval_count`, `eval_duration`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Eval {
+    pub tokens: u64,
+    pub duration: Duration,
+}
+
+impl Eval {
+    /// Read the counters from a response body or NDJSON line without parsing it.
+    /// Ollama puts them at the end, after the (possibly large) generated text.
+    pub fn scan(body: &[u8]) -> Option<Self> {
+        let tokens = scan_number(body, b"\"eval_count\":")?;
+        let nanos = scan_number(body, b"\"eval_duration\":")?;
+        (tokens > 0 && nanos > 0).then(|| Self { tokens, duration: Duration::from_nanos(nanos) })
+    }
+
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens as f64 / self.duration.as_secs_f64()
+    }
+}
+
+/// The number after the last occurrence of `key` (e.g. `"eval_count":`)
+fn scan_number(body: &[u8], key: &[u8]) -> Option<u64> {
+    let start = body.windows(key.len()).rposition(|window| window == key)? + key.len();
+    let digits: String = body[start..]
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace())
+        .take_while(|b| b.is_ascii_digit())
+        .map(|&b| b as char)
+        .collect();
+    digits.parse().ok()
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+    tokens: u64,
+    eval_time: Duration,
+    rates: VecDeque<f64>,
+    ttft: VecDeque<Duration>,
+}
+
+fn push<T>(window: &mut VecDeque<T>, value: T) {
+    if window.len() == WINDOW {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
+
+/// The `quantile` (0.0-1.0) of `values`, which must be sorted
+fn quantile<T: Copy>(sorted: &[T], quantile: f64) -> Option<T> {
+    let rank = (quantile * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1).min(sorted.len().saturating_sub(1))).copied()
+}
+
+fn distribution_ms(mut samples: Vec<Duration>) -> Value {
+    samples.sort_unstable();
+    let mut out = Map::new();
+    for (q, name) in QUANTILES {
+        let value = quantile(&samples, q).map(|d| (d.as_secs_f64() * 10_000.0).round() / 10.0);
+        out.insert(name.to_string(), json!(value));
+    }
+    out.insert("samples".to_string(), json!(samples.len()));
+    Value::Object(out)
+}
+
+/// Throughput and time-to-first-token history per model; request latencies come from the
+/// shared `LatencyTracker`
+#[derive(Debug)]
+pub struct ModelStats {
+    models: Mutex<BTreeMap<String, Samples>>,
+    latency: Arc<LatencyTracker>,
+}
+
+impl ModelStats {
+    pub fn new(latency: Arc<LatencyTracker>) -> Self {
+        Self { models: Mutex::new(BTreeMap::new()), latency }
+    }
+
+    pub fn record_eval(&self, model: &str, eval: Eval) {
+        let mut models = self.models.lock().unwrap();
+        let samples = models.entry(model.to_string()).or_default();
+        samples.tokens += eval.tokens;
+        samples.eval_time += eval.duration;
+        push(&mut samples.rates, eval.tokens_per_second());
+    }
+
+    /// Time from sending a streamed request until its first line arrived
+    pub fn record_ttft(&self, model: &str, elapsed: Duration) {
+        let mut models = self.models.lock().unwrap();
+        push(&mut models.entry(model.to_string()).or_default().ttft, elapsed);
+    }
+
+    /// Everything known per model, for /proxy/admin/stats
+    pub fn snapshot(&self) -> Value {
+        let mut out: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+        for (model, samples) in self.models.lock().unwrap().iter() {
+            let mut rates: Vec<f64> = samples.rates.iter().copied().collect();
+            rates.sort_unstable_by(f64::total_cmp);
+            let entry = out.entry(model.clone()).or_default();
+            entry.insert("generated_tokens".to_string(), json!(samples.tokens));
+            entry.insert(
+                "tokens_per_second".to_string(),
+                json!({
+                    "mean": (samples.eval_time > Duration::ZERO)
+                        .then(|| samples.tokens as f64 / samples.eval_time.as_secs_f64()),
+                    "p10": quantile(&rates, 0.1),
+                    "p50": quantile(&rates, 0.5),
+                    "samples": rates.len(),
+                }),
+            );
+            if !samples.ttft.is_empty() {
+                entry.insert(
+                    "time_to_first_token_ms".to_string(),
+                    distribution_ms(samples.ttft.iter().copied().collect()),
+                );
+            }
+        }
+        for ((model, class), samples) in self.latency.windows() {
+            let entry = out.entry(model).or_default();
+            let latency = entry.entry("latency_ms").or_insert_with(|| json!({}));
+            latency[class.name()] = distribution_ms(samples);
+        }
+        let models: Vec<Value> = out
+            .into_iter()
+            .map(|(model, mut entry)| {
+                entry.insert("model".to_string(), json!(model));
+                Value::Object(entry)
+            })
+            .collect();
+        json!({ "models": models })
+    }
+
+    /// Prometheus text for /metrics
+    pub fn render_metrics(&self) -> String {
+        let mut out = String::new();
+        let models = self.models.lock().unwrap();
+        let _ = writeln!(out, "# HELP ollama_proxy_generated_tokens_total Tokens generated per model (Ollama eval_count)");
+        let _ = writeln!(out, "# TYPE ollama_proxy_generated_tokens_total counter");
+        for (model, samples) in models.iter() {
+            let _ = writeln!(out, "ollama_proxy_generated_tokens_total{{model=\"{}\"}} {}", model, samples.tokens);
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_generation_seconds_total Time spent generating per model (Ollama eval_duration)");
+        let _ = writeln!(out, "# TYPE ollama_proxy_generation_seconds_total counter");
+        for (model, samples) in models.iter() {
+            let _ = writeln!(
+                out,
+                "ollama_proxy_generation_seconds_total{{model=\"{}\"}} {}",
+                model,
+                samples.eval_time.as_secs_f64()
+            );
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_tokens_per_second Recent generation throughput per model");
+        let _ = writeln!(out, "# TYPE ollama_proxy_tokens_per_second gauge");
+        for (model, samples) in models.iter() {
+            let mut rates: Vec<f64> = samples.rates.iter().copied().collect();
+            rates.sort_unstable_by(f64::total_cmp);
+            for (q, _) in QUANTILES {
+                if let Some(rate) = quantile(&rates, q) {
+                    let _ = writeln!(out, "ollama_proxy_tokens_per_second{{model=\"{}\",quantile=\"{}\"}} {}", model, q, rate);
+                }
+            }
+        }
+        let _ = writeln!(out, "# HELP ollama_proxy_time_to_first_token_seconds Time until the first streamed line per model");
+        let _ = writeln!(out, "# TYPE ollama_proxy_time_to_first_token_seconds gauge");
+        for (model, samples) in models.iter() {
+            let mut ttft: Vec<Duration> = samples.ttft.iter().copied().collect();
+            ttft.sort_unstable();
+            for (q, _) in QUANTILES {
+                if let Some(elapsed) = quantile(&ttft, q) {
+                    let _ = writeln!(
+                        out,
+                        "ollama_proxy_time_to_first_token_seconds{{model=\"{}\",quantile=\"{}\"}} {}",
+                        model,
+                        q,
+                        elapsed.as_secs_f64()
+                    );
+                }
+            }
+        }
+        drop(models);
+        let _ = writeln!(out, "# HELP ollama_proxy_request_latency_seconds Recent non-streaming request latency per model");
+        let _ = writeln!(out, "# TYPE ollama_proxy_request_latency_seconds gauge");
+        for ((model, class), mut samples) in self.latency.windows() {
+            samples.sort_unstable();
+            for (q, _) in QUANTILES {
+                if let Some(elapsed) = quantile(&samples, q) {
+                    let _ = writeln!(
+                        out,
+                        "ollama_proxy_request_latency_seconds{{model=\"{}\",class=\"{}\",quantile=\"{}\"}} {}",
+                        model,
+                        class.name(),
+                        q,
+                        elapsed.as_secs_f64()
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Records time to first token and the final counters of one streamed response
+#[derive(Debug)]
+pub struct StreamRecorder {
+    stats: Arc<ModelStats>,
+    model: String,
+    started: Instant,
+    seen_first_line: bool,
+}
+
+impl StreamRecorder {
+    /// `started` is when the request was sent to Ollama
+    pub fn new(stats: Arc<ModelStats>, model: &str, started: Instant) -> Self {
+        Self { stats, model: model.to_string(), started, seen_first_line: false }
+    }
+
+    pub fn line(&mut self, line: &[u8]) {
+        if !self.seen_first_line {
+            self.seen_first_line = true;
+            self.stats.record_ttft(&self.model, self.started.elapsed());
+        }
+        if let Some(eval) = Eval::scan(line) {
+            self.stats.record_eval(&self.model, eval);
+        }
+    }
+}
+
+/// Keeps the end of a passed-through body and reads its counters once it has been sent
+struct TailGuard {
+    stats: Arc<ModelStats>,
+    model: String,
+    tail: Vec<u8>,
+}
+
+impl Drop for TailGuard {
+    fn drop(&mut self) {
+        if let Some(eval) = Eval::scan(&self.tail) {
+            self.stats.record_eval(&self.model, eval);
+        }
+    }
+}
+
+/// Pass `stream` through, recording the counters at the end of the response it carries
+pub fn record_passthrough<S, E>(stats: Arc<ModelStats>, model: &str, stream: S) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    const TAIL_BYTES: usize = 1024;
+    let mut guard = TailGuard { stats, model: model.to_string(), tail: Vec::new() };
+    stream.map(move |chunk| {
+        let guard = &mut guard;
+        if let Ok(bytes) = &chunk {
+            guard.tail.extend_from_slice(bytes);
+            let excess = guard.tail.len().saturating_sub(TAIL_BYTES);
+            guard.tail.drain(..excess);
+        }
+        chunk
+    })
+}
+
+/// GET /proxy/admin/stats
+pub async fn stats_handler(State(state): State<ProxyState>) -> impl IntoResponse {
+    Json(state.model_stats.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeouts::EndpointClass;
+
+    #[test]
+    fn test_scan_reads_final_counters() {
+        let line = br#"{"model":"llama3","response":"","done":true,"prompt_eval_count":12,"prompt_eval_duration":5000,"eval_count":40,"eval_duration":2000000000}"#;
+        let eval = Eval::scan(line).unwrap();
+        assert_eq!(eval, Eval { tokens: 40, duration: Duration::from_secs(2) });
+        assert_eq!(eval.tokens_per_second(), 20.0);
+        assert_eq!(Eval::scan(br#"{"model":"llama3","response":"Hi","done":false}"#), None);
+        assert_eq!(Eval::scan(br#"{"prompt_eval_count":12,"prompt_eval_duration":5000}"#), None);
+    }
+
+    #[test]
+    fn test_snapshot_and_metrics() {
+        let latency = Arc::new(LatencyTracker::new());
+        latency.record("llama3", EndpointClass::Chat, Duration::from_millis(800));
+        let stats = ModelStats::new(latency);
+        stats.record_eval("llama3", Eval { tokens: 40, duration: Duration::from_secs(2) });
+        stats.record_eval("llama3", Eval { tokens: 60, duration: Duration::from_secs(2) });
+        stats.record_ttft("llama3", Duration::from_millis(150));
+
+        let snapshot = stats.snapshot();
+        let model = &snapshot["models"][0];
+        assert_eq!(model["model"], "llama3");
+        assert_eq!(model["generated_tokens"], 100);
+        assert_eq!(model["tokens_per_second"]["mean"], 25.0);
+        assert_eq!(model["tokens_per_second"]["p50"], 20.0);
+        assert_eq!(model["time_to_first_token_ms"]["p50"], 150.0);
+        assert_eq!(model["latency_ms"]["chat"]["p99"], 800.0);
+
+        let metrics = stats.render_metrics();
+        assert!(metrics.contains("ollama_proxy_generated_tokens_total{model=\"llama3\"} 100\n"));
+        assert!(metrics.contains("ollama_proxy_tokens_per_second{model=\"llama3\",quantile=\"0.99\"} 30\n"));
+        assert!(metrics.contains("ollama_proxy_time_to_first_token_seconds{model=\"llama3\",quantile=\"0.5\"} 0.15\n"));
+        assert!(metrics.contains("ollama_proxy_request_latency_seconds{model=\"llama3\",class=\"chat\",quantile=\"0.9\"} 0.8\n"));
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_reads_counters_split_across_chunks() {
+        let stats = Arc::new(ModelStats::new(Arc::new(LatencyTracker::new())));
+        let body = format!(r#"{{"response":"{}","done":true,"eval_count":10,"eval_duration":500000000}}"#, "x".repeat(5000));
+        let chunks: Vec<Result<Bytes, std::io::Error>> =
+            body.as_bytes().chunks(700).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+        let forwarded: Vec<_> = record_passthrough(stats.clone(), "llama3", futures::stream::iter(chunks)).collect().await;
+        assert_eq!(forwarded.len(), body.len().div_ceil(700));
+        assert_eq!(stats.snapshot()["models"][0]["tokens_per_second"]["mean"], 20.0);
+    }
+}
@@ -0,0 +1,384 @@
+/// Validation of structured (JSON mode / JSON schema) outputs
+use axum::body::Body;
+use axum::http::{Response, StatusCode};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::errors::openai_error;
+
+/// What to do when a streamed structured output turns out not to be valid JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StructuredFailure {
+    /// Forward the stream as it arrives and mark the final line with `structured_output_error`
+    #[default]
+    Flag,
+    /// Hold the stream back and, if the output is invalid, replace it with one non-streaming retry
+    Retry,
+}
+
+impl StructuredFailure {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "flag" => Some(Self::Flag),
+            "retry" => Some(Self::Retry),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Flag => "flag",
+            Self::Retry => "retry",
+        }
+    }
+}
+
+/// The structured output a native request asks for: `format: "json"` (None) or a schema
+pub fn requested_format(json: &Value) -> Option<Option<Value>> {
+    match json.get("format")? {
+        Value::String(format) if format == "json" => Some(None),
+        schema @ Value::Object(_) => Some(Some(schema.clone())),
+        _ => None,
+    }
+}
+
+/// Tracks the JSON structure of text as it streams in, spotting output that can
+/// never become valid JSON (wrong first character, mismatched brackets, trailing text)
+#[derive(Debug, Default)]
+pub struct JsonStreamValidator {
+    text: String,
+    stack: Vec<char>,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    closed: bool,
+    error: Option<String>,
+}
+
+impl JsonStreamValidator {
+    pub fn push(&mut self, fragment: &str) {
+        self.text.push_str(fragment);
+        if self.error.is_some() {
+            return;
+        }
+        for c in fragment.chars() {
+            if let Err(e) = self.step(c) {
+                self.error = Some(e);
+                return;
+            }
+        }
+    }
+
+    fn step(&mut self, c: char) -> Result<(), String> {
+        if self.in_string {
+            match (self.escaped, c) {
+                (true, _) => self.escaped = false,
+                (false, '\\') => self.escaped = true,
+                (false, '"') => self.in_string = false,
+                _ => {}
+            }
+            return Ok(());
+        }
+        if c.is_whitespace() {
+            return Ok(());
+        }
+        if self.closed {
+            return Err(format!("unexpected '{}' after the JSON value", c));
+        }
+        if !self.started {
+            if c != '{' && c != '[' {
+                return Err(format!("output starts with '{}' instead of an object or array", c));
+            }
+            self.started = true;
+        }
+        match c {
+            '"' => self.in_string = true,
+            '{' | '[' => self.stack.push(c),
+            '}' | ']' => {
+                let open = if c == '}' { '{' } else { '[' };
+                if self.stack.pop() != Some(open) {
+                    return Err(format!("mismatched '{}'", c));
+                }
+                self.closed = self.stack.is_empty();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Problem found so far, if any
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Final verdict on the complete output
+    pub fn finish(&self, schema: Option<&Value>) -> Result<(), String> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        validate(&self.text, schema)
+    }
+}
+
+/// Check that `text` parses as JSON and matches the basic constraints of `schema`
+/// (`type`, `required`, `properties`, `items`, `enum`)
+pub fn validate(text: &str, schema: Option<&Value>) -> Result<(), String> {
+    let value: Value = serde_json::from_str(text.trim()).map_err(|e| format!("invalid JSON: {}", e))?;
+    match schema {
+        Some(schema) => check_schema(&value, schema, "$"),
+        None => Ok(()),
+    }
+}
+
+fn check_schema(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{} should be {}", path, expected));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{} is not one of the allowed values", path));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for key in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(key) = key.as_str().filter(|k| !object.contains_key(*k)) {
+                return Err(format!("{} is missing required property '{}'", path, key));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property) in properties {
+                if let Some(field) = object.get(key) {
+                    check_schema(field, property, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+    if let (Some(items), Some(schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check_schema(item, schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+/// OpenAI-style error for a completion whose content doesn't match the requested format
+pub fn mismatch_response(reason: &str) -> Response<Body> {
+    let message = format!("Model output does not match the requested response_format: {}", reason);
+    openai_error(StatusCode::BAD_GATEWAY, &message, Some("response_format"), Some("invalid_structured_output"))
+}
+
+/// The request to repeat (non-streaming) when a held-back output fails validation
+pub struct RetryRequest {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub body: Value,
+    pub timeout: Option<std::time::Duration>,
+}
+
+/// What to do with a streamed line after validation
+pub enum Checked {
+    /// Send these bytes on
+    Forward(Vec<u8>),
+    /// Held back until the output is known to be valid
+    Hold,
+    /// The output is invalid; call [`StructuredCheck::retry`] for the replacement
+    Retry,
+}
+
+/// Validates the text of a streamed chat or generate response as it passes through
+pub struct StructuredCheck {
+    validator: JsonStreamValidator,
+    schema: Option<Value>,
+    retry: Option<RetryRequest>,
+    held: Vec<u8>,
+}
+
+impl StructuredCheck {
+    /// `retry` is only used with [`StructuredFailure::Retry`]
+    pub fn new(schema: Option<Value>, policy: StructuredFailure, retry: RetryRequest) -> Self {
+        Self {
+            validator: JsonStreamValidator::default(),
+            schema,
+            retry: (policy == StructuredFailure::Retry).then_some(retry),
+            held: Vec::new(),
+        }
+    }
+
+    pub fn check_line(&mut self, line: Vec<u8>) -> Checked {
+        let Ok(mut json) = serde_json::from_slice::<Value>(&line) else {
+            return self.pass(line);
+        };
+        let content = json
+            .get("response")
+            .or_else(|| json.get("message").and_then(|m| m.get("content")))
+            .and_then(Value::as_str);
+        if let Some(content) = content {
+            self.validator.push(content);
+        }
+        let done = json.get("done").and_then(Value::as_bool).unwrap_or(false);
+
+        if self.retry.is_some() {
+            if self.validator.error().is_some() {
+                return Checked::Retry;
+            }
+            self.held.extend_from_slice(&line);
+            if !done {
+                return Checked::Hold;
+            }
+            return match self.validator.finish(self.schema.as_ref()) {
+                Ok(()) => Checked::Forward(std::mem::take(&mut self.held)),
+                Err(_) => Checked::Retry,
+            };
+        }
+
+        if done {
+            if let Err(e) = self.validator.finish(self.schema.as_ref()) {
+                warn!("⚠️  Streamed structured output is invalid: {}", e);
+                json["structured_output_error"] = json!(e);
+                return Checked::Forward(format!("{}\n", json).into_bytes());
+            }
+        }
+        Checked::Forward(line)
+    }
+
+    fn pass(&mut self, line: Vec<u8>) -> Checked {
+        if self.retry.is_some() {
+            self.held.extend_from_slice(&line);
+            return Checked::Hold;
+        }
+        Checked::Forward(line)
+    }
+
+    /// Lines still held back (the stream ended early or broke off)
+    pub fn take_held(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.held)
+    }
+
+    /// Repeat the request without streaming and return the line to send instead of
+    /// the held output. If the retry fails, the held output goes out flagged.
+    pub async fn retry(&mut self) -> Vec<u8> {
+        let reason = self
+            .validator
+            .finish(self.schema.as_ref())
+            .err()
+            .unwrap_or_else(|| "invalid output".to_string());
+        let Some(request) = self.retry.take() else {
+            return self.take_held();
+        };
+        info!("🔁 Structured output invalid ({}), retrying once without streaming", reason);
+
+        let result = async {
+            let mut retry = request.client.post(&request.url).json(&request.body);
+            if let Some(timeout) = request.timeout {
+                retry = retry.timeout(timeout);
+            }
+            let response = retry
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            if !response.status().is_success() {
+                return Err(format!("retry returned {}", response.status()));
+            }
+            response.json::<Value>().await.map_err(|e| e.to_string())
+        }
+        .await;
+
+        match result {
+            Ok(mut json) => {
+                let content = json
+                    .get("response")
+                    .or_else(|| json.get("message").and_then(|m| m.get("content")))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let verdict = validate(content, self.schema.as_ref());
+                json["structured_output_retried"] = json!(true);
+                if let Err(e) = verdict {
+                    warn!("⚠️  Structured output retry is also invalid: {}", e);
+                    json["structured_output_error"] = json!(e);
+                }
+                format!("{}\n", json).into_bytes()
+            }
+            Err(e) => {
+                warn!("⚠️  Structured output retry failed: {}", e);
+                let mut held = self.take_held();
+                held.extend_from_slice(format!("{}\n", json!({"structured_output_error": reason, "done": true})).as_bytes());
+                held
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_validator() {
+        let mut validator = JsonStreamValidator::default();
+        for fragment in ["{\"a\": [1, ", "\"}]\"", "]}", "  "] {
+            validator.push(fragment);
+            assert_eq!(validator.error(), None);
+        }
+        assert_eq!(validator.finish(None), Ok(()));
+
+        let mut prose = JsonStreamValidator::default();
+        prose.push("Sure! Here is the JSON");
+        assert!(prose.error().unwrap().contains("starts with 'S'"));
+
+        let mut trailing = JsonStreamValidator::default();
+        trailing.push("{\"a\": 1} and more");
+        assert!(trailing.error().unwrap().contains("after the JSON value"));
+
+        let mut truncated = JsonStreamValidator::default();
+        truncated.push("{\"a\": ");
+        assert!(truncated.error().is_none());
+        assert!(truncated.finish(None).is_err());
+    }
+
+    #[test]
+    fn test_schema_checks() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "age"],
+            "properties": {
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "kind": {"enum": ["cat", "dog"]}
+            }
+        });
+        assert_eq!(validate(r#"{"name": "Rex", "age": 3, "tags": ["a"], "kind": "dog"}"#, Some(&schema)), Ok(()));
+        assert!(validate(r#"{"name": "Rex"}"#, Some(&schema)).unwrap_err().contains("'age'"));
+        assert!(validate(r#"{"name": "Rex", "age": 3.5}"#, Some(&schema)).unwrap_err().contains("$.age"));
+        assert!(validate(r#"{"name": "Rex", "age": 3, "tags": [1]}"#, Some(&schema)).unwrap_err().contains("$.tags[0]"));
+        assert!(validate(r#"{"name": "Rex", "age": 3, "kind": "cow"}"#, Some(&schema)).is_err());
+
+        assert_eq!(requested_format(&json!({"format": "json"})), Some(None));
+        assert_eq!(requested_format(&json!({"format": schema.clone()})), Some(Some(schema)));
+        assert_eq!(requested_format(&json!({"prompt": "hi"})), None);
+    }
+
+    #[test]
+    fn test_flag_marks_final_line() {
+        let retry = RetryRequest { client: reqwest::Client::new(), url: String::new(), body: Value::Null, timeout: None };
+        let mut check = StructuredCheck::new(None, StructuredFailure::Flag, retry);
+        let lines = ["{\"response\":\"{\\\"a\\\":\",\"done\":false}\n", "{\"response\":\"\",\"done\":true}\n"];
+        assert!(matches!(check.check_line(lines[0].as_bytes().to_vec()), Checked::Forward(_)));
+        let Checked::Forward(last) = check.check_line(lines[1].as_bytes().to_vec()) else {
+            panic!("expected the final line");
+        };
+        let last: Value = serde_json::from_slice(&last).unwrap();
+        assert!(last["structured_output_error"].as_str().unwrap().contains("invalid JSON"));
+    }
+}
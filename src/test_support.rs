@@ -0,0 +1,208 @@
+/// In-process proxy and mock Ollama for integration tests (`test-support` feature)
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, Response, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use crate::builder::ProxyBuilder;
+use crate::config::ProxyConfig;
+use crate::proxy::ProxyState;
+
+/// Serve `router` on an ephemeral localhost port until the returned handle is aborted
+pub async fn serve(router: Router) -> (SocketAddr, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind test listener");
+    let addr = listener.local_addr().expect("Test listener has no address");
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .expect("Test server error");
+    });
+    (addr, handle)
+}
+
+/// A request received by [`MockOllama`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HeaderMap,
+    /// JSON body (Null when empty or not JSON)
+    pub body: Value,
+}
+
+/// Stand-in for an Ollama server. The default routes answer `/api/tags`, `/api/version`,
+/// `/api/ps`, `/api/show` (llama architecture, 8192 context, tool support, vision for
+/// `*vision*` models), `/api/embed`, `/api/chat` and `/api/generate` (streaming and
+/// non-streaming), and echo anything else.
+pub struct MockOllama {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockOllama {
+    pub async fn start() -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let router = Router::new().fallback(mock_handler).with_state(requests.clone());
+        let (addr, handle) = serve(router).await;
+        Self { url: format!("http://{}", addr), requests, handle }
+    }
+
+    /// Serve custom routes instead of the defaults (requests are not recorded)
+    pub async fn with_router(router: Router) -> Self {
+        let (addr, handle) = serve(router).await;
+        Self { url: format!("http://{}", addr), requests: Arc::default(), handle }
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Most recent request to `path`
+    pub fn last_request(&self, path: &str) -> Option<RecordedRequest> {
+        self.requests().into_iter().rev().find(|r| r.path == path)
+    }
+}
+
+impl Drop for MockOllama {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// The proxy running on an ephemeral port
+pub struct TestProxy {
+    pub url: String,
+    pub state: ProxyState,
+    handle: JoinHandle<()>,
+}
+
+impl TestProxy {
+    /// Start the proxy with `config` (point `config.ollama_host` at a [`MockOllama`])
+    pub async fn start(config: ProxyConfig) -> Self {
+        let (router, state) = ProxyBuilder::from_config(config)
+            .build()
+            .expect("Invalid test proxy config");
+        let (addr, handle) = serve(router).await;
+        Self { url: format!("http://{}", addr), state, handle }
+    }
+
+    /// Start the proxy with default settings in front of `upstream_url`
+    pub async fn for_upstream(upstream_url: &str) -> Self {
+        Self::start(ProxyBuilder::new(upstream_url).config().expect("Invalid test proxy config")).await
+    }
+
+    /// Full URL for `path` on the proxy
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.url, path)
+    }
+}
+
+impl Drop for TestProxy {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+const CREATED_AT: &str = "2025-01-01T00:00:00Z";
+
+async fn mock_handler(State(requests): State<Arc<Mutex<Vec<RecordedRequest>>>>, request: Request) -> Response<Body> {
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let headers = request.headers().clone();
+    let bytes = request
+        .into_body()
+        .collect()
+        .await
+        .map(|collected| collected.to_bytes())
+        .unwrap_or_default();
+    let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    requests.lock().unwrap().push(RecordedRequest { method, path: path.clone(), headers, body: body.clone() });
+
+    let model = body.get("model").cloned().unwrap_or(Value::Null);
+    let stream = body.get("stream").and_then(Value::as_bool).unwrap_or(false);
+    match path.as_str() {
+        "/api/tags" => json_response(json!({
+            "models": [{"name": "llama3:latest", "model": "llama3:latest", "modified_at": CREATED_AT, "size": 1}]
+        })),
+        "/api/version" => json_response(json!({"version": "0.5.0"})),
+        "/api/ps" => json_response(json!({
+            "models": [{"name": "llama3:latest", "model": "llama3:latest", "size": 1}]
+        })),
+        "/api/show" => {
+            let name = body.get("name").and_then(Value::as_str).unwrap_or_default();
+            let mut capabilities = vec!["completion", "tools"];
+            if name.contains("vision") {
+                capabilities.push("vision");
+            }
+            json_response(json!({
+                "model_info": {"general.architecture": "llama", "llama.context_length": 8192},
+                "template": "{{ .System }}",
+                "capabilities": capabilities
+            }))
+        }
+        "/api/embed" => {
+            let count = match body.get("input") {
+                Some(Value::Array(items)) => items.len(),
+                Some(_) => 1,
+                None => 0,
+            };
+            json_response(json!({
+                "model": model,
+                "embeddings": vec![vec![0.1, 0.2, 0.3]; count],
+                "prompt_eval_count": 3
+            }))
+        }
+        "/api/chat" | "/api/generate" => {
+            let chunk = |content: &str, done: bool| {
+                let mut line = json!({"model": model, "created_at": CREATED_AT, "done": done});
+                if path == "/api/chat" {
+                    line["message"] = json!({"role": "assistant", "content": content});
+                } else {
+                    line["response"] = json!(content);
+                }
+                if done {
+                    line["done_reason"] = json!("stop");
+                    line["prompt_eval_count"] = json!(5);
+                    line["eval_count"] = json!(3);
+                    line["eval_duration"] = json!(1_000_000);
+                }
+                line
+            };
+            if stream {
+                let lines: String = ["Hel", "lo", " there"]
+                    .iter()
+                    .map(|t| chunk(t, false))
+                    .chain(std::iter::once(chunk("", true)))
+                    .map(|line| format!("{}\n", line))
+                    .collect();
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/x-ndjson")
+                    .body(Body::from(lines))
+                    .unwrap()
+            } else {
+                json_response(chunk("Hello there", true))
+            }
+        }
+        _ => json_response(json!({"echo": body})),
+    }
+}
+
+fn json_response(value: Value) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
@@ -0,0 +1,263 @@
+/// Per-endpoint-class request timeouts
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Header a client sets to pick its own timeout in seconds, e.g. a batch job allowing
+/// a long generation
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout";
+
+/// Latency profile of an endpoint, used to pick its timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    /// Embeddings (usually seconds)
+    Embeddings,
+    /// Chat completions (minutes for large models)
+    Chat,
+    /// Raw text generation
+    Generate,
+    /// Model transfers and management: pull, push, create, blobs (unbounded by default)
+    Transfer,
+    /// Everything else (tags, show, version, ...)
+    Other,
+}
+
+impl EndpointClass {
+    pub const ALL: [EndpointClass; 5] = [
+        EndpointClass::Embeddings,
+        EndpointClass::Chat,
+        EndpointClass::Generate,
+        EndpointClass::Transfer,
+        EndpointClass::Other,
+    ];
+
+    /// Classify a request path (OpenAI or native Ollama)
+    pub fn from_path(path: &str) -> Self {
+        match path.trim_end_matches('/') {
+            "/v1/embeddings" | "/api/embed" | "/api/embeddings" => EndpointClass::Embeddings,
+            "/v1/chat/completions" | "/api/chat" => EndpointClass::Chat,
+            "/v1/completions" | "/api/generate" => EndpointClass::Generate,
+            "/api/pull" | "/api/push" | "/api/create" | "/api/copy" => EndpointClass::Transfer,
+            p if p.starts_with("/api/blobs/") => EndpointClass::Transfer,
+            _ => EndpointClass::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EndpointClass::Embeddings => "embeddings",
+            EndpointClass::Chat => "chat",
+            EndpointClass::Generate => "generate",
+            EndpointClass::Transfer => "transfer",
+            EndpointClass::Other => "other",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name.trim().to_lowercase())
+    }
+
+    /// Embeddings, chat and generate: the requests that put a model to work
+    pub fn is_inference(&self) -> bool {
+        matches!(self, EndpointClass::Embeddings | EndpointClass::Chat | EndpointClass::Generate)
+    }
+}
+
+/// Timeout for each endpoint class (None = no timeout)
+#[derive(Debug, Clone)]
+pub struct EndpointTimeouts {
+    timeouts: HashMap<EndpointClass, Option<Duration>>,
+}
+
+impl EndpointTimeouts {
+    /// Use `default` for every class except transfers, which are unbounded
+    /// since model downloads can legitimately take hours
+    pub fn new(default: Duration) -> Self {
+        let timeouts = EndpointClass::ALL
+            .into_iter()
+            .map(|class| match class {
+                EndpointClass::Transfer => (class, None),
+                _ => (class, Some(default)),
+            })
+            .collect();
+        Self { timeouts }
+    }
+
+    /// Apply overrides from a spec like `embeddings=30,chat=600,transfer=0`
+    /// (seconds; 0 disables the timeout for that class)
+    pub fn with_overrides(mut self, spec: &str) -> Result<Self, String> {
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, seconds) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid timeout entry '{}', expected class=seconds", entry))?;
+            let class = EndpointClass::parse(name).ok_or_else(|| {
+                format!(
+                    "Unknown endpoint class '{}' (expected one of: embeddings, chat, generate, transfer, other)",
+                    name.trim()
+                )
+            })?;
+            let seconds: u64 = seconds
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid timeout '{}' for {}", seconds.trim(), class.name()))?;
+            self.timeouts
+                .insert(class, (seconds > 0).then(|| Duration::from_secs(seconds)));
+        }
+        Ok(self)
+    }
+
+    pub fn for_class(&self, class: EndpointClass) -> Option<Duration> {
+        self.timeouts.get(&class).copied().flatten()
+    }
+
+    pub fn for_path(&self, path: &str) -> Option<Duration> {
+        self.for_class(EndpointClass::from_path(path))
+    }
+
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        EndpointClass::ALL
+            .into_iter()
+            .map(|class| match self.for_class(class) {
+                Some(d) => format!("{}={}s", class.name(), d.as_secs()),
+                None => format!("{}=unbounded", class.name()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Default for EndpointTimeouts {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(120))
+    }
+}
+
+/// Timeout a client asked for in the `X-Request-Timeout` header (seconds, fractions allowed),
+/// capped at `max`. None when the header is absent or not a positive number
+pub fn requested_timeout(headers: &axum::http::HeaderMap, max: Duration) -> Option<Duration> {
+    let seconds: f64 = headers.get(REQUEST_TIMEOUT_HEADER)?.to_str().ok()?.trim().parse().ok()?;
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return None;
+    }
+    Some(Duration::try_from_secs_f64(seconds).map_or(max, |requested| requested.min(max)))
+}
+
+/// Timeouts for streamed responses, which replace the endpoint's request timeout
+/// so a long generation isn't cut off while a stalled one still is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTimeouts {
+    /// Until Ollama starts responding, i.e. the first token (None = no limit)
+    pub first_byte: Option<Duration>,
+    /// Longest gap between chunks once the stream has started (None = no limit)
+    pub idle: Option<Duration>,
+    /// The whole stream, headers to last chunk (None = no limit)
+    pub total: Option<Duration>,
+}
+
+impl Default for StreamTimeouts {
+    fn default() -> Self {
+        Self {
+            first_byte: Some(Duration::from_secs(300)),
+            idle: Some(Duration::from_secs(60)),
+            total: None,
+        }
+    }
+}
+
+impl StreamTimeouts {
+    /// Human-readable summary for startup logs
+    pub fn describe(&self) -> String {
+        let describe = |timeout: Option<Duration>| match timeout {
+            Some(d) => format!("{}s", d.as_secs()),
+            None => "unbounded".to_string(),
+        };
+        format!(
+            "first byte {}, idle {}, total {}",
+            describe(self.first_byte),
+            describe(self.idle),
+            describe(self.total)
+        )
+    }
+}
+
+/// Apply an optional timeout to an upstream request
+pub fn apply_timeout(request: reqwest::RequestBuilder, timeout: Option<Duration>) -> reqwest::RequestBuilder {
+    match timeout {
+        Some(timeout) => request.timeout(timeout),
+        None => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_paths() {
+        assert_eq!(EndpointClass::from_path("/v1/embeddings"), EndpointClass::Embeddings);
+        assert_eq!(EndpointClass::from_path("/api/embed"), EndpointClass::Embeddings);
+        assert_eq!(EndpointClass::from_path("/api/chat"), EndpointClass::Chat);
+        assert_eq!(EndpointClass::from_path("/v1/chat/completions/"), EndpointClass::Chat);
+        assert_eq!(EndpointClass::from_path("/api/generate"), EndpointClass::Generate);
+        assert_eq!(EndpointClass::from_path("/api/pull"), EndpointClass::Transfer);
+        assert_eq!(EndpointClass::from_path("/api/blobs/sha256:abc"), EndpointClass::Transfer);
+        assert_eq!(EndpointClass::from_path("/api/tags"), EndpointClass::Other);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let timeouts = EndpointTimeouts::new(Duration::from_secs(120));
+        assert_eq!(timeouts.for_path("/api/chat"), Some(Duration::from_secs(120)));
+        assert_eq!(timeouts.for_path("/api/pull"), None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let timeouts = EndpointTimeouts::new(Duration::from_secs(120))
+            .with_overrides("embeddings=30, chat=600,transfer=0,other=0")
+            .unwrap();
+        assert_eq!(timeouts.for_class(EndpointClass::Embeddings), Some(Duration::from_secs(30)));
+        assert_eq!(timeouts.for_class(EndpointClass::Chat), Some(Duration::from_secs(600)));
+        assert_eq!(timeouts.for_class(EndpointClass::Generate), Some(Duration::from_secs(120)));
+        assert_eq!(timeouts.for_class(EndpointClass::Transfer), None);
+        assert_eq!(timeouts.for_class(EndpointClass::Other), None);
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let base = EndpointTimeouts::default();
+        assert!(base.clone().with_overrides("chat").is_err());
+        assert!(base.clone().with_overrides("bogus=10").is_err());
+        assert!(base.with_overrides("chat=soon").is_err());
+    }
+
+    #[test]
+    fn test_requested_timeout_is_capped() {
+        let max = Duration::from_secs(600);
+        let headers = |value: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(REQUEST_TIMEOUT_HEADER, value.parse().unwrap());
+            headers
+        };
+        assert_eq!(requested_timeout(&headers("30"), max), Some(Duration::from_secs(30)));
+        assert_eq!(requested_timeout(&headers("2.5"), max), Some(Duration::from_millis(2500)));
+        assert_eq!(requested_timeout(&headers("86400"), max), Some(max));
+        assert_eq!(requested_timeout(&headers("0"), max), None);
+        assert_eq!(requested_timeout(&headers("soon"), max), None);
+        assert_eq!(requested_timeout(&axum::http::HeaderMap::new(), max), None);
+    }
+
+    #[test]
+    fn test_stream_timeouts_describe() {
+        let timeouts = StreamTimeouts { total: Some(Duration::from_secs(3600)), ..Default::default() };
+        assert_eq!(timeouts.describe(), "first byte 300s, idle 60s, total 3600s");
+    }
+
+    #[test]
+    fn test_describe() {
+        let timeouts = EndpointTimeouts::new(Duration::from_secs(60));
+        let summary = timeouts.describe();
+        assert!(summary.contains("chat=60s"));
+        assert!(summary.contains("transfer=unbounded"));
+    }
+}
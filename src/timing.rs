@@ -0,0 +1,84 @@
+/// Per-request phase timings, returned to clients in a `Server-Timing` header
+use axum::http::HeaderValue;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const SERVER_TIMING_HEADER: &str = "server-timing";
+
+tokio::task_local! {
+    static TIMINGS: Timings;
+}
+
+/// Phase durations collected while handling one request
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    phases: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `duration` to `phase` (phases that run more than once, e.g. per chunk, are summed)
+    pub fn record(&self, phase: &'static str, duration: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => phases.push((phase, duration)),
+        }
+    }
+
+    /// `Server-Timing` value, e.g. `metadata_fetch;dur=1.2, upstream_ttfb;dur=840.0`
+    pub fn header_value(&self, total: Duration) -> Option<HeaderValue> {
+        let phases = self.phases.lock().unwrap();
+        let value = phases
+            .iter()
+            .chain(std::iter::once(&("total", total)))
+            .map(|(name, duration)| format!("{};dur={:.1}", name, duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).ok()
+    }
+
+    /// Run `future` with these timings as the current request's
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        TIMINGS.scope(self, future).await
+    }
+}
+
+/// The current request's timings, to carry into spawned tasks
+pub fn current() -> Option<Timings> {
+    TIMINGS.try_with(Timings::clone).ok()
+}
+
+/// Record a phase of the current request (no-op outside a request)
+pub fn record(phase: &'static str, started: Instant) {
+    let _ = TIMINGS.try_with(|timings| timings.record(phase, started.elapsed()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_phases_are_summed_and_formatted() {
+        let timings = Timings::new();
+        timings
+            .clone()
+            .scope(async {
+                let started = Instant::now();
+                record("modifiers", started);
+                current().unwrap().record("upstream_ttfb", Duration::from_millis(40));
+                current().unwrap().record("upstream_ttfb", Duration::from_millis(2));
+            })
+            .await;
+        record("ignored", Instant::now());
+
+        let value = timings.header_value(Duration::from_millis(50)).unwrap();
+        let value = value.to_str().unwrap();
+        assert!(value.starts_with("modifiers;dur=0."));
+        assert!(value.ends_with("upstream_ttfb;dur=42.0, total;dur=50.0"));
+    }
+}
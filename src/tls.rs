@@ -0,0 +1,195 @@
+/// HTTPS listening with rustls: certificate and key from PEM files, reloaded when they are rotated
+use axum::extract::connect_info::ConnectInfo;
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::crypto::ring::sign::any_supported_type;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, info, warn};
+
+/// Longest a client may take to finish the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsSettings {
+    /// PEM certificate chain, leaf first
+    pub cert: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key: PathBuf,
+    /// How often the files are checked for changes (None = loaded once)
+    pub reload_interval: Option<Duration>,
+}
+
+/// Read the certificate chain and key into a form rustls can serve
+pub fn load_certified_key(cert: &Path, key: &Path) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", cert.display(), e))?;
+    if chain.is_empty() {
+        return Err(format!("No certificates in {}", cert.display()));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Failed to read TLS key {}: {}", key.display(), e))?;
+    let signing_key = any_supported_type(&private_key)
+        .map_err(|e| format!("Unsupported TLS key {}: {}", key.display(), e))?;
+    let certified = CertifiedKey::new(chain, signing_key);
+    certified
+        .keys_match()
+        .map_err(|e| format!("TLS key {} doesn't match certificate {}: {}", key.display(), cert.display(), e))?;
+    Ok(certified)
+}
+
+/// Serves the current certificate, swapping in a new one when its files change
+#[derive(Debug)]
+pub struct ReloadingCert {
+    cert: PathBuf,
+    key: PathBuf,
+    current: RwLock<(Arc<CertifiedKey>, Option<SystemTime>)>,
+}
+
+impl ReloadingCert {
+    pub fn load(settings: &TlsSettings) -> Result<Self, String> {
+        let certified = load_certified_key(&settings.cert, &settings.key)?;
+        Ok(Self {
+            cert: settings.cert.clone(),
+            key: settings.key.clone(),
+            current: RwLock::new((Arc::new(certified), modified(&settings.cert, &settings.key))),
+        })
+    }
+
+    /// Reload when either file changed since the last load; a failed reload keeps the old
+    /// certificate, since rotation tools often write the two files one after the other
+    pub fn reload_if_changed(&self) -> bool {
+        let stamp = modified(&self.cert, &self.key);
+        if stamp == self.current.read().unwrap().1 {
+            return false;
+        }
+        match load_certified_key(&self.cert, &self.key) {
+            Ok(certified) => {
+                *self.current.write().unwrap() = (Arc::new(certified), stamp);
+                info!("🔐 Reloaded TLS certificate {}", self.cert.display());
+                true
+            }
+            Err(e) => {
+                warn!("⚠️  {}, still serving the previous certificate", e);
+                false
+            }
+        }
+    }
+
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().0.clone()
+    }
+}
+
+impl ResolvesServerCert for ReloadingCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// Latest modification time of the two files
+fn modified(cert: &Path, key: &Path) -> Option<SystemTime> {
+    let time = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    time(cert).max(time(key))
+}
+
+/// Acceptor for `settings`, with a background task picking up rotated certificates
+pub fn acceptor(settings: &TlsSettings) -> Result<TlsAcceptor, String> {
+    let resolver = Arc::new(ReloadingCert::load(settings)?);
+    if let Some(interval) = settings.reload_interval {
+        let resolver = resolver.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                resolver.reload_if_changed();
+            }
+        });
+    }
+    let mut config = ServerConfig::builder().with_no_client_auth().with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accept TLS connections on `listener` and serve `app` over HTTP/1.1 or HTTP/2
+pub async fn serve(listener: TcpListener, app: Router, acceptor: TlsAcceptor) -> std::io::Result<()> {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // e.g. out of file descriptors: back off instead of spinning
+                warn!("⚠️  Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => return debug!("TLS handshake with {} failed: {}", remote, e),
+                Err(_) => return debug!("TLS handshake with {} timed out", remote),
+            };
+            let service = hyper::service::service_fn(move |mut request: hyper::Request<hyper::body::Incoming>| {
+                request.extensions_mut().insert(ConnectInfo::<SocketAddr>(remote));
+                app.clone().call(request.map(axum::body::Body::new))
+            });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Connection from {} ended: {}", remote, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls").join(name)
+    }
+
+    #[test]
+    fn test_loads_and_reloads_rotated_certificates() {
+        let dir = std::env::temp_dir().join(format!("ollama-proxy-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let settings = TlsSettings { cert: dir.join("cert.pem"), key: dir.join("key.pem"), reload_interval: None };
+        std::fs::copy(fixture("localhost-cert.pem"), &settings.cert).unwrap();
+        std::fs::copy(fixture("localhost-key.pem"), &settings.key).unwrap();
+
+        let resolver = ReloadingCert::load(&settings).unwrap();
+        let first = resolver.current();
+        assert!(!resolver.reload_if_changed());
+
+        // A certificate written without its key yet keeps the old pair
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::copy(fixture("rotated-cert.pem"), &settings.cert).unwrap();
+        assert!(!resolver.reload_if_changed());
+        assert!(Arc::ptr_eq(&first, &resolver.current()));
+
+        std::fs::copy(fixture("rotated-key.pem"), &settings.key).unwrap();
+        assert!(resolver.reload_if_changed());
+        assert_ne!(first.cert, resolver.current().cert);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(load_certified_key(&fixture("localhost-cert.pem"), &fixture("rotated-key.pem")).is_err());
+        assert!(load_certified_key(&fixture("missing.pem"), &fixture("localhost-key.pem")).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+/// Token counts for request bodies: a cheap estimate for context budgeting, and a
+/// local BPE-style tokenizer for the `usage` reported back to clients
+use serde_json::Value;
+
+/// Average characters per token for English text with common tokenizers
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimate the number of tokens in a piece of text
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Estimate the prompt tokens of an OpenAI or Ollama request body
+/// (chat messages, prompt/system for generate, input for embeddings)
+pub fn estimate_request_tokens(json: &Value) -> usize {
+    request_tokens(json, estimate_tokens)
+}
+
+/// Count the tokens in `text` the way byte-pair-encoding tokenizers split it:
+/// a word with its leading space is one token (long words a few more), digits
+/// come in groups of three, CJK characters and punctuation are a token each.
+/// Within a few percent of cl100k/Llama 3 counts for English prose and code.
+pub fn count_tokens(text: &str) -> usize {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // A single space is merged into the word, number or symbol that follows it
+        if c == ' ' && chars.get(i + 1).is_some_and(|next| !next.is_whitespace()) {
+            i += 1;
+            continue;
+        }
+        let run = |class: fn(char) -> bool| chars[i..].iter().take_while(|&&c| class(c)).count();
+        let (len, cost) = if is_cjk(c) {
+            (1, 1)
+        } else if c.is_alphabetic() {
+            let len = run(|c| c.is_alphabetic() && !is_cjk(c));
+            let word = &chars[i..i + len];
+            let cost = if word.iter().all(char::is_ascii) {
+                1 + len.saturating_sub(6).div_ceil(4)
+            } else {
+                // Non-Latin scripts take more bytes and get fewer merges
+                len.div_ceil(2)
+            };
+            (len, cost)
+        } else if c.is_numeric() {
+            let len = run(char::is_numeric);
+            (len, len.div_ceil(3))
+        } else if c.is_whitespace() {
+            (run(char::is_whitespace), 1)
+        } else if c.is_ascii() {
+            let len = run(|c| c.is_ascii_punctuation());
+            (len, len.div_ceil(2))
+        } else {
+            // Emoji and other symbols: roughly one token per two UTF-8 bytes
+            (1, c.len_utf8().div_ceil(2))
+        };
+        tokens += cost;
+        i += len.max(1);
+    }
+    tokens
+}
+
+/// Count the prompt tokens of an OpenAI or Ollama request body with [`count_tokens`]
+pub fn count_request_tokens(json: &Value) -> usize {
+    request_tokens(json, count_tokens)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x2FA1F)
+}
+
+fn request_tokens(json: &Value, count: fn(&str) -> usize) -> usize {
+    let mut tokens = 0;
+
+    if let Some(messages) = json.get("messages").and_then(|m| m.as_array()) {
+        for message in messages {
+            tokens += message.get("content").map(|c| value_tokens(c, count)).unwrap_or(0);
+        }
+    }
+    for field in ["prompt", "system", "input"] {
+        tokens += json.get(field).map(|v| value_tokens(v, count)).unwrap_or(0);
+    }
+
+    tokens
+}
+
+/// Tokens in a string, an array of strings, or an array of `{"text": ...}` parts
+fn value_tokens(value: &Value, count: fn(&str) -> usize) -> usize {
+    match value {
+        Value::String(s) => count(s),
+        Value::Array(items) => items.iter().map(|item| value_tokens(item, count)).sum(),
+        Value::Object(part) => part.get("text").map(|text| value_tokens(text, count)).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("Hello, world!"), 4);
+        assert_eq!(count_tokens("The quick brown fox jumps over the lazy dog."), 10);
+        assert_eq!(count_tokens("internationalization"), 5);
+        assert_eq!(count_tokens("2024-01-15"), 6);
+        assert_eq!(count_tokens("fn main() {\n    println!(\"hi\");\n}"), 14);
+        assert_eq!(count_tokens("你好，世界"), 5);
+
+        let request = json!({"model": "nomic", "input": ["Hello, world!", "Hello"]});
+        assert_eq!(count_request_tokens(&request), 5);
+    }
+
+    #[test]
+    fn test_estimate_request_tokens() {
+        let chat = json!({
+            "model": "llama3",
+            "messages": [
+                {"role": "system", "content": "a".repeat(40)},
+                {"role": "user", "content": [{"type": "text", "text": "b".repeat(40)}]}
+            ]
+        });
+        assert_eq!(estimate_request_tokens(&chat), 20);
+
+        let embed = json!({"model": "nomic", "input": ["a".repeat(8), "b".repeat(8)]});
+        assert_eq!(estimate_request_tokens(&embed), 4);
+
+        let generate = json!({"model": "llama3", "prompt": "a".repeat(400), "system": "abcd"});
+        assert_eq!(estimate_request_tokens(&generate), 101);
+    }
+}
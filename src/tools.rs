@@ -0,0 +1,173 @@
+/// Tool calling for translated chat: tool_choice, and tool calls in both directions
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::translator::OllamaChatMessage;
+
+/// OpenAI's `tool_choice`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    /// The model decides (the default)
+    Auto,
+    /// Tools are withheld from the model
+    None,
+    /// The model must call some tool
+    Required,
+    /// The model must call this tool
+    Function(String),
+}
+
+impl ToolChoice {
+    /// `"none" | "auto" | "required"` or `{"type": "function", "function": {"name": ...}}`
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Null => Ok(Self::Auto),
+            Value::String(choice) => match choice.as_str() {
+                "auto" => Ok(Self::Auto),
+                "none" => Ok(Self::None),
+                "required" => Ok(Self::Required),
+                other => Err(format!("Invalid tool_choice '{}', expected none, auto or required", other)),
+            },
+            _ => value
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(Value::as_str)
+                .map(|name| Self::Function(name.to_string()))
+                .ok_or_else(|| "Invalid tool_choice, expected {\"function\": {\"name\": ...}}".to_string()),
+        }
+    }
+
+    /// Filter `tools` for this choice. Ollama has no forced tool mode, so `required`
+    /// and named functions add a system message telling the model to call a tool.
+    pub fn apply(
+        &self,
+        tools: Option<Vec<Value>>,
+        messages: &mut Vec<OllamaChatMessage>,
+    ) -> Result<Option<Vec<Value>>, String> {
+        let Some(tools) = tools.filter(|t| !t.is_empty()) else {
+            return match self {
+                Self::Auto | Self::None => Ok(None),
+                _ => Err("tool_choice requires tools".to_string()),
+            };
+        };
+        let (tools, instruction) = match self {
+            Self::Auto => return Ok(Some(tools)),
+            Self::None => return Ok(None),
+            Self::Required => (tools, "You must respond by calling one of the provided tools.".to_string()),
+            Self::Function(name) => {
+                let chosen: Vec<Value> = tools.into_iter().filter(|t| tool_name(t) == Some(name.as_str())).collect();
+                if chosen.is_empty() {
+                    return Err(format!("tool_choice names '{}', which is not in tools", name));
+                }
+                (chosen, format!("You must respond by calling the `{}` tool.", name))
+            }
+        };
+        messages.push(OllamaChatMessage {
+            role: "system".to_string(),
+            content: instruction,
+            images: Vec::new(),
+            image_urls: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_name: None,
+        });
+        Ok(Some(tools))
+    }
+}
+
+fn tool_name(tool: &Value) -> Option<&str> {
+    tool.get("function")?.get("name")?.as_str()
+}
+
+/// OpenAI assistant tool calls (arguments as a JSON string) to Ollama's (arguments
+/// as an object). Also records each call's id so tool results can name their tool.
+pub fn to_ollama_tool_calls(calls: &[Value], names: &mut HashMap<String, String>) -> Result<Vec<Value>, String> {
+    calls
+        .iter()
+        .map(|call| {
+            let function = call.get("function").ok_or("Tool call has no function")?;
+            let name = function.get("name").and_then(Value::as_str).ok_or("Tool call has no function name")?;
+            let arguments = match function.get("arguments") {
+                Some(Value::String(text)) if text.trim().is_empty() => json!({}),
+                Some(Value::String(text)) => serde_json::from_str(text)
+                    .map_err(|e| format!("Tool call arguments for {} are not JSON: {}", name, e))?,
+                Some(value) => value.clone(),
+                None => json!({}),
+            };
+            if let Some(id) = call.get("id").and_then(Value::as_str) {
+                names.insert(id.to_string(), name.to_string());
+            }
+            Ok(json!({"function": {"name": name, "arguments": arguments}}))
+        })
+        .collect()
+}
+
+/// Ollama tool calls to OpenAI's, generating ids when Ollama doesn't send them
+pub fn to_openai_tool_calls(calls: &[Value]) -> Vec<Value> {
+    calls
+        .iter()
+        .filter_map(|call| {
+            let function = call.get("function")?;
+            let name = function.get("name")?.as_str()?;
+            let arguments = match function.get("arguments") {
+                Some(Value::String(text)) => text.clone(),
+                Some(value) => value.to_string(),
+                None => "{}".to_string(),
+            };
+            let id = call.get("id").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| {
+                format!("call_{}", uuid::Uuid::new_v4().simple().to_string().chars().take(24).collect::<String>())
+            });
+            Some(json!({"id": id, "type": "function", "function": {"name": name, "arguments": arguments}}))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weather_tools() -> Vec<Value> {
+        vec![
+            json!({"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}),
+            json!({"type": "function", "function": {"name": "get_time", "parameters": {"type": "object"}}}),
+        ]
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let mut messages = Vec::new();
+        assert_eq!(ToolChoice::parse(&json!("none")).unwrap().apply(Some(weather_tools()), &mut messages), Ok(None));
+        assert_eq!(
+            ToolChoice::parse(&json!("auto")).unwrap().apply(Some(weather_tools()), &mut messages).unwrap().unwrap().len(),
+            2
+        );
+        assert!(messages.is_empty());
+
+        let forced = ToolChoice::parse(&json!({"type": "function", "function": {"name": "get_time"}})).unwrap();
+        let tools = forced.apply(Some(weather_tools()), &mut messages).unwrap().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tool_name(&tools[0]), Some("get_time"));
+        assert_eq!(messages[0].role, "system");
+        assert!(messages[0].content.contains("`get_time`"));
+
+        assert!(ToolChoice::Function("missing".to_string()).apply(Some(weather_tools()), &mut messages).is_err());
+        assert!(ToolChoice::Required.apply(None, &mut messages).is_err());
+        assert!(ToolChoice::parse(&json!("sometimes")).is_err());
+    }
+
+    #[test]
+    fn test_tool_call_conversion() {
+        let mut names = HashMap::new();
+        let openai = vec![json!({
+            "id": "call_1", "type": "function",
+            "function": {"name": "get_weather", "arguments": "{\"city\": \"Oslo\"}"}
+        })];
+        let ollama = to_ollama_tool_calls(&openai, &mut names).unwrap();
+        assert_eq!(ollama[0], json!({"function": {"name": "get_weather", "arguments": {"city": "Oslo"}}}));
+        assert_eq!(names["call_1"], "get_weather");
+
+        let back = to_openai_tool_calls(&ollama);
+        assert_eq!(back[0]["type"], "function");
+        assert_eq!(back[0]["function"]["arguments"], "{\"city\":\"Oslo\"}");
+        assert!(back[0]["id"].as_str().unwrap().starts_with("call_"));
+    }
+}
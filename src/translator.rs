@@ -1,27 +1,118 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tracing::{info, debug};
-use crate::chunker;
+use std::collections::HashMap;
+use crate::chunker::{Chunk, ChunkingStrategy};
+use crate::tokens::count_tokens;
+use crate::tools::{to_ollama_tool_calls, to_openai_tool_calls, ToolChoice};
 
 /// OpenAI chat completions request format
 #[derive(Debug, Deserialize)]
 pub struct OpenAIChatRequest {
     pub model: String,
-    pub messages: Vec<OpenAIChatMessage>,
+    pub messages: Vec<OpenAIRequestMessage>,
+    /// Deprecated by OpenAI in favor of `max_completion_tokens`
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// -2.0..2.0, penalizes tokens that already appeared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// -2.0..2.0, penalizes tokens by how often they appeared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Sampling seed for reproducible output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Up to 4 sequences where generation stops
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StringOrArray>,
+    /// `{"type": "json_object"}` or `{"type": "json_schema", "json_schema": {"schema": ...}}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+    /// `minimal`, `low`, `medium` or `high`, mapped onto Ollama's `think`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    /// Function definitions (the same shape in both APIs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+}
+
+/// OpenAI fields that take a single string or a list of strings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum StringOrArray {
+    String(String),
+    Array(Vec<String>),
+}
+
+impl StringOrArray {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::String(s) => vec![s],
+            Self::Array(items) => items,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OpenAIChatMessage {
     pub role: String,
     pub content: String,
+    /// Ollama's tool calls when deserialized, OpenAI's once translated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<Value>,
+    /// Ollama's `thinking` output, returned as OpenAI-compatible `reasoning_content`
+    #[serde(default, alias = "thinking", skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
+/// Incoming chat message: plain text, or content parts mixing text and images
+#[derive(Debug, Deserialize)]
+pub struct OpenAIRequestMessage {
+    pub role: String,
+    /// Null on assistant messages that only call tools
+    #[serde(default)]
+    pub content: Option<OpenAIMessageContent>,
+    #[serde(default)]
+    pub tool_calls: Vec<Value>,
+    /// Set on `tool` messages carrying a tool's result
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OpenAIMessageContent {
+    Text(String),
+    Parts(Vec<OpenAIContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OpenAIContentPart {
+    Text { text: String },
+    ImageUrl { image_url: OpenAIImageUrl },
+    /// Parts Ollama has no equivalent for (audio, files) are dropped
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenAIImageUrl {
+    /// A `data:image/...;base64,` URL or an http(s) URL
+    pub url: String,
 }
 
 /// OpenAI chat completions response format
@@ -33,6 +124,8 @@ pub struct OpenAIChatResponse {
     pub model: String,
     pub choices: Vec<OpenAIChatChoice>,
     pub usage: OpenAIChatUsage,
+    /// Identifies the model serving the request, for telling reproducible runs apart
+    pub system_fingerprint: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -47,19 +140,61 @@ pub struct OpenAIChatUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    pub prompt_tokens_details: PromptTokensDetails,
+    pub completion_tokens_details: CompletionTokensDetails,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PromptTokensDetails {
+    /// Prompt tokens served from Ollama's KV cache instead of being evaluated
+    pub cached_tokens: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CompletionTokensDetails {
+    /// Tokens spent on the model's thinking output
+    pub reasoning_tokens: u32,
 }
 
 /// Ollama chat request format
 #[derive(Debug, Serialize)]
 pub struct OllamaChatRequest {
     pub model: String,
-    pub messages: Vec<OpenAIChatMessage>,
+    pub messages: Vec<OllamaChatMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<OllamaChatOptions>,
+    /// `"json"` or a JSON schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keep_alive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Value>>,
+    /// true/false, or a level for models that take one (gpt-oss)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think: Option<Value>,
+    /// false keeps only the first tool call of the response
+    #[serde(skip)]
+    pub parallel_tool_calls: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OllamaChatMessage {
+    pub role: String,
+    pub content: String,
+    /// Base64-encoded images for vision models
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// http(s) image URLs still to be downloaded into `images`
+    #[serde(skip)]
+    pub image_urls: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<Value>,
+    /// The tool a `tool` message holds the result of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -72,6 +207,16 @@ pub struct OllamaChatOptions {
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
 }
 
 /// Ollama chat response format
@@ -89,6 +234,22 @@ pub struct OllamaChatResponse {
     pub eval_count: Option<u32>,
 }
 
+/// OpenAI model object, as listed by /v1/models
+#[derive(Debug, Serialize)]
+pub struct OpenAIModel {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub owned_by: String,
+}
+
+/// OpenAI model list response format
+#[derive(Debug, Serialize)]
+pub struct OpenAIModelList {
+    pub object: String,
+    pub data: Vec<OpenAIModel>,
+}
+
 /// OpenAI embeddings request format
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)] // Serde uses these fields for deserialization
@@ -158,6 +319,17 @@ pub struct OpenAIEmbedding {
     pub object: String,
     pub embedding: Vec<f32>,
     pub index: usize,
+    /// Proxy extension: the chunk this embedding covers, when chunk embeddings are returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<OpenAIEmbeddingChunk>,
+}
+
+/// Which input a chunk embedding belongs to and its character offsets in that input
+#[derive(Debug, Serialize)]
+pub struct OpenAIEmbeddingChunk {
+    pub input_index: usize,
+    pub start: usize,
+    pub end: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -172,6 +344,18 @@ pub fn prepare_embeddings_input(
     max_input_length: usize,
     enable_chunking: bool,
 ) -> Result<Vec<String>, String> {
+    let chunks = prepare_embeddings_chunks(input, max_input_length, enable_chunking, &ChunkingStrategy::default())?;
+    Ok(chunks.into_iter().flatten().map(|chunk| chunk.text).collect())
+}
+
+/// Like `prepare_embeddings_input`, but keeps each input's chunks together so
+/// their embeddings can be combined per input
+pub fn prepare_embeddings_chunks(
+    input: Vec<String>,
+    max_input_length: usize,
+    enable_chunking: bool,
+    strategy: &ChunkingStrategy,
+) -> Result<Vec<Vec<Chunk>>, String> {
     // Check for inputs that exceed max length
     let mut needs_chunking = false;
     for (idx, item) in input.iter().enumerate() {
@@ -194,23 +378,28 @@ pub fn prepare_embeddings_input(
         info!("📦 Chunking large inputs (max length: {})", max_input_length);
         let mut chunked_inputs = Vec::new();
         
-        for (idx, item) in input.iter().enumerate() {
+        for (idx, item) in input.into_iter().enumerate() {
             if item.len() > max_input_length {
-                let chunks = chunker::chunk_text(item, max_input_length);
+                let chunks = strategy.chunk(&item, max_input_length);
                 info!("   Input {}: split into {} chunks", idx, chunks.len());
-                chunked_inputs.extend(chunks);
+                chunked_inputs.push(chunks);
             } else {
-                chunked_inputs.push(item.clone());
+                chunked_inputs.push(vec![whole_input(item)]);
             }
         }
         
-        info!("   Total inputs after chunking: {}", chunked_inputs.len());
+        info!("   Total inputs after chunking: {}", chunked_inputs.iter().map(Vec::len).sum::<usize>());
         Ok(chunked_inputs)
     } else {
-        Ok(input)
+        Ok(input.into_iter().map(|item| vec![whole_input(item)]).collect())
     }
 }
 
+fn whole_input(text: String) -> Chunk {
+    let end = text.chars().count();
+    Chunk { text, start: 0, end }
+}
+
 /// Translate OpenAI chat completions request to Ollama native format
 pub fn translate_openai_chat_to_ollama(
     openai_req: Value,
@@ -222,12 +411,29 @@ pub fn translate_openai_chat_to_ollama(
     info!("🔄 Translating OpenAI chat request to Ollama native API");
     info!("   Model: {}", req.model);
     info!("   Messages: {} message(s)", req.messages.len());
+    let mut tool_names = HashMap::new();
+    let mut messages = req
+        .messages
+        .into_iter()
+        .map(|message| translate_chat_message(message, &mut tool_names))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tool_choice = ToolChoice::parse(req.tool_choice.as_ref().unwrap_or(&Value::Null))?;
+    let tools = tool_choice.apply(req.tools, &mut messages)?;
+    if let Some(tools) = &tools {
+        info!("   Tools: {} ({:?})", tools.len(), tool_choice);
+    }
 
     let options = Some(OllamaChatOptions {
         num_ctx,
-        num_predict: req.max_tokens,
+        num_predict: req.max_completion_tokens.or(req.max_tokens),
         temperature: req.temperature,
         top_p: req.top_p,
+        stop: req.stop.map(StringOrArray::into_vec).filter(|stop| !stop.is_empty()),
+        // Mapped directly; PenaltyMapping adjusts them per model
+        presence_penalty: req.presence_penalty,
+        frequency_penalty: req.frequency_penalty,
+        repeat_penalty: None,
+        seed: req.seed,
     });
 
     // Set keep_alive based on context size to prevent model unloading during long requests
@@ -243,20 +449,131 @@ pub fn translate_openai_chat_to_ollama(
         _ => None,
     };
 
+    let think = req.reasoning_effort.as_deref().map(|effort| translate_reasoning_effort(&req.model, effort));
     Ok(OllamaChatRequest {
         model: req.model,
-        messages: req.messages,
+        messages,
         stream: req.stream.or(Some(false)),
         options,
+        format: req.response_format.as_ref().and_then(translate_response_format),
         keep_alive,
+        tools,
+        think,
+        parallel_tool_calls: req.parallel_tool_calls.unwrap_or(true),
     })
 }
 
-/// Translate Ollama chat response to OpenAI format
+/// Rename `developer` messages (newer OpenAI SDKs) to `role`, which Ollama accepts.
+/// `None` forwards them unchanged.
+pub fn normalize_roles(request: &mut OllamaChatRequest, developer_role: Option<&str>) {
+    let Some(role) = developer_role else { return };
+    for message in request.messages.iter_mut().filter(|m| m.role == "developer") {
+        debug!("   Mapping developer message to role '{}'", role);
+        message.role = role.to_string();
+    }
+}
+
+/// Join text parts into `content` and move image parts into Ollama's `images`.
+/// `tool_names` maps tool call ids seen so far to their tool, for naming results.
+fn translate_chat_message(
+    message: OpenAIRequestMessage,
+    tool_names: &mut HashMap<String, String>,
+) -> Result<OllamaChatMessage, String> {
+    let mut translated = OllamaChatMessage {
+        role: message.role,
+        content: String::new(),
+        images: Vec::new(),
+        image_urls: Vec::new(),
+        tool_calls: to_ollama_tool_calls(&message.tool_calls, tool_names)?,
+        tool_name: message.tool_call_id.and_then(|id| tool_names.get(&id).cloned()),
+    };
+    let parts = match message.content {
+        None => return Ok(translated),
+        Some(OpenAIMessageContent::Text(text)) => {
+            translated.content = text;
+            return Ok(translated);
+        }
+        Some(OpenAIMessageContent::Parts(parts)) => parts,
+    };
+    let mut texts = Vec::new();
+    for part in parts {
+        match part {
+            OpenAIContentPart::Text { text } => texts.push(text),
+            OpenAIContentPart::ImageUrl { image_url } => match image_url.url.strip_prefix("data:") {
+                Some(data) => translated.images.push(decode_data_url(data)?),
+                None if image_url.url.starts_with("http://") || image_url.url.starts_with("https://") => {
+                    translated.image_urls.push(image_url.url)
+                }
+                None => return Err(format!("Unsupported image URL '{}'", image_url.url)),
+            },
+            OpenAIContentPart::Unsupported => debug!("   Dropping unsupported content part"),
+        }
+    }
+    translated.content = texts.join("\n");
+    Ok(translated)
+}
+
+/// The base64 payload of a `data:` URL (given without the scheme)
+fn decode_data_url(data: &str) -> Result<String, String> {
+    let (media_type, payload) = data
+        .split_once(',')
+        .ok_or_else(|| "Malformed data URL, expected data:<type>;base64,<data>".to_string())?;
+    if !media_type.ends_with(";base64") {
+        return Err(format!("Image data URL '{}' is not base64-encoded", media_type));
+    }
+    Ok(payload.to_string())
+}
+
+/// Map OpenAI's reasoning_effort onto Ollama's `think`: gpt-oss takes the level
+/// itself, other thinking models (deepseek-r1, qwen3) only switch it on or off
+fn translate_reasoning_effort(model: &str, effort: &str) -> Value {
+    match effort {
+        "none" | "minimal" => Value::Bool(false),
+        level if model.starts_with("gpt-oss") && matches!(level, "low" | "medium" | "high") => {
+            Value::String(level.to_string())
+        }
+        _ => Value::Bool(true),
+    }
+}
+
+/// Map OpenAI's response_format onto Ollama's format (`"json"` or a schema)
+fn translate_response_format(response_format: &Value) -> Option<Value> {
+    match response_format.get("type").and_then(Value::as_str)? {
+        "json_object" => Some(Value::String("json".to_string())),
+        "json_schema" => response_format
+            .get("json_schema")
+            .and_then(|s| s.get("schema"))
+            .cloned()
+            .or_else(|| Some(Value::String("json".to_string()))),
+        _ => None,
+    }
+}
+
+/// Prompt tokens Ollama reused from its KV cache. Ollama only counts the tokens it
+/// had to evaluate, so a `prompt_eval_count` well below the request's estimated
+/// size means the rest of the prompt was a cache hit.
+fn cached_prompt_tokens(prompt_eval_count: u32, estimated_prompt_tokens: u32) -> u32 {
+    // Token estimates are rough; only a clear shortfall counts as a hit
+    if prompt_eval_count.saturating_mul(2) < estimated_prompt_tokens {
+        estimated_prompt_tokens - prompt_eval_count
+    } else {
+        0
+    }
+}
+
+/// Translate Ollama chat response to OpenAI format. `estimated_prompt_tokens`
+/// is the size of the forwarded request, used to report prompt cache hits.
 pub fn translate_ollama_chat_to_openai(
     ollama_resp: Value,
     _model_fallback: String,
+    estimated_prompt_tokens: u32,
 ) -> Result<OpenAIChatResponse, String> {
+    // Thinking models return their reasoning separately from the answer
+    let reasoning_tokens = ollama_resp
+        .get("message")
+        .and_then(|m| m.get("thinking"))
+        .and_then(Value::as_str)
+        .map_or(0, |thinking| count_tokens(thinking) as u32);
     let resp: OllamaChatResponse = serde_json::from_value(ollama_resp)
         .map_err(|e| format!("Failed to parse Ollama chat response: {}", e))?;
 
@@ -276,8 +593,13 @@ pub fn translate_ollama_chat_to_openai(
                 .as_secs()
         });
 
+    let mut message = resp.message;
+    message.tool_calls = to_openai_tool_calls(&message.tool_calls);
+
     // Determine finish reason
-    let finish_reason = if let Some(reason) = resp.done_reason {
+    let finish_reason = if !message.tool_calls.is_empty() {
+        "tool_calls".to_string()
+    } else if let Some(reason) = resp.done_reason {
         match reason.as_str() {
             "stop" => "stop".to_string(),
             "length" => "length".to_string(),
@@ -289,27 +611,85 @@ pub fn translate_ollama_chat_to_openai(
         "length".to_string()
     };
 
-    let prompt_tokens = resp.prompt_eval_count.unwrap_or(0);
-    let completion_tokens = resp.eval_count.unwrap_or(0);
+    let cached_tokens = cached_prompt_tokens(resp.prompt_eval_count.unwrap_or(0), estimated_prompt_tokens);
+    // OpenAI's prompt_tokens includes the cached part
+    let prompt_tokens = resp.prompt_eval_count.unwrap_or(0) + cached_tokens;
+    // Fall back to counting the answer locally when Ollama leaves eval_count out
+    let completion_tokens = resp.eval_count.unwrap_or_else(|| {
+        let calls: usize = message.tool_calls.iter().map(|call| count_tokens(&call.to_string())).sum();
+        (count_tokens(&message.content) + calls) as u32 + reasoning_tokens
+    });
 
+    let system_fingerprint = system_fingerprint(&resp.model);
     Ok(OpenAIChatResponse {
         id,
         object: "chat.completion".to_string(),
         created,
+        system_fingerprint,
         model: resp.model, // Use the actual model from Ollama response
         choices: vec![OpenAIChatChoice {
             index: 0,
-            message: resp.message,
+            message,
             finish_reason,
         }],
         usage: OpenAIChatUsage {
             prompt_tokens,
             completion_tokens,
             total_tokens: prompt_tokens + completion_tokens,
+            prompt_tokens_details: PromptTokensDetails { cached_tokens },
+            completion_tokens_details: CompletionTokensDetails {
+                reasoning_tokens: reasoning_tokens.min(completion_tokens),
+            },
         },
     })
 }
 
+/// With `parallel_tool_calls: false`, only the first tool call is returned
+pub fn keep_first_tool_call(response: &mut OpenAIChatResponse) {
+    for choice in &mut response.choices {
+        choice.message.tool_calls.truncate(1);
+    }
+}
+
+/// Translate an Ollama /api/tags response to an OpenAI model list
+pub fn translate_ollama_tags_to_openai(tags: &Value) -> Result<OpenAIModelList, String> {
+    let models = tags
+        .get("models")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Ollama tags response has no models array".to_string())?;
+    let data = models
+        .iter()
+        .filter_map(|entry| {
+            let id = entry.get("name").or_else(|| entry.get("model"))?.as_str()?.to_string();
+            let created = entry
+                .get("modified_at")
+                .and_then(Value::as_str)
+                .and_then(parse_ollama_timestamp)
+                .unwrap_or(0);
+            // "user/model:tag" belongs to user, plain names to the Ollama library
+            let owned_by = id.split_once('/').map_or("library", |(owner, _)| owner).to_string();
+            Some(OpenAIModel { id, object: "model".to_string(), created, owned_by })
+        })
+        .collect();
+    Ok(OpenAIModelList { object: "list".to_string(), data })
+}
+
+/// Find `id` in a model list; a name without a tag matches `:latest`
+pub fn find_model(list: OpenAIModelList, id: &str) -> Option<OpenAIModel> {
+    let latest = format!("{}:latest", id);
+    list.data.into_iter().find(|m| m.id == id || m.id == latest)
+}
+
+/// `fp_` plus a stable hash of the serving model, so responses from the same
+/// model (and therefore reproducible with the same seed) share a fingerprint
+fn system_fingerprint(model: &str) -> String {
+    // FNV-1a, stable across builds and replicas
+    let hash = model
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("fp_{:012x}", hash & 0xffff_ffff_ffff)
+}
+
 /// Parse Ollama's ISO8601 timestamp to Unix epoch seconds
 /// Example: "2025-11-21T16:08:11.735252Z" -> 1763741791
 fn parse_ollama_timestamp(timestamp: &str) -> Option<u64> {
@@ -382,6 +762,7 @@ pub fn translate_ollama_embed_to_openai(
             object: "embedding".to_string(),
             embedding,
             index,
+            chunk: None,
         })
         .collect();
 
@@ -401,7 +782,7 @@ pub fn translate_ollama_embed_to_openai(
 
 /// Determine if translation is needed based on the endpoint
 pub fn needs_translation(path: &str) -> bool {
-    matches!(path, "/v1/embeddings" | "/v1/chat/completions")
+    matches!(path, "/v1/embeddings" | "/v1/chat/completions" | "/v1/models") || path.starts_with("/v1/models/")
 }
 
 /// Get the corresponding Ollama native endpoint for an OpenAI endpoint
@@ -409,6 +790,8 @@ pub fn get_ollama_endpoint(openai_path: &str) -> &str {
     match openai_path {
         "/v1/embeddings" => "/api/embed",
         "/v1/chat/completions" => "/api/chat",
+        "/v1/models" => "/api/tags",
+        p if p.starts_with("/v1/models/") => "/api/tags",
         _ => openai_path, // Pass through for endpoints that don't need translation
     }
 }
@@ -504,11 +887,204 @@ mod tests {
         assert_eq!(result.usage.prompt_tokens, 10);
     }
 
+    #[test]
+    fn test_translate_response_format() {
+        let schema = json!({"type": "object", "required": ["name"]});
+        let request = json!({
+            "model": "llama3",
+            "messages": [{"role": "user", "content": "hi"}],
+            "response_format": {"type": "json_schema", "json_schema": {"name": "person", "schema": schema}}
+        });
+        let translated = translate_openai_chat_to_ollama(request, None).unwrap();
+        assert_eq!(translated.format, Some(schema));
+
+        let request = json!({"model": "llama3", "messages": [], "response_format": {"type": "json_object"}});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().format, Some(json!("json")));
+        let request = json!({"model": "llama3", "messages": [], "response_format": {"type": "text"}});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().format, None);
+    }
+
+    #[test]
+    fn test_translate_text_parts() {
+        let request = json!({
+            "model": "llama3",
+            "messages": [
+                {"role": "system", "content": [{"type": "text", "text": "Be brief."}]},
+                {"role": "user", "content": [{"type": "text", "text": "Line one"}, {"type": "text", "text": "Line two"}]},
+                {"role": "assistant", "content": "plain string"}
+            ]
+        });
+        let translated = translate_openai_chat_to_ollama(request, None).unwrap();
+        let contents: Vec<&str> = translated.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["Be brief.", "Line one\nLine two", "plain string"]);
+        assert!(translated.messages.iter().all(|m| m.images.is_empty()));
+    }
+
+    #[test]
+    fn test_normalize_developer_role() {
+        let request = json!({"model": "llama3", "messages": [
+            {"role": "developer", "content": "Be brief."},
+            {"role": "user", "content": "hi"}
+        ]});
+        let mut translated = translate_openai_chat_to_ollama(request, None).unwrap();
+        normalize_roles(&mut translated, None);
+        assert_eq!(translated.messages[0].role, "developer");
+        normalize_roles(&mut translated, Some("system"));
+        assert_eq!(translated.messages[0].role, "system");
+        assert_eq!(translated.messages[1].role, "user");
+    }
+
+    #[test]
+    fn test_translate_image_parts() {
+        let request = json!({
+            "model": "llama3.2-vision",
+            "messages": [
+                {"role": "system", "content": "Describe images."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo=", "detail": "low"}},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.jpg"}},
+                    {"type": "input_audio", "input_audio": {"data": "", "format": "wav"}}
+                ]}
+            ]
+        });
+        let translated = translate_openai_chat_to_ollama(request, None).unwrap();
+        let user = &translated.messages[1];
+        assert_eq!(user.content, "What is this?");
+        assert_eq!(user.images, vec!["iVBORw0KGgo="]);
+        assert_eq!(user.image_urls, vec!["https://example.com/cat.jpg"]);
+        let json = serde_json::to_value(&translated).unwrap();
+        assert!(json["messages"][0].get("images").is_none());
+        assert!(json["messages"][1].get("image_urls").is_none());
+
+        let request = json!({"model": "m", "messages": [{"role": "user", "content": [
+            {"type": "image_url", "image_url": {"url": "file:///etc/passwd"}}
+        ]}]});
+        assert!(translate_openai_chat_to_ollama(request, None).is_err());
+    }
+
+    #[test]
+    fn test_max_completion_tokens_preferred() {
+        let request = json!({"model": "llama3", "messages": [], "max_tokens": 100, "max_completion_tokens": 250});
+        let options = translate_openai_chat_to_ollama(request, None).unwrap().options.unwrap();
+        assert_eq!(options.num_predict, Some(250));
+
+        let request = json!({"model": "llama3", "messages": [], "max_tokens": 100});
+        let options = translate_openai_chat_to_ollama(request, None).unwrap().options.unwrap();
+        assert_eq!(options.num_predict, Some(100));
+    }
+
+    #[test]
+    fn test_translate_stop_sequences() {
+        let request = json!({"model": "llama3", "messages": [], "stop": "\n\n"});
+        let options = translate_openai_chat_to_ollama(request, None).unwrap().options.unwrap();
+        assert_eq!(options.stop, Some(vec!["\n\n".to_string()]));
+
+        let request = json!({"model": "llama3", "messages": [], "stop": ["END", "###"]});
+        let options = translate_openai_chat_to_ollama(request, None).unwrap().options.unwrap();
+        assert_eq!(options.stop, Some(vec!["END".to_string(), "###".to_string()]));
+
+        let request = json!({"model": "llama3", "messages": []});
+        let json = serde_json::to_value(translate_openai_chat_to_ollama(request, None).unwrap()).unwrap();
+        assert!(json["options"].get("stop").is_none());
+    }
+
+    #[test]
+    fn test_seed_and_fingerprint() {
+        let request = json!({"model": "llama3", "messages": [], "seed": 42});
+        let options = translate_openai_chat_to_ollama(request, None).unwrap().options.unwrap();
+        assert_eq!(options.seed, Some(42));
+
+        let response = |model: &str| {
+            json!({"model": model, "created_at": "2025-11-21T16:08:11Z", "message": {"role": "assistant", "content": "hi"}, "done": true})
+        };
+        let first = translate_ollama_chat_to_openai(response("llama3"), String::new(), 0).unwrap();
+        let second = translate_ollama_chat_to_openai(response("llama3"), String::new(), 0).unwrap();
+        let other = translate_ollama_chat_to_openai(response("mistral"), String::new(), 0).unwrap();
+        assert!(first.system_fingerprint.starts_with("fp_"));
+        assert_eq!(first.system_fingerprint, second.system_fingerprint);
+        assert_ne!(first.system_fingerprint, other.system_fingerprint);
+    }
+
+    #[test]
+    fn test_reasoning_effort_and_content() {
+        let request = json!({"model": "qwen3", "messages": [], "reasoning_effort": "high"});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().think, Some(json!(true)));
+        let request = json!({"model": "gpt-oss:20b", "messages": [], "reasoning_effort": "low"});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().think, Some(json!("low")));
+        let request = json!({"model": "deepseek-r1", "messages": [], "reasoning_effort": "minimal"});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().think, Some(json!(false)));
+        let request = json!({"model": "qwen3", "messages": []});
+        assert_eq!(translate_openai_chat_to_ollama(request, None).unwrap().think, None);
+
+        let response = json!({
+            "model": "qwen3",
+            "created_at": "2025-11-21T16:08:11Z",
+            "message": {"role": "assistant", "content": "4", "thinking": "2 + 2 = 4"},
+            "done": true
+        });
+        let translated = translate_ollama_chat_to_openai(response, String::new(), 0).unwrap();
+        let message = serde_json::to_value(&translated.choices[0].message).unwrap();
+        assert_eq!(message["reasoning_content"], "2 + 2 = 4");
+        assert!(message.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_usage_details() {
+        let response = json!({
+            "model": "qwen3",
+            "created_at": "2025-11-21T16:08:11.735252Z",
+            "message": {"role": "assistant", "content": "4", "thinking": "two plus two is four"},
+            "done": true,
+            "prompt_eval_count": 20,
+            "eval_count": 12
+        });
+        // Most of a 500-token prompt came from the cache
+        let usage = translate_ollama_chat_to_openai(response.clone(), "qwen3".to_string(), 500).unwrap().usage;
+        assert_eq!(usage.prompt_tokens, 500);
+        assert_eq!(usage.prompt_tokens_details.cached_tokens, 480);
+        assert_eq!(usage.completion_tokens_details.reasoning_tokens, 5);
+        assert_eq!(usage.total_tokens, 512);
+
+        // Close to the estimate: nothing was cached
+        let mut response = response;
+        let usage = translate_ollama_chat_to_openai(response.clone(), "qwen3".to_string(), 30).unwrap().usage;
+        assert_eq!(usage.prompt_tokens, 20);
+        assert_eq!(usage.prompt_tokens_details.cached_tokens, 0);
+
+        // No eval_count: the answer and reasoning are counted locally
+        response.as_object_mut().unwrap().remove("eval_count");
+        let usage = translate_ollama_chat_to_openai(response, "qwen3".to_string(), 30).unwrap().usage;
+        assert_eq!(usage.completion_tokens, 6);
+    }
+
+    #[test]
+    fn test_translate_tags_to_models() {
+        let tags = json!({"models": [
+            {"name": "llama3:latest", "modified_at": "2025-11-21T16:08:11.735252Z", "size": 1},
+            {"name": "jmorgan/phi:2", "modified_at": "not a date"}
+        ]});
+        let list = translate_ollama_tags_to_openai(&tags).unwrap();
+        assert_eq!(list.object, "list");
+        assert_eq!(list.data[0].id, "llama3:latest");
+        assert_eq!(list.data[0].created, 1763741291);
+        assert_eq!(list.data[0].owned_by, "library");
+        assert_eq!(list.data[1].owned_by, "jmorgan");
+        assert_eq!(list.data[1].created, 0);
+
+        let list = translate_ollama_tags_to_openai(&tags).unwrap();
+        assert_eq!(find_model(list, "llama3").unwrap().id, "llama3:latest");
+        assert!(find_model(translate_ollama_tags_to_openai(&tags).unwrap(), "mistral").is_none());
+        assert!(translate_ollama_tags_to_openai(&json!({})).is_err());
+    }
+
     #[test]
     fn test_needs_translation() {
         assert!(needs_translation("/v1/embeddings"));
         assert!(needs_translation("/v1/chat/completions"));
-        assert!(!needs_translation("/v1/models"));
+        assert!(needs_translation("/v1/models"));
+        assert!(needs_translation("/v1/models/llama3:latest"));
+        assert!(!needs_translation("/v1/completions"));
         assert!(!needs_translation("/api/embed"));
     }
 
@@ -516,7 +1092,8 @@ mod tests {
     fn test_get_ollama_endpoint() {
         assert_eq!(get_ollama_endpoint("/v1/embeddings"), "/api/embed");
         assert_eq!(get_ollama_endpoint("/v1/chat/completions"), "/api/chat");
-        assert_eq!(get_ollama_endpoint("/v1/models"), "/v1/models"); // Passthrough
+        assert_eq!(get_ollama_endpoint("/v1/models"), "/api/tags");
+        assert_eq!(get_ollama_endpoint("/v1/completions"), "/v1/completions"); // Passthrough
     }
 }
 
@@ -0,0 +1,91 @@
+/// OpenAI request parameters the translation layer can't pass on to Ollama
+use serde_json::Value;
+
+/// Response header listing parameters the proxy dropped (warn policy)
+pub const WARNINGS_HEADER: &str = "x-proxy-warnings";
+
+/// Chat completion parameters with no Ollama equivalent
+const CHAT_PARAMS: &[&str] = &[
+    "logit_bias",
+    "logprobs",
+    "top_logprobs",
+    "n",
+    "audio",
+    "modalities",
+    "prediction",
+    "service_tier",
+    "web_search_options",
+];
+
+/// What to do when a request uses an unsupported parameter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnsupportedPolicy {
+    /// Drop the parameter silently
+    Strip,
+    /// Drop it, log a warning and name it in the `x-proxy-warnings` header
+    #[default]
+    Warn,
+    /// Refuse the request with a 400 naming the parameter
+    Reject,
+}
+
+impl UnsupportedPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "strip" => Some(Self::Strip),
+            "warn" => Some(Self::Warn),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Strip => "strip",
+            Self::Warn => "warn",
+            Self::Reject => "reject",
+        }
+    }
+}
+
+/// Unsupported chat parameters set in `body`. Values that ask for the default
+/// behavior (`null`, `false`, `n: 1`) are fine.
+pub fn find_chat_params(body: &Value) -> Vec<&'static str> {
+    CHAT_PARAMS
+        .iter()
+        .copied()
+        .filter(|name| match body.get(*name) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::Number(n)) if *name == "n" => n.as_u64() != Some(1),
+            Some(_) => true,
+        })
+        .collect()
+}
+
+/// `x-proxy-warnings` value for the dropped parameters
+pub fn warning(params: &[&str]) -> String {
+    format!("unsupported parameters ignored: {}", params.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_chat_params() {
+        let body = json!({
+            "model": "llama3",
+            "logit_bias": {"50256": -100},
+            "logprobs": false,
+            "n": 1,
+            "top_logprobs": null,
+            "temperature": 0.2
+        });
+        assert_eq!(find_chat_params(&body), vec!["logit_bias"]);
+        assert_eq!(find_chat_params(&json!({"n": 3, "logprobs": true})), vec!["logprobs", "n"]);
+        assert_eq!(warning(&["logit_bias", "n"]), "unsupported parameters ignored: logit_bias, n");
+        assert_eq!(UnsupportedPolicy::parse("Reject"), Some(UnsupportedPolicy::Reject));
+        assert_eq!(UnsupportedPolicy::parse("ignore"), None);
+    }
+}
@@ -0,0 +1,2755 @@
+use ollama_proxy_rs::test_support::{MockOllama, TestProxy};
+use ollama_proxy_rs::ProxyBuilder;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_chat_context_is_capped_to_model_limit() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let response = reqwest::Client::new()
+        .post(proxy.url("/api/chat"))
+        .json(&json!({
+            "model": "llama3",
+            "messages": [{"role": "user", "content": "hi"}],
+            "stream": false,
+            "options": {"num_ctx": 100000}
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"]["content"], "Hello there");
+
+    // The model was trained with 8192 tokens of context
+    let forwarded = ollama.last_request("/api/chat").unwrap();
+    assert_eq!(forwarded.body["options"]["num_ctx"], 8192);
+}
+
+#[tokio::test]
+async fn test_streaming_chat_passes_every_line() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 4);
+    let content: String = lines.iter().filter_map(|l| l["message"]["content"].as_str()).collect();
+    assert_eq!(content, "Hello there");
+    assert_eq!(lines[3]["done"], true);
+}
+
+#[tokio::test]
+async fn test_openai_embeddings_are_translated() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let body: Value = reqwest::Client::new()
+        .post(proxy.url("/v1/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "input": ["one", "two"]}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["object"], "list");
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    assert_eq!(body["data"][1]["index"], 1);
+    let forwarded = ollama.last_request("/api/embed").unwrap();
+    assert_eq!(forwarded.body["input"], json!(["one", "two"]));
+}
+
+#[tokio::test]
+async fn test_get_requests_pass_through() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let body: Value = reqwest::get(proxy.url("/api/tags")).await.unwrap().json().await.unwrap();
+    assert_eq!(body["models"][0]["name"], "llama3:latest");
+    assert_eq!(ollama.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_successful_responses_stream_through_with_their_headers() {
+    use axum::{body::Body, http::header, response::IntoResponse, routing::post, Router};
+    use futures::StreamExt;
+
+    // Pull progress: the final status only arrives after a pause
+    let router = Router::new().route(
+        "/api/pull",
+        post(|| async {
+            let lines = futures::stream::iter(["pulling manifest", "success"]).then(|status| async move {
+                if status == "success" {
+                    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+                }
+                Ok::<_, std::io::Error>(bytes::Bytes::from(format!("{}\n", json!({"status": status}))))
+            });
+            (
+                [(header::CONTENT_TYPE, "application/x-ndjson"), (header::CACHE_CONTROL, "no-store")],
+                [("x-ollama-request", "pull-1")],
+                Body::from_stream(lines),
+            )
+                .into_response()
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let started = std::time::Instant::now();
+    let mut response = reqwest::Client::new()
+        .post(proxy.url("/api/pull"))
+        .json(&json!({"model": "llama3"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    assert_eq!(response.headers()["x-ollama-request"], "pull-1");
+    let mut body = Vec::new();
+    let mut first_chunk_at = None;
+    while let Some(chunk) = response.chunk().await.unwrap() {
+        first_chunk_at.get_or_insert(started.elapsed());
+        body.extend_from_slice(&chunk);
+    }
+    // Not buffered: progress arrives before Ollama has finished
+    assert!(first_chunk_at.unwrap() < std::time::Duration::from_millis(300));
+    let text = String::from_utf8(body).unwrap();
+    assert_eq!(text, "{\"status\":\"pulling manifest\"}\n{\"status\":\"success\"}\n");
+}
+
+#[tokio::test]
+async fn test_oversized_error_response_is_streamed_through() {
+    use axum::{body::Body, http::StatusCode, response::IntoResponse, routing::post, Router};
+    use futures::StreamExt;
+
+    // A 64 KiB error body in 8 KiB pieces, the last one after a pause. Error bodies are
+    // buffered for logging, up to MAX_BUFFERED_RESPONSE_BYTES.
+    let router = Router::new().route(
+        "/api/show",
+        post(|| async {
+            let pieces = futures::stream::iter(0..8u64).then(|i| async move {
+                if i == 7 {
+                    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+                }
+                Ok::<_, std::io::Error>(bytes::Bytes::from(vec![b'a' + i as u8; 8192]))
+            });
+            (StatusCode::NOT_FOUND, Body::from_stream(pieces)).into_response()
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url).max_buffered_response_bytes(16 * 1024).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let started = std::time::Instant::now();
+    let mut response = reqwest::Client::new()
+        .post(proxy.url("/api/show"))
+        .json(&json!({"name": "llama3"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let mut body = Vec::new();
+    let mut first_chunk_at = None;
+    while let Some(chunk) = response.chunk().await.unwrap() {
+        first_chunk_at.get_or_insert(started.elapsed());
+        body.extend_from_slice(&chunk);
+    }
+    // Past the cap the rest is forwarded as it arrives, not held until the end
+    assert!(first_chunk_at.unwrap() < std::time::Duration::from_millis(300));
+    assert_eq!(body.len(), 64 * 1024);
+    assert!(body[..8192].iter().all(|&b| b == b'a'));
+    assert!(body[7 * 8192..].iter().all(|&b| b == b'h'));
+}
+
+#[tokio::test]
+async fn test_health_details_reports_backends() {
+    let ollama = MockOllama::start().await;
+    // Nothing listens on port 9, so the second backend is unreachable
+    let config = ProxyBuilder::new(&ollama.url)
+        .backends(&["http://127.0.0.1:9"])
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let response = reqwest::get(proxy.url("/healthz/details")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["upstream"]["version"], "0.5.0");
+    assert_eq!(body["loaded_models"], json!(["llama3:latest"]));
+    assert_eq!(body["backends"][1]["reachable"], false);
+    assert_eq!(body["backends"][1]["healthy"], true);
+}
+
+#[tokio::test]
+async fn test_failing_health_checks_take_backend_out_of_rotation() {
+    use axum::{http::StatusCode, routing::get, Router};
+    use ollama_proxy_rs::backends::ProbeThresholds;
+    use ollama_proxy_rs::health::HealthCheck;
+
+    // The primary still accepts connections but its health check fails
+    let sick = MockOllama::with_router(Router::new().route("/api/version", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))).await;
+    let healthy = MockOllama::start().await;
+    let config = ProxyBuilder::new(&sick.url)
+        .backends(&[healthy.url.as_str()])
+        .health_check(HealthCheck {
+            interval: Some(std::time::Duration::from_millis(50)),
+            thresholds: ProbeThresholds { unhealthy: 2, healthy: 2 },
+        })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+    let body: Value = reqwest::get(proxy.url("/proxy/admin/backends")).await.unwrap().json().await.unwrap();
+    assert_eq!(body["backends"][0]["healthy"], false);
+    assert_eq!(body["backends"][0]["failing_health_checks"], true);
+    assert_eq!(body["backends"][0]["last_health_check_error"], "HTTP 500 Internal Server Error");
+    assert_eq!(body["backends"][1]["healthy"], true);
+
+    let response = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(healthy.last_request("/api/generate").is_some());
+}
+
+#[tokio::test]
+async fn test_requests_go_to_backend_with_the_model() {
+    use axum::{routing::get, routing::post, Json, Router};
+    use ollama_proxy_rs::inventory::ModelRouting;
+
+    let primary = MockOllama::start().await;
+    let qwen_box = MockOllama::with_router(
+        Router::new()
+            .route("/api/tags", get(|| async { Json(json!({"models": [{"name": "qwen2.5:latest"}]})) }))
+            .route("/api/generate", post(|| async { Json(json!({"response": "from qwen box", "done": true})) })),
+    )
+    .await;
+    let config = ProxyBuilder::new(&primary.url)
+        .backends(&[qwen_box.url.as_str()])
+        .model_routing(ModelRouting { refresh: Some(std::time::Duration::from_secs(60)), ..Default::default() })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let generate = |model: &str| {
+        reqwest::Client::new()
+            .post(proxy.url("/api/generate"))
+            .json(&json!({"model": model, "prompt": "hi", "stream": false}))
+            .send()
+    };
+
+    let body: Value = generate("qwen2.5").await.unwrap().json().await.unwrap();
+    assert_eq!(body["response"], "from qwen box");
+    assert!(primary.last_request("/api/generate").is_none());
+
+    let missing = generate("mistral").await.unwrap();
+    assert_eq!(missing.status(), 404);
+    let body: Value = missing.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("not pulled on any backend"));
+}
+
+#[tokio::test]
+async fn test_canary_alias_splits_traffic_and_counts_models() {
+    use ollama_proxy_rs::routing::CanaryRoutes;
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .canary_routes(CanaryRoutes::parse("assistant=llama3.3@50,llama3.1").unwrap())
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    for _ in 0..40 {
+        let response = client
+            .post(proxy.url("/api/generate"))
+            .json(&json!({"model": "assistant", "prompt": "hi", "stream": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+    let models: Vec<Value> = ollama.requests().into_iter().map(|r| r.body["model"].clone()).collect();
+    assert!(models.contains(&json!("llama3.3")) && models.contains(&json!("llama3.1")));
+    assert!(!models.contains(&json!("assistant")));
+
+    // Every turn of a chat stays on one version
+    for turn in 0..5 {
+        let messages = json!([{"role": "user", "content": "Plan my trip"}, {"role": "user", "content": turn.to_string()}]);
+        client
+            .post(proxy.url("/api/chat"))
+            .json(&json!({"model": "assistant", "messages": messages, "stream": false}))
+            .send()
+            .await
+            .unwrap();
+    }
+    let chats: Vec<Value> = ollama.requests().into_iter().filter(|r| r.path == "/api/chat").map(|r| r.body["model"].clone()).collect();
+    assert_eq!(chats.len(), 5);
+    assert!(chats.iter().all(|model| *model == chats[0]));
+
+    let metrics = client.get(proxy.url("/metrics")).send().await.unwrap().text().await.unwrap();
+    assert!(metrics.contains("ollama_proxy_canary_requests_total{alias=\"assistant\",model=\"llama3.3\"}"));
+}
+
+#[tokio::test]
+async fn test_conversation_turns_stay_on_one_backend() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::backends::{Placement, PlacementStrategy};
+
+    let backend = |name: &'static str| {
+        Router::new().route(
+            "/api/chat",
+            post(move || async move { Json(json!({"message": {"role": "assistant", "content": name}, "done": true})) }),
+        )
+    };
+    let a = MockOllama::with_router(backend("a")).await;
+    let b = MockOllama::with_router(backend("b")).await;
+    let mut config = ProxyBuilder::new(&a.url).backends(&[b.url.as_str()]).config().unwrap();
+    config.upstream.placement = Placement {
+        strategy: PlacementStrategy::Weighted,
+        sticky_conversations: true,
+        ..Default::default()
+    };
+    let proxy = &TestProxy::start(config).await;
+    let chat = |messages: Value| async move {
+        let body: Value = reqwest::Client::new()
+            .post(proxy.url("/api/chat"))
+            .json(&json!({"model": "llama3", "messages": messages, "stream": false}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        body["message"]["content"].as_str().unwrap().to_string()
+    };
+
+    // Round-robin would alternate; every turn of the chat goes to the same backend instead
+    let mut history = vec![json!({"role": "user", "content": "Tell me about otters"})];
+    let home = chat(json!(history)).await;
+    for turn in 0..3 {
+        history.push(json!({"role": "assistant", "content": "Otters are..."}));
+        history.push(json!({"role": "user", "content": format!("Go on ({})", turn)}));
+        assert_eq!(chat(json!(history)).await, home);
+    }
+}
+
+#[tokio::test]
+async fn test_missing_and_failing_models_fall_back_to_cloud() {
+    use axum::{http::HeaderMap, http::StatusCode, routing::get, routing::post, Json, Router};
+    use ollama_proxy_rs::cloud::CloudSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let local_chats = Arc::new(AtomicUsize::new(0));
+    let counter = local_chats.clone();
+    let local = MockOllama::with_router(
+        Router::new()
+            .route("/api/tags", get(|| async { Json(json!({"models": [{"name": "llama3:latest"}]})) }))
+            .route(
+                "/api/chat",
+                post(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "out of memory")
+                }),
+            ),
+    )
+    .await;
+    let cloud = MockOllama::with_router(Router::new().route(
+        "/chat/completions",
+        post(|headers: HeaderMap, Json(body): Json<Value>| async move {
+            let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+            Json(json!({"model": body["model"], "auth": auth, "choices": []}))
+        }),
+    ))
+    .await;
+    let mut settings = CloudSettings::new(&cloud.url);
+    settings.api_key = Some("sk-cloud".to_string());
+    let config = ProxyBuilder::new(&local.url).cloud_fallback(Some(settings)).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let chat = |model: &str| {
+        reqwest::Client::new()
+            .post(proxy.url("/v1/chat/completions"))
+            .json(&json!({"model": model, "messages": [{"role": "user", "content": "hi"}]}))
+            .send()
+    };
+
+    // Not pulled locally: straight to the cloud with its own key
+    let response = chat("gpt-4o-mini").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-proxy-upstream"], "cloud");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["model"], "gpt-4o-mini");
+    assert_eq!(body["auth"], "Bearer sk-cloud");
+    assert_eq!(local_chats.load(Ordering::SeqCst), 0);
+
+    // Pulled but failing locally: retried in the cloud
+    let response = chat("llama3").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-proxy-upstream"], "cloud");
+    assert_eq!(local_chats.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_cloud_fallback_answers_go_through_output_filters() {
+    use axum::{response::IntoResponse, routing::get, routing::post, Json, Router};
+    use ollama_proxy_rs::cloud::CloudSettings;
+    use ollama_proxy_rs::filters::OutputFilters;
+
+    let local = MockOllama::with_router(Router::new().route("/api/tags", get(|| async { Json(json!({"models": []})) })))
+        .await;
+    let cloud = MockOllama::with_router(Router::new().route(
+        "/chat/completions",
+        post(|Json(body): Json<Value>| async move {
+            if body["stream"] == true {
+                let chunk = |content: &str, finish: Value| {
+                    json!({"choices": [{"delta": {"content": content}, "finish_reason": finish}]})
+                };
+                let events = [chunk("Project Fal", Value::Null), chunk("con says hello", json!("stop"))]
+                    .iter()
+                    .map(|event| format!("data: {}\n\n", event))
+                    .collect::<String>()
+                    + "data: [DONE]\n\n";
+                ([("content-type", "text/event-stream")], events).into_response()
+            } else {
+                Json(json!({"choices": [{"message": {"role": "assistant", "content": "Falcon says hello"}}]}))
+                    .into_response()
+            }
+        }),
+    ))
+    .await;
+    let config = ProxyBuilder::new(&local.url)
+        .cloud_fallback(Some(CloudSettings::new(&cloud.url)))
+        .output_filters(OutputFilters::parse("words:Falcon,hello=>[redacted]").unwrap())
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let chat = |stream: bool| {
+        reqwest::Client::new()
+            .post(proxy.url("/v1/chat/completions"))
+            .json(&json!({"model": "gpt-4o-mini", "messages": [{"role": "user", "content": "hi"}], "stream": stream}))
+            .send()
+    };
+
+    let response = chat(false).await.unwrap();
+    assert_eq!(response.headers()["x-proxy-upstream"], "cloud");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "[redacted] says [redacted]");
+
+    let text = chat(true).await.unwrap().text().await.unwrap();
+    let content: String = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .filter_map(|event| event["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+        .collect();
+    assert_eq!(content, "Project [redacted] says [redacted]");
+    assert!(text.trim_end().ends_with("data: [DONE]"));
+}
+
+#[tokio::test]
+async fn test_busy_tier_spills_to_next_tier() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::backends::Placement;
+
+    let backend = |name: &'static str, delay_ms: u64| {
+        Router::new().route(
+            "/api/generate",
+            post(move || async move {
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                Json(json!({"response": name, "done": true}))
+            }),
+        )
+    };
+    let fast = MockOllama::with_router(backend("4090", 300)).await;
+    let mini = MockOllama::with_router(backend("mini", 0)).await;
+    let mut config = ProxyBuilder::new(&fast.url).backends(&[mini.url.as_str()]).config().unwrap();
+    config.upstream.placement = Placement {
+        tiers: Placement::parse_backend_numbers(&format!("{}=2", mini.url)).unwrap(),
+        max_in_flight: 1,
+        ..Default::default()
+    };
+    let proxy = TestProxy::start(config).await;
+    let generate = || async {
+        let body: Value = reqwest::Client::new()
+            .post(proxy.url("/api/generate"))
+            .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        body["response"].as_str().unwrap().to_string()
+    };
+
+    // The 4090 is busy with the first request, so the second spills to the Mac mini
+    let (first, second) = tokio::join!(generate(), async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        generate().await
+    });
+    assert_eq!(first, "4090");
+    assert_eq!(second, "mini");
+    assert_eq!(generate().await, "4090");
+}
+
+#[tokio::test]
+async fn test_stream_cut_off_by_upstream_ends_with_error_line() {
+    use axum::{body::Body, routing::post, Router};
+    use futures::StreamExt;
+
+    // Two lines, then the connection drops mid-stream
+    let router = Router::new().route(
+        "/api/generate",
+        post(|| async {
+            let lines = futures::stream::iter(vec![
+                Ok::<_, std::io::Error>(bytes::Bytes::from("{\"response\":\"Hel\",\"done\":false}\n")),
+                Ok(bytes::Bytes::from("{\"response\":\"lo\",\"done\":false}\n")),
+            ])
+            .chain(futures::stream::once(async {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "restarting"))
+            }));
+            Body::from_stream(lines)
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1]["response"], "lo");
+    assert_eq!(lines[2]["error_type"], "upstream_disconnected");
+}
+
+#[tokio::test]
+async fn test_stalled_stream_ends_after_idle_timeout() {
+    use axum::{body::Body, routing::post, Router};
+    use futures::StreamExt;
+    use ollama_proxy_rs::timeouts::StreamTimeouts;
+
+    // One line, then Ollama goes quiet without closing the connection
+    let router = Router::new().route(
+        "/api/generate",
+        post(|| async {
+            let lines = futures::stream::iter(vec![Ok::<_, std::io::Error>(bytes::Bytes::from(
+                "{\"response\":\"Hel\",\"done\":false}\n",
+            ))])
+            .chain(futures::stream::pending());
+            Body::from_stream(lines)
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .stream_timeouts(StreamTimeouts { idle: Some(std::time::Duration::from_secs(1)), ..Default::default() })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["response"], "Hel");
+    assert_eq!(lines[1]["error_type"], "upstream_timeout");
+    assert_eq!(lines[1]["lines_received"], 1);
+}
+
+/// Stream `script` (delay in ms, then a line) through a proxy batching with `batching`,
+/// returning each frame the client received with when it arrived
+async fn batched_frames(
+    batching: ollama_proxy_rs::proxy::StreamBatching,
+    script: &'static [(u64, &'static str)],
+) -> Vec<(std::time::Duration, String)> {
+    use axum::{body::Body, routing::post, Router};
+    use futures::StreamExt;
+
+    let router = Router::new().route(
+        "/api/generate",
+        post(move || async move {
+            let lines = futures::stream::iter(script).then(|&(delay, line)| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                Ok::<_, std::io::Error>(bytes::Bytes::from(format!("{}\n", line)))
+            });
+            Body::from_stream(lines)
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url).stream_batching(batching).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let started = std::time::Instant::now();
+    let mut response = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap();
+    let mut frames = Vec::new();
+    while let Some(chunk) = response.chunk().await.unwrap() {
+        frames.push((started.elapsed(), String::from_utf8(chunk.to_vec()).unwrap()));
+    }
+    frames
+}
+
+const BATCHED_LINES: &[(u64, &str)] = &[
+    (0, r#"{"response":"a","done":false}"#),
+    (0, r#"{"response":"b","done":false}"#),
+    (0, r#"{"response":"c","done":false}"#),
+    (400, r#"{"response":"","done":true}"#),
+];
+
+#[tokio::test]
+async fn test_stream_batches_flush_once_full() {
+    use ollama_proxy_rs::proxy::StreamBatching;
+
+    // Two 30-byte lines fill the batch; the third waits for the next line or the end
+    let batching = StreamBatching { flush_interval: Some(std::time::Duration::from_secs(10)), flush_bytes: 60 };
+    let frames = batched_frames(batching, BATCHED_LINES).await;
+    assert!(frames[0].0 < std::time::Duration::from_millis(300));
+    assert_eq!(frames[0].1.lines().count(), 2);
+    let text: String = frames.iter().map(|(_, frame)| frame.as_str()).collect();
+    assert_eq!(text.lines().count(), 4);
+}
+
+#[tokio::test]
+async fn test_stream_batches_flush_after_the_interval() {
+    use ollama_proxy_rs::proxy::StreamBatching;
+
+    let batching = StreamBatching { flush_interval: Some(std::time::Duration::from_millis(100)), flush_bytes: 0 };
+    let frames = batched_frames(batching, BATCHED_LINES).await;
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].0 < std::time::Duration::from_millis(300));
+    assert_eq!(frames[0].1.lines().count(), 3);
+    assert!(frames[1].1.contains("\"done\":true"));
+}
+
+#[tokio::test]
+async fn test_stream_batches_flush_when_the_stream_ends() {
+    use ollama_proxy_rs::proxy::StreamBatching;
+
+    // Neither trigger fires before Ollama finishes
+    let batching = StreamBatching { flush_interval: Some(std::time::Duration::from_secs(10)), flush_bytes: 100_000 };
+    let frames = batched_frames(batching, BATCHED_LINES).await;
+    assert_eq!(frames.len(), 1);
+    assert!(frames[0].0 >= std::time::Duration::from_millis(400));
+    assert_eq!(frames[0].1.lines().count(), 4);
+}
+
+#[tokio::test]
+async fn test_size_only_stream_batches_still_flush_slow_streams() {
+    use ollama_proxy_rs::proxy::StreamBatching;
+
+    // Without an interval, a stream that never fills the batch falls back to the default one
+    let batching = StreamBatching { flush_interval: None, flush_bytes: 100_000 };
+    let frames = batched_frames(batching, BATCHED_LINES).await;
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].0 < std::time::Duration::from_millis(300));
+    assert_eq!(frames[0].1.lines().count(), 3);
+}
+
+#[tokio::test]
+async fn test_request_timeout_header_sets_the_deadline() {
+    use axum::{routing::post, Json, Router};
+
+    // Generation takes a second
+    let router = Router::new().route(
+        "/api/generate",
+        post(|| async {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            Json(json!({"response": "done", "done": true}))
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .max_request_timeout(Some(std::time::Duration::from_secs(30)))
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let generate = |timeout: &'static str| {
+        reqwest::Client::new()
+            .post(proxy.url("/api/generate"))
+            .header("X-Request-Timeout", timeout)
+            .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+            .send()
+    };
+
+    assert_eq!(generate("0.2").await.unwrap().status(), 504);
+    assert_eq!(generate("5").await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn test_adaptive_timeout_grows_when_a_model_slows_down() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::latency::AdaptiveTimeouts;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    let delay_ms = Arc::new(AtomicU64::new(10));
+    let delay = delay_ms.clone();
+    let router = Router::new().route(
+        "/api/generate",
+        post(move || async move {
+            tokio::time::sleep(std::time::Duration::from_millis(delay.load(Ordering::SeqCst))).await;
+            Json(json!({"response": "done", "done": true}))
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let mut config = ProxyBuilder::new(&ollama.url).config().unwrap();
+    config.upstream.adaptive_timeouts = AdaptiveTimeouts {
+        enabled: true,
+        min_samples: 5,
+        floor: std::time::Duration::from_millis(200),
+        ..Default::default()
+    };
+    let proxy = TestProxy::start(config).await;
+    let generate = || {
+        reqwest::Client::new()
+            .post(proxy.url("/api/generate"))
+            .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+            .send()
+    };
+
+    // Fast history puts the timeout at the 200ms floor
+    for _ in 0..5 {
+        assert_eq!(generate().await.unwrap().status(), 200);
+    }
+    // The model slows to 300ms: the first request times out, and counts as 200ms
+    // in the history, which lifts the timeout enough for the next one
+    delay_ms.store(300, Ordering::SeqCst);
+    assert_eq!(generate().await.unwrap().status(), 504);
+    assert_eq!(generate().await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn test_slow_stream_is_hedged_to_second_backend() {
+    use axum::{routing::post, Router};
+    use ollama_proxy_rs::hedge::HedgePolicy;
+
+    let backend = |delay_ms: u64, text: &'static str| {
+        Router::new().route(
+            "/api/generate",
+            post(move || async move {
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                format!("{{\"response\":\"{}\",\"done\":true}}\n", text)
+            }),
+        )
+    };
+    let slow = MockOllama::with_router(backend(2000, "slow")).await;
+    let fast = MockOllama::with_router(backend(0, "fast")).await;
+    let config = ProxyBuilder::new(&slow.url)
+        .backends(&[fast.url.as_str()])
+        .hedge(HedgePolicy { delay: Some(std::time::Duration::from_millis(100)), ..Default::default() })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let started = std::time::Instant::now();
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let line: Value = serde_json::from_str(text.trim()).unwrap();
+    assert_eq!(line["response"], "fast");
+    assert!(started.elapsed() < std::time::Duration::from_millis(1500));
+}
+
+#[tokio::test]
+async fn test_pinned_model_stays_loaded() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    let pinned: Value = client
+        .post(proxy.url("/proxy/admin/models/llama3:8b/pin"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(pinned["pinned"], true);
+    assert_eq!(ollama.last_request("/api/generate").unwrap().body["keep_alive"], "-1m");
+
+    // A client asking for a short keep_alive can't unload the pinned model
+    client
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3:8b", "prompt": "hi", "stream": false, "keep_alive": "5m"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(ollama.last_request("/api/generate").unwrap().body["keep_alive"], "-1m");
+
+    client.post(proxy.url("/proxy/admin/models/llama3:8b/unpin")).send().await.unwrap();
+    assert_eq!(ollama.last_request("/api/generate").unwrap().body["keep_alive"], 0);
+    let listed: Value = reqwest::get(proxy.url("/proxy/admin/models")).await.unwrap().json().await.unwrap();
+    assert_eq!(listed["pinned"], json!([]));
+}
+
+#[tokio::test]
+async fn test_output_filters_scrub_streamed_text() {
+    use ollama_proxy_rs::filters::OutputFilters;
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .output_filters(OutputFilters::parse("words:hello=>Hi").unwrap())
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let content: String = lines.iter().filter_map(|l| l["message"]["content"].as_str()).collect();
+    assert_eq!(content, "Hi there");
+    assert_eq!(lines.last().unwrap()["done"], true);
+}
+
+#[tokio::test]
+async fn test_invalid_structured_stream_is_retried() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::structured::StructuredFailure;
+
+    // Streams prose despite format=json; the non-streaming retry behaves
+    let router = Router::new().route(
+        "/api/generate",
+        post(|Json(body): Json<Value>| async move {
+            if body["stream"] == true {
+                "{\"response\":\"Sure! \",\"done\":false}\n{\"response\":\"{}\",\"done\":true}\n".to_string()
+            } else {
+                json!({"response": "{\"answer\": 42}", "done": true}).to_string()
+            }
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .structured_failure(StructuredFailure::Retry)
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let text = reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "answer in JSON", "format": "json", "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["response"], "{\"answer\": 42}");
+    assert_eq!(lines[0]["structured_output_retried"], true);
+    assert!(lines[0].get("structured_output_error").is_none());
+}
+
+#[tokio::test]
+async fn test_head_options_and_wrong_methods() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    // HEAD reaches Ollama as GET and comes back without a body
+    let response = client.head(proxy.url("/api/version/")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "");
+    let forwarded = ollama.last_request("/api/version").unwrap();
+    assert_eq!(forwarded.method, "GET");
+
+    let response = client.request(reqwest::Method::OPTIONS, proxy.url("/v1/chat/completions")).send().await.unwrap();
+    assert_eq!(response.status(), 204);
+    assert_eq!(response.headers()["allow"], "POST, OPTIONS");
+
+    let response = client.get(proxy.url("/v1/chat/completions")).send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["allow"], "POST, OPTIONS");
+    assert!(ollama.last_request("/api/chat").is_none());
+}
+
+#[tokio::test]
+async fn test_default_model_fills_missing_model() {
+    use ollama_proxy_rs::routing::DefaultModels;
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .default_models(DefaultModels { chat: Some("llama3".to_string()), embed: None })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let response = reqwest::Client::new()
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"messages": [{"role": "user", "content": "hi"}], "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-proxy-default-model"], "llama3");
+    assert_eq!(ollama.last_request("/api/chat").unwrap().body["model"], "llama3");
+}
+
+#[tokio::test]
+async fn test_remote_chat_images_are_only_fetched_from_public_hosts() {
+    let ollama = MockOllama::start().await;
+    let image_url = format!("{}/images/cat.png", ollama.url);
+    let chat = json!({
+        "model": "llama3.2-vision",
+        "messages": [{"role": "user", "content": [
+            {"type": "text", "text": "What is this?"},
+            {"type": "image_url", "image_url": {"url": image_url}}
+        ]}]
+    });
+    let send = |proxy: &TestProxy| reqwest::Client::new().post(proxy.url("/v1/chat/completions")).json(&chat).send();
+
+    // Off by default
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let response = send(&proxy).await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_image_url");
+
+    // Enabled, a URL on the proxy's own network is refused without saying why
+    let config = ProxyBuilder::new(&ollama.url).fetch_image_urls(true).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let response = send(&proxy).await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["message"], "Failed to fetch image URL");
+    assert!(ollama.requests().iter().all(|r| r.path != "/images/cat.png"));
+    assert!(ollama.last_request("/api/chat").is_none());
+}
+
+#[tokio::test]
+async fn test_requests_needing_missing_capabilities_are_rejected_early() {
+    use axum::{routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // A model that can only complete text: no tools, no images
+    let chats = Arc::new(AtomicUsize::new(0));
+    let counter = chats.clone();
+    let ollama = MockOllama::with_router(
+        Router::new()
+            .route("/api/show", post(|| async { Json(json!({"capabilities": ["completion"]})) }))
+            .route(
+                "/api/chat",
+                post(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Json(json!({"message": {"role": "assistant", "content": "ok"}, "done": true}))
+                }),
+            ),
+    )
+    .await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.url("/v1/chat/completions"))
+        .json(&json!({
+            "model": "phi3",
+            "messages": [{"role": "user", "content": "Weather in Oslo?"}],
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["param"], "tools");
+    assert_eq!(body["error"]["code"], "unsupported_capability");
+
+    let response = client
+        .post(proxy.url("/api/chat"))
+        .json(&json!({
+            "model": "phi3",
+            "messages": [{"role": "user", "content": "What is this?", "images": ["aGk="]}],
+            "stream": false
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Model 'phi3' does not support vision");
+
+    let response = client
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "phi3", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(chats.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_openai_models_are_listed_from_tags() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let body: Value = reqwest::get(proxy.url("/v1/models")).await.unwrap().json().await.unwrap();
+    assert_eq!(body["object"], "list");
+    assert_eq!(body["data"][0]["id"], "llama3:latest");
+    assert_eq!(body["data"][0]["object"], "model");
+    assert_eq!(body["data"][0]["owned_by"], "library");
+    assert!(ollama.last_request("/api/tags").is_some());
+
+    let model: Value = reqwest::get(proxy.url("/v1/models/llama3")).await.unwrap().json().await.unwrap();
+    assert_eq!(model["id"], "llama3:latest");
+    assert_eq!(reqwest::get(proxy.url("/v1/models/mistral")).await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_openai_json_schema_mismatch_is_an_error() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let schema = json!({"type": "object", "required": ["name"]});
+
+    let response = reqwest::Client::new()
+        .post(proxy.url("/v1/chat/completions"))
+        .json(&json!({
+            "model": "llama3",
+            "messages": [{"role": "user", "content": "Who are you?"}],
+            "response_format": {"type": "json_schema", "json_schema": {"name": "person", "schema": schema}}
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_structured_output");
+
+    // The schema reached Ollama as its format
+    assert_eq!(ollama.last_request("/api/chat").unwrap().body["format"], schema);
+}
+
+#[tokio::test]
+async fn test_openai_tool_calls_round_trip() {
+    use axum::{routing::post, Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    let forwarded = Arc::new(Mutex::new(Value::Null));
+    let seen = forwarded.clone();
+    let router = Router::new().route(
+        "/api/chat",
+        post(move |Json(body): Json<Value>| async move {
+            *seen.lock().unwrap() = body;
+            Json(json!({
+                "model": "llama3.1",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": {"role": "assistant", "content": "", "tool_calls": [
+                    {"function": {"name": "get_weather", "arguments": {"city": "Oslo"}}},
+                    {"function": {"name": "get_weather", "arguments": {"city": "Bergen"}}}
+                ]},
+                "done": true,
+                "done_reason": "stop"
+            }))
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+
+    let body: Value = reqwest::Client::new()
+        .post(proxy.url("/v1/chat/completions"))
+        .json(&json!({
+            "model": "llama3.1",
+            "messages": [
+                {"role": "user", "content": "Weather in Oslo?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_a", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Oslo\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_a", "content": "12C"}
+            ],
+            "tools": [
+                {"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}},
+                {"type": "function", "function": {"name": "get_time", "parameters": {"type": "object"}}}
+            ],
+            "tool_choice": {"type": "function", "function": {"name": "get_weather"}},
+            "parallel_tool_calls": false
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let choice = &body["choices"][0];
+    assert_eq!(choice["finish_reason"], "tool_calls");
+    let calls = choice["message"]["tool_calls"].as_array().unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["function"]["arguments"], "{\"city\":\"Oslo\"}");
+
+    let forwarded = forwarded.lock().unwrap().clone();
+    assert_eq!(forwarded["tools"].as_array().unwrap().len(), 1);
+    assert_eq!(forwarded["messages"][1]["tool_calls"][0]["function"]["arguments"]["city"], "Oslo");
+    assert_eq!(forwarded["messages"][2]["tool_name"], "get_weather");
+    assert_eq!(forwarded["messages"][3]["role"], "system");
+}
+
+#[tokio::test]
+async fn test_proxy_errors_use_openai_format_on_v1() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.url("/v1/chat/completions"))
+        .header("Content-Type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+    assert!(body["error"]["message"].as_str().unwrap().starts_with("Invalid JSON body"));
+
+    let response = client.get(proxy.url("/v1/models/missing-model")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "model_not_found");
+
+    let response = client.get(proxy.url("/v1/chat/completions")).send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+}
+
+#[tokio::test]
+async fn test_unsupported_openai_parameters() {
+    use ollama_proxy_rs::unsupported::UnsupportedPolicy;
+
+    let ollama = MockOllama::start().await;
+    let request = json!({
+        "model": "llama3",
+        "messages": [{"role": "user", "content": "hi"}],
+        "logit_bias": {"50256": -100}
+    });
+
+    // Warn (the default): the request goes through with a header naming the parameter
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let response = reqwest::Client::new().post(proxy.url("/v1/chat/completions")).json(&request).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-proxy-warnings"], "unsupported parameters ignored: logit_bias");
+
+    let config = ProxyBuilder::new(&ollama.url).unsupported_params(UnsupportedPolicy::Reject).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let response = reqwest::Client::new().post(proxy.url("/v1/chat/completions")).json(&request).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["param"], "logit_bias");
+    assert_eq!(body["error"]["code"], "unsupported_parameter");
+}
+
+#[tokio::test]
+async fn test_chunked_embeddings_keep_one_vector_per_input() {
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url).max_embedding_input_length(100).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let long = "This sentence is long enough to need chunking. ".repeat(10);
+    let response = reqwest::Client::new()
+        .post(proxy.url("/v1/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "input": ["short one", long, "short two"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let data = body["data"].as_array().unwrap();
+    assert_eq!(data.len(), 3);
+    for (i, item) in data.iter().enumerate() {
+        assert_eq!(item["index"], i);
+        assert_eq!(item["embedding"].as_array().unwrap().len(), 3);
+    }
+}
+
+#[tokio::test]
+async fn test_embeddings_dimensions_are_truncated() {
+    use ollama_proxy_rs::dimensions::DimensionPolicy;
+
+    let ollama = MockOllama::start().await;
+    let policy = DimensionPolicy { allow: vec!["nomic-embed-text*".to_string()], deny: Vec::new() };
+    let config = ProxyBuilder::new(&ollama.url).embed_dimensions(policy).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.url("/v1/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "input": "hello", "dimensions": 2}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let embedding: Vec<f64> = serde_json::from_value(body["data"][0]["embedding"].clone()).unwrap();
+    assert_eq!(embedding.len(), 2);
+    let norm: f64 = embedding.iter().map(|v| v * v).sum::<f64>().sqrt();
+    assert!((norm - 1.0).abs() < 1e-5);
+
+    let response = client
+        .post(proxy.url("/v1/embeddings"))
+        .json(&json!({"model": "all-minilm", "input": "hello", "dimensions": 2}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_chunk_embeddings_are_returned_with_offsets() {
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url).max_embedding_input_length(100).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let long = "This sentence is long enough to need chunking. ".repeat(10);
+    let response = reqwest::Client::new()
+        .post(proxy.url("/v1/embeddings"))
+        .header("X-Proxy-Return-Chunks", "true")
+        .json(&json!({"model": "nomic-embed-text", "input": ["short one", long]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let data = body["data"].as_array().unwrap();
+    assert!(data.len() > 2);
+    assert_eq!(data[0]["chunk"], json!({"input_index": 0, "start": 0, "end": 9}));
+    assert_eq!(data[1]["chunk"]["input_index"], 1);
+    assert_eq!(data[1]["chunk"]["start"], 0);
+    let last = data.last().unwrap();
+    assert_eq!(last["index"], data.len() - 1);
+    assert_eq!(last["chunk"]["end"], long.len());
+}
+
+#[tokio::test]
+async fn test_embedding_cache_serves_repeated_inputs() {
+    use ollama_proxy_rs::cache::CacheLimits;
+
+    let ollama = MockOllama::start().await;
+    let limits = CacheLimits { max_entries: 100, max_bytes: 0, ttl: None };
+    let config = ProxyBuilder::new(&ollama.url).embedding_cache(limits).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let embed_calls = || ollama.requests().iter().filter(|r| r.path == "/api/embed").count();
+
+    let embed = |input: Value| {
+        client
+            .post(proxy.url("/v1/embeddings"))
+            .json(&json!({"model": "nomic-embed-text", "input": input}))
+            .send()
+    };
+    assert_eq!(embed(json!(["alpha", "beta"])).await.unwrap().status(), 200);
+    assert_eq!(embed_calls(), 1);
+
+    // Both inputs cached: Ollama isn't called
+    let body: Value = embed(json!(["beta", "alpha"])).await.unwrap().json().await.unwrap();
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    assert_eq!(body["data"][1]["index"], 1);
+    assert_eq!(embed_calls(), 1);
+
+    // Only the new input is sent
+    let body: Value = embed(json!(["alpha", "gamma"])).await.unwrap().json().await.unwrap();
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    assert_eq!(embed_calls(), 2);
+    assert_eq!(ollama.last_request("/api/embed").unwrap().body["input"], json!(["gamma"]));
+
+    let metrics = client.get(proxy.url("/metrics")).send().await.unwrap().text().await.unwrap();
+    assert!(metrics.contains("ollama_proxy_embedding_cache_hits_total 3"));
+}
+
+#[tokio::test]
+async fn test_chat_cache_serves_identical_requests() {
+    use ollama_proxy_rs::cache::CacheLimits;
+
+    let ollama = MockOllama::start().await;
+    let limits = CacheLimits { max_entries: 10, max_bytes: 0, ttl: None };
+    let config = ProxyBuilder::new(&ollama.url).chat_cache(limits).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let chat_calls = || ollama.requests().iter().filter(|r| r.path == "/api/chat").count();
+
+    let chat = |temperature: f64, cache: &'static str| {
+        client
+            .post(proxy.url("/v1/chat/completions"))
+            .header("x-proxy-cache", cache)
+            .json(&json!({
+                "model": "llama3",
+                "messages": [{"role": "user", "content": "Hello"}],
+                "temperature": temperature
+            }))
+            .send()
+    };
+    let first = chat(0.0, "").await.unwrap();
+    assert_eq!(first.headers()["x-proxy-cache"], "miss");
+    let first: Value = first.json().await.unwrap();
+
+    let second = chat(0.0, "").await.unwrap();
+    assert_eq!(second.headers()["x-proxy-cache"], "hit");
+    assert_eq!(second.json::<Value>().await.unwrap(), first);
+    assert_eq!(chat_calls(), 1);
+
+    // Different sampling parameters, or an explicit bypass, reach Ollama
+    assert_eq!(chat(0.7, "").await.unwrap().headers()["x-proxy-cache"], "miss");
+    let bypassed = chat(0.0, "bypass").await.unwrap();
+    assert!(bypassed.headers().get("x-proxy-cache").is_none());
+    assert_eq!(chat_calls(), 3);
+}
+
+#[tokio::test]
+async fn test_concurrent_identical_chats_share_one_upstream_call() {
+    use axum::{routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let chat_calls = calls.clone();
+    let router = Router::new()
+        .route(
+            "/api/show",
+            post(|| async { Json(json!({"model_info": {"general.architecture": "llama", "llama.context_length": 8192}})) }),
+        )
+        .route(
+            "/api/chat",
+            post(move || {
+                chat_calls.fetch_add(1, Ordering::SeqCst);
+                async {
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                    Json(json!({
+                        "model": "llama3",
+                        "created_at": "2025-01-01T00:00:00Z",
+                        "message": {"role": "assistant", "content": "Hello there"},
+                        "done": true,
+                        "done_reason": "stop"
+                    }))
+                }
+            }),
+        );
+    let ollama = MockOllama::with_router(router).await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    let chat = || async {
+        let response = client
+            .post(proxy.url("/v1/chat/completions"))
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        response.json::<Value>().await.unwrap()
+    };
+    let (a, b, c) = tokio::join!(chat(), chat(), chat());
+    assert_eq!(a["choices"][0]["message"]["content"], "Hello there");
+    assert_eq!(b["choices"], a["choices"]);
+    assert_eq!(c["choices"], a["choices"]);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let metrics = client.get(proxy.url("/metrics")).send().await.unwrap().text().await.unwrap();
+    assert!(metrics.contains("ollama_proxy_coalesced_requests_total 2"));
+}
+
+#[tokio::test]
+async fn test_legacy_embeddings_are_translated_to_embed() {
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url).max_embedding_input_length(100).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(proxy.url("/api/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "prompt": "hello"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["embedding"].as_array().unwrap().len(), 3);
+    let forwarded = ollama.last_request("/api/embed").unwrap();
+    assert_eq!(forwarded.body["input"], json!(["hello"]));
+    assert_eq!(forwarded.body["options"]["num_ctx"], 8192);
+    assert!(ollama.last_request("/api/embeddings").is_none());
+
+    // Long prompts are chunked like /v1/embeddings inputs
+    let long_prompt = "This sentence is here to make the prompt long. ".repeat(10);
+    let body: Value = client
+        .post(proxy.url("/api/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "prompt": long_prompt}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["embedding"].as_array().unwrap().len(), 3);
+    let embed_calls = ollama.requests().iter().filter(|r| r.path == "/api/embed").count();
+    assert!(embed_calls > 2);
+
+    let response = client
+        .post(proxy.url("/api/embeddings"))
+        .json(&json!({"model": "nomic-embed-text"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.json::<Value>().await.unwrap()["error"], "No prompt specified");
+}
+
+#[tokio::test]
+async fn test_too_many_chunks_is_rejected_with_413() {
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .max_embedding_input_length(100)
+        .max_chunks_per_request(3)
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let response = reqwest::Client::new()
+        .post(proxy.url("/v1/embeddings"))
+        .json(&json!({"model": "nomic-embed-text", "input": "Far too long a document. ".repeat(40)}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 413);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "too_many_chunks");
+    assert_eq!(body["error"]["param"], "input");
+    assert!(ollama.last_request("/api/embed").is_none());
+}
+
+#[tokio::test]
+async fn test_embedding_job_runs_in_background() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    let inputs: Vec<String> = (0..40).map(|i| format!("document {}", i)).collect();
+    let response = client
+        .post(proxy.url("/proxy/jobs"))
+        .json(&json!({"model": "nomic-embed-text", "input": inputs}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let job: Value = response.json().await.unwrap();
+    let id = job["id"].as_str().unwrap().to_string();
+    assert_eq!(job["progress"]["total"], 40);
+
+    let mut status = job;
+    for _ in 0..100 {
+        if status["status"] == "succeeded" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        status = client.get(proxy.url(&format!("/proxy/jobs/{}", id))).send().await.unwrap().json().await.unwrap();
+    }
+    assert_eq!(status["status"], "succeeded");
+    assert_eq!(status["progress"]["completed"], 40);
+
+    let result: Value = client
+        .get(proxy.url(&format!("/proxy/jobs/{}/result", id)))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let data = result["data"].as_array().unwrap();
+    assert_eq!(data.len(), 40);
+    assert_eq!(data[39]["index"], 39);
+    // Batched rather than one call per input or one huge call
+    let embed_calls = ollama.requests().iter().filter(|r| r.path == "/api/embed").count();
+    assert_eq!(embed_calls, 3);
+
+    let response = client.get(proxy.url("/proxy/jobs/job-unknown")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_embedding_job_batches_wait_for_upstream_slots() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::concurrency::ConcurrencyLimits;
+    use ollama_proxy_rs::jobs::JobSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Slow embeddings that record how many calls overlapped
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let router = Router::new().route(
+        "/api/embed",
+        post({
+            let (running, peak) = (running.clone(), peak.clone());
+            move |Json(body): Json<Value>| async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                let count = body["input"].as_array().map_or(1, Vec::len);
+                Json(json!({"model": body["model"], "embeddings": vec![vec![0.1, 0.2, 0.3]; count]}))
+            }
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .concurrency_limits(ConcurrencyLimits { max_concurrent: 1, ..Default::default() })
+        .jobs(JobSettings { concurrency: 2, ..Default::default() })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    // Two jobs run at once, but their batches share the single upstream slot
+    let mut ids = Vec::new();
+    for job in 0..2 {
+        let inputs: Vec<String> = (0..40).map(|i| format!("job {} document {}", job, i)).collect();
+        let job: Value = client
+            .post(proxy.url("/proxy/jobs"))
+            .json(&json!({"model": "nomic-embed-text", "input": inputs}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        ids.push(job["id"].as_str().unwrap().to_string());
+    }
+    for id in ids {
+        let mut status = Value::Null;
+        for _ in 0..100 {
+            status = client.get(proxy.url(&format!("/proxy/jobs/{}", id))).send().await.unwrap().json().await.unwrap();
+            if status["status"] == "succeeded" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(status["status"], "succeeded");
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+    assert_eq!(proxy.state.concurrency.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn test_embedding_jobs_resume_from_journal() {
+    use ollama_proxy_rs::jobs::JobSettings;
+
+    let ollama = MockOllama::start().await;
+    let journal = std::env::temp_dir().join(format!("ollama-proxy-jobs-{}.jsonl", std::process::id()));
+    let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let options = json!({"preprocess": "off", "aggregation": "mean", "return_chunks": false});
+    let lines = [
+        json!({"id": "job-interrupted", "status": "queued", "at": created, "total": 2, "options": options,
+               "request": {"model": "nomic-embed-text", "input": ["a", "b"]}}),
+        json!({"id": "job-interrupted", "status": "running", "at": created}),
+    ];
+    std::fs::write(&journal, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+
+    let settings = JobSettings { journal: Some(journal.clone()), ..Default::default() };
+    let config = ProxyBuilder::new(&ollama.url).jobs(settings).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        status = client.get(proxy.url("/proxy/jobs/job-interrupted")).send().await.unwrap().json().await.unwrap();
+        if status["status"] == "succeeded" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status["status"], "succeeded");
+    assert_eq!(ollama.last_request("/api/embed").unwrap().body["input"], json!(["a", "b"]));
+
+    // Journal lines are written in the background
+    let mut last_line = String::new();
+    for _ in 0..100 {
+        let journal_text = std::fs::read_to_string(&journal).unwrap();
+        last_line = journal_text.lines().last().unwrap_or_default().to_string();
+        if last_line.contains("\"succeeded\"") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(last_line.contains("\"succeeded\""));
+    let _ = std::fs::remove_file(&journal);
+}
+
+#[tokio::test]
+async fn test_server_timing_header_reports_phases() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+    let phases = |response: &reqwest::Response| -> Vec<String> {
+        response.headers()["server-timing"]
+            .to_str()
+            .unwrap()
+            .split(", ")
+            .map(|phase| phase.split(';').next().unwrap().to_string())
+            .collect()
+    };
+
+    let response = client
+        .post(proxy.url("/v1/chat/completions"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(phases(&response), ["metadata_fetch", "modifiers", "upstream_ttfb", "upstream_total", "total"]);
+
+    // Streamed responses only know the time to first byte
+    let response = client
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(phases(&response), ["metadata_fetch", "modifiers", "upstream_ttfb", "total"]);
+
+    let config = ProxyBuilder::new(&ollama.url).server_timing(false).config().unwrap();
+    let quiet = TestProxy::start(config).await;
+    let response = client.get(quiet.url("/api/tags")).send().await.unwrap();
+    assert!(response.headers().get("server-timing").is_none());
+}
+
+fn upstream_client() -> std::sync::Arc<ollama_proxy_rs::upstream::UpstreamClient> {
+    use ollama_proxy_rs::metrics::Metrics;
+    use ollama_proxy_rs::upstream::{UpstreamClient, UpstreamOptions};
+
+    std::sync::Arc::new(UpstreamClient::new(UpstreamOptions::default(), std::sync::Arc::new(Metrics::new())))
+}
+
+#[tokio::test]
+async fn test_metadata_misses_are_deduplicated_and_never_block_other_models() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::model_metadata::ModelMetadataCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let shows = Arc::new(AtomicUsize::new(0));
+    let counter = shows.clone();
+    let ollama = MockOllama::with_router(Router::new().route(
+        "/api/show",
+        post(move |Json(body): Json<Value>| async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if body["name"] == "slow:latest" {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Json(json!({"model_info": {"llama.context_length": 4096}}))
+        }),
+    ))
+    .await;
+    let cache = Arc::new(ModelMetadataCache::new(ollama.url.clone(), upstream_client()));
+
+    // Ten concurrent misses for the slow model share one /api/show call...
+    let slow: Vec<_> = (0..10)
+        .map(|_| {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.get_model_info("slow").await })
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // ...while another model is looked up without waiting for it
+    let started = Instant::now();
+    assert_eq!(cache.get_model_info("fast").await.unwrap().n_ctx_train, 4096);
+    assert!(started.elapsed() < Duration::from_millis(300));
+
+    for lookup in slow {
+        assert_eq!(lookup.await.unwrap().unwrap().n_ctx_train, 4096);
+    }
+    assert_eq!(shows.load(Ordering::SeqCst), 2);
+    assert_eq!(cache.cached_models().await, ["fast:latest", "slow:latest"]);
+}
+
+#[tokio::test]
+async fn test_stale_metadata_is_served_while_it_refreshes() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::model_metadata::ModelMetadataCache;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // The model is re-created with a larger context after the first lookup
+    let context = Arc::new(AtomicU64::new(4096));
+    let current = context.clone();
+    let ollama = MockOllama::with_router(Router::new().route(
+        "/api/show",
+        post(move || async move {
+            let context = current.load(Ordering::SeqCst);
+            if context > 4096 {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            Json(json!({"model_info": {"llama.context_length": context}}))
+        }),
+    ))
+    .await;
+    let cache = ModelMetadataCache::new(ollama.url.clone(), upstream_client()).with_ttl(Some(Duration::from_millis(100)));
+    assert_eq!(cache.get_model_info("llama3").await.unwrap().n_ctx_train, 4096);
+    context.store(32768, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    // Expired: the old value comes back right away while the refresh runs
+    let started = Instant::now();
+    assert_eq!(cache.get_model_info("llama3").await.unwrap().n_ctx_train, 4096);
+    assert!(started.elapsed() < Duration::from_millis(100));
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(cache.get_model_info("llama3").await.unwrap().n_ctx_train, 32768);
+}
+
+#[tokio::test]
+async fn test_metadata_lookups_time_out() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::model_metadata::ModelMetadataCache;
+    use std::time::{Duration, Instant};
+
+    // A hung /api/show gives up after the timeout
+    let hung = MockOllama::with_router(Router::new().route(
+        "/api/show",
+        post(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Json(json!({}))
+        }),
+    ))
+    .await;
+    let cache = ModelMetadataCache::new(hung.url.clone(), upstream_client()).with_timeout(Some(Duration::from_millis(100)));
+    let started = Instant::now();
+    assert!(cache.get_model_info("llama3").await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn test_metadata_is_prefetched_at_startup() {
+    use ollama_proxy_rs::model_metadata::MetadataPrefetch;
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .metadata_prefetch(MetadataPrefetch { enabled: true, interval: None })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    let mut cached = Vec::new();
+    for _ in 0..50 {
+        cached = proxy.state.metadata_cache.cached_models().await;
+        if !cached.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(cached, ["llama3:latest"]);
+    assert_eq!(ollama.last_request("/api/show").unwrap().body["name"], "llama3:latest");
+
+    // The first request to the model is served from the cache
+    let shows = ollama.requests().len();
+    reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert!(ollama.requests()[shows..].iter().all(|r| r.path != "/api/show"));
+}
+
+#[tokio::test]
+async fn test_cached_metadata_can_be_listed_and_invalidated() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+    let generate = || {
+        client
+            .post(proxy.url("/api/generate"))
+            .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+            .send()
+    };
+    let shows = || ollama.requests().iter().filter(|r| r.path == "/api/show").count();
+
+    generate().await.unwrap();
+    let body: Value = reqwest::get(proxy.url("/proxy/admin/metadata")).await.unwrap().json().await.unwrap();
+    assert_eq!(body["models"][0]["model"], "llama3:latest");
+    assert_eq!(body["models"][0]["n_ctx_train"], 8192);
+    assert_eq!(body["models"][0]["capabilities"], json!(["completion", "tools"]));
+    assert_eq!(body["models"][0]["stale"], false);
+    assert!(body["models"][0]["age_seconds"].is_u64());
+
+    // Edited Modelfile: drop the entry and the next request fetches it again
+    let response = client.delete(proxy.url("/proxy/admin/metadata/llama3")).send().await.unwrap();
+    assert_eq!(response.status(), 204);
+    let body: Value = reqwest::get(proxy.url("/proxy/admin/metadata")).await.unwrap().json().await.unwrap();
+    assert_eq!(body["models"], json!([]));
+    let response = client.delete(proxy.url("/proxy/admin/metadata/llama3")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    assert_eq!(shows(), 1);
+    generate().await.unwrap();
+    assert_eq!(shows(), 2);
+}
+
+#[tokio::test]
+async fn test_metadata_invalidation_needs_an_admin_key() {
+    use ollama_proxy_rs::auth::ApiKeys;
+
+    let ollama = MockOllama::start().await;
+    let keys = ApiKeys::parse_file("sk-admin name=ops admin\nsk-user name=user\n").unwrap();
+    let config = ProxyBuilder::new(&ollama.url).api_keys(ApiKeys::new(keys)).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    client
+        .post(proxy.url("/api/generate"))
+        .bearer_auth("sk-user")
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+        .send()
+        .await
+        .unwrap();
+
+    let invalidate = |key: &'static str| client.delete(proxy.url("/proxy/admin/metadata/llama3")).bearer_auth(key).send();
+    assert_eq!(invalidate("sk-user").await.unwrap().status(), 403);
+    assert_eq!(proxy.state.metadata_cache.cached_models().await, ["llama3:latest"]);
+    assert_eq!(invalidate("sk-admin").await.unwrap().status(), 204);
+    assert!(proxy.state.metadata_cache.cached_models().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_shadow_mode_logs_both_answers() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::access_log::LogDestination;
+    use ollama_proxy_rs::redact::{BodyLogging, LogBody};
+    use ollama_proxy_rs::shadow::ShadowSettings;
+
+    let ollama = MockOllama::start().await;
+    let candidate = MockOllama::with_router(Router::new().route(
+        "/api/chat",
+        post(|Json(body): Json<Value>| async move {
+            assert_eq!(body["model"], "candidate");
+            assert_eq!(body["stream"], false);
+            Json(json!({"message": {"role": "assistant", "content": "Hi!"}, "eval_count": 7, "done": true}))
+        }),
+    ))
+    .await;
+    let path = std::env::temp_dir().join(format!("ollama-proxy-shadow-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = ProxyBuilder::new(&ollama.url)
+        .shadow(Some(ShadowSettings {
+            model: Some("candidate".to_string()),
+            backend: Some(candidate.url.clone()),
+            models: vec![],
+            sample_percent: 100,
+            log: LogDestination::File(path.clone()),
+        }))
+        .body_logging(BodyLogging { policy: LogBody::Truncated, max_chars: 5, redact_fields: Vec::new() })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+
+    // The client gets the real stream, untouched
+    let body = reqwest::Client::new()
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}], "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body.lines().count(), 4);
+
+    let mut log = String::new();
+    for _ in 0..100 {
+        log = std::fs::read_to_string(&path).unwrap_or_default();
+        if !log.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    let line: Value = serde_json::from_str(log.trim()).unwrap();
+    assert_eq!(line["model"], "llama3");
+    assert_eq!(line["shadow_model"], "candidate");
+    // Answers are logged under the LOG_BODY policy
+    assert_eq!(line["primary"]["response"], "Hello… (11 bytes total)");
+    assert_eq!(line["primary"]["tokens"], 3);
+    assert_eq!(line["shadow"]["response"], "Hi!");
+    assert_eq!(line["token_delta"], 4);
+    assert!(line["latency_delta_ms"].is_number());
+}
+
+#[tokio::test]
+async fn test_shadow_requests_only_use_free_upstream_slots() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::access_log::LogDestination;
+    use ollama_proxy_rs::concurrency::ConcurrencyLimits;
+    use ollama_proxy_rs::shadow::ShadowSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let ollama = MockOllama::start().await;
+    let mirrored = Arc::new(AtomicUsize::new(0));
+    let counter = mirrored.clone();
+    let candidate = MockOllama::with_router(Router::new().route(
+        "/api/chat",
+        post(move || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Json(json!({"message": {"role": "assistant", "content": "Hi!"}, "done": true}))
+        }),
+    ))
+    .await;
+    let path = std::env::temp_dir().join(format!("ollama-proxy-shadow-slots-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let start = |max_concurrent: usize| {
+        let config = ProxyBuilder::new(&ollama.url)
+            .shadow(Some(ShadowSettings {
+                model: None,
+                backend: Some(candidate.url.clone()),
+                models: vec![],
+                sample_percent: 100,
+                log: LogDestination::File(path.clone()),
+            }))
+            .concurrency_limits(ConcurrencyLimits { max_concurrent, ..Default::default() })
+            .config()
+            .unwrap();
+        TestProxy::start(config)
+    };
+    let chat = |proxy: &TestProxy| {
+        reqwest::Client::new()
+            .post(proxy.url("/api/chat"))
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}], "stream": false}))
+            .send()
+    };
+
+    // The client's request holds the only slot, so there is none for the mirror
+    let proxy = start(1).await;
+    assert_eq!(chat(&proxy).await.unwrap().status(), 200);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(mirrored.load(Ordering::SeqCst), 0);
+
+    // With a spare slot the mirror runs, and gives the slot back once answered
+    let proxy = start(2).await;
+    assert_eq!(chat(&proxy).await.unwrap().status(), 200);
+    for _ in 0..100 {
+        if std::fs::read_to_string(&path).is_ok_and(|log| !log.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(mirrored.load(Ordering::SeqCst), 1);
+    assert_eq!(proxy.state.concurrency.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn test_access_log_records_each_request() {
+    use ollama_proxy_rs::access_log::{AccessLogFormat, LogDestination};
+
+    let ollama = MockOllama::start().await;
+    let path = std::env::temp_dir().join(format!("ollama-proxy-access-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = ProxyBuilder::new(&ollama.url)
+        .access_log(LogDestination::File(path.clone()), AccessLogFormat::Json)
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let body = client
+        .post(proxy.url("/v1/chat/completions"))
+        .bearer_auth("sk-secret")
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}]}))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    client.get(proxy.url("/healthz")).send().await.unwrap().bytes().await.unwrap();
+
+    // Lines are written once the server drops the finished body, just after the client sees it
+    let mut log = String::new();
+    for _ in 0..50 {
+        log = std::fs::read_to_string(&path).unwrap_or_default();
+        if log.lines().count() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["method"], "POST");
+    assert_eq!(lines[0]["path"], "/v1/chat/completions");
+    assert_eq!(lines[0]["status"], 200);
+    assert_eq!(lines[0]["bytes"], body.len());
+    assert_eq!(lines[0]["model"], "llama3");
+    assert!(lines[0]["key_id"].as_str().unwrap().starts_with("key-"));
+    assert!(!log.contains("sk-secret"));
+    assert_eq!(lines[1]["path"], "/healthz");
+    assert_eq!(lines[1]["model"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_model_stats_track_throughput_and_time_to_first_token() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    let client = reqwest::Client::new();
+
+    client
+        .post(proxy.url("/api/generate"))
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": true}))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    client
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    client
+        .post(proxy.url("/v1/chat/completions"))
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}]}))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+
+    // Each mock answer reports 3 tokens generated in 1ms
+    let mut stats = Value::Null;
+    for _ in 0..50 {
+        stats = client.get(proxy.url("/proxy/admin/stats")).send().await.unwrap().json().await.unwrap();
+        if stats["models"][0]["generated_tokens"] == 9 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let model = &stats["models"][0];
+    assert_eq!(model["model"], "llama3");
+    assert_eq!(model["generated_tokens"], 9);
+    assert_eq!(model["tokens_per_second"]["mean"], 3000.0);
+    assert_eq!(model["time_to_first_token_ms"]["samples"], 1);
+    assert_eq!(model["latency_ms"]["chat"]["samples"], 2);
+
+    let metrics = client.get(proxy.url("/metrics")).send().await.unwrap().text().await.unwrap();
+    assert!(metrics.contains("ollama_proxy_generated_tokens_total{model=\"llama3\"} 9\n"));
+    assert!(metrics.contains("ollama_proxy_time_to_first_token_seconds{model=\"llama3\",quantile=\"0.5\"}"));
+}
+
+#[tokio::test]
+async fn test_model_sections_override_global_defaults() {
+    use ollama_proxy_rs::overrides::{ModelOverrides, ModelSettings};
+
+    let ollama = MockOllama::start().await;
+    let mut overrides = ModelOverrides::default();
+    overrides.insert(
+        "llama3",
+        ModelSettings {
+            max_context: Some(4096),
+            num_predict_default: Some(256),
+            keep_alive: Some("10m".to_string()),
+            max_concurrent: None,
+        },
+    );
+    let config = ProxyBuilder::new(&ollama.url).model_overrides(overrides).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    for path in ["/api/chat", "/v1/chat/completions"] {
+        let response = client
+            .post(proxy.url(path))
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let forwarded = ollama.last_request("/api/chat").unwrap();
+        assert_eq!(forwarded.body["options"]["num_ctx"], 4096, "{}", path);
+        assert_eq!(forwarded.body["options"]["num_predict"], 256, "{}", path);
+        assert_eq!(forwarded.body["keep_alive"], "10m", "{}", path);
+    }
+
+    // Other models keep the global defaults
+    client
+        .post(proxy.url("/api/chat"))
+        .json(&json!({"model": "mistral", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    let forwarded = ollama.last_request("/api/chat").unwrap();
+    assert_eq!(forwarded.body["options"]["num_ctx"], 8192);
+    assert_eq!(forwarded.body["options"]["num_predict"], 4096);
+    assert!(forwarded.body.get("keep_alive").is_none());
+}
+
+#[tokio::test]
+async fn test_api_keys_are_required_except_for_liveness() {
+    use ollama_proxy_rs::auth::{ApiKey, ApiKeys};
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .api_keys(ApiKeys::new([ApiKey::new("sk-valid")]))
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let chat = || {
+        client
+            .post(proxy.url("/v1/chat/completions"))
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "Hello"}]}))
+    };
+
+    let missing = chat().send().await.unwrap();
+    assert_eq!(missing.status(), 401);
+    assert_eq!(missing.headers()["www-authenticate"], "Bearer");
+    let body: Value = missing.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "authentication_error");
+    assert_eq!(body["error"]["code"], "invalid_api_key");
+
+    let wrong = chat().bearer_auth("sk-wrong").send().await.unwrap();
+    assert_eq!(wrong.status(), 401);
+    assert!(ollama.last_request("/api/chat").is_none());
+
+    assert_eq!(chat().bearer_auth("sk-valid").send().await.unwrap().status(), 200);
+    assert_eq!(client.get(proxy.url("/api/tags")).send().await.unwrap().status(), 401);
+    assert_eq!(client.get(proxy.url("/metrics")).send().await.unwrap().status(), 401);
+    assert_eq!(client.get(proxy.url("/healthz")).send().await.unwrap().status(), 200);
+
+    // The proxy's key stays with the proxy
+    let generate = client
+        .post(proxy.url("/api/generate"))
+        .bearer_auth("sk-valid")
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(generate.status(), 200);
+    assert!(!ollama.last_request("/api/generate").unwrap().headers.contains_key("authorization"));
+}
+
+#[tokio::test]
+async fn test_authorization_reaches_ollama_when_the_proxy_does_not_authenticate() {
+    let ollama = MockOllama::start().await;
+    let proxy = TestProxy::for_upstream(&ollama.url).await;
+    reqwest::Client::new()
+        .post(proxy.url("/api/generate"))
+        .bearer_auth("ollama-token")
+        .json(&json!({"model": "llama3", "prompt": "hi", "stream": false}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(ollama.last_request("/api/generate").unwrap().headers["authorization"], "Bearer ollama-token");
+}
+
+#[tokio::test]
+async fn test_rate_limits_per_key_with_global_default() {
+    use ollama_proxy_rs::auth::ApiKeys;
+    use ollama_proxy_rs::ratelimit::RateLimits;
+
+    let ollama = MockOllama::start().await;
+    let keys = ApiKeys::parse_file("sk-limited name=limited\nsk-tokens name=tokens rpm=0 tpm=50\n").unwrap();
+    let config = ProxyBuilder::new(&ollama.url)
+        .api_keys(ApiKeys::new(keys))
+        .rate_limits(RateLimits { requests_per_minute: Some(2), tokens_per_minute: None })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let chat = |key: &str, content: &str| {
+        client
+            .post(proxy.url("/v1/chat/completions"))
+            .bearer_auth(key)
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": content}]}))
+    };
+
+    // The global 2 requests/min applies to the key without its own limit
+    for _ in 0..2 {
+        assert_eq!(chat("sk-limited", "Hello").send().await.unwrap().status(), 200);
+    }
+    let limited = chat("sk-limited", "Hello").send().await.unwrap();
+    assert_eq!(limited.status(), 429);
+    let retry_after: u64 = limited.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((1..=30).contains(&retry_after));
+    let body: Value = limited.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "rate_limit_error");
+    assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+
+    // rpm=0 lifts the request limit; its 50 tokens/min still applies
+    for _ in 0..3 {
+        assert_eq!(chat("sk-tokens", "Hello").send().await.unwrap().status(), 200);
+    }
+    let too_long = chat("sk-tokens", &"word ".repeat(100)).send().await.unwrap();
+    assert_eq!(too_long.status(), 429);
+
+    // Embedding jobs draw on the same budgets
+    let job = |key: &str, input: Value| {
+        client
+            .post(proxy.url("/proxy/jobs"))
+            .bearer_auth(key)
+            .json(&json!({"model": "nomic-embed-text", "input": input}))
+    };
+    assert_eq!(job("sk-limited", json!("Hello")).send().await.unwrap().status(), 429);
+    let inputs: Vec<String> = (0..20).map(|i| format!("document number {} of the batch", i)).collect();
+    assert_eq!(job("sk-tokens", json!(inputs)).send().await.unwrap().status(), 429);
+}
+
+#[tokio::test]
+async fn test_scoped_keys_are_held_to_their_models_endpoints_and_caps() {
+    use ollama_proxy_rs::auth::ApiKeys;
+
+    let ollama = MockOllama::start().await;
+    let keys = ApiKeys::parse_file(
+        "sk-embed name=search models=nomic-embed-text endpoints=embeddings\n\
+         sk-chat name=notebooks models=llama3* max_context=2048 max_num_predict=128\n\
+         sk-bge name=bge models=bge*\n",
+    )
+    .unwrap();
+    let config = ProxyBuilder::new(&ollama.url).api_keys(ApiKeys::new(keys)).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let chat = |key: &str, model: &str, extra: Value| {
+        let mut body = json!({"model": model, "messages": [{"role": "user", "content": "Hello"}]});
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        client.post(proxy.url("/v1/chat/completions")).bearer_auth(key).json(&body)
+    };
+
+    // Embeddings-only key
+    let response = client
+        .post(proxy.url("/v1/embeddings"))
+        .bearer_auth("sk-embed")
+        .json(&json!({"model": "nomic-embed-text", "input": "hello"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let refused = chat("sk-embed", "nomic-embed-text", json!({})).send().await.unwrap();
+    assert_eq!(refused.status(), 403);
+    let body: Value = refused.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "permission_error");
+    assert_eq!(body["error"]["code"], "endpoint_not_allowed");
+
+    // Chat key: other models are refused, context and generation are capped
+    let refused = chat("sk-chat", "qwen2.5", json!({})).send().await.unwrap();
+    assert_eq!(refused.status(), 403);
+    let body: Value = refused.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "model_not_allowed");
+    assert_eq!(body["error"]["param"], "model");
+    assert!(ollama.last_request("/api/chat").is_none());
+
+    let response = chat("sk-chat", "llama3", json!({"max_tokens": 1000})).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let forwarded = ollama.last_request("/api/chat").unwrap();
+    assert_eq!(forwarded.body["options"]["num_ctx"], 2048);
+    assert_eq!(forwarded.body["options"]["num_predict"], 128);
+
+    let response = client
+        .post(proxy.url("/api/chat"))
+        .bearer_auth("sk-chat")
+        .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": false, "options": {"num_ctx": 100000}}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let forwarded = ollama.last_request("/api/chat").unwrap();
+    assert_eq!(forwarded.body["options"]["num_ctx"], 2048);
+    assert_eq!(forwarded.body["options"]["num_predict"], 128);
+
+    // Model management names models outside `model`
+    let delete = |body: Value| client.delete(proxy.url("/api/delete")).bearer_auth("sk-bge").json(&body).send();
+    let refused = delete(json!({"name": "llama3"})).await.unwrap();
+    assert_eq!(refused.status(), 403);
+    assert!(ollama.last_request("/api/delete").is_none());
+    let copy = client
+        .post(proxy.url("/api/copy"))
+        .bearer_auth("sk-bge")
+        .json(&json!({"source": "llama3", "destination": "bge-llama"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(copy.status(), 403);
+    assert_eq!(delete(json!({"name": "bge-m3"})).await.unwrap().status(), 200);
+
+    // Embedding jobs are held to the same scope as /v1/embeddings
+    let job = |key: &str, model: &str| {
+        client.post(proxy.url("/proxy/jobs")).bearer_auth(key).json(&json!({"model": model, "input": ["a", "b"]})).send()
+    };
+    let refused = job("sk-bge", "llama3").await.unwrap();
+    assert_eq!(refused.status(), 403);
+    let body: Value = refused.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "model_not_allowed");
+    assert_eq!(job("sk-embed", "llama3").await.unwrap().status(), 403);
+    assert_eq!(job("sk-embed", "nomic-embed-text").await.unwrap().status(), 202);
+}
+
+#[tokio::test]
+async fn test_embedding_jobs_are_private_to_their_key() {
+    use ollama_proxy_rs::auth::ApiKeys;
+
+    let ollama = MockOllama::start().await;
+    let keys = ApiKeys::parse_file("sk-search name=search\nsk-notebooks name=notebooks\n").unwrap();
+    let config = ProxyBuilder::new(&ollama.url).api_keys(ApiKeys::new(keys)).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let job: Value = client
+        .post(proxy.url("/proxy/jobs"))
+        .bearer_auth("sk-search")
+        .json(&json!({"model": "nomic-embed-text", "input": ["a", "b"]}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = job["id"].as_str().unwrap();
+    let status = |key: &str| client.get(proxy.url(&format!("/proxy/jobs/{}", id))).bearer_auth(key).send();
+    let result = |key: &str| client.get(proxy.url(&format!("/proxy/jobs/{}/result", id))).bearer_auth(key).send();
+
+    let mut own: Value = Value::Null;
+    for _ in 0..100 {
+        own = status("sk-search").await.unwrap().json().await.unwrap();
+        if own["status"] == "succeeded" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(own["status"], "succeeded");
+    assert_eq!(result("sk-search").await.unwrap().status(), 200);
+
+    // Another key can't tell the job exists
+    let other = status("sk-notebooks").await.unwrap();
+    assert_eq!(other.status(), 404);
+    let body: Value = other.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "job_not_found");
+    assert_eq!(result("sk-notebooks").await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn test_admin_api_creates_rotates_and_revokes_keys() {
+    use ollama_proxy_rs::auth::ApiKeys;
+
+    let ollama = MockOllama::start().await;
+    let path = std::env::temp_dir().join(format!("ollama-proxy-key-store-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let keys = ApiKeys::parse_file("sk-admin name=ops admin\nsk-user name=user\n").unwrap();
+    let config = ProxyBuilder::new(&ollama.url)
+        .api_keys(ApiKeys::new(keys))
+        .api_key_store(&path)
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let tags = |key: &str| client.get(proxy.url("/api/tags")).bearer_auth(key).send();
+
+    // Only admin keys manage keys
+    let refused = client.get(proxy.url("/proxy/admin/keys")).bearer_auth("sk-user").send().await.unwrap();
+    assert_eq!(refused.status(), 403);
+
+    // ...or use any other admin endpoint
+    let pin = client.post(proxy.url("/proxy/admin/models/llama3/pin")).bearer_auth("sk-user").send().await.unwrap();
+    assert_eq!(pin.status(), 403);
+    assert!(ollama.last_request("/api/generate").is_none());
+    let stats = client.get(proxy.url("/proxy/admin/stats")).bearer_auth("sk-user").send().await.unwrap();
+    assert_eq!(stats.status(), 403);
+    let stats = client.get(proxy.url("/proxy/admin/stats")).bearer_auth("sk-admin").send().await.unwrap();
+    assert_eq!(stats.status(), 200);
+
+    let created = client
+        .post(proxy.url("/proxy/admin/keys"))
+        .bearer_auth("sk-admin")
+        .json(&json!({"name": "search", "endpoints": ["embeddings"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 201);
+    let created: Value = created.json().await.unwrap();
+    let id = created["id"].as_str().unwrap().to_string();
+    let secret = created["key"].as_str().unwrap().to_string();
+    assert!(secret.starts_with("sk-"));
+
+    // The new key works right away, within its scope
+    let embed = client
+        .post(proxy.url("/v1/embeddings"))
+        .bearer_auth(&secret)
+        .json(&json!({"model": "nomic-embed-text", "input": "hello"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(embed.status(), 200);
+    assert_eq!(tags(&secret).await.unwrap().status(), 403);
+
+    let listed = client.get(proxy.url("/proxy/admin/keys")).bearer_auth("sk-admin").send().await.unwrap().text().await.unwrap();
+    let listed: Value = serde_json::from_str(&listed).unwrap();
+    assert_eq!(listed["keys"].as_array().unwrap().len(), 3);
+    assert!(!listed.to_string().contains(&secret));
+    assert!(!listed.to_string().contains("sk-admin"));
+
+    let rotated: Value = client
+        .post(proxy.url(&format!("/proxy/admin/keys/{}/rotate", id)))
+        .bearer_auth("sk-admin")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let rotated_secret = rotated["key"].as_str().unwrap().to_string();
+    assert_ne!(rotated_secret, secret);
+    assert_eq!(tags(&secret).await.unwrap().status(), 401);
+
+    let revoked = client
+        .delete(proxy.url(&format!("/proxy/admin/keys/{}", id)))
+        .bearer_auth("sk-admin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(revoked.status(), 204);
+    assert_eq!(tags(&rotated_secret).await.unwrap().status(), 401);
+    assert!(std::fs::read_to_string(&path).unwrap().trim() == "[]");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_jwts_from_the_issuer_are_accepted_with_their_claims() {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use ollama_proxy_rs::jwt::JwtSettings;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+    let point = key.public_key().as_ref().to_vec();
+
+    // A minimal OIDC issuer: discovery document and JWKS
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let jwks = json!({"keys": [{
+        "kty": "EC", "kid": "k1", "crv": "P-256",
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+    }]});
+    let discovery = json!({"issuer": issuer, "jwks_uri": format!("{}/jwks", issuer)});
+    let app = axum::Router::new()
+        .route("/.well-known/openid-configuration", axum::routing::get(move || async move { axum::Json(discovery) }))
+        .route("/jwks", axum::routing::get(move || async move { axum::Json(jwks) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let ollama = MockOllama::start().await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .jwt(JwtSettings { audience: Some("ollama-proxy".to_string()), ..JwtSettings::new(&issuer) })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let token = |claims: Value| {
+        let encode = |value: Value| URL_SAFE_NO_PAD.encode(value.to_string());
+        let message = format!("{}.{}", encode(json!({"alg": "ES256", "kid": "k1"})), encode(claims));
+        let signature = key.sign(&rng, message.as_bytes()).unwrap();
+        format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    };
+    let claims = json!({
+        "iss": issuer, "aud": "ollama-proxy", "sub": "alice", "exp": now + 600,
+        "ollama_proxy": {"models": ["llama3*"]},
+    });
+    let chat = |token: String, model: &str| {
+        client
+            .post(proxy.url("/v1/chat/completions"))
+            .bearer_auth(token)
+            .json(&json!({"model": model, "messages": [{"role": "user", "content": "Hello"}]}))
+            .send()
+    };
+
+    assert_eq!(chat(token(claims.clone()), "llama3").await.unwrap().status(), 200);
+    // Scope from the limits claim
+    assert_eq!(chat(token(claims.clone()), "qwen2.5").await.unwrap().status(), 403);
+
+    let mut expired = claims.clone();
+    expired["exp"] = json!(now - 3600);
+    let response = chat(token(expired), "llama3").await.unwrap();
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["message"], "Invalid token: token has expired");
+
+    let mut wrong_audience = claims;
+    wrong_audience["aud"] = json!("another-service");
+    assert_eq!(chat(token(wrong_audience), "llama3").await.unwrap().status(), 401);
+    assert_eq!(chat("sk-anything".to_string(), "llama3").await.unwrap().status(), 401);
+}
+
+#[tokio::test]
+async fn test_https_listener_serves_the_proxy() {
+    use ollama_proxy_rs::tls::{self, TlsSettings};
+
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls");
+    let settings = TlsSettings {
+        cert: fixtures.join("localhost-cert.pem"),
+        key: fixtures.join("localhost-key.pem"),
+        reload_interval: None,
+    };
+    let ollama = MockOllama::start().await;
+    let app = ProxyBuilder::new(&ollama.url).tls(settings.clone()).router().unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(tls::serve(listener, app, tls::acceptor(&settings).unwrap()));
+
+    let ca = reqwest::Certificate::from_pem(&std::fs::read(&settings.cert).unwrap()).unwrap();
+    let client = reqwest::Client::builder().add_root_certificate(ca).build().unwrap();
+    let response = client.get(format!("https://localhost:{}/api/tags", port)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.json::<Value>().await.unwrap()["models"].is_array());
+
+    // Plain HTTP on the TLS port fails instead of being served
+    assert!(reqwest::get(format!("http://127.0.0.1:{}/healthz", port)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_client_certificate_is_presented_to_mutual_tls_upstreams() {
+    use ollama_proxy_rs::tls;
+    use ollama_proxy_rs::upstream::{load_ca_bundle, load_client_identity, UpstreamOptions};
+    use std::sync::Arc;
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_rustls::rustls::server::WebPkiClientVerifier;
+    use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+
+    // An "Ollama" behind a gateway that only accepts clients signed by client-ca.pem
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tls");
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(fixtures.join("client-ca.pem")).unwrap()).unwrap();
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots)).build().unwrap();
+    let server = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![CertificateDer::from_pem_file(fixtures.join("localhost-cert.pem")).unwrap()],
+            PrivateKeyDer::from_pem_file(fixtures.join("localhost-key.pem")).unwrap(),
+        )
+        .unwrap();
+    let app = axum::Router::new().route("/api/tags", axum::routing::get(|| async { axum::Json(json!({"models": []})) }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_url = format!("https://localhost:{}", listener.local_addr().unwrap().port());
+    tokio::spawn(tls::serve(listener, app, tokio_rustls::TlsAcceptor::from(Arc::new(server))));
+
+    let trust = UpstreamOptions {
+        ca_certificates: load_ca_bundle(&fixtures.join("localhost-cert.pem")).unwrap(),
+        ..UpstreamOptions::default()
+    };
+    let identity = load_client_identity(&fixtures.join("client-cert.pem"), &fixtures.join("client-key.pem")).unwrap();
+    let with_cert = UpstreamOptions { client_identity: Some(identity), ..trust.clone() };
+    let proxy = TestProxy::start(ProxyBuilder::new(&upstream_url).upstream(with_cert).config().unwrap()).await;
+    let response = reqwest::get(format!("{}/api/tags", proxy.url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Without the client certificate the gateway refuses the handshake
+    let proxy = TestProxy::start(ProxyBuilder::new(&upstream_url).upstream(trust).config().unwrap()).await;
+    let response = reqwest::get(format!("{}/api/tags", proxy.url)).await.unwrap();
+    assert!(response.status().is_server_error());
+
+    assert!(load_client_identity(&fixtures.join("client-cert.pem"), &fixtures.join("missing.pem")).is_err());
+}
+
+#[tokio::test]
+async fn test_ip_filter_checks_peers_and_trusted_forwarded_clients() {
+    use ollama_proxy_rs::ipfilter::{IpFilter, IpNet};
+
+    let ollama = MockOllama::start().await;
+    let client = reqwest::Client::new();
+    let start = |filter: IpFilter| {
+        TestProxy::start(ProxyBuilder::new(&ollama.url).ip_filter(filter).config().unwrap())
+    };
+    let status = |proxy: &TestProxy, forwarded: Option<&str>| {
+        let mut request = client.get(proxy.url("/api/tags"));
+        if let Some(forwarded) = forwarded {
+            request = request.header("X-Forwarded-For", forwarded);
+        }
+        async move { request.send().await.unwrap().status().as_u16() }
+    };
+
+    // Not behind a trusted proxy: the header is ignored and the peer (127.0.0.1) is checked
+    let proxy = start(IpFilter { allow: IpNet::parse_list("10.0.0.0/8").unwrap(), ..Default::default() }).await;
+    assert_eq!(status(&proxy, None).await, 403);
+    assert_eq!(status(&proxy, Some("10.1.2.3")).await, 403);
+    let response = client.post(proxy.url("/v1/embeddings")).json(&json!({"model": "m", "input": "hi"})).send().await.unwrap();
+    assert_eq!(response.json::<Value>().await.unwrap()["error"]["code"], "ip_not_allowed");
+
+    // Behind a trusted proxy the forwarded client is checked instead
+    let proxy = start(IpFilter {
+        allow: IpNet::parse_list("10.0.0.0/8").unwrap(),
+        deny: IpNet::parse_list("10.9.0.0/16").unwrap(),
+        trusted_proxies: IpNet::parse_list("127.0.0.1").unwrap(),
+    })
+    .await;
+    assert_eq!(status(&proxy, Some("10.1.2.3")).await, 200);
+    assert_eq!(status(&proxy, Some("10.1.2.3, 8.8.8.8")).await, 403);
+    assert_eq!(status(&proxy, Some("10.9.0.1")).await, 403);
+
+    let proxy = start(IpFilter { deny: IpNet::parse_list("127.0.0.0/8").unwrap(), ..Default::default() }).await;
+    assert_eq!(status(&proxy, None).await, 403);
+}
+
+#[tokio::test]
+async fn test_concurrency_limit_queues_excess_requests() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::concurrency::ConcurrencyLimits;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Slow chat that records how many calls overlapped
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let router = Router::new().route(
+        "/api/chat",
+        post({
+            let (running, peak) = (running.clone(), peak.clone());
+            move || async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Json(json!({"model": "llama3", "message": {"role": "assistant", "content": "hi"}, "done": true}))
+            }
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let config = ProxyBuilder::new(&ollama.url)
+        .concurrency_limits(ConcurrencyLimits { max_concurrent: 2, max_queued: 2, queue_timeout: None })
+        .config()
+        .unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+    let chat = || {
+        client
+            .post(proxy.url("/api/chat"))
+            .json(&json!({"model": "llama3", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+            .send()
+    };
+
+    // Four at once: two run while two wait, so Ollama never sees more than two
+    let statuses: Vec<u16> = futures::future::join_all((0..4).map(|_| chat()))
+        .await
+        .into_iter()
+        .map(|response| response.unwrap().status().as_u16())
+        .collect();
+    assert_eq!(statuses, vec![200; 4]);
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+    // With two running and two queued, a fifth request finds the queue full
+    let (held, rejected) = tokio::join!(futures::future::join_all((0..4).map(|_| chat())), async {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        chat().await.unwrap()
+    });
+    assert!(held.into_iter().all(|response| response.unwrap().status() == 200));
+    assert_eq!(rejected.status(), 503);
+    assert!(rejected.headers().contains_key("retry-after"));
+    let body: Value = rejected.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "queue_full");
+
+    let metrics = client.get(proxy.url("/metrics")).send().await.unwrap().text().await.unwrap();
+    assert!(metrics.contains("ollama_proxy_queue_rejections_total{reason=\"full\"} 1"));
+    assert!(metrics.contains("ollama_proxy_upstream_in_flight 0"));
+    assert!(metrics.contains("ollama_proxy_upstream_queued 0"));
+}
+
+#[tokio::test]
+async fn test_model_max_concurrent_serializes_its_requests() {
+    use axum::{routing::post, Json, Router};
+    use ollama_proxy_rs::overrides::{ModelOverrides, ModelSettings};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let router = Router::new().route(
+        "/api/chat",
+        post({
+            let (running, peak) = (running.clone(), peak.clone());
+            move || async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Json(json!({"model": "big", "message": {"role": "assistant", "content": "hi"}, "done": true}))
+            }
+        }),
+    );
+    let ollama = MockOllama::with_router(router).await;
+    let mut overrides = ModelOverrides::default();
+    overrides.insert("big", ModelSettings { max_concurrent: Some(1), ..Default::default() });
+    let config = ProxyBuilder::new(&ollama.url).model_overrides(overrides).config().unwrap();
+    let proxy = TestProxy::start(config).await;
+    let client = reqwest::Client::new();
+
+    let responses = futures::future::join_all((0..3).map(|_| {
+        client
+            .post(proxy.url("/api/chat"))
+            .json(&json!({"model": "big", "messages": [{"role": "user", "content": "hi"}], "stream": false}))
+            .send()
+    }))
+    .await;
+    assert!(responses.into_iter().all(|response| response.unwrap().status() == 200));
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+}